@@ -0,0 +1,146 @@
+use cgmath::{Matrix3, Matrix4, Point3, Vector3, Rad, EuclideanSpace, InnerSpace, SquareMatrix};
+
+use mesh::HalfEdgeMesh;
+use bvh::Bvh;
+use ptr::VertRc;
+use util::OpTimer;
+
+/// Settings for `HalfEdgeMesh::align_to`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IcpOptions {
+  /// Most rounds of matching and moving to run
+  pub max_iterations: usize,
+  /// Stop once a round lowers the mean squared distance to the target by less than this
+  pub tolerance: f32,
+  /// Vertices further than this from the target are left out of the matching, so parts of a scan
+  /// which the target doesn't cover don't drag the mesh toward them
+  pub max_distance: f32,
+  /// Vertices whose normal is more than this angle, in radians, from the normal of the target where
+  /// they match are left out too, which keeps the front of a thin part from matching its back
+  pub max_normal_angle: f32,
+  /// Move the mesh into place. Otherwise it's left alone and the transform is only returned
+  pub apply: bool,
+}
+
+impl Default for IcpOptions {
+  fn default() -> IcpOptions {
+    IcpOptions {
+      max_iterations: 50,
+      tolerance: 1e-7,
+      max_distance: f32::INFINITY,
+      max_normal_angle: ::std::f32::consts::FRAC_PI_3,
+      apply: true,
+    }
+  }
+}
+
+/// Solves the 6x6 system by Gaussian elimination with partial pivoting. None if it's singular
+fn solve6(mut a: [[f64; 6]; 6], mut b: [f64; 6]) -> Option<[f64; 6]> {
+  for col in 0..6 {
+    let pivot = (col..6).max_by(|&i, &j| a[i][col].abs().total_cmp(& a[j][col].abs()))?;
+    if a[pivot][col].abs() < 1e-12 { return None; }
+    a.swap(col, pivot);
+    b.swap(col, pivot);
+    for row in col + 1..6 {
+      let factor = a[row][col] / a[col][col];
+      let pivot_row = a[col];
+      for (value, pivot_value) in a[row].iter_mut().zip(& pivot_row).skip(col) { *value -= factor * pivot_value; }
+      b[row] -= factor * b[col];
+    }
+  }
+  let mut x = [0.0; 6];
+  for row in (0..6).rev() {
+    let rest: f64 = (row + 1..6).map(|k| a[row][k] * x[k]).sum();
+    x[row] = (b[row] - rest) / a[row][row];
+  }
+  Some(x)
+}
+
+impl HalfEdgeMesh {
+  /// Moves the mesh to line up with the target, by point-to-plane iterative closest point (Chen and Medioni):
+  /// each round, every vertex is matched to the closest point on the target, and the mesh is moved rigidly to
+  /// bring the vertices as close as it can to the target's planes at those points. This only finds the nearest
+  /// fit, so the mesh should start roughly in place, as with two scans of the same thing from nearby viewpoints.
+  ///
+  /// Returns the transform from where the mesh was to where it lines up, which is the identity if nothing
+  /// matched. Only triangular faces of the target are matched against, as in `Bvh`
+  pub fn align_to(&mut self, target: & HalfEdgeMesh, opts: IcpOptions) -> Matrix4<f32> {
+    let start = OpTimer::start();
+    let bvh = Bvh::new(target);
+    let verts: Vec<VertRc> = self.vertex_order().iter().map(|id| self.vertices[id].clone()).collect();
+    let mut points: Vec<Point3<f32>> = verts.iter().map(|v| v.borrow().get_pos()).collect();
+    let mut normals: Vec<Vector3<f32>> = verts.iter().map(|v| v.borrow().normal()).collect();
+    let min_cos = opts.max_normal_angle.cos();
+
+    let mut rotation = Matrix3::identity();
+    let mut translation = Vector3::new(0.0, 0.0, 0.0);
+    let mut last_error = f32::INFINITY;
+    let mut iterations = 0;
+    while iterations < opts.max_iterations {
+      iterations += 1;
+
+      // Each matched vertex gives a row n . (w x p + t) = n . (q - p) of the linearized problem,
+      // solved by least squares through the normal equations
+      let mut ata = [[0.0f64; 6]; 6];
+      let mut atb = [0.0f64; 6];
+      let mut error = 0.0;
+      let mut matched = 0;
+      for (point, normal) in points.iter().zip(& normals) {
+        let (closest, hit) = match bvh.closest_point(point, opts.max_distance) {
+          Some(found) => found,
+          None => continue,
+        };
+        let plane_normal = match target.faces.get(& hit.face_id) {
+          Some(face) => face.borrow().normal,
+          None => continue,
+        };
+        // Vertices without faces have no normal to compare
+        if normal.magnitude2() > 0.0 && normal.dot(plane_normal) < min_cos { continue; }
+
+        let residual = plane_normal.dot(closest - point);
+        let arm = point.to_vec().cross(plane_normal);
+        let row = [arm.x, arm.y, arm.z, plane_normal.x, plane_normal.y, plane_normal.z].map(f64::from);
+        for i in 0..6 {
+          for j in 0..6 { ata[i][j] += row[i] * row[j]; }
+          atb[i] += row[i] * residual as f64;
+        }
+        error += residual * residual;
+        matched += 1;
+      }
+      if matched == 0 { break; }
+      error /= matched as f32;
+
+      // A little damping keeps directions the target doesn't pin down, like sliding along a plane, from running off
+      let damping = 1e-9 * (0..6).map(|i| ata[i][i]).sum::<f64>().max(1e-12);
+      for (i, row) in ata.iter_mut().enumerate() { row[i] += damping; }
+      let step = match solve6(ata, atb) {
+        Some(step) => step,
+        None => break,
+      };
+      let angle = Vector3::new(step[0], step[1], step[2]).map(|c| c as f32);
+      let shift = Vector3::new(step[3], step[4], step[5]).map(|c| c as f32);
+      let step_rotation = if angle.magnitude2() > 0.0 {
+        Matrix3::from_axis_angle(angle.normalize(), Rad(angle.magnitude()))
+      } else {
+        Matrix3::identity()
+      };
+
+      for point in points.iter_mut() { *point = Point3::from_vec(step_rotation * point.to_vec() + shift); }
+      for normal in normals.iter_mut() { *normal = step_rotation * *normal; }
+      rotation = step_rotation * rotation;
+      translation = step_rotation * translation + shift;
+
+      let improvement = last_error - error;
+      last_error = error;
+      if improvement < opts.tolerance { break; }
+    }
+
+    if opts.apply {
+      for (vert, point) in verts.iter().zip(& points) { self.move_vert(vert, *point); }
+      for tangent in self.tangents.values_mut() { *tangent = (rotation * tangent.truncate()).extend(tangent.w); }
+      for face in self.faces.values() { face.borrow_mut().compute_attrs(); }
+    }
+    debug!("align_to: {} iterations, mean squared distance {}, took {:?}", iterations, last_error, start.elapsed());
+    Matrix4::from_translation(translation) * Matrix4::from(rotation)
+  }
+}
@@ -0,0 +1,313 @@
+use std::any::Any;
+use std::collections::HashMap;
+
+use cgmath::{Vector2, Vector3, Vector4};
+
+/// Values which can be blended, so that editing operations can work out values for the vertices they add or merge
+pub trait Interpolate: Sized + Clone {
+  /// The value a fraction t of the way from a to b
+  fn lerp(a: & Self, b: & Self, t: f32) -> Self;
+
+  /// The average of the values, which mustn't be empty. By default a running mean of `lerp`s
+  fn average(items: & [Self]) -> Self {
+    let mut mean = items[0].clone();
+    for (idx, item) in items.iter().enumerate().skip(1) { mean = Self::lerp(& mean, item, 1.0 / (idx + 1) as f32); }
+    mean
+  }
+}
+
+impl Interpolate for f32 {
+  fn lerp(a: & f32, b: & f32, t: f32) -> f32 { a + (b - a) * t }
+  fn average(items: & [f32]) -> f32 { items.iter().sum::<f32>() / items.len() as f32 }
+}
+
+impl Interpolate for Vector2<f32> {
+  fn lerp(a: & Vector2<f32>, b: & Vector2<f32>, t: f32) -> Vector2<f32> { a + (b - a) * t }
+}
+
+impl Interpolate for Vector3<f32> {
+  fn lerp(a: & Vector3<f32>, b: & Vector3<f32>, t: f32) -> Vector3<f32> { a + (b - a) * t }
+}
+
+impl Interpolate for Vector4<f32> {
+  fn lerp(a: & Vector4<f32>, b: & Vector4<f32>, t: f32) -> Vector4<f32> { a + (b - a) * t }
+}
+
+/// Gives the vertex `into` the value a fraction t of the way from vertex a's to vertex b's. If only one of them
+/// has a value, `into` gets that one, and if neither does, it's left without one
+pub(crate) fn interpolate_vert<T: Interpolate>(values: &mut HashMap<u32, T>, a: u32, b: u32, t: f32, into: u32) {
+  let value = match (values.get(& a), values.get(& b)) {
+    (Some(a), Some(b)) => T::lerp(a, b, t),
+    (Some(only), None) | (None, Some(only)) => only.clone(),
+    (None, None) => return,
+  };
+  values.insert(into, value);
+}
+
+/// Values which can be kept per vertex in an `AttributeRegistry`, and written out flat into buffers.
+/// They're interpolated when edges are split or collapsed, and when the mesh is subdivided
+pub trait AttributeValue: Any + Interpolate {
+  /// Number of floats the value is written out as
+  const COMPONENTS: usize;
+  /// Writes the value out as `COMPONENTS` floats
+  fn write_components(& self, out: &mut Vec<f32>);
+}
+
+impl AttributeValue for f32 {
+  const COMPONENTS: usize = 1;
+  fn write_components(& self, out: &mut Vec<f32>) { out.push(*self); }
+}
+
+impl AttributeValue for Vector2<f32> {
+  const COMPONENTS: usize = 2;
+  fn write_components(& self, out: &mut Vec<f32>) { out.extend_from_slice(& [self.x, self.y]); }
+}
+
+impl AttributeValue for Vector3<f32> {
+  const COMPONENTS: usize = 3;
+  fn write_components(& self, out: &mut Vec<f32>) { out.extend_from_slice(& [self.x, self.y, self.z]); }
+}
+
+impl AttributeValue for Vector4<f32> {
+  const COMPONENTS: usize = 4;
+  fn write_components(& self, out: &mut Vec<f32>) { out.extend_from_slice(& [self.x, self.y, self.z, self.w]); }
+}
+
+/// What an attribute means to exporters, which decides the name it's written out under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AttributeSemantic {
+  /// Texture coordinate set, from 0
+  TexCoord(u32),
+  /// Vertex color set, from 0
+  Color(u32),
+  /// Skinning joint indices, from 0
+  Joints(u32),
+  /// Skinning weights, from 0
+  Weights(u32),
+  /// Application data, written out under its own name
+  #[default]
+  Custom,
+  /// Kept on the mesh but left out of exports
+  Internal,
+}
+
+impl AttributeSemantic {
+  /// Guesses the semantic from a registered name, like "uv0" or "texcoord_1" for texture coordinates,
+  /// "color" or "col1" for colors, and "joints0" and "weights0" for skinning. Anything else is custom
+  pub fn from_name(name: & str) -> AttributeSemantic {
+    let lower = name.to_ascii_lowercase();
+    let stem = lower.trim_end_matches(|c: char| c.is_ascii_digit());
+    let set: u32 = lower[stem.len()..].parse().unwrap_or(0);
+    match stem.trim_end_matches('_') {
+      "uv" | "texcoord" | "st" => AttributeSemantic::TexCoord(set),
+      "color" | "colour" | "col" => AttributeSemantic::Color(set),
+      "joints" => AttributeSemantic::Joints(set),
+      "weights" => AttributeSemantic::Weights(set),
+      _ => AttributeSemantic::Custom,
+    }
+  }
+
+  /// Name of the attribute in glTF: the standard names like TEXCOORD_0 and COLOR_0, and the registered
+  /// name after an underscore for custom attributes, as glTF requires. None for internal attributes
+  pub fn gltf_name(& self, name: & str) -> Option<String> {
+    match *self {
+      AttributeSemantic::TexCoord(set) => Some(format!("TEXCOORD_{}", set)),
+      AttributeSemantic::Color(set) => Some(format!("COLOR_{}", set)),
+      AttributeSemantic::Joints(set) => Some(format!("JOINTS_{}", set)),
+      AttributeSemantic::Weights(set) => Some(format!("WEIGHTS_{}", set)),
+      AttributeSemantic::Custom => Some(format!("_{}", name.to_ascii_uppercase())),
+      AttributeSemantic::Internal => None,
+    }
+  }
+
+  /// Names of the attribute's vertex properties in PLY, one per component: s and t for the first texture
+  /// coordinates, red, green, blue, and alpha for the first colors, and the name with a suffix otherwise.
+  /// Empty for internal attributes
+  pub fn ply_names(& self, name: & str, components: usize) -> Vec<String> {
+    let standard: & [& str] = match *self {
+      AttributeSemantic::TexCoord(0) => & ["s", "t"],
+      AttributeSemantic::Color(0) => & ["red", "green", "blue", "alpha"],
+      AttributeSemantic::Internal => return Vec::new(),
+      _ => & [],
+    };
+    if components <= standard.len() { return standard[.. components].iter().map(|s| s.to_string()).collect(); }
+    if components == 1 { return vec![name.to_string()]; }
+    ["x", "y", "z", "w"].iter().take(components).map(|axis| format!("{}_{}", name, axis)).collect()
+  }
+}
+
+/// Values of one attribute, by vertex id, with the type erased so attributes of different types can be kept together
+trait AttributeColumn {
+  fn as_any(& self) -> & dyn Any;
+  fn as_any_mut(&mut self) -> &mut dyn Any;
+  fn components(& self) -> usize;
+  /// Writes the vertex's value out, or zeros if it doesn't have one
+  fn write_vert(& self, id: u32, out: &mut Vec<f32>);
+  fn remove_vert(&mut self, id: u32);
+  fn interpolate_vert(&mut self, a: u32, b: u32, t: f32, into: u32);
+  fn renumber(&mut self, ids: & HashMap<u32, u32>);
+  fn clone_column(& self) -> Box<dyn AttributeColumn>;
+}
+
+impl<T: AttributeValue> AttributeColumn for HashMap<u32, T> {
+  fn as_any(& self) -> & dyn Any { self }
+  fn as_any_mut(&mut self) -> &mut dyn Any { self }
+  fn components(& self) -> usize { T::COMPONENTS }
+
+  fn write_vert(& self, id: u32, out: &mut Vec<f32>) {
+    match self.get(& id) {
+      Some(value) => value.write_components(out),
+      None => out.extend((0..T::COMPONENTS).map(|_| 0.0)),
+    }
+  }
+
+  fn remove_vert(&mut self, id: u32) { self.remove(& id); }
+
+  fn interpolate_vert(&mut self, a: u32, b: u32, t: f32, into: u32) { interpolate_vert(self, a, b, t, into); }
+
+  fn renumber(&mut self, ids: & HashMap<u32, u32>) {
+    *self = self.drain().filter_map(|(old, value)| ids.get(& old).map(|&new| (new, value))).collect();
+  }
+
+  fn clone_column(& self) -> Box<dyn AttributeColumn> { Box::new(self.clone()) }
+}
+
+struct RegisteredAttribute {
+  name: String,
+  semantic: AttributeSemantic,
+  column: Box<dyn AttributeColumn>,
+}
+
+/// An attribute written out into buffers, as flat floats, `components` per vertex
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BufferAttribute {
+  /// Name it was registered under
+  pub name: String,
+  pub semantic: AttributeSemantic,
+  pub components: usize,
+  pub data: Vec<f32>,
+}
+
+/// Named per-vertex attributes of a mesh, of any `AttributeValue` type, kept in the order they were registered.
+/// Each has a semantic, which is how `to_buffers` knows to write it out, and under what name.
+/// Vertices without a value for an attribute are written out with zeros
+#[derive(Default)]
+pub struct AttributeRegistry {
+  attributes: Vec<RegisteredAttribute>,
+}
+
+impl AttributeRegistry {
+  /// Adds an attribute, with the semantic guessed from the name by `AttributeSemantic::from_name`.
+  /// Registering a name again with the same type keeps the values it has. Fails if the name is already
+  /// registered with another type
+  pub fn register<T: AttributeValue>(&mut self, name: & str) -> Result<(), &'static str> {
+    self.register_as::<T>(name, AttributeSemantic::from_name(name))
+  }
+
+  /// `register`, with the semantic given
+  pub fn register_as<T: AttributeValue>(&mut self, name: & str, semantic: AttributeSemantic) -> Result<(), &'static str> {
+    if let Some(existing) = self.attributes.iter_mut().find(|a| a.name == name) {
+      if !existing.column.as_any().is::<HashMap<u32, T>>() { return Err("Attribute is already registered with another type"); }
+      existing.semantic = semantic;
+      return Ok(());
+    }
+    self.attributes.push(RegisteredAttribute { name: name.to_string(), semantic, column: Box::new(HashMap::<u32, T>::new()) });
+    Ok(())
+  }
+
+  /// Removes the attribute and its values. Returns whether it was registered
+  pub fn unregister(&mut self, name: & str) -> bool {
+    let before = self.attributes.len();
+    self.attributes.retain(|a| a.name != name);
+    self.attributes.len() != before
+  }
+
+  pub fn contains(& self, name: & str) -> bool { self.attributes.iter().any(|a| a.name == name) }
+
+  pub fn len(& self) -> usize { self.attributes.len() }
+
+  pub fn is_empty(& self) -> bool { self.attributes.is_empty() }
+
+  /// Registered names, in order of registration
+  pub fn names(& self) -> impl Iterator<Item=& str> { self.attributes.iter().map(|a| a.name.as_str()) }
+
+  /// Semantic of the attribute, if it's registered
+  pub fn semantic(& self, name: & str) -> Option<AttributeSemantic> {
+    self.attributes.iter().find(|a| a.name == name).map(|a| a.semantic)
+  }
+
+  /// Changes how the attribute is exported. Fails if it isn't registered
+  pub fn set_semantic(&mut self, name: & str, semantic: AttributeSemantic) -> Result<(), &'static str> {
+    let attribute = self.attributes.iter_mut().find(|a| a.name == name).ok_or("Attribute isn't registered")?;
+    attribute.semantic = semantic;
+    Ok(())
+  }
+
+  /// Values of the attribute by vertex id. None if it isn't registered, or is registered with another type
+  pub fn values<T: AttributeValue>(& self, name: & str) -> Option<& HashMap<u32, T>> {
+    self.attributes.iter().find(|a| a.name == name)?.column.as_any().downcast_ref()
+  }
+
+  /// Values of the attribute by vertex id, for changing. None if it isn't registered, or is registered with another type
+  pub fn values_mut<T: AttributeValue>(&mut self, name: & str) -> Option<&mut HashMap<u32, T>> {
+    self.attributes.iter_mut().find(|a| a.name == name)?.column.as_any_mut().downcast_mut()
+  }
+
+  /// Value of the attribute at the vertex, if it has one
+  pub fn get<T: AttributeValue>(& self, name: & str, vert_id: u32) -> Option<& T> {
+    self.values::<T>(name)?.get(& vert_id)
+  }
+
+  /// Sets the value of the attribute at the vertex. Fails if the attribute isn't registered with this type
+  pub fn set<T: AttributeValue>(&mut self, name: & str, vert_id: u32, value: T) -> Result<(), &'static str> {
+    self.values_mut::<T>(name).ok_or("Attribute isn't registered with this type")?.insert(vert_id, value);
+    Ok(())
+  }
+
+  /// Drops the vertex's values from every attribute
+  pub(crate) fn remove_vert(&mut self, vert_id: u32) {
+    for attribute in &mut self.attributes { attribute.column.remove_vert(vert_id); }
+  }
+
+  /// Gives the vertex `into` values blended from vertex a's and b's, in every attribute, as `Interpolate::lerp` does
+  pub(crate) fn interpolate_vert(&mut self, a: u32, b: u32, t: f32, into: u32) {
+    for attribute in &mut self.attributes { attribute.column.interpolate_vert(a, b, t, into); }
+  }
+
+  /// Moves every value from its old vertex id to the new one, dropping values of vertices not in the map
+  pub(crate) fn renumber(&mut self, ids: & HashMap<u32, u32>) {
+    for attribute in &mut self.attributes { attribute.column.renumber(ids); }
+  }
+
+  /// Appends the vertex's value of each exported attribute to the buffer attributes, adding any that are missing,
+  /// in order of registration
+  pub(crate) fn write_vert(& self, id: u32, out: &mut Vec<BufferAttribute>) {
+    for (idx, attribute) in self.attributes.iter().filter(|a| a.semantic != AttributeSemantic::Internal).enumerate() {
+      if out.len() <= idx {
+        out.push(BufferAttribute {
+          name: attribute.name.clone(),
+          semantic: attribute.semantic,
+          components: attribute.column.components(),
+          data: Vec::new(),
+        });
+      }
+      attribute.column.write_vert(id, &mut out[idx].data);
+    }
+  }
+}
+
+impl Clone for AttributeRegistry {
+  fn clone(& self) -> AttributeRegistry {
+    AttributeRegistry {
+      attributes: self.attributes.iter()
+        .map(|a| RegisteredAttribute { name: a.name.clone(), semantic: a.semantic, column: a.column.clone_column() })
+        .collect(),
+    }
+  }
+}
+
+impl ::std::fmt::Debug for AttributeRegistry {
+  fn fmt(& self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+    f.debug_map().entries(self.attributes.iter().map(|a| (& a.name, a.semantic))).finish()
+  }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, Vector2, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use geom::Ray;
+use iterators::ToPtrVec;
+use tangents::triangle_tangents;
+use surface::SurfacePoint;
+
+/// Normal map rays search this fraction of the low-poly mesh's bounding box diagonal,
+/// in front of and behind the surface
+const NORMAL_MAP_REACH: f32 = 0.05;
+
+/// A floating-point RGBA image. Row 0 is the top of the image, which is v = 1 in texture space
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+  pub width: usize,
+  pub height: usize,
+  pub pixels: Vec<[f32; 4]>,
+}
+
+impl Image {
+  /// A transparent black image
+  pub fn new(width: usize, height: usize) -> Image {
+    Image { width, height, pixels: vec![[0.0; 4]; width * height] }
+  }
+
+  pub fn get(& self, x: usize, y: usize) -> [f32; 4] { self.pixels[y * self.width + x] }
+
+  pub fn set(&mut self, x: usize, y: usize, value: [f32; 4]) { self.pixels[y * self.width + x] = value; }
+}
+
+/// Two unit vectors perpendicular to the normal and to each other
+fn tangent_frame(normal: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+  let helper = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+  let tangent = normal.cross(helper).normalize();
+  (tangent, normal.cross(tangent))
+}
+
+/// Evenly spread, cosine-weighted directions on the hemisphere around +z (a Fibonacci spiral).
+/// Deterministic, so that repeated bakes give the same result
+fn hemisphere_directions(count: u32) -> Vec<Vector3<f32>> {
+  let golden_angle = ::std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+  (0..count).map(|idx| {
+    let u = (idx as f32 + 0.5) / count as f32;
+    let radius = u.sqrt();
+    let phi = idx as f32 * golden_angle;
+    Vector3::new(radius * phi.cos(), radius * phi.sin(), (1.0 - u).sqrt())
+  }).collect()
+}
+
+/// Bakes a tangent-space normal map for the low-poly mesh, which must have texture coordinates
+/// given by `uvs` (vertex id to uv). For each texel covered by a low-poly face, a ray is cast back
+/// along the normal from a point a short distance outside the low-poly surface, and the smooth normal
+/// of the first high-poly hit is stored in the texel's tangent frame, packed into [0, 1] as usual.
+/// Alpha is 1 for texels that found the high-poly surface, and 0 elsewhere.
+/// Assumes triangular faces on both meshes
+pub fn normal_map(high: & HalfEdgeMesh, low: & HalfEdgeMesh, resolution: usize, uvs: & HashMap<u32, Vector2<f32>>) -> Image {
+  let mut image = Image::new(resolution, resolution);
+  if resolution == 0 { return image; }
+
+  let bvh = high.build_bvh();
+  let reach = low.aabb().size().magnitude() * NORMAL_MAP_REACH;
+  let high_normals: HashMap<u32, Vector3<f32>> = high.vertices.iter()
+    .map(|(&id, v)| (id, v.borrow().normal()))
+    .collect();
+
+  for face in low.faces.values() {
+    let face_b = face.borrow();
+    let corners = match face_b.triangle() { Some(c) => c, None => continue };
+    let verts = face_b.adjacent_verts().to_ptr_vec();
+    let face_uvs = match (uvs.get(& verts[0].borrow().id), uvs.get(& verts[1].borrow().id), uvs.get(& verts[2].borrow().id)) {
+      (Some(&a), Some(&b), Some(&c)) => [a, b, c],
+      _ => continue,
+    };
+    let (tangent, bitangent) = match triangle_tangents(& corners, & face_uvs) { Some(t) => t, None => continue };
+    let normals: Vec<Vector3<f32>> = verts.iter().map(|v| v.borrow().normal()).collect();
+
+    for_each_texel(& face_uvs, resolution, |x, y, (w_0, w_1, w_2)| {
+      let point = Point3::from_vec(corners[0].to_vec() * w_0 + corners[1].to_vec() * w_1 + corners[2].to_vec() * w_2);
+      let normal = normals[0] * w_0 + normals[1] * w_1 + normals[2] * w_2;
+      if normal.magnitude2() == 0.0 { return; }
+      let normal = normal.normalize();
+
+      // Cast from just outside the search range back through the surface
+      let ray = Ray::new(point + normal * reach, -normal);
+      let hit = match bvh.raycast(& ray, 2.0 * reach) { Some(h) => h, None => return };
+      let hit_face = match high.faces.get(& hit.face_id) { Some(f) => f, None => return };
+      let hit_verts = hit_face.borrow().adjacent_verts().to_ptr_vec();
+      let mut high_normal = Vector3::new(0.0, 0.0, 0.0);
+      for (vert, weight) in hit_verts.iter().zip(hit.bary.iter()) {
+        high_normal += high_normals[& vert.borrow().id] * *weight;
+      }
+      if high_normal.magnitude2() == 0.0 { return; }
+      let high_normal = high_normal.normalize();
+
+      // Orthonormal tangent frame at the texel, keeping the uv handedness
+      let t = (tangent - normal * normal.dot(tangent)).normalize();
+      let handedness = if normal.cross(t).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+      let b = normal.cross(t) * handedness;
+
+      image.set(x, y, [
+        high_normal.dot(t) * 0.5 + 0.5,
+        high_normal.dot(b) * 0.5 + 0.5,
+        high_normal.dot(normal) * 0.5 + 0.5,
+        1.0,
+      ]);
+    });
+  }
+
+  image
+}
+
+/// Calls the function with every texel whose center is inside the triangle with the given texture
+/// coordinates, and the barycentric coordinates of the center. Texel rows go down the image, so v = 1 is row 0
+fn for_each_texel<F>(face_uvs: & [Vector2<f32>; 3], resolution: usize, mut f: F) where F: FnMut(usize, usize, (f32, f32, f32)) {
+  let size = resolution as f32;
+  let texel: Vec<Vector2<f32>> = face_uvs.iter().map(|uv| Vector2::new(uv.x * size, (1.0 - uv.y) * size)).collect();
+  let min_x = texel.iter().fold(f32::INFINITY, |m, t| m.min(t.x)).floor().max(0.0) as usize;
+  let max_x = texel.iter().fold(f32::NEG_INFINITY, |m, t| m.max(t.x)).ceil().min(size) as usize;
+  let min_y = texel.iter().fold(f32::INFINITY, |m, t| m.min(t.y)).floor().max(0.0) as usize;
+  let max_y = texel.iter().fold(f32::NEG_INFINITY, |m, t| m.max(t.y)).ceil().min(size) as usize;
+  let area = (texel[1] - texel[0]).perp_dot(texel[2] - texel[0]);
+  if area.abs() <= 1e-12 { return; }
+
+  for y in min_y..max_y {
+    for x in min_x..max_x {
+      // Barycentric coordinates of the texel center
+      let center = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+      let w_0 = (texel[1] - center).perp_dot(texel[2] - center) / area;
+      let w_1 = (texel[2] - center).perp_dot(texel[0] - center) / area;
+      let w_2 = 1.0 - w_0 - w_1;
+      if w_0 < 0.0 || w_1 < 0.0 || w_2 < 0.0 { continue; }
+      f(x, y, (w_0, w_1, w_2));
+    }
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Calls the function with every texel of a square texture of the given resolution which the mesh's
+  /// texture coordinates cover, as (column, row) with row 0 at v = 1, and the point on the surface under it,
+  /// for baking any signal into a texture: positions, curvature, occlusion. A texel is covered by a face if its
+  /// center is inside the face's triangle in texture space, so texels where charts overlap come more than once,
+  /// and those along the edges of charts may be missed; pad the result afterwards to fill them in.
+  /// Faces are visited in order of id. Faces which aren't triangles, or are missing texture coordinates, are skipped
+  pub fn rasterize_uv_space<F>(& self, resolution: usize, mut f: F) where F: FnMut((usize, usize), SurfacePoint) {
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+    for face_id in face_ids {
+      let face = & self.faces[& face_id];
+      let verts = face.borrow().adjacent_verts().to_ptr_vec();
+      if verts.len() != 3 { continue; }
+      let face_uvs = match (self.uvs.get(& verts[0].borrow().id), self.uvs.get(& verts[1].borrow().id), self.uvs.get(& verts[2].borrow().id)) {
+        (Some(&a), Some(&b), Some(&c)) => [a, b, c],
+        _ => continue,
+      };
+      for_each_texel(& face_uvs, resolution, |x, y, bary| f((x, y), SurfacePoint::new(face.clone(), bary)));
+    }
+  }
+
+  /// Bakes ambient occlusion at each vertex by casting `samples` rays over the hemisphere
+  /// around the vertex normal, and counting how many of them hit the mesh.
+  /// Returns a map from vertex id to occlusion: 0 for fully open, 1 for fully blocked
+  pub fn bake_vertex_ao(& self, samples: u32) -> HashMap<u32, f32> {
+    let mut occlusion: HashMap<u32, f32> = HashMap::new();
+    if samples == 0 { return occlusion; }
+
+    let bvh = self.build_bvh();
+    let reach = self.aabb().size().magnitude();
+    // Keeps rays from hitting the faces around the vertex they start at
+    let offset = reach * 1e-4;
+    let directions = hemisphere_directions(samples);
+
+    for vert in self.vertices.values() {
+      let vert_b = vert.borrow();
+      let normal = vert_b.normal();
+      if normal.magnitude2() == 0.0 {
+        occlusion.insert(vert_b.id, 0.0);
+        continue;
+      }
+
+      let (tangent, bitangent) = tangent_frame(normal);
+      let origin = vert_b.get_pos() + normal * offset;
+      let hits = directions.iter()
+        .filter(|dir| {
+          let world_dir = tangent * dir.x + bitangent * dir.y + normal * dir.z;
+          bvh.any_hit(& Ray::new(origin, world_dir), reach)
+        })
+        .count();
+
+      occlusion.insert(vert_b.id, hits as f32 / samples as f32);
+    }
+
+    occlusion
+  }
+}
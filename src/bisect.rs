@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Point2, Point3, Vector2, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use geom::{Plane, Polygon2, ear_clip};
+use util::OpTimer;
+
+/// Vertices closer to a cutting plane than this fraction of the mesh's size count as on it. Otherwise a vertex
+/// which rounding put a hair off the plane gets an edge split right beside it, leaving faces with no area
+const ON_PLANE_TOLERANCE: f32 = 1e-6;
+
+/// z component of the cross product of two vectors in the plane
+fn cross_2d(a: Vector2<f32>, b: Vector2<f32>) -> f32 { a.x * b.y - a.y * b.x }
+
+/// Whether the segments a-b and c-d cross each other, away from their ends
+fn segments_cross(a: Point2<f32>, b: Point2<f32>, c: Point2<f32>, d: Point2<f32>) -> bool {
+  let (d_1, d_2) = (cross_2d(b - a, c - a), cross_2d(b - a, d - a));
+  let (d_3, d_4) = (cross_2d(d - c, a - c), cross_2d(d - c, b - c));
+  d_1 * d_2 < 0.0 && d_3 * d_4 < 0.0
+}
+
+/// Joins the holes, which run clockwise, into the outer loop, by a slit from the rightmost corner of each hole
+/// to the closest corner it can see, so that the whole thing can be triangulated as one polygon
+fn bridge_holes(points: & [Point2<f32>], mut outer: Vec<usize>, mut holes: Vec<Vec<usize>>) -> Vec<usize> {
+  let rightmost = |hole: & [usize]| (0..hole.len()).max_by(|&i, &j| points[hole[i]].x.total_cmp(& points[hole[j]].x)).unwrap_or(0);
+  holes.sort_by(|a, b| points[b[rightmost(b)]].x.total_cmp(& points[a[rightmost(a)]].x));
+
+  for (hole_idx, hole) in holes.iter().enumerate() {
+    let start = rightmost(hole);
+    let from = hole[start];
+    let loops: Vec<& [usize]> = Some(outer.as_slice()).into_iter().chain(holes[hole_idx..].iter().map(|h| h.as_slice())).collect();
+    let blocked = |to: usize| loops.iter().any(|ring| {
+      (0..ring.len()).any(|idx| {
+        let (c, d) = (ring[idx], ring[(idx + 1) % ring.len()]);
+        c != from && d != from && c != to && d != to && segments_cross(points[from], points[to], points[c], points[d])
+      })
+    });
+    let mut order: Vec<usize> = (0..outer.len()).collect();
+    order.sort_by(|&i, &j| (points[outer[i]] - points[from]).magnitude2().total_cmp(& (points[outer[j]] - points[from]).magnitude2()));
+    let join = match order.iter().find(|&&idx| !blocked(outer[idx])).or(order.first()) { Some(&idx) => idx, None => continue };
+
+    let mut joined: Vec<usize> = outer[..= join].to_vec();
+    joined.extend(hole[start..].iter().chain(& hole[..= start]));
+    joined.extend(outer[join..].iter());
+    outer = joined;
+  }
+  outer
+}
+
+/// Triangles covering the loops, which are indices into the points. Loops running counterclockwise are
+/// outlines, and clockwise ones holes in the smallest outline around them. Holes outside every outline are left open
+fn cap_loops(points: & [Point2<f32>], loops: Vec<Vec<usize>>) -> Vec<[usize; 3]> {
+  let lifted: Vec<Point3<f32>> = points.iter().map(|p| Point3::new(p.x, p.y, 0.0)).collect();
+  let area = |ring: & [usize]| Polygon2::new(ring.iter().map(|&idx| points[idx]).collect()).signed_area();
+  let (outlines, holes): (Vec<Vec<usize>>, Vec<Vec<usize>>) = loops.into_iter().partition(|ring| area(ring) > 0.0);
+
+  let mut outline_holes: Vec<Vec<Vec<usize>>> = vec![Vec::new(); outlines.len()];
+  for hole in holes {
+    let around = outlines.iter().enumerate()
+      .filter(|(_, outline)| Polygon2::new(outline.iter().map(|&idx| points[idx]).collect()).contains(& points[hole[0]]))
+      .min_by(|(_, a), (_, b)| area(a).total_cmp(& area(b)));
+    if let Some((idx, _)) = around { outline_holes[idx].push(hole); }
+  }
+
+  outlines.into_iter().zip(outline_holes)
+    .flat_map(|(outline, holes)| ear_clip(& lifted, bridge_holes(points, outline, holes), false, |_, _| true).unwrap_or_default())
+    .collect()
+}
+
+/// Chains the edges, given as (from, to) indices into the points, into closed loops running around whatever
+/// is on their left. Where loops meet at a corner, each one takes the edge turning furthest to the left, which
+/// keeps them from crossing over each other. Chains which don't close are dropped
+fn chain_loops(points: & [Point2<f32>], edges: & [(usize, usize)]) -> Vec<Vec<usize>> {
+  let mut leaving: HashMap<usize, Vec<usize>> = HashMap::new();
+  for (idx, &(from, _)) in edges.iter().enumerate() { leaving.entry(from).or_default().push(idx); }
+  let mut used = vec![false; edges.len()];
+  let mut loops: Vec<Vec<usize>> = Vec::new();
+
+  for first in 0..edges.len() {
+    if used[first] { continue; }
+    used[first] = true;
+    let mut ring = vec![edges[first].0];
+    let (mut from, mut at) = edges[first];
+    while at != ring[0] {
+      let incoming = points[at] - points[from];
+      let turn = |idx: & usize| {
+        let outgoing = points[edges[*idx].1] - points[at];
+        cross_2d(incoming, outgoing).atan2(incoming.dot(outgoing))
+      };
+      let next = leaving.get(& at).and_then(|out| {
+        out.iter().filter(|&&idx| !used[idx]).max_by(|a, b| turn(a).total_cmp(& turn(b)).then(b.cmp(a))).cloned()
+      });
+      match next {
+        Some(idx) => {
+          used[idx] = true;
+          ring.push(at);
+          from = at;
+          at = edges[idx].1;
+        },
+        None => break,
+      }
+    }
+    if at == ring[0] && ring.len() >= 3 { loops.push(ring); }
+  }
+  loops
+}
+
+impl HalfEdgeMesh {
+  /// Cuts the mesh in two along the plane, and closes up each half where it was cut, as for breaking
+  /// something apart. Returns the half on the side the plane's normal points to, then the other one.
+  /// Faces crossing the plane are split along it with `cut_along_isoline`, so both halves meet exactly,
+  /// and the caps are the same triangles, facing opposite ways. Cuts through a closed mesh always close up;
+  /// where the cut runs into a border of the mesh, the cap is left open. Faces lying in the plane go with the
+  /// half they're the outside of. Texture coordinates and registered attributes go with their vertices,
+  /// including those made by the cut, which get them interpolated, and edge weights with their edges. Vertices within a millionth of the mesh's size
+  /// of the plane count as on it. Fails if either half comes out broken, as `validate` finds
+  pub fn bisect(& self, plane: & Plane) -> Result<(HalfEdgeMesh, HalfEdgeMesh), &'static str> {
+    if plane.normal.magnitude2() == 0.0 || !plane.offset.is_finite() { return Err("Plane has no normal"); }
+    let tolerance = self.aabb().size().magnitude() * ON_PLANE_TOLERANCE;
+    let distances: HashMap<u32, f32> = self.vertices.iter().map(|(&id, v)| {
+      let distance = plane.signed_distance(& v.borrow().get_pos());
+      (id, if distance.abs() <= tolerance { 0.0 } else { distance })
+    }).collect();
+    self.clone().bisect_by_distances(plane, & distances)
+  }
+
+  /// `bisect`, with the distance of every vertex from the plane worked out already. Vertices at a distance
+  /// of exactly 0 are on the plane, and the sign of the rest says which half they go to
+  pub(crate) fn bisect_by_distances(self, plane: & Plane, distances: & HashMap<u32, f32>) -> Result<(HalfEdgeMesh, HalfEdgeMesh), &'static str> {
+    let start = OpTimer::start();
+    let mut cut = self;
+    let on_plane = cut.cut_along_isoline(& |id| distances[& id], 0.0)?.verts;
+
+    let order = cut.vertex_order();
+    let (positions, polygons) = cut.to_polygons();
+    let face_ids = {
+      let mut ids: Vec<u32> = cut.faces.keys().cloned().collect();
+      ids.sort();
+      ids
+    };
+    let flat: Vec<bool> = order.iter().map(|id| on_plane.contains(id)).collect();
+
+    // Every corner of a face is now on one side of the plane, or on it
+    let mut front: Vec<bool> = Vec::with_capacity(polygons.len());
+    for (polygon, face_id) in polygons.iter().zip(& face_ids) {
+      let lean: f32 = polygon.iter().filter(|&&idx| !flat[idx]).filter_map(|&idx| distances.get(& order[idx])).sum();
+      front.push(if lean != 0.0 { lean > 0.0 } else { cut.faces[face_id].borrow().normal.dot(plane.normal) < 0.0 });
+    }
+
+    // The front half's border along the plane, turned around, runs around the cap which closes it
+    let front_edges: HashSet<(usize, usize)> = polygons.iter().zip(& front)
+      .filter(|&(_, &is_front)| is_front)
+      .flat_map(|(polygon, _)| (0..polygon.len()).map(move |idx| (polygon[idx], polygon[(idx + 1) % polygon.len()])))
+      .collect();
+    let mut cap_edges: Vec<(usize, usize)> = front_edges.iter()
+      .filter(|&&(a, b)| flat[a] && flat[b] && !front_edges.contains(& (b, a)))
+      .map(|&(a, b)| (b, a))
+      .collect();
+    cap_edges.sort();
+
+    // Flattened so that the cap of the front half, which faces against the plane's normal, runs counterclockwise
+    let normal = plane.normal.normalize();
+    let side = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let u = side.cross(normal).normalize();
+    let v = -normal.cross(u);
+    let flattened: Vec<Point2<f32>> = positions.iter().map(|p| Point2::new(p.x * u.x + p.y * u.y + p.z * u.z, p.x * v.x + p.y * v.y + p.z * v.z)).collect();
+    let front_cap = cap_loops(& flattened, chain_loops(& flattened, & cap_edges));
+    let mut front_polygons: Vec<Vec<usize>> = Vec::new();
+    let mut back_polygons: Vec<Vec<usize>> = Vec::new();
+    for (polygon, is_front) in polygons.into_iter().zip(front) {
+      if is_front { front_polygons.push(polygon); } else { back_polygons.push(polygon); }
+    }
+    front_polygons.extend(front_cap.iter().map(|tri| tri.to_vec()));
+    back_polygons.extend(front_cap.iter().map(|tri| vec![tri[0], tri[2], tri[1]]));
+
+    let halves = (cut.half_from_polygons(& order, & positions, & front_polygons), cut.half_from_polygons(& order, & positions, & back_polygons));
+    halves.0.validate()?;
+    halves.1.validate()?;
+    debug!("bisect: {} and {} faces, with {} cap triangles, took {:?}", halves.0.num_faces(), halves.1.num_faces(), front_cap.len(), start.elapsed());
+    Ok(halves)
+  }
+
+  /// Breaks the mesh into shards, one for each seed's Voronoi cell: the part of the mesh closer to that seed
+  /// than to any other. Each cell is cut out with `bisect`, along the planes halfway between its seed and each
+  /// of the others, nearest first, skipping planes the shard is already entirely on the near side of. Shards
+  /// are closed where the mesh is, and neighboring ones share their cut faces exactly. Returns the shards in the
+  /// order of their seeds, leaving out cells which miss the mesh. A shard of a mesh which isn't convex can be
+  /// in more than one piece. Seeds at the same place as an earlier one get nothing
+  pub fn fracture_voronoi(& self, seeds: & [Point3<f32>]) -> Result<Vec<HalfEdgeMesh>, &'static str> {
+    let start = OpTimer::start();
+    let mut shards: Vec<HalfEdgeMesh> = Vec::new();
+    let mut cuts = 0;
+    for (seed_idx, seed) in seeds.iter().enumerate() {
+      if seeds[.. seed_idx].contains(seed) { continue; }
+      let mut others: Vec<& Point3<f32>> = seeds.iter().filter(|other| *other != seed).collect();
+      others.sort_by(|a, b| (*a - seed).magnitude2().total_cmp(& (*b - seed).magnitude2()));
+
+      let mut shard = self.clone();
+      for other in others {
+        let plane = Plane::from_point_normal(seed.midpoint(*other), other - seed);
+        let (mut nearest, mut furthest) = (f32::INFINITY, f32::NEG_INFINITY);
+        for vert in shard.vertices.values() {
+          let distance = plane.signed_distance(& vert.borrow().get_pos());
+          nearest = nearest.min(distance);
+          furthest = furthest.max(distance);
+        }
+        let tolerance = shard.aabb().size().magnitude() * ON_PLANE_TOLERANCE;
+        if furthest <= tolerance { continue; }
+        if nearest > -tolerance || shard.faces.is_empty() {
+          shard = HalfEdgeMesh::empty();
+          break;
+        }
+        shard = shard.bisect(& plane)?.1;
+        cuts += 1;
+      }
+      if !shard.faces.is_empty() { shards.push(shard); }
+    }
+    debug!("fracture_voronoi: {} shards from {} seeds, with {} cuts, took {:?}", shards.len(), seeds.len(), cuts, start.elapsed());
+    Ok(shards)
+  }
+
+  /// A new mesh from the polygons, which index into the positions of this mesh's vertices, in the order given
+  /// by their ids. Only the vertices used are kept, along with their texture coordinates and attributes
+  fn half_from_polygons(& self, ids: & [u32], positions: & [Point3<f32>], polygons: & [Vec<usize>]) -> HalfEdgeMesh {
+    let mut used: Vec<usize> = polygons.iter().flatten().cloned().collect();
+    used.sort();
+    used.dedup();
+    let index: HashMap<usize, usize> = used.iter().enumerate().map(|(new, &old)| (old, new)).collect();
+    let kept: Vec<Point3<f32>> = used.iter().map(|&idx| positions[idx]).collect();
+    let remapped: Vec<Vec<usize>> = polygons.iter().map(|polygon| polygon.iter().map(|idx| index[idx]).collect()).collect();
+
+    let mut half = HalfEdgeMesh::from_polygons(& kept, & remapped);
+    half.set_units(self.units());
+    let new_ids = half.vertex_order();
+    let renumbered: HashMap<u32, u32> = used.iter().map(|&idx| ids[idx]).zip(new_ids).collect();
+    half.uvs = self.uvs.iter().filter_map(|(old, uv)| renumbered.get(old).map(|&new| (new, *uv))).collect();
+    half.tangents = self.tangents.iter().filter_map(|(old, t)| renumbered.get(old).map(|&new| (new, *t))).collect();
+    let mut attributes = self.attributes().clone();
+    attributes.renumber(& renumbered);
+    *half.attributes_mut() = attributes;
+    half.carry_edge_weights(self, & renumbered);
+    half
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Point3, Vector3};
+
+  use mesh::HalfEdgeMesh;
+  use geom::Plane;
+  use iterators::ToPtrVec;
+
+  fn sphere() -> HalfEdgeMesh { HalfEdgeMesh::icosphere(Point3::new(0.0, 0.0, 0.0), 1.0, 2) }
+
+  #[test]
+  fn crossing_cuts_stay_valid() {
+    // Which edges the cap chaining meets first depends on hash order, so try it a few times
+    for _ in 0..10 {
+      let first = Plane::from_point_normal(Point3::new(0.15, 0.2, 0.05), Vector3::new(0.3, -0.4, -0.1));
+      let second = Plane::from_point_normal(Point3::new(-0.15, 0.2, 0.05), Vector3::new(-0.3, -0.4, -0.1));
+      let (_, half) = sphere().bisect(& first).unwrap();
+      assert_eq!(half.validate(), Ok(()));
+      let (front, back) = half.bisect(& second).unwrap();
+      assert_eq!(front.validate(), Ok(()));
+      assert_eq!(back.validate(), Ok(()));
+      assert!((front.signed_volume() + back.signed_volume() - half.signed_volume()).abs() < 1e-4);
+    }
+  }
+
+  #[test]
+  fn voronoi_shards_are_closed_and_fill_the_mesh() {
+    let mesh = sphere();
+    let seeds = [Point3::new(0.3, 0.0, 0.0), Point3::new(-0.3, 0.0, 0.0), Point3::new(0.0, 0.4, 0.1)];
+    let shards = mesh.fracture_voronoi(& seeds).unwrap();
+    assert_eq!(shards.len(), 3);
+    for shard in & shards {
+      assert_eq!(shard.validate(), Ok(()));
+      assert!(shard.boundary_loops().is_empty());
+    }
+    let total: f32 = shards.iter().map(|s| s.signed_volume()).sum();
+    assert!((total - mesh.signed_volume()).abs() < 1e-4);
+  }
+
+  #[test]
+  fn halves_keep_edge_weights() {
+    let mut mesh = sphere();
+    let edges: Vec<_> = mesh.edges().cloned().collect();
+    for edge in & edges { mesh.set_edge_weight(edge, 3.0); }
+
+    let (front, back) = mesh.bisect(& Plane::from_point_normal(Point3::new(0.0, 0.0, 0.1), Vector3::new(0.0, 0.0, 1.0))).unwrap();
+    // Edges between two of the sphere's vertices keep their weight, and so do the pieces of the ones which were cut,
+    // which run from one of them to the plane
+    let old_pos: Vec<Point3<f32>> = mesh.verts().map(|v| v.borrow().get_pos()).collect();
+    let mut pieces = 0;
+    for half in & [front, back] {
+      for edge in half.edges() {
+        let ends: Vec<Point3<f32>> = edge.borrow().adjacent_verts().to_ptr_vec().iter().map(|v| v.borrow().get_pos()).collect();
+        match ends.iter().filter(|pos| old_pos.contains(pos)).count() {
+          2 => assert_eq!(half.edge_weight(edge), 3.0),
+          1 if half.edge_weight(edge) == 3.0 => pieces += 1,
+          _ => {},
+        }
+      }
+    }
+    assert!(pieces > 0);
+  }
+}
@@ -0,0 +1,179 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use cgmath::{Point3, Vector2, Vector3, MetricSpace};
+
+use mesh::{HalfEdgeMesh, vert_neighbor_ids};
+use surface::SurfacePoint;
+
+/// How a brush's strength drops off from its center to its radius
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Falloff {
+  /// Full strength all the way out
+  Constant,
+  /// Straight down to nothing at the radius
+  Linear,
+  /// Smoothstep, flat at the center and at the radius, so the edit blends in without a crease
+  Smooth,
+  /// The profile of a ball, full strength for most of the way, then dropping off steeply
+  Sphere,
+}
+
+impl Falloff {
+  /// Strength at the distance, from 1 at the center to 0 at the radius and beyond
+  pub fn weight(& self, distance: f32, radius: f32) -> f32 {
+    if radius <= 0.0 || distance > radius { return 0.0; }
+    let t = (distance / radius).clamp(0.0, 1.0);
+    match *self {
+      Falloff::Constant => 1.0,
+      Falloff::Linear => 1.0 - t,
+      Falloff::Smooth => 1.0 - t * t * (3.0 - 2.0 * t),
+      Falloff::Sphere => (1.0 - t * t).sqrt(),
+    }
+  }
+}
+
+/// How a brush measures the distance from its center
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushMetric {
+  /// Along the edges of the mesh, so a brush on one side of a thin part doesn't reach the other side
+  Geodesic,
+  /// In a straight line, though only to vertices connected to the center through others in reach
+  Euclidean,
+}
+
+/// A vertex as handed to a brush's edit. Changes to `pos` and `uv` are written back to the mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VertexData {
+  pub id: u32,
+  pub pos: Point3<f32>,
+  /// Texture coordinate, if the mesh has one for the vertex. Setting it adds one
+  pub uv: Option<Vector2<f32>>,
+  /// Normal of the vertex before the stroke, for reading. Changes are ignored
+  pub normal: Vector3<f32>,
+}
+
+/// Heap entry for gathering what a brush reaches, ordered so that the closest vertex is popped first
+struct BrushCandidate {
+  dist: f32,
+  vert_id: u32,
+}
+
+impl PartialEq for BrushCandidate {
+  fn eq(& self, other: & BrushCandidate) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for BrushCandidate {}
+
+impl PartialOrd for BrushCandidate {
+  fn partial_cmp(& self, other: & BrushCandidate) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for BrushCandidate {
+  fn cmp(& self, other: & BrushCandidate) -> Ordering {
+    other.dist.partial_cmp(& self.dist).unwrap_or(Ordering::Equal)
+      .then_with(|| other.vert_id.cmp(& self.vert_id))
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Distance from the center to every vertex within the radius, spreading out from the corners of the
+  /// center's face, so only the part of the mesh around the center is looked at however large the mesh is
+  pub(crate) fn brush_reach(& self, center: & SurfacePoint, radius: f32, metric: BrushMetric) -> HashMap<u32, f32> {
+    let origin = center.position();
+    let mut reached: HashMap<u32, f32> = HashMap::new();
+    let mut heap: BinaryHeap<BrushCandidate> = BinaryHeap::new();
+    for vert in center.verts() {
+      let vert_b = vert.borrow();
+      heap.push(BrushCandidate { dist: vert_b.get_pos().distance(origin), vert_id: vert_b.id });
+    }
+
+    while let Some(BrushCandidate { dist, vert_id }) = heap.pop() {
+      if dist > radius || reached.contains_key(& vert_id) { continue; }
+      let vert = match self.vertices.get(& vert_id) { Some(vert) => vert, None => continue };
+      reached.insert(vert_id, dist);
+      let pos = vert.borrow().get_pos();
+      for next_id in vert_neighbor_ids(vert) {
+        if reached.contains_key(& next_id) { continue; }
+        let next_pos = match self.vertices.get(& next_id) { Some(next) => next.borrow().get_pos(), None => continue };
+        let next_dist = match metric {
+          BrushMetric::Geodesic => dist + pos.distance(next_pos),
+          BrushMetric::Euclidean => origin.distance(next_pos),
+        };
+        if next_dist <= radius { heap.push(BrushCandidate { dist: next_dist, vert_id: next_id }); }
+      }
+    }
+    reached
+  }
+
+  /// Edits every vertex within `radius` of the center, measured along the mesh, the way sculpting and vertex
+  /// painting tools do: `apply` gets each vertex and the strength of the brush there, from 1 at the center
+  /// to 0 at the radius by the falloff, and can move it or change its texture coordinate. Normals are from
+  /// before the stroke, so an edit like pushing out along the normal isn't thrown off by its own changes.
+  /// Faces around moved vertices are brought up to date afterwards. Returns the number of vertices reached
+  pub fn brush<F>(&mut self, center: & SurfacePoint, radius: f32, falloff: Falloff, apply: F) -> usize
+      where F: FnMut(&mut VertexData, f32) {
+    self.brush_with(center, radius, falloff, BrushMetric::Geodesic, apply)
+  }
+
+  /// `brush`, measuring the distance from the center with the metric
+  pub fn brush_with<F>(&mut self, center: & SurfacePoint, radius: f32, falloff: Falloff, metric: BrushMetric, mut apply: F) -> usize
+      where F: FnMut(&mut VertexData, f32) {
+    let reached = self.brush_reach(center, radius, metric);
+    let mut ids: Vec<u32> = reached.keys().cloned().collect();
+    ids.sort();
+    let verts: Vec<_> = ids.iter().map(|id| self.vertices[id].clone()).collect();
+    let normals: Vec<Vector3<f32>> = verts.iter().map(|v| v.borrow().normal()).collect();
+
+    let mut changed_faces: HashSet<u32> = HashSet::new();
+    for ((id, vert), normal) in ids.iter().zip(& verts).zip(normals) {
+      let before = VertexData { id: *id, pos: vert.borrow().get_pos(), uv: self.uvs.get(id).cloned(), normal };
+      let mut data = before;
+      apply(&mut data, falloff.weight(reached[id], radius));
+
+      if data.pos != before.pos {
+        self.move_vert(vert, data.pos);
+        changed_faces.extend(vert.borrow().outgoing_edges().iter().filter_map(|e| e.borrow().get_face()).map(|f| f.borrow().id));
+      }
+      if data.uv != before.uv {
+        match data.uv {
+          Some(uv) => { self.uvs.insert(*id, uv); },
+          None => { self.uvs.remove(id); },
+        }
+      }
+    }
+    for face_id in changed_faces {
+      if let Some(face) = self.faces.get(& face_id) { face.borrow_mut().compute_attrs(); }
+    }
+    trace!("brush: reached {} vertices", ids.len());
+    ids.len()
+  }
+
+  /// Visits every face the brush reaches, for painting data kept per face, with the strength of the brush
+  /// at the face's center. A face is reached when it has a corner in reach, and its center is as far
+  /// as the closest such corner plus the straight line from there. Faces are visited in order of id
+  pub fn brush_faces<F>(& self, center: & SurfacePoint, radius: f32, falloff: Falloff, mut apply: F) -> usize
+      where F: FnMut(u32, f32) {
+    let reached = self.brush_reach(center, radius, BrushMetric::Geodesic);
+    let center_face = center.face.borrow().id;
+    let origin = center.position();
+
+    let mut faces: HashMap<u32, f32> = HashMap::new();
+    faces.insert(center_face, center.face.borrow().center.distance(origin));
+    for (id, &dist) in & reached {
+      for out_edge in self.vertices[id].borrow().outgoing_edges() {
+        let face = match out_edge.borrow().get_face() { Some(face) => face, None => continue };
+        let face_b = face.borrow();
+        if face_b.id == center_face { continue; }
+        let through = dist + self.vertices[id].borrow().get_pos().distance(face_b.center);
+        let entry = faces.entry(face_b.id).or_insert(through);
+        if through < *entry { *entry = through; }
+      }
+    }
+
+    let mut ids: Vec<u32> = faces.iter().filter(|&(_, &dist)| dist <= radius).map(|(&id, _)| id).collect();
+    ids.sort();
+    for id in & ids { apply(*id, falloff.weight(faces[id], radius)); }
+    ids.len()
+  }
+}
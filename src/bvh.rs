@@ -0,0 +1,242 @@
+use cgmath::{Point3, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use geom::{Aabb, Plane, Ray, closest_point_on_triangle};
+
+/// Leaves hold at most this many triangles
+const LEAF_SIZE: usize = 4;
+
+/// A face of the mesh, as stored in the bvh
+#[derive(Debug, Clone)]
+pub struct BvhTriangle {
+  pub face_id: u32,
+  pub corners: [Point3<f32>; 3],
+  pub aabb: Aabb,
+}
+
+/// A ray hit returned from `Bvh::raycast`, or a point found by `Bvh::closest_point`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhHit {
+  pub face_id: u32,
+  /// Distance along the ray, or from the query point
+  pub distance: f32,
+  /// Barycentric coordinates of the point, matching the order of the face's vertices
+  pub bary: [f32; 3],
+}
+
+#[derive(Debug, Clone)]
+struct BvhNode {
+  aabb: Aabb,
+  /// Leaves cover triangles[start..start + count]. Inner nodes have count == 0,
+  /// with children at start and start + 1 in the node list
+  start: usize,
+  count: usize,
+}
+
+/// Bounding volume hierarchy over the triangular faces of a mesh, for fast ray and box queries.
+/// This is a snapshot: it has to be rebuilt after the mesh is modified
+#[derive(Debug, Clone)]
+pub struct Bvh {
+  nodes: Vec<BvhNode>,
+  triangles: Vec<BvhTriangle>,
+}
+
+impl Bvh {
+  /// Builds the hierarchy by recursively splitting faces at the median of their centers,
+  /// along the longest axis. Non-triangular faces are skipped
+  pub fn new(mesh: & HalfEdgeMesh) -> Bvh {
+    let mut triangles: Vec<BvhTriangle> = mesh.faces.values()
+      .filter_map(|face| {
+        let face_b = face.borrow();
+        face_b.triangle().map(|corners| BvhTriangle { face_id: face_b.id, corners, aabb: Aabb::from_points(& corners) })
+      })
+      .collect();
+    // Sorting makes the structure independent of HashMap ordering
+    triangles.sort_by_key(|t| t.face_id);
+    Bvh::from_triangles(triangles)
+  }
+
+  /// Builds the hierarchy over the triangles, which can be anything, not only faces of a mesh
+  pub(crate) fn from_triangles(triangles: Vec<BvhTriangle>) -> Bvh {
+    let mut bvh = Bvh { nodes: Vec::new(), triangles: Vec::new() };
+    if triangles.is_empty() { return bvh; }
+
+    let count = triangles.len();
+    bvh.nodes.push(BvhNode { aabb: Aabb::empty(), start: 0, count });
+    bvh.triangles = triangles;
+    bvh.split(0, 0, count);
+    bvh
+  }
+
+  /// Fills in the node at node_idx, covering triangles[start..start + count]
+  fn split(&mut self, node_idx: usize, start: usize, count: usize) {
+    let mut aabb = Aabb::empty();
+    let mut centers = Aabb::empty();
+    for tri in & self.triangles[start..start + count] {
+      aabb = aabb.union(& tri.aabb);
+      centers.extend(& tri.aabb.center());
+    }
+
+    self.nodes[node_idx] = BvhNode { aabb, start, count };
+    if count <= LEAF_SIZE { return; }
+
+    let size = centers.size();
+    let axis = if size.x >= size.y && size.x >= size.z { 0 } else if size.y >= size.z { 1 } else { 2 };
+    let half = count / 2;
+    self.triangles[start..start + count].select_nth_unstable_by(half, |a, b| {
+      a.aabb.center()[axis].partial_cmp(& b.aabb.center()[axis]).unwrap_or(::std::cmp::Ordering::Equal)
+    });
+
+    let left_idx = self.nodes.len();
+    self.nodes.push(BvhNode { aabb: Aabb::empty(), start, count: 0 });
+    self.nodes.push(BvhNode { aabb: Aabb::empty(), start, count: 0 });
+    self.nodes[node_idx] = BvhNode { aabb, start: left_idx, count: 0 };
+
+    self.split(left_idx, start, half);
+    self.split(left_idx + 1, start + half, count - half);
+  }
+
+  pub fn is_empty(& self) -> bool { self.triangles.is_empty() }
+
+  /// Bounds of everything in the hierarchy
+  pub fn aabb(& self) -> Aabb {
+    self.nodes.first().map_or(Aabb::empty(), |n| n.aabb)
+  }
+
+  pub fn triangles(& self) -> & [BvhTriangle] { & self.triangles }
+
+  /// Visits every triangle whose leaf is accepted by `enter`, which is also used to prune inner nodes
+  fn visit<E, V>(& self, mut enter: E, mut visit: V) where E: FnMut(& Aabb) -> bool, V: FnMut(& BvhTriangle) {
+    if self.nodes.is_empty() { return; }
+    let mut stack: Vec<usize> = vec![0];
+    while let Some(node_idx) = stack.pop() {
+      let node = & self.nodes[node_idx];
+      if !enter(& node.aabb) { continue; }
+      if node.count > 0 {
+        for tri in & self.triangles[node.start..node.start + node.count] { visit(tri); }
+      } else {
+        stack.push(node.start);
+        stack.push(node.start + 1);
+      }
+    }
+  }
+
+  /// Finds the closest face hit by the ray, no further away than max_distance
+  pub fn raycast(& self, ray: & Ray, max_distance: f32) -> Option<BvhHit> {
+    let mut closest: Option<BvhHit> = None;
+    let mut limit = max_distance;
+    if self.nodes.is_empty() { return None; }
+
+    let mut stack: Vec<usize> = vec![0];
+    while let Some(node_idx) = stack.pop() {
+      let node = & self.nodes[node_idx];
+      match ray.intersect_aabb(& node.aabb) {
+        Some((t_enter, _)) if t_enter <= limit => {},
+        _ => continue,
+      }
+      if node.count > 0 {
+        for tri in & self.triangles[node.start..node.start + node.count] {
+          if let Some((distance, bary)) = ray.intersect_triangle_bary(& tri.corners) {
+            if distance <= limit {
+              limit = distance;
+              closest = Some(BvhHit { face_id: tri.face_id, distance, bary });
+            }
+          }
+        }
+      } else {
+        stack.push(node.start);
+        stack.push(node.start + 1);
+      }
+    }
+
+    closest
+  }
+
+  /// Checks if the ray hits anything within max_distance. Faster than `raycast`,
+  /// since it stops at the first hit it finds
+  pub fn any_hit(& self, ray: & Ray, max_distance: f32) -> bool {
+    if self.nodes.is_empty() { return false; }
+
+    let mut stack: Vec<usize> = vec![0];
+    while let Some(node_idx) = stack.pop() {
+      let node = & self.nodes[node_idx];
+      match ray.intersect_aabb(& node.aabb) {
+        Some((t_enter, _)) if t_enter <= max_distance => {},
+        _ => continue,
+      }
+      if node.count > 0 {
+        let hit = self.triangles[node.start..node.start + node.count].iter()
+          .any(|tri| ray.intersect_triangle(& tri.corners).is_some_and(|t| t <= max_distance));
+        if hit { return true; }
+      } else {
+        stack.push(node.start);
+        stack.push(node.start + 1);
+      }
+    }
+
+    false
+  }
+
+  /// Finds the closest point on any face to the point, no further away than max_distance
+  pub fn closest_point(& self, point: & Point3<f32>, max_distance: f32) -> Option<(Point3<f32>, BvhHit)> {
+    let mut closest: Option<(Point3<f32>, BvhHit)> = None;
+    let mut limit = max_distance;
+    if self.nodes.is_empty() { return None; }
+
+    let mut stack: Vec<usize> = vec![0];
+    while let Some(node_idx) = stack.pop() {
+      let node = & self.nodes[node_idx];
+      if node.aabb.distance_to_point(point) > limit { continue; }
+      if node.count > 0 {
+        for tri in & self.triangles[node.start..node.start + node.count] {
+          let (found, bary) = closest_point_on_triangle(point, & tri.corners);
+          let distance = (found - point).magnitude();
+          if distance <= limit {
+            limit = distance;
+            closest = Some((found, BvhHit { face_id: tri.face_id, distance, bary }));
+          }
+        }
+      } else {
+        // Visit the nearer child first, so that the limit shrinks sooner
+        let (near, far) = (node.start, node.start + 1);
+        if self.nodes[near].aabb.distance_to_point(point) <= self.nodes[far].aabb.distance_to_point(point) {
+          stack.push(far);
+          stack.push(near);
+        } else {
+          stack.push(near);
+          stack.push(far);
+        }
+      }
+    }
+
+    closest
+  }
+
+  /// Ids of the faces whose bounding boxes overlap the box
+  pub fn query_aabb(& self, aabb: & Aabb) -> Vec<u32> {
+    let mut found: Vec<u32> = Vec::new();
+    self.visit(|node_aabb| node_aabb.intersects(aabb), |tri| {
+      if tri.aabb.intersects(aabb) { found.push(tri.face_id); }
+    });
+    found
+  }
+
+  /// Ids of the faces whose bounding boxes aren't entirely behind any of the planes, such as those of a frustum
+  /// with its normals pointing inward. Faces whose boxes reach over a corner of the region, without touching it,
+  /// can be included too
+  pub fn query_planes(& self, planes: & [Plane]) -> Vec<u32> {
+    let in_front = |aabb: & Aabb| planes.iter().all(|plane| plane.intersects_aabb(aabb) || plane.signed_distance(& aabb.center()) >= 0.0);
+    let mut found: Vec<u32> = Vec::new();
+    self.visit(in_front, |tri| {
+      if in_front(& tri.aabb) { found.push(tri.face_id); }
+    });
+    found
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Builds a bounding volume hierarchy over the faces of the mesh
+  pub fn build_bvh(& self) -> Bvh {
+    Bvh::new(self)
+  }
+}
@@ -0,0 +1,239 @@
+use export::MeshBuffers;
+
+/// First bytes of every compressed mesh
+const COMPRESSED_MAGIC: [u8; 4] = *b"HEMZ";
+
+/// Version of the layout written by `MeshBuffers::compress`
+pub const COMPRESSED_VERSION: u32 = 1;
+
+/// Finest quantization there's any point in, since f32 only has 24 bits of precision
+const MAX_BITS: u8 = 24;
+
+/// Options for `MeshBuffers::compress`. Each attribute is snapped to a grid with this many bits per component,
+/// so fewer bits make a smaller file with bigger errors. Zero bits leaves an attribute out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressOptions {
+  /// Bits per coordinate, over the largest side of the bounding box. 14 bits is within 1/16000 of the mesh's size
+  pub position_bits: u8,
+  /// Bits per component of the normals' octahedral encoding
+  pub normal_bits: u8,
+  /// Bits per coordinate, over the range of the texture coordinates
+  pub uv_bits: u8,
+}
+
+impl Default for CompressOptions {
+  fn default() -> CompressOptions {
+    CompressOptions { position_bits: 14, normal_bits: 10, uv_bits: 12 }
+  }
+}
+
+fn max_quantized(bits: u8) -> f32 { ((1u32 << bits) - 1) as f32 }
+
+/// Maps a signed number to an unsigned one, small either way: 0, -1, 1, -2 become 0, 1, 2, 3
+fn zigzag(value: i64) -> u64 { ((value << 1) ^ (value >> 63)) as u64 }
+
+fn unzigzag(value: u64) -> i64 { (value >> 1) as i64 ^ -((value & 1) as i64) }
+
+/// LEB128: seven bits per byte, with the high bit set on every byte but the last
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+  while value >= 0x80 {
+    out.push((value as u8) | 0x80);
+    value >>= 7;
+  }
+  out.push(value as u8);
+}
+
+/// Unit vector to a point on the square [-1, 1]², by way of the octahedron
+fn octahedral_encode(n: [f32; 3]) -> [f32; 2] {
+  let sum = n[0].abs() + n[1].abs() + n[2].abs();
+  if sum == 0.0 { return [0.0, 0.0]; }
+  let (x, y) = (n[0] / sum, n[1] / sum);
+  if n[2] >= 0.0 { [x, y] } else { [(1.0 - y.abs()) * x.signum(), (1.0 - x.abs()) * y.signum()] }
+}
+
+fn octahedral_decode(p: [f32; 2]) -> [f32; 3] {
+  let z = 1.0 - p[0].abs() - p[1].abs();
+  let fold = (-z).max(0.0);
+  let x = if p[0] >= 0.0 { p[0] - fold } else { p[0] + fold };
+  let y = if p[1] >= 0.0 { p[1] - fold } else { p[1] + fold };
+  let len = (x * x + y * y + z * z).sqrt();
+  if len > 0.0 { [x / len, y / len, z / len] } else { [0.0, 0.0, 1.0] }
+}
+
+/// Snaps the values to integers within the range from `min`, `step` apart
+/// and writes them as differences from the ones before
+fn write_quantized<const N: usize>(out: &mut Vec<u8>, values: & [[f32; N]], min: [f32; N], step: f32, bits: u8) {
+  let top = max_quantized(bits);
+  let mut prev = [0i64; N];
+  for value in values {
+    for axis in 0..N {
+      let q = if step > 0.0 { ((value[axis] - min[axis]) / step).round().clamp(0.0, top) as i64 } else { 0 };
+      write_varint(out, zigzag(q - prev[axis]));
+      prev[axis] = q;
+    }
+  }
+}
+
+/// Smallest value of each component, and the grid step which fits the largest range into the bits
+fn quantize_range<const N: usize>(values: & [[f32; N]], bits: u8) -> ([f32; N], f32) {
+  let mut min = [f32::INFINITY; N];
+  let mut max = [f32::NEG_INFINITY; N];
+  for value in values {
+    for axis in 0..N {
+      min[axis] = min[axis].min(value[axis]);
+      max[axis] = max[axis].max(value[axis]);
+    }
+  }
+  if values.is_empty() { return ([0.0; N], 0.0); }
+  let extent = (0..N).map(|axis| max[axis] - min[axis]).fold(0.0, f32::max);
+  (min, extent / max_quantized(bits))
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  at: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+    let end = self.at.checked_add(len).filter(|&end| end <= self.bytes.len()).ok_or("Compressed mesh is cut short")?;
+    let taken = & self.bytes[self.at..end];
+    self.at = end;
+    Ok(taken)
+  }
+
+  fn u8(&mut self) -> Result<u8, &'static str> { Ok(self.take(1)?[0]) }
+
+  fn u32(&mut self) -> Result<u32, &'static str> {
+    let b = self.take(4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+  }
+
+  fn f32(&mut self) -> Result<f32, &'static str> { self.u32().map(f32::from_bits) }
+
+  fn varint(&mut self) -> Result<u64, &'static str> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+      let byte = self.u8()?;
+      value |= ((byte & 0x7f) as u64) << shift;
+      if byte & 0x80 == 0 { return Ok(value); }
+    }
+    Err("Compressed mesh has a number that's too long")
+  }
+
+  fn quantized<const N: usize>(&mut self, count: usize, min: [f32; N], step: f32) -> Result<Vec<[f32; N]>, &'static str> {
+    let mut prev = [0i64; N];
+    let mut values = Vec::with_capacity(count);
+    for _ in 0..count {
+      let mut value = [0.0f32; N];
+      for axis in 0..N {
+        prev[axis] = prev[axis].checked_add(unzigzag(self.varint()?)).ok_or("Corrupt quantized coordinate")?;
+        value[axis] = min[axis] + prev[axis] as f32 * step;
+      }
+      values.push(value);
+    }
+    Ok(values)
+  }
+}
+
+impl MeshBuffers {
+  /// Packs the positions, normals, texture coordinates, and indices into a compact byte string for sending
+  /// over a network, by snapping the attributes to a grid (see `CompressOptions`) and writing the differences
+  /// between consecutive values and indices as variable-length numbers. Vertices are put in the order the triangles
+  /// first use them, so that neighbors tend to be written one after the other; vertices no triangle uses are dropped.
+  /// Tangents and vertex ids aren't kept. With the default options, a triangle mesh takes about 14 bytes per vertex,
+  /// against 48 for its float positions and normals and 32-bit indices, before any general-purpose compression like gzip
+  pub fn compress(& self, options: & CompressOptions) -> Vec<u8> {
+    let position_bits = options.position_bits.clamp(1, MAX_BITS);
+    let normal_bits = if self.normals.len() == self.positions.len() { options.normal_bits.min(MAX_BITS) } else { 0 };
+    let uv_bits = if self.uvs.len() == self.positions.len() { options.uv_bits.min(MAX_BITS) } else { 0 };
+
+    // Vertices in order of first use, and the indices written as how far back from the next new vertex they are,
+    // which is small for vertices used recently and zero for a new one
+    let mut order: Vec<usize> = Vec::new();
+    let mut new_index: Vec<Option<u32>> = vec![None; self.positions.len()];
+    let mut index_codes: Vec<u64> = Vec::with_capacity(self.indices.len());
+    for &idx in & self.indices {
+      let next = order.len() as u32;
+      let mapped = *new_index[idx as usize].get_or_insert_with(|| { order.push(idx as usize); next });
+      index_codes.push(if mapped == next { 0 } else { (next - mapped) as u64 });
+    }
+
+    let mut out: Vec<u8> = Vec::new();
+    out.extend_from_slice(& COMPRESSED_MAGIC);
+    for value in & [COMPRESSED_VERSION, order.len() as u32, self.indices.len() as u32] {
+      out.extend_from_slice(& value.to_le_bytes());
+    }
+    out.extend_from_slice(& [position_bits, normal_bits, uv_bits]);
+
+    let positions: Vec<[f32; 3]> = order.iter().map(|&idx| self.positions[idx]).collect();
+    let (min, step) = quantize_range(& positions, position_bits);
+    for value in min.iter().chain(Some(& step)) { out.extend_from_slice(& value.to_le_bytes()); }
+    let uvs: Vec<[f32; 2]> = if uv_bits > 0 { order.iter().map(|&idx| self.uvs[idx]).collect() } else { Vec::new() };
+    let (uv_min, uv_step) = quantize_range(& uvs, uv_bits.max(1));
+    if uv_bits > 0 {
+      for value in uv_min.iter().chain(Some(& uv_step)) { out.extend_from_slice(& value.to_le_bytes()); }
+    }
+
+    write_quantized(&mut out, & positions, min, step, position_bits);
+    if normal_bits > 0 {
+      let encoded: Vec<[f32; 2]> = order.iter().map(|&idx| octahedral_encode(self.normals[idx])).collect();
+      write_quantized(&mut out, & encoded, [-1.0, -1.0], 2.0 / max_quantized(normal_bits), normal_bits);
+    }
+    if uv_bits > 0 { write_quantized(&mut out, & uvs, uv_min, uv_step, uv_bits); }
+    for code in index_codes { write_varint(&mut out, code); }
+    out
+  }
+
+  /// Unpacks buffers packed by `compress`. Attributes which were left out come back empty,
+  /// and so do the tangents and vertex ids
+  pub fn decompress(bytes: & [u8]) -> Result<MeshBuffers, &'static str> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(4).ok() != Some(& COMPRESSED_MAGIC[..]) { return Err("Not a compressed mesh"); }
+    if reader.u32()? != COMPRESSED_VERSION { return Err("Unsupported compressed mesh version"); }
+    let (num_vertices, num_indices) = (reader.u32()? as usize, reader.u32()? as usize);
+    let (position_bits, normal_bits, uv_bits) = (reader.u8()?, reader.u8()?, reader.u8()?);
+    if position_bits > MAX_BITS || normal_bits > MAX_BITS || uv_bits > MAX_BITS { return Err("Compressed mesh has too many bits per value"); }
+    // Every vertex and index takes at least a byte, so this guards against allocating for bogus counts
+    if num_vertices.saturating_add(num_indices) > bytes.len() { return Err("Compressed mesh is cut short"); }
+
+    let min = [reader.f32()?, reader.f32()?, reader.f32()?];
+    let step = reader.f32()?;
+    let (uv_min, uv_step) = if uv_bits > 0 { ([reader.f32()?, reader.f32()?], reader.f32()?) } else { ([0.0; 2], 0.0) };
+
+    let mut buffers = MeshBuffers { positions: reader.quantized(num_vertices, min, step)?, ..MeshBuffers::default() };
+    if normal_bits > 0 {
+      let encoded = reader.quantized(num_vertices, [-1.0, -1.0], 2.0 / max_quantized(normal_bits))?;
+      buffers.normals = encoded.into_iter().map(octahedral_decode).collect();
+    }
+    if uv_bits > 0 { buffers.uvs = reader.quantized(num_vertices, uv_min, uv_step)?; }
+
+    let mut next = 0u64;
+    for _ in 0..num_indices {
+      let code = reader.varint()?;
+      let idx = if code == 0 { next += 1; next - 1 } else { next.checked_sub(code).ok_or("Compressed mesh has a bad index")? };
+      if idx >= num_vertices as u64 { return Err("Compressed mesh has a bad index"); }
+      buffers.indices.push(idx as u32);
+    }
+    Ok(buffers)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use export::MeshBuffers;
+  use super::{COMPRESSED_MAGIC, COMPRESSED_VERSION, write_varint};
+
+  #[test]
+  fn overflowing_coordinates_are_an_error() {
+    let mut bytes: Vec<u8> = COMPRESSED_MAGIC.to_vec();
+    bytes.extend_from_slice(& COMPRESSED_VERSION.to_le_bytes());
+    bytes.extend_from_slice(& 2u32.to_le_bytes());
+    bytes.extend_from_slice(& 0u32.to_le_bytes());
+    bytes.extend_from_slice(& [16, 0, 0]);
+    for value in & [0.0f32, 0.0, 0.0, 1.0] { bytes.extend_from_slice(& value.to_le_bytes()); }
+    // The largest step up, twice over, runs past i64::MAX
+    for _ in 0..6 { write_varint(&mut bytes, u64::MAX - 1); }
+    assert_eq!(MeshBuffers::decompress(& bytes).err(), Some("Corrupt quantized coordinate"));
+  }
+}
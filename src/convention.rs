@@ -0,0 +1,97 @@
+use cgmath::{Matrix3, Point3, Vector3, EuclideanSpace, Matrix, SquareMatrix};
+
+use mesh::HalfEdgeMesh;
+use ptr::VertRc;
+
+/// Which axis points up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UpAxis {
+  #[default]
+  Y,
+  Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Handedness {
+  #[default]
+  Right,
+  Left,
+}
+
+/// How a program or file format lays out its axes. In every convention x points right.
+/// With y up, z points toward the viewer in a right-handed system and away in a left-handed one.
+/// With z up, y points away from the viewer in a right-handed system and toward them in a left-handed one.
+/// So changing handedness mirrors the forward axis, and changing the up axis turns the scene
+/// a quarter turn around x. The default is y-up and right-handed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CoordinateConvention {
+  pub up: UpAxis,
+  pub handedness: Handedness,
+}
+
+impl CoordinateConvention {
+  /// OpenGL, glTF, Maya
+  pub const Y_UP_RIGHT: CoordinateConvention = CoordinateConvention { up: UpAxis::Y, handedness: Handedness::Right };
+  /// Direct3D, Unity
+  pub const Y_UP_LEFT: CoordinateConvention = CoordinateConvention { up: UpAxis::Y, handedness: Handedness::Left };
+  /// Blender, 3ds Max, most CAD packages
+  pub const Z_UP_RIGHT: CoordinateConvention = CoordinateConvention { up: UpAxis::Z, handedness: Handedness::Right };
+  /// Unreal
+  pub const Z_UP_LEFT: CoordinateConvention = CoordinateConvention { up: UpAxis::Z, handedness: Handedness::Left };
+
+  pub fn new(up: UpAxis, handedness: Handedness) -> CoordinateConvention {
+    CoordinateConvention { up, handedness }
+  }
+
+  /// Where the x, y, and z axes point, as columns in y-up right-handed coordinates
+  fn basis(& self) -> Matrix3<f32> {
+    let (right, up, back) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+    match (self.up, self.handedness) {
+      (UpAxis::Y, Handedness::Right) => Matrix3::from_cols(right, up, back),
+      (UpAxis::Y, Handedness::Left) => Matrix3::from_cols(right, up, -back),
+      (UpAxis::Z, Handedness::Right) => Matrix3::from_cols(right, -back, up),
+      (UpAxis::Z, Handedness::Left) => Matrix3::from_cols(right, back, up),
+    }
+  }
+
+  /// Maps coordinates in this convention to the same points in the other one
+  pub fn matrix_to(& self, to: & CoordinateConvention) -> Matrix3<f32> {
+    // The bases are rotations or reflections, so the transpose is the inverse
+    to.basis().transpose() * self.basis()
+  }
+
+  /// Whether converting to the other convention mirrors the mesh, which turns its faces inside out
+  /// unless their winding is reversed too
+  pub fn flips_winding(& self, to: & CoordinateConvention) -> bool {
+    self.handedness != to.handedness
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Converts the mesh from one axis convention to another, e.g. right after building it from a Z-up CAD file
+  /// for use in a Y-up engine. Vertices and tangents are moved over, and if the conversion mirrors the mesh
+  /// the winding of every face is reversed, so that faces still point outward
+  pub fn convert_axes(&mut self, from: CoordinateConvention, to: CoordinateConvention) {
+    let matrix = from.matrix_to(& to);
+    if matrix == Matrix3::identity() { return; }
+    let flip = from.flips_winding(& to);
+
+    let verts: Vec<VertRc> = self.vertices.values().cloned().collect();
+    for vert in & verts {
+      let pos = vert.borrow().get_pos();
+      self.move_vert(vert, Point3::from_vec(matrix * pos.to_vec()));
+    }
+    for tangent in self.tangents.values_mut() {
+      let dir = matrix * tangent.truncate();
+      // Mirroring reverses cross(normal, tangent), so the bitangent sign has to change with it
+      let sign = if flip { -tangent.w } else { tangent.w };
+      *tangent = dir.extend(sign);
+    }
+
+    if flip {
+      self.flip_normals();
+    } else {
+      for face in self.faces.values() { face.borrow_mut().compute_attrs(); }
+    }
+  }
+}
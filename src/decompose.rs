@@ -0,0 +1,230 @@
+use std::cmp::Ordering;
+
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use geom::{Aabb, Plane};
+use exact::{SnapGrid, orient3d};
+
+/// Settings for `HalfEdgeMesh::convex_decomposition`
+#[derive(Debug, Clone)]
+pub struct DecomposeOptions {
+  /// A piece is split again while some part of the surface is further than this inside its hull,
+  /// as a fraction of the diagonal of the mesh's bounding box
+  pub max_concavity: f32,
+  /// Pieces are split at most this many times, so there are at most 2^max_depth of them
+  pub max_depth: usize,
+  /// Some number of bits per coordinate to decide which side of each cut every point is on exactly, with
+  /// `orient3d`, on a `SnapGrid::for_aabb` grid over the mesh. The surface, the cutting planes, and the points
+  /// where they cross edges are all snapped to the grid, so a corner is never on one side of a cut for one
+  /// triangle and the other side for the next. None to decide within a small tolerance in floating point
+  pub exact_bits: Option<u32>,
+}
+
+impl Default for DecomposeOptions {
+  fn default() -> DecomposeOptions {
+    DecomposeOptions { max_concavity: 0.02, max_depth: 5, exact_bits: None }
+  }
+}
+
+/// Part of the surface, as loose triangles
+type Piece = Vec<[Point3<f32>; 3]>;
+
+/// A cutting plane, with how to tell which side of it a point is on
+struct Cut<'a> {
+  plane: Plane,
+  side: CutSide<'a>,
+}
+
+enum CutSide<'a> {
+  /// Points within this distance of the plane are on it
+  Tolerance(f32),
+  /// Points are on the grid, and the plane is the one through the three grid points, facing the way the
+  /// triangle between them does
+  Exact(& 'a SnapGrid, [[i64; 3]; 3]),
+}
+
+impl<'a> Cut<'a> {
+  /// Greater on the side the plane's normal points to, Less on the other, and Equal on the plane
+  fn side(& self, point: & Point3<f32>) -> Ordering {
+    match self.side {
+      CutSide::Tolerance(eps) => {
+        let distance = self.plane.signed_distance(point);
+        if distance > eps { Ordering::Greater } else if distance < -eps { Ordering::Less } else { Ordering::Equal }
+      },
+      CutSide::Exact(grid, ref corners) => match grid.snap(point) {
+        Ok(snapped) => orient3d(& corners[0], & corners[1], & corners[2], & snapped).reverse(),
+        Err(_) => self.plane.signed_distance(point).partial_cmp(& 0.0).unwrap_or(Ordering::Equal),
+      },
+    }
+  }
+
+  /// Where the edge from a to b crosses the plane, on the grid if there is one
+  fn crossing(& self, a: Point3<f32>, b: Point3<f32>) -> Point3<f32> {
+    let (d_a, d_b) = (self.plane.signed_distance(& a), self.plane.signed_distance(& b));
+    let cut = a + (b - a) * (d_a / (d_a - d_b));
+    match self.side {
+      CutSide::Exact(grid, _) => grid.snap(& cut).map_or(cut, |snapped| grid.unsnap(& snapped)),
+      CutSide::Tolerance(_) => cut,
+    }
+  }
+}
+
+/// Splits a triangle by the plane into the polygons on its positive and negative sides.
+/// Corners on the plane belong to both sides
+fn clip_triangle(tri: & [Point3<f32>; 3], cut: & Cut) -> (Vec<Point3<f32>>, Vec<Point3<f32>>) {
+  let mut front: Vec<Point3<f32>> = Vec::new();
+  let mut back: Vec<Point3<f32>> = Vec::new();
+  for idx in 0..3 {
+    let (a, b) = (tri[idx], tri[(idx + 1) % 3]);
+    let (side_a, side_b) = (cut.side(& a), cut.side(& b));
+    if side_a != Ordering::Less { front.push(a); }
+    if side_a != Ordering::Greater { back.push(a); }
+    if side_a != Ordering::Equal && side_b == side_a.reverse() {
+      let crossing = cut.crossing(a, b);
+      front.push(crossing);
+      back.push(crossing);
+    }
+  }
+  (front, back)
+}
+
+/// Adds a convex polygon to the piece as a triangle fan
+fn push_fan(piece: &mut Piece, polygon: & [Point3<f32>]) {
+  for idx in 1..polygon.len().saturating_sub(1) {
+    piece.push([polygon[0], polygon[idx], polygon[idx + 1]]);
+  }
+}
+
+/// Cuts the piece in two along the plane. Triangles lying in the plane are left out,
+/// since the hulls of both sides cover them anyway
+fn split_piece(piece: & [[Point3<f32>; 3]], cut: & Cut) -> (Piece, Piece) {
+  let mut front: Piece = Vec::new();
+  let mut back: Piece = Vec::new();
+  for tri in piece {
+    if tri.iter().all(|p| cut.side(p) == Ordering::Equal) { continue; }
+    let (front_poly, back_poly) = clip_triangle(tri, cut);
+    push_fan(&mut front, & front_poly);
+    push_fan(&mut back, & back_poly);
+  }
+  (front, back)
+}
+
+/// A piece along with its convex hull, and how far the piece's surface gets from the hull's surface
+struct HulledPiece {
+  piece: Piece,
+  hull: HalfEdgeMesh,
+  concavity: f32,
+}
+
+/// Builds the piece's hull, and measures the concavity at the corners, edge midpoints,
+/// and centers of its triangles. None if the piece is flat, and has no hull
+fn hull_piece(piece: Piece) -> Option<HulledPiece> {
+  let corners: Vec<Point3<f32>> = piece.iter().flat_map(|t| t.iter().cloned()).collect();
+  let hull = HalfEdgeMesh::convex_hull(& corners).ok()?;
+
+  let mut concavity: f32 = 0.0;
+  for tri in & piece {
+    let samples = [
+      tri[0], tri[1], tri[2],
+      Point3::midpoint(tri[0], tri[1]), Point3::midpoint(tri[1], tri[2]), Point3::midpoint(tri[2], tri[0]),
+      Point3::centroid(tri),
+    ];
+    for sample in & samples {
+      concavity = concavity.max(hull.convex_depth(sample));
+    }
+  }
+
+  Some(HulledPiece { piece, hull, concavity })
+}
+
+impl HalfEdgeMesh {
+  /// Approximate convex decomposition, in the spirit of V-HACD: the surface is cut in two
+  /// by axis-aligned planes, again and again, until the convex hull of each piece is close to the surface
+  /// it covers (see `DecomposeOptions`). Each cut is the one of a few candidates along each axis which
+  /// leaves the least concave pieces. Returns the hulls of the pieces, e.g. for use as physics colliders.
+  /// Flat pieces, which have no volume, are dropped. Only the triangular faces of the mesh are used
+  pub fn convex_decomposition(& self, options: & DecomposeOptions) -> Vec<HalfEdgeMesh> {
+    let grid = options.exact_bits.map(|bits| SnapGrid::for_aabb(& self.aabb(), bits));
+    let on_grid = |p: & Point3<f32>| grid.as_ref().and_then(|g| g.snap(p).ok().map(|s| g.unsnap(& s))).unwrap_or(*p);
+    let surface: Piece = self.faces.values()
+      .filter_map(|f| f.borrow().triangle())
+      .map(|tri| [on_grid(& tri[0]), on_grid(& tri[1]), on_grid(& tri[2])])
+      .collect();
+    let tolerance = options.max_concavity * self.aabb().size().magnitude();
+
+    let mut hulls: Vec<HalfEdgeMesh> = Vec::new();
+    let mut stack: Vec<(HulledPiece, usize)> = hull_piece(surface).into_iter().map(|p| (p, 0)).collect();
+
+    while let Some((current, depth)) = stack.pop() {
+      if current.concavity <= tolerance || depth >= options.max_depth {
+        hulls.push(current.hull);
+        continue;
+      }
+
+      let bounds = Aabb::from_points(current.piece.iter().flat_map(|t| t.iter()));
+      let eps = bounds.size().magnitude() * 1e-5;
+      let mut best: Option<(f32, Vec<HulledPiece>)> = None;
+      for axis in 0..3 {
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[axis] = 1.0;
+        for frac in & [0.25, 0.5, 0.75] {
+          let mut through = bounds.min;
+          through[axis] += (bounds.max[axis] - bounds.min[axis]) * frac;
+          let cut = match grid.as_ref().and_then(|g| g.snap(& through).ok().map(|s| (g, s))) {
+            Some((grid, snapped)) => {
+              // Grid points on the snapped plane, around a triangle facing along its normal
+              let (mut u, mut v) = (snapped, snapped);
+              u[(axis + 1) % 3] += 1;
+              v[(axis + 2) % 3] += 1;
+              Cut { plane: Plane::new(normal, grid.unsnap(& snapped)[axis]), side: CutSide::Exact(grid, [snapped, u, v]) }
+            },
+            None => Cut { plane: Plane::new(normal, through[axis]), side: CutSide::Tolerance(eps) },
+          };
+          let (front, back) = split_piece(& current.piece, & cut);
+          if front.is_empty() || back.is_empty() { continue; }
+
+          let parts: Vec<HulledPiece> = vec![front, back].into_iter().filter_map(hull_piece).collect();
+          let cost = parts.iter().map(|p| p.concavity).fold(0.0, f32::max);
+          if best.as_ref().is_none_or(|b| cost < b.0) { best = Some((cost, parts)); }
+        }
+      }
+
+      match best {
+        Some((_, parts)) => {
+          for part in parts { stack.push((part, depth + 1)); }
+        },
+        // Nothing could be cut off, so this is as good as it gets
+        None => hulls.push(current.hull),
+      }
+    }
+
+    hulls
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Point3;
+
+  use mesh::HalfEdgeMesh;
+  use exact::SnapGrid;
+  use super::DecomposeOptions;
+
+  #[test]
+  fn exact_pieces_stay_on_the_grid() {
+    let mesh = HalfEdgeMesh::icosphere(Point3::new(0.1, 0.2, 0.3), 1.0, 1);
+    let options = DecomposeOptions { max_concavity: 0.0, max_depth: 2, exact_bits: Some(16) };
+    let grid = SnapGrid::for_aabb(& mesh.aabb(), 16);
+
+    let hulls = mesh.convex_decomposition(& options);
+    assert_eq!(hulls.len(), 4);
+    for hull in & hulls {
+      assert_eq!(hull.validate(), Ok(()));
+      for vert in hull.verts() {
+        let pos = vert.borrow().get_pos();
+        assert_eq!(grid.unsnap(& grid.snap(& pos).unwrap()), pos);
+      }
+    }
+  }
+}
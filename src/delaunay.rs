@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Point2, MetricSpace};
+
+use planar::{HalfEdgeMesh2, Welder, WELD_TOLERANCE};
+
+/// How many times bigger than the points the triangle which starts out around all of them is.
+/// Too small, and the hull of the triangulation can come out slightly concave
+const SUPER_TRIANGLE_SCALE: f64 = 1e4;
+
+type Pt = [f64; 2];
+
+/// A triangle being built, with its circumcircle
+struct Tri {
+  corners: [usize; 3],
+  center: Pt,
+  radius2: f64,
+}
+
+impl Tri {
+  fn new(corners: [usize; 3], points: & [Pt]) -> Tri {
+    let (a, b, c) = (points[corners[0]], points[corners[1]], points[corners[2]]);
+    let d = 2.0 * (a[0] * (b[1] - c[1]) + b[0] * (c[1] - a[1]) + c[0] * (a[1] - b[1]));
+    if d == 0.0 {
+      // A flat triangle is in the way of every point, so it gets replaced as soon as possible
+      return Tri { corners, center: a, radius2: f64::INFINITY };
+    }
+    let (a2, b2, c2) = (a[0] * a[0] + a[1] * a[1], b[0] * b[0] + b[1] * b[1], c[0] * c[0] + c[1] * c[1]);
+    let center = [
+      (a2 * (b[1] - c[1]) + b2 * (c[1] - a[1]) + c2 * (a[1] - b[1])) / d,
+      (a2 * (c[0] - b[0]) + b2 * (a[0] - c[0]) + c2 * (b[0] - a[0])) / d,
+    ];
+    let radius2 = (a[0] - center[0]).powi(2) + (a[1] - center[1]).powi(2);
+    Tri { corners, center, radius2 }
+  }
+
+  fn circle_contains(& self, point: Pt) -> bool {
+    (point[0] - self.center[0]).powi(2) + (point[1] - self.center[1]).powi(2) < self.radius2
+  }
+}
+
+/// Merges points which are (nearly) in the same place
+fn weld_points(points: & [Point2<f32>]) -> Vec<Point2<f32>> {
+  let mut min = Point2::new(f32::INFINITY, f32::INFINITY);
+  let mut max = Point2::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+  for point in points {
+    min = Point2::new(min.x.min(point.x), min.y.min(point.y));
+    max = Point2::new(max.x.max(point.x), max.y.max(point.y));
+  }
+  let tolerance = if points.is_empty() { 0.0 } else { min.distance(max) * WELD_TOLERANCE };
+  let mut welder = Welder::new(tolerance);
+  for point in points { welder.weld(*point); }
+  welder.positions
+}
+
+/// Delaunay triangles of the points, counterclockwise, with the Bowyer-Watson algorithm:
+/// each point in turn replaces the triangles whose circumcircles it's inside with a fan around itself.
+/// Takes time quadratic in the number of points. Empty if the points are all on one line
+fn triangulate(points: & [Pt]) -> Vec<Tri> {
+  if points.len() < 3 { return Vec::new(); }
+  let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+  for point in points {
+    for axis in 0..2 {
+      min[axis] = min[axis].min(point[axis]);
+      max[axis] = max[axis].max(point[axis]);
+    }
+  }
+  let size = (max[0] - min[0]).max(max[1] - min[1]).max(f64::MIN_POSITIVE) * SUPER_TRIANGLE_SCALE;
+  let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+
+  let mut all: Vec<Pt> = points.to_vec();
+  let first_super = all.len();
+  all.push([center[0] - 2.0 * size, center[1] - size]);
+  all.push([center[0] + 2.0 * size, center[1] - size]);
+  all.push([center[0], center[1] + 2.0 * size]);
+
+  let mut tris: Vec<Tri> = vec![Tri::new([first_super, first_super + 1, first_super + 2], & all)];
+  for (idx, &point) in points.iter().enumerate() {
+    let (bad, good): (Vec<Tri>, Vec<Tri>) = tris.into_iter().partition(|t| t.circle_contains(point));
+    tris = good;
+
+    // The edges around the hole left by the bad triangles, which aren't shared by two of them
+    let inside: HashSet<(usize, usize)> = bad.iter()
+      .flat_map(|t| (0..3).map(move |c| (t.corners[c], t.corners[(c + 1) % 3])))
+      .collect();
+    for tri in & bad {
+      for c in 0..3 {
+        let (a, b) = (tri.corners[c], tri.corners[(c + 1) % 3]);
+        if !inside.contains(& (b, a)) { tris.push(Tri::new([a, b, idx], & all)); }
+      }
+    }
+  }
+
+  tris.retain(|t| t.corners.iter().all(|&c| c < first_super));
+  tris
+}
+
+fn to_pts(points: & [Point2<f32>]) -> Vec<Pt> {
+  points.iter().map(|p| [p.x as f64, p.y as f64]).collect()
+}
+
+/// Indices of the points in order along the line they're on
+fn along_line(points: & [Point2<f32>]) -> Vec<usize> {
+  let mut order: Vec<usize> = (0..points.len()).collect();
+  order.sort_by(|&a, &b| {
+    (points[a].x, points[a].y).partial_cmp(& (points[b].x, points[b].y)).unwrap_or(::std::cmp::Ordering::Equal)
+  });
+  order
+}
+
+/// The part of the segment inside the box, as fractions of the way along it (Liang-Barsky)
+fn clip_segment(from: Pt, to: Pt, min: Pt, max: Pt) -> Option<(f64, f64)> {
+  let (mut t0, mut t1) = (0.0f64, 1.0f64);
+  for axis in 0..2 {
+    let delta = to[axis] - from[axis];
+    for &(p, q) in & [(-delta, from[axis] - min[axis]), (delta, max[axis] - from[axis])] {
+      if p == 0.0 {
+        if q < 0.0 { return None; }
+      } else {
+        let t = q / p;
+        if p < 0.0 { t0 = t0.max(t); } else { t1 = t1.min(t); }
+      }
+    }
+  }
+  if t0 < t1 { Some((t0, t1)) } else { None }
+}
+
+impl HalfEdgeMesh2 {
+  /// Delaunay triangulation of the points: the triangulation in which no point is inside the circumcircle
+  /// of any triangle, which avoids thin triangles as far as possible. Points which (nearly) coincide are merged,
+  /// and otherwise vertex i is the i-th point. The convex hull is the boundary of the unbounded face.
+  /// If the points are all on one line, they're just joined up along it.
+  /// Takes time quadratic in the number of points
+  pub fn delaunay_triangulation(points: & [Point2<f32>]) -> HalfEdgeMesh2 {
+    let positions = weld_points(points);
+    let tris = triangulate(& to_pts(& positions));
+
+    let edges: Vec<(usize, usize)> = if tris.is_empty() {
+      along_line(& positions).windows(2).map(|pair| (pair[0], pair[1])).collect()
+    } else {
+      tris.iter().flat_map(|t| (0..3).map(move |c| (t.corners[c], t.corners[(c + 1) % 3]))).collect()
+    };
+    HalfEdgeMesh2::from_edges(positions, & edges)
+  }
+
+  /// Voronoi diagram of the points, clipped to a box around them half again as big as the points' bounds.
+  /// See `voronoi_diagram_in`
+  pub fn voronoi_diagram(points: & [Point2<f32>]) -> HalfEdgeMesh2 {
+    let mut min = Point2::new(f32::INFINITY, f32::INFINITY);
+    let mut max = Point2::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for point in points {
+      min = Point2::new(min.x.min(point.x), min.y.min(point.y));
+      max = Point2::new(max.x.max(point.x), max.y.max(point.y));
+    }
+    if points.is_empty() { return HalfEdgeMesh2::default(); }
+    let margin = ((max.x - min.x).max(max.y - min.y) / 2.0).max(1.0);
+    HalfEdgeMesh2::voronoi_diagram_in(points, Point2::new(min.x - margin, min.y - margin), Point2::new(max.x + margin, max.y + margin))
+  }
+
+  /// Voronoi diagram of the points, clipped to the box from `min` to `max`: each bounded face is the cell
+  /// of the points closest to one of them, so the cell of a point is `face_containing(point)`.
+  /// Built as the dual of the Delaunay triangulation, with the circumcenters of the triangles as vertices,
+  /// and rays going out from the hull
+  pub fn voronoi_diagram_in(points: & [Point2<f32>], min: Point2<f32>, max: Point2<f32>) -> HalfEdgeMesh2 {
+    let sites = weld_points(points);
+    let pts = to_pts(& sites);
+    let tris = triangulate(& pts);
+    let (box_min, box_max): (Pt, Pt) = ([min.x as f64, min.y as f64], [max.x as f64, max.y as f64]);
+    // Long enough to get out of the box from anywhere near it
+    let reach = |from: Pt| {
+      let (dx, dy) = (from[0] - (box_min[0] + box_max[0]) / 2.0, from[1] - (box_min[1] + box_max[1]) / 2.0);
+      (dx * dx + dy * dy).sqrt() + (box_max[0] - box_min[0]) + (box_max[1] - box_min[1])
+    };
+
+    let mut segments: Vec<(Pt, Pt)> = Vec::new();
+    if tris.is_empty() {
+      // Points on a line are split up by the lines halfway between neighbors
+      for pair in along_line(& sites).windows(2) {
+        let (a, b) = (pts[pair[0]], pts[pair[1]]);
+        let mid = [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+        let len = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+        let across = [-(b[1] - a[1]) / len * reach(mid), (b[0] - a[0]) / len * reach(mid)];
+        segments.push(([mid[0] - across[0], mid[1] - across[1]], [mid[0] + across[0], mid[1] + across[1]]));
+      }
+    } else {
+      let mut by_edge: HashMap<(usize, usize), usize> = HashMap::new();
+      for (idx, tri) in tris.iter().enumerate() {
+        for c in 0..3 { by_edge.insert((tri.corners[c], tri.corners[(c + 1) % 3]), idx); }
+      }
+      let mut keys: Vec<(usize, usize)> = by_edge.keys().cloned().collect();
+      keys.sort();
+      for (a, b) in keys {
+        let from = tris[by_edge[& (a, b)]].center;
+        match by_edge.get(& (b, a)) {
+          Some(&other) => if a < b { segments.push((from, tris[other].center)); },
+          None => {
+            // A hull edge: the cell border heads straight out from it, away from the triangle
+            let (dx, dy) = (pts[b][0] - pts[a][0], pts[b][1] - pts[a][1]);
+            let len = (dx * dx + dy * dy).sqrt();
+            let far = reach(from);
+            segments.push((from, [from[0] + dy / len * far, from[1] - dx / len * far]));
+          },
+        }
+      }
+    }
+
+    let tolerance = min.distance(max) * WELD_TOLERANCE;
+    let mut welder = Welder::new(tolerance);
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    let mut on_box: Vec<usize> = Vec::new();
+    let point_at = |from: Pt, to: Pt, t: f64| Point2::new((from[0] + (to[0] - from[0]) * t) as f32, (from[1] + (to[1] - from[1]) * t) as f32);
+    for (from, to) in segments {
+      let (t0, t1) = match clip_segment(from, to, box_min, box_max) { Some(range) => range, None => continue };
+      let (a, b) = (welder.weld(point_at(from, to, t0)), welder.weld(point_at(from, to, t1)));
+      edges.push((a, b));
+      if t0 > 0.0 { on_box.push(a); }
+      if t1 < 1.0 { on_box.push(b); }
+    }
+
+    // The box closes off the outer cells. Its sides are split wherever cell borders meet them
+    let corners = [min, Point2::new(max.x, min.y), max, Point2::new(min.x, max.y)];
+    let corner_ids: Vec<usize> = corners.iter().map(|c| welder.weld(*c)).collect();
+    for side in 0..4 {
+      let (start, end) = (corners[side], corners[(side + 1) % 4]);
+      let along = |p: Point2<f32>| (p - start).x * (end - start).x + (p - start).y * (end - start).y;
+      let length2 = along(end);
+      let mut stops: Vec<usize> = on_box.iter().cloned()
+        .filter(|&idx| {
+          let p = welder.positions[idx];
+          let t = along(p) / length2;
+          t > 0.0 && t < 1.0 && (start + (end - start) * t).distance(p) <= tolerance.max(f32::EPSILON * min.distance(max))
+        })
+        .collect();
+      stops.sort_by(|&a, &b| along(welder.positions[a]).partial_cmp(& along(welder.positions[b])).unwrap_or(::std::cmp::Ordering::Equal));
+      stops.dedup();
+      let mut prev = corner_ids[side];
+      for stop in stops.into_iter().chain(Some(corner_ids[(side + 1) % 4])) {
+        edges.push((prev, stop));
+        prev = stop;
+      }
+    }
+
+    HalfEdgeMesh2::from_edges(welder.positions, & edges)
+  }
+}
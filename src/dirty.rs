@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+/// Vertices and faces changed since the last call to `HalfEdgeMesh::take_dirty`, e.g. to update GPU buffers
+/// without rebuilding them (see `HalfEdgeMesh::to_buffers_patch`). A new mesh reports everything in it as added.
+/// Changes made by editing the mesh's maps or elements directly aren't seen, and neither are edges,
+/// since they don't show up in buffers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirtySet {
+  pub added_verts: HashSet<u32>,
+  /// Vertices moved, leaving out the ones which were also added
+  pub moved_verts: HashSet<u32>,
+  /// Vertices removed, leaving out the ones which were also added since the last call
+  pub removed_verts: HashSet<u32>,
+  pub added_faces: HashSet<u32>,
+  /// Faces whose corners changed without the face being removed
+  pub changed_faces: HashSet<u32>,
+  /// Faces removed, leaving out the ones which were also added since the last call
+  pub removed_faces: HashSet<u32>,
+}
+
+impl DirtySet {
+  pub fn is_empty(& self) -> bool {
+    self.added_verts.is_empty() && self.moved_verts.is_empty() && self.removed_verts.is_empty() &&
+      self.added_faces.is_empty() && self.changed_faces.is_empty() && self.removed_faces.is_empty()
+  }
+
+  pub(crate) fn add_vert(&mut self, id: u32) { self.added_verts.insert(id); }
+
+  pub(crate) fn move_vert(&mut self, id: u32) {
+    if !self.added_verts.contains(& id) { self.moved_verts.insert(id); }
+  }
+
+  pub(crate) fn remove_vert(&mut self, id: u32) {
+    self.moved_verts.remove(& id);
+    // Nobody has seen the vertex if it was added since the last call
+    if !self.added_verts.remove(& id) { self.removed_verts.insert(id); }
+  }
+
+  pub(crate) fn add_face(&mut self, id: u32) { self.added_faces.insert(id); }
+
+  pub(crate) fn change_face(&mut self, id: u32) {
+    if !self.added_faces.contains(& id) { self.changed_faces.insert(id); }
+  }
+
+  pub(crate) fn remove_face(&mut self, id: u32) {
+    self.changed_faces.remove(& id);
+    if !self.added_faces.remove(& id) { self.removed_faces.insert(id); }
+  }
+}
@@ -1,5 +1,7 @@
 use std::hash;
 
+use cgmath::InnerSpace;
+
 use ptr::{Ptr, EdgePtr, VertPtr, FacePtr, EdgeRc, VertRc, FaceRc};
 use iterators::*;
 
@@ -10,6 +12,9 @@ pub struct Edge {
   pub origin: VertPtr,
   pub face: FacePtr,
   pub id: u32,
+  /// Marks a crease or other feature edge that algorithms should preserve.
+  /// Set by `HalfEdgeMesh::detect_feature_edges`
+  pub sharp: bool,
 }
 
 // TODO: change the name of set_*_rc to just set_*, and change the current set_* to set_*_ptr
@@ -22,6 +27,7 @@ impl Edge {
       pair: EdgePtr::empty(),
       origin: VertPtr::empty(),
       face: FacePtr::empty(),
+      sharp: false,
     }
   }
 
@@ -32,6 +38,7 @@ impl Edge {
       pair: EdgePtr::empty(),
       origin: origin,
       face: FacePtr::empty(),
+      sharp: false,
     }
   }
 
@@ -78,6 +85,25 @@ impl Edge {
 
   pub fn get_pair_face(& self) -> Option<FaceRc> { self.get_pair().and_then(|p| p.borrow().get_face()) }
 
+  /// The edge whose next pointer points at this edge, found by walking around the face
+  pub fn get_prev(& self) -> Option<EdgeRc> {
+    FaceAdjacentEdgeIterator::new(self.next.clone())
+      .filter_map(|e| e.upgrade())
+      .find(|e| e.borrow().next.upgrade().is_some_and(|n| n.borrow().id == self.id))
+  }
+
+  /// Angle between the normals of the faces on either side of the edge, in radians.
+  /// 0 for a flat edge. None for a border edge
+  pub fn dihedral_angle(& self) -> Option<f32> {
+    let face = self.get_face()?;
+    let pair_face = self.get_pair_face()?;
+    let cos = face.borrow().normal.dot(pair_face.borrow().normal);
+    Some(cos.clamp(-1.0, 1.0).acos())
+  }
+
+  /// An edge without a pair lies on the border of an open mesh
+  pub fn is_boundary(& self) -> bool { !self.pair.is_valid() }
+
   /// Yields edge.origin, then edge.next.origin
   /// Gives you first the source of the half-edge, and then its target
   pub fn adjacent_verts(& self) -> EdgeAdjacentVertIterator {
@@ -0,0 +1,190 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use cgmath::{Point3, Vector3, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use geom::{Aabb, Plane};
+use util::OpTimer;
+
+/// Most cells from the grid's origin along any axis, so that `orient3d` can't overflow
+pub const MAX_GRID_CELLS: i64 = 1 << 30;
+
+/// A grid of integer coordinates to snap points to, so that predicates on them can be worked out exactly.
+/// Floating point tests like which side of a plane a point is on give inconsistent answers for points
+/// on or near the plane, which is what breaks clipping and booleans on coplanar faces. On the grid,
+/// points are either exactly on the plane through three others or not, and every test agrees.
+/// `HalfEdgeMesh::bisect_exact` cuts this way, and so does `convex_decomposition` with `DecomposeOptions::exact_bits`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapGrid {
+  pub origin: Point3<f32>,
+  /// Size of a grid cell. Points closer together than this may snap to the same place
+  pub cell: f32,
+}
+
+impl SnapGrid {
+  pub fn new(origin: Point3<f32>, cell: f32) -> SnapGrid {
+    SnapGrid { origin, cell }
+  }
+
+  /// The finest grid, in cells of a power of two, which still fits the box with `bits` bits per coordinate,
+  /// up to 24, the precision of an f32. The origin is put on a whole number of cells, at or below the box's
+  /// corner, so every grid point is a multiple of the cell, and `unsnap` gives it back exactly as long as it's
+  /// within 2^24 cells of zero, as it is unless the box is far from zero for its size
+  pub fn for_aabb(aabb: & Aabb, bits: u32) -> SnapGrid {
+    let bits = bits.clamp(1, 24);
+    let size = aabb.size();
+    let extent = size.x.max(size.y).max(size.z).max(f32::MIN_POSITIVE);
+    let cell = (extent / (1u32 << bits) as f32).log2().ceil().exp2();
+    let floor = |coord: f32| (coord / cell).floor() * cell;
+    SnapGrid { origin: Point3::new(floor(aabb.min.x), floor(aabb.min.y), floor(aabb.min.z)), cell }
+  }
+
+  /// Nearest grid point to the point. Fails if it's further from the origin than `MAX_GRID_CELLS`
+  pub fn snap(& self, point: & Point3<f32>) -> Result<[i64; 3], &'static str> {
+    let mut snapped = [0i64; 3];
+    for (axis, coord) in snapped.iter_mut().enumerate() {
+      let cells = ((point[axis] - self.origin[axis]) / self.cell).round();
+      if cells.is_nan() || cells.abs() > MAX_GRID_CELLS as f32 { return Err("Point is outside the grid"); }
+      *coord = cells as i64;
+    }
+    Ok(snapped)
+  }
+
+  /// Position of the grid point
+  pub fn unsnap(& self, snapped: & [i64; 3]) -> Point3<f32> {
+    Point3::new(
+      self.origin.x + snapped[0] as f32 * self.cell,
+      self.origin.y + snapped[1] as f32 * self.cell,
+      self.origin.z + snapped[2] as f32 * self.cell,
+    )
+  }
+}
+
+/// Which side of the plane through a, b, and c the point d is on, exactly: Greater if it's on the side the
+/// triangle's normal points away from, Less if it's in front, and Equal if the four are coplanar.
+/// Coordinates have to be within `MAX_GRID_CELLS` of the origin
+pub fn orient3d(a: & [i64; 3], b: & [i64; 3], c: & [i64; 3], d: & [i64; 3]) -> Ordering {
+  let row = |p: & [i64; 3]| [(p[0] - d[0]) as i128, (p[1] - d[1]) as i128, (p[2] - d[2]) as i128];
+  let (a, b, c) = (row(a), row(b), row(c));
+  let det = a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0]) + a[2] * (b[0] * c[1] - b[1] * c[0]);
+  det.cmp(& 0)
+}
+
+/// Whether c is to the left of the line from a to b, Greater, to the right, Less, or on it, Equal, exactly
+pub fn orient2d(a: & [i64; 2], b: & [i64; 2], c: & [i64; 2]) -> Ordering {
+  let det = (b[0] - a[0]) as i128 * (c[1] - a[1]) as i128 - (b[1] - a[1]) as i128 * (c[0] - a[0]) as i128;
+  det.cmp(& 0)
+}
+
+impl HalfEdgeMesh {
+  /// Moves every vertex onto the grid, so that exact predicates on the mesh agree with its coordinates.
+  /// Faces which were meant to be coplanar, but drifted apart by rounding, come back together if they lie
+  /// on the grid's axes or diagonals. Returns the number of vertices moved. Fails without changing anything
+  /// if any vertex is outside the grid
+  pub fn snap_to_grid(&mut self, grid: & SnapGrid) -> Result<usize, &'static str> {
+    let start = OpTimer::start();
+    let mut moves: Vec<(u32, Point3<f32>)> = Vec::new();
+    for id in self.vertex_order() {
+      let pos = self.vertices[& id].borrow().get_pos();
+      let snapped = grid.unsnap(& grid.snap(& pos)?);
+      if snapped != pos { moves.push((id, snapped)); }
+    }
+    for &(id, pos) in & moves {
+      let vert = self.vertices[& id].clone();
+      self.move_vert(& vert, pos);
+    }
+    for face in self.faces.values() { face.borrow_mut().compute_attrs(); }
+    debug!("snap_to_grid: moved {} vertices, took {:?}", moves.len(), start.elapsed());
+    Ok(moves.len())
+  }
+
+  /// `bisect`, with every vertex snapped to the grid first, and the plane replaced by the one through three
+  /// grid points on it, so that which side of the plane each vertex is on, or whether it's on it, is decided
+  /// exactly by `orient3d`. The halves agree on every vertex, and a vertex on the plane is never split off from
+  /// itself by rounding, however the plane lies. Where edges cross the plane, the new vertices are placed in
+  /// floating point, so they may be off the grid. The snapped plane can tilt by up to about a cell across the mesh.
+  /// Fails if any vertex is outside the grid, or the plane is too steep to pin down with three grid points
+  pub fn bisect_exact(& self, plane: & Plane, grid: & SnapGrid) -> Result<(HalfEdgeMesh, HalfEdgeMesh), &'static str> {
+    if plane.normal.magnitude2() == 0.0 || !plane.offset.is_finite() { return Err("Plane has no normal"); }
+    let mut snapped = self.clone();
+    snapped.snap_to_grid(grid)?;
+
+    // Three grid points spread across the mesh, with the triangle between them facing along the plane's normal
+    let normal = plane.normal.normalize();
+    let center = self.aabb().center();
+    let through = center - normal * plane.signed_distance(& center);
+    let reach = self.aabb().size().magnitude().max(grid.cell);
+    let side = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let u = side.cross(normal).normalize();
+    let v = normal.cross(u);
+    let corners = [grid.snap(& through)?, grid.snap(& (through + u * reach))?, grid.snap(& (through + v * reach))?];
+    let points: Vec<Point3<f32>> = corners.iter().map(|c| grid.unsnap(c)).collect();
+    let snapped_normal = (points[1] - points[0]).cross(points[2] - points[0]);
+    if snapped_normal.magnitude2() == 0.0 { return Err("Plane doesn't pass through three separate grid points"); }
+    let snapped_plane = Plane::from_point_normal(points[0], snapped_normal);
+
+    // The exact side, with the floating point distance to place the crossings, kept from going to 0 or changing sign
+    let mut distances: HashMap<u32, f32> = HashMap::new();
+    for (&id, vert) in & snapped.vertices {
+      let pos = vert.borrow().get_pos();
+      let distance = snapped_plane.signed_distance(& pos).abs().max(f32::MIN_POSITIVE);
+      let side = match orient3d(& corners[0], & corners[1], & corners[2], & grid.snap(& pos)?) {
+        Ordering::Less => distance,
+        Ordering::Greater => -distance,
+        Ordering::Equal => 0.0,
+      };
+      distances.insert(id, side);
+    }
+    snapped.bisect_by_distances(& snapped_plane, & distances)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cmp::Ordering;
+
+  use cgmath::{Point3, Vector3};
+
+  use mesh::HalfEdgeMesh;
+  use geom::Plane;
+  use super::{SnapGrid, orient2d, orient3d};
+
+  #[test]
+  fn orientation_signs() {
+    let (a, b, c) = ([0, 0, 0], [4, 0, 0], [0, 4, 0]);
+    assert_eq!(orient3d(& a, & b, & c, & [1, 1, 1]), Ordering::Less);
+    assert_eq!(orient3d(& a, & b, & c, & [1, 1, -1]), Ordering::Greater);
+    assert_eq!(orient3d(& a, & b, & c, & [3, 7, 0]), Ordering::Equal);
+    assert_eq!(orient2d(& [0, 0], & [4, 0], & [1, 1]), Ordering::Greater);
+    assert_eq!(orient2d(& [0, 0], & [4, 0], & [9, 0]), Ordering::Equal);
+  }
+
+  #[test]
+  fn exact_bisect_through_vertices() {
+    let mesh = HalfEdgeMesh::icosphere(Point3::new(0.0, 0.0, 0.0), 1.0, 2);
+    let grid = SnapGrid::for_aabb(& mesh.aabb(), 20);
+    // Both planes run through the ring of vertices at x = 0, and cross each other there
+    let first = Plane::from_point_normal(Point3::new(0.15, 0.2, 0.05), Vector3::new(0.3, -0.4, -0.1));
+    let second = Plane::from_point_normal(Point3::new(-0.15, 0.2, 0.05), Vector3::new(-0.3, -0.4, -0.1));
+    let (_, half) = mesh.bisect_exact(& first, & grid).unwrap();
+    assert_eq!(half.validate(), Ok(()));
+    let (front, back) = half.bisect_exact(& second, & grid).unwrap();
+    assert_eq!(front.validate(), Ok(()));
+    assert_eq!(back.validate(), Ok(()));
+    assert!((front.signed_volume() + back.signed_volume() - half.signed_volume()).abs() < 1e-4);
+  }
+
+  #[test]
+  fn grid_points_round_trip() {
+    let aabb = ::geom::Aabb::from_points(& [Point3::new(0.3, -1.1, 2.05), Point3::new(1.7, 0.4, 2.5)]);
+    let grid = SnapGrid::for_aabb(& aabb, 30);
+    assert!(grid.cell >= 1.4 / (1 << 24) as f32);
+    for point in & [aabb.min, aabb.max, Point3::new(0.912_345, -0.3, 2.111)] {
+      let on_grid = grid.unsnap(& grid.snap(point).unwrap());
+      assert_eq!(grid.unsnap(& grid.snap(& on_grid).unwrap()), on_grid);
+      let offset = on_grid - point;
+      assert!(offset.x.abs() <= grid.cell / 2.0 && offset.y.abs() <= grid.cell / 2.0 && offset.z.abs() <= grid.cell / 2.0);
+    }
+  }
+}
@@ -0,0 +1,489 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use cgmath::Vector3;
+
+use ptr::{EdgeRc, FaceRc};
+use mesh::HalfEdgeMesh;
+use iterators::ToPtrVec;
+use dirty::DirtySet;
+use convention::CoordinateConvention;
+use units::LengthUnit;
+use attributes::BufferAttribute;
+
+/// Size of the simulated post-transform cache used by `optimize_vertex_cache`
+const CACHE_SIZE: usize = 32;
+
+/// Options for `HalfEdgeMesh::to_buffers`
+#[derive(Debug, Clone, Default)]
+pub struct BufferOptions {
+  /// Reorder triangles and vertices for the GPU's post-transform vertex cache
+  /// (Tom Forsyth's linear-speed algorithm). Takes a little longer to export
+  pub optimize_vertex_cache: bool,
+  /// Convert the buffers from the first axis convention to the second, leaving the mesh as it is.
+  /// See `MeshBuffers::convert_axes`
+  pub convert_axes: Option<(CoordinateConvention, CoordinateConvention)>,
+  /// Write the positions in these units instead of the mesh's, e.g. millimeters for a 3D printer
+  pub units: Option<LengthUnit>,
+}
+
+/// Flat vertex and index arrays, ready to be uploaded to a GPU.
+/// Vertices are shared between faces, with smooth normals
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshBuffers {
+  pub positions: Vec<[f32; 3]>,
+  pub normals: Vec<[f32; 3]>,
+  /// Texture coordinates, if the mesh has any. Vertices without them get (0, 0)
+  pub uvs: Vec<[f32; 2]>,
+  /// Tangents with handedness in w, if they've been computed with `compute_tangents`.
+  /// Vertices without one get +x
+  pub tangents: Vec<[f32; 4]>,
+  /// The mesh's registered attributes, in order of registration, leaving out internal ones.
+  /// Each one's semantic gives the name exporters write it under
+  pub attributes: Vec<BufferAttribute>,
+  /// Three indices per triangle, in counterclockwise order. Polygons are split into triangle fans
+  pub indices: Vec<u32>,
+  /// Id of the mesh vertex behind each buffer vertex
+  pub vert_ids: Vec<u32>,
+}
+
+impl MeshBuffers {
+  pub fn num_triangles(& self) -> usize { self.indices.len() / 3 }
+
+  /// Converts the buffers from one axis convention to another, as `HalfEdgeMesh::convert_axes` does for a mesh.
+  /// If the conversion mirrors, the triangles are wound the other way, so that they keep facing out
+  pub fn convert_axes(&mut self, from: CoordinateConvention, to: CoordinateConvention) {
+    let matrix = from.matrix_to(& to);
+    let flip = from.flips_winding(& to);
+    let convert = |v: [f32; 3]| -> [f32; 3] { (matrix * Vector3::from(v)).into() };
+
+    for pos in &mut self.positions { *pos = convert(*pos); }
+    for normal in &mut self.normals { *normal = convert(*normal); }
+    for tangent in &mut self.tangents {
+      let dir = convert([tangent[0], tangent[1], tangent[2]]);
+      *tangent = [dir[0], dir[1], dir[2], if flip { -tangent[3] } else { tangent[3] }];
+    }
+    if flip {
+      for tri in self.indices.chunks_mut(3) { tri.swap(1, 2); }
+    }
+  }
+
+  /// Scales the positions from one unit of length to another
+  pub fn convert_units(&mut self, from: LengthUnit, to: LengthUnit) {
+    let scale = from.scale_to(to);
+    if scale == 1.0 { return; }
+    for pos in &mut self.positions {
+      for coord in pos.iter_mut() { *coord *= scale; }
+    }
+  }
+
+  /// Average cache miss ratio: vertex transforms per triangle, for a FIFO cache of the given size.
+  /// Ranges from 3 (no reuse) down to about 0.5 for a well-ordered large mesh
+  pub fn acmr(& self, cache_size: usize) -> f32 {
+    if self.indices.is_empty() { return 0.0; }
+    let mut cache: ::std::collections::VecDeque<u32> = ::std::collections::VecDeque::new();
+    let mut misses = 0;
+    for &index in & self.indices {
+      if !cache.contains(& index) {
+        misses += 1;
+        cache.push_back(index);
+        if cache.len() > cache_size { cache.pop_front(); }
+      }
+    }
+    misses as f32 / self.num_triangles() as f32
+  }
+
+  /// Reorders the triangles for vertex cache efficiency, using Tom Forsyth's algorithm:
+  /// each vertex is scored by its position in a simulated LRU cache and by how many
+  /// triangles still use it, and the triangle with the best total score is emitted next.
+  /// The vertices are then renumbered in order of first use, for memory locality
+  pub fn optimize_vertex_cache(&mut self) {
+    let num_verts = self.positions.len();
+    let num_tris = self.num_triangles();
+    if num_tris == 0 { return; }
+
+    let mut vert_tris: Vec<Vec<usize>> = vec![Vec::new(); num_verts];
+    for tri in 0..num_tris {
+      for corner in 0..3 { vert_tris[self.indices[tri * 3 + corner] as usize].push(tri); }
+    }
+    let mut remaining: Vec<usize> = vert_tris.iter().map(|t| t.len()).collect();
+    let mut cache_pos: Vec<Option<usize>> = vec![None; num_verts];
+    let mut vert_score: Vec<f32> = (0..num_verts).map(|v| forsyth_score(None, remaining[v])).collect();
+    let mut tri_score: Vec<f32> = (0..num_tris)
+      .map(|tri| (0..3).map(|c| vert_score[self.indices[tri * 3 + c] as usize]).sum())
+      .collect();
+    let mut emitted = vec![false; num_tris];
+    let mut cache: Vec<usize> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut order: Vec<usize> = Vec::with_capacity(num_tris);
+    // Fallback scan position, for when nothing in the cache has triangles left
+    let mut scan = 0;
+
+    let mut best = (0..num_tris).max_by(|&a, &b| tri_score[a].partial_cmp(& tri_score[b]).unwrap_or(::std::cmp::Ordering::Equal));
+
+    while let Some(tri) = best {
+      emitted[tri] = true;
+      order.push(tri);
+
+      // Move the triangle's vertices to the front of the cache
+      let corners: Vec<usize> = (0..3).map(|c| self.indices[tri * 3 + c] as usize).collect();
+      for &vert in & corners {
+        remaining[vert] -= 1;
+        if let Some(pos) = vert_tris[vert].iter().position(|&t| t == tri) { vert_tris[vert].swap_remove(pos); }
+        cache.retain(|&v| v != vert);
+      }
+      for &vert in corners.iter().rev() { cache.insert(0, vert); }
+
+      // Rescore everything that was in the cache, including vertices pushed out of it
+      let evicted: Vec<usize> = if cache.len() > CACHE_SIZE { cache.split_off(CACHE_SIZE) } else { Vec::new() };
+      for &vert in & evicted { cache_pos[vert] = None; }
+      for (pos, &vert) in cache.iter().enumerate() { cache_pos[vert] = Some(pos); }
+
+      best = None;
+      let mut best_score = -1.0f32;
+      for &vert in cache.iter().chain(evicted.iter()) {
+        let score = forsyth_score(cache_pos[vert], remaining[vert]);
+        let change = score - vert_score[vert];
+        vert_score[vert] = score;
+        for &t in & vert_tris[vert] {
+          tri_score[t] += change;
+        }
+      }
+      for &vert in & cache {
+        for &t in & vert_tris[vert] {
+          if tri_score[t] > best_score { best_score = tri_score[t]; best = Some(t); }
+        }
+      }
+
+      if best.is_none() {
+        while scan < num_tris && emitted[scan] { scan += 1; }
+        if scan < num_tris { best = Some(scan); }
+      }
+    }
+
+    // Renumber the vertices by first use
+    let mut remap: Vec<Option<u32>> = vec![None; num_verts];
+    let mut new_indices: Vec<u32> = Vec::with_capacity(self.indices.len());
+    let mut old_order: Vec<usize> = Vec::with_capacity(num_verts);
+    for tri in order {
+      for corner in 0..3 {
+        let old = self.indices[tri * 3 + corner] as usize;
+        let new = *remap[old].get_or_insert_with(|| {
+          old_order.push(old);
+          (old_order.len() - 1) as u32
+        });
+        new_indices.push(new);
+      }
+    }
+    // Vertices which aren't used by any triangle go at the end
+    for (old, new) in remap.iter().enumerate() {
+      if new.is_none() { old_order.push(old); }
+    }
+
+    self.indices = new_indices;
+    self.positions = old_order.iter().map(|&old| self.positions[old]).collect();
+    self.normals = old_order.iter().map(|&old| self.normals[old]).collect();
+    if !self.uvs.is_empty() { self.uvs = old_order.iter().map(|&old| self.uvs[old]).collect(); }
+    if !self.tangents.is_empty() { self.tangents = old_order.iter().map(|&old| self.tangents[old]).collect(); }
+    for attribute in &mut self.attributes {
+      let size = attribute.components;
+      attribute.data = old_order.iter().flat_map(|&old| attribute.data[old * size .. (old + 1) * size].to_vec()).collect();
+    }
+    self.vert_ids = old_order.iter().map(|&old| self.vert_ids[old]).collect();
+  }
+}
+
+/// Partial update for buffers made by `to_buffers` with the default options, from `to_buffers_patch`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuffersPatch {
+  /// Number of vertices in the buffers after the update
+  pub num_vertices: usize,
+  /// Buffer positions of the vertices to overwrite, in increasing order.
+  /// Positions past the end of the old buffers are new vertices
+  pub vertex_indices: Vec<u32>,
+  /// New data for those vertices, in the same order. Its indices are empty
+  pub vertices: MeshBuffers,
+  /// Where the new indices start in the index buffer
+  pub index_start: usize,
+  /// Replaces the index buffer from `index_start` on, to its end. None if the indices haven't changed
+  pub indices: Option<Vec<u32>>,
+}
+
+impl BuffersPatch {
+  pub fn is_empty(& self) -> bool { self.vertex_indices.is_empty() && self.indices.is_none() }
+
+  /// Updates a CPU-side copy of the buffers. A GPU upload would write the same ranges
+  pub fn apply(& self, buffers: &mut MeshBuffers) {
+    let count = self.num_vertices;
+    buffers.positions.resize(count, [0.0; 3]);
+    buffers.normals.resize(count, [0.0; 3]);
+    buffers.vert_ids.resize(count, 0);
+    if !self.vertices.uvs.is_empty() { buffers.uvs.resize(count, [0.0; 2]); }
+    if !self.vertices.tangents.is_empty() { buffers.tangents.resize(count, [1.0, 0.0, 0.0, 1.0]); }
+    for attribute in & self.vertices.attributes {
+      match buffers.attributes.iter_mut().find(|a| a.name == attribute.name) {
+        Some(existing) => existing.data.resize(count * attribute.components, 0.0),
+        None => buffers.attributes.push(BufferAttribute { data: vec![0.0; count * attribute.components], ..attribute.clone() }),
+      }
+    }
+
+    for (patch_idx, &buffer_idx) in self.vertex_indices.iter().enumerate() {
+      let buffer_idx = buffer_idx as usize;
+      buffers.positions[buffer_idx] = self.vertices.positions[patch_idx];
+      buffers.normals[buffer_idx] = self.vertices.normals[patch_idx];
+      buffers.vert_ids[buffer_idx] = self.vertices.vert_ids[patch_idx];
+      if let Some(uv) = self.vertices.uvs.get(patch_idx) { buffers.uvs[buffer_idx] = *uv; }
+      if let Some(tangent) = self.vertices.tangents.get(patch_idx) { buffers.tangents[buffer_idx] = *tangent; }
+      for attribute in & self.vertices.attributes {
+        let size = attribute.components;
+        if let Some(target) = buffers.attributes.iter_mut().find(|a| a.name == attribute.name) {
+          target.data[buffer_idx * size .. (buffer_idx + 1) * size].copy_from_slice(& attribute.data[patch_idx * size .. (patch_idx + 1) * size]);
+        }
+      }
+    }
+
+    if let Some(ref indices) = self.indices {
+      buffers.indices.truncate(self.index_start);
+      buffers.indices.extend_from_slice(indices);
+    }
+  }
+}
+
+/// Tom Forsyth's vertex score, from its position in the cache and the number of triangles left to use it
+fn forsyth_score(cache_pos: Option<usize>, remaining: usize) -> f32 {
+  if remaining == 0 { return -1.0; }
+  let cache_score = match cache_pos {
+    None => 0.0,
+    // The most recent triangle's vertices get a fixed score, so that the next triangle
+    // doesn't just reuse the same edge
+    Some(pos) if pos < 3 => 0.75,
+    Some(pos) => (1.0 - (pos - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+  };
+  // Boost vertices with few triangles left, to finish them off and avoid leaving lone triangles
+  cache_score + 2.0 * (remaining as f32).powf(-0.5)
+}
+
+/// Number of unvisited faces next to a face
+fn open_neighbors(face: & FaceRc, visited: & HashSet<u32>) -> usize {
+  face.borrow().adjacent_edges().to_ptr_vec().iter()
+    .filter_map(|e| e.borrow().get_pair_face())
+    .filter(|f| f.borrow().num_vertices() == 3 && !visited.contains(& f.borrow().id))
+    .count()
+}
+
+/// Follows a strip out of the face through the `exit` edge, without modifying anything.
+/// Returns the faces and the vertex ids added to the strip, in order
+fn follow_strip(exit: & EdgeRc, visited: & HashSet<u32>) -> (Vec<FaceRc>, Vec<u32>) {
+  let mut faces: Vec<FaceRc> = Vec::new();
+  let mut ids: Vec<u32> = Vec::new();
+  let mut claimed: HashSet<u32> = HashSet::new();
+  let mut exit = exit.clone();
+
+  loop {
+    let pair = match exit.borrow().get_pair() { Some(p) => p, None => break };
+    let face = match pair.borrow().get_face() { Some(f) => f, None => break };
+    let face_id = face.borrow().id;
+    if visited.contains(& face_id) || claimed.contains(& face_id) || face.borrow().num_vertices() != 3 { break; }
+
+    // The pair runs from q to p (the last two strip vertices), so the new corner r
+    // is the target of the pair's next edge
+    let next = match pair.borrow().get_next() { Some(n) => n, None => break };
+    let (third, next_exit) = match (next.borrow().get_target(), next.borrow().get_next()) {
+      (Some(t), Some(n)) => (t, n),
+      _ => break,
+    };
+
+    // The strip leaves through the edge between q and r. Since the winding alternates,
+    // that's the edge into r for every other face, and the edge out of r for the rest
+    let exit_edge = if faces.len().is_multiple_of(2) { next_exit } else { next.clone() };
+
+    claimed.insert(face_id);
+    faces.push(face);
+    ids.push(third.borrow().id);
+    exit = exit_edge;
+  }
+
+  (faces, ids)
+}
+
+impl HalfEdgeMesh {
+  /// Covers the triangular faces of the mesh with triangle strips, using the greedy
+  /// SGI approach: each strip starts at the face with the fewest unvisited neighbors,
+  /// and is extended through whichever side of that face gives the longest strip.
+  /// Each strip is a list of vertex ids, where triangle i is made of entries i, i + 1, and i + 2,
+  /// with the winding flipped for odd triangles, as usual for strips. Non-triangular faces are skipped
+  pub fn to_triangle_strips(& self) -> Vec<Vec<u32>> {
+    let mut visited: HashSet<u32> = HashSet::new();
+    let mut strips: Vec<Vec<u32>> = Vec::new();
+
+    let triangles: HashMap<u32, &FaceRc> = self.faces.iter()
+      .filter(|&(_, f)| f.borrow().num_vertices() == 3)
+      .map(|(&id, f)| (id, f))
+      .collect();
+
+    // Faces by number of open neighbors. Entries go stale as faces are visited,
+    // so new ones are pushed for the neighbors, and old ones are skipped
+    let mut queue: BinaryHeap<Reverse<(usize, u32)>> = triangles.iter()
+      .map(|(&id, face)| Reverse((open_neighbors(face, & visited), id)))
+      .collect();
+
+    while let Some(Reverse((count, id))) = queue.pop() {
+      if visited.contains(& id) { continue; }
+      let start = triangles[& id];
+      if open_neighbors(start, & visited) != count {
+        queue.push(Reverse((open_neighbors(start, & visited), id)));
+        continue;
+      }
+
+      visited.insert(id);
+
+      // Try leaving the start triangle through each of its sides
+      let edges = start.borrow().adjacent_edges().to_ptr_vec();
+      let mut best: Option<(Vec<u32>, Vec<FaceRc>)> = None;
+      for rotation in 0..3 {
+        // With the strip starting (a, b, c), the first exit is the edge b -> c
+        let corners: Vec<u32> = (0..3)
+          .filter_map(|idx| edges[(rotation + idx) % 3].borrow().get_origin().map(|v| v.borrow().id))
+          .collect();
+        if corners.len() != 3 { continue; }
+        let (faces, mut ids) = follow_strip(& edges[(rotation + 1) % 3], & visited);
+        if best.as_ref().is_none_or(|b| faces.len() > b.1.len()) {
+          let mut strip = corners;
+          strip.append(&mut ids);
+          best = Some((strip, faces));
+        }
+      }
+
+      let (strip, faces) = match best { Some(b) => b, None => continue };
+      for face in faces.iter().chain(::std::iter::once(start)) {
+        visited.insert(face.borrow().id);
+        for neighbor in face.borrow().adjacent_faces() {
+          if let Some(neighbor) = neighbor.upgrade() {
+            let neighbor_id = neighbor.borrow().id;
+            if triangles.contains_key(& neighbor_id) && !visited.contains(& neighbor_id) {
+              queue.push(Reverse((open_neighbors(& neighbor, & visited), neighbor_id)));
+            }
+          }
+        }
+      }
+      strips.push(strip);
+    }
+
+    strips
+  }
+
+  /// Builds flat vertex and index buffers for rendering, with one vertex per mesh vertex,
+  /// ordered by id unless the options reorder them
+  pub fn to_buffers(& self, options: & BufferOptions) -> MeshBuffers {
+    let vert_ids = self.vertex_order();
+    let index = self.vertex_index();
+    let mut buffers = MeshBuffers::default();
+
+    for &id in & vert_ids {
+      self.push_buffer_vert(&mut buffers, id);
+    }
+
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+    for id in face_ids {
+      self.push_face_indices(&mut buffers.indices, & self.faces[& id], & index);
+    }
+
+    if options.optimize_vertex_cache { buffers.optimize_vertex_cache(); }
+    if let Some((from, to)) = options.convert_axes { buffers.convert_axes(from, to); }
+    if let Some(units) = options.units { buffers.convert_units(self.units(), units); }
+
+    buffers
+  }
+
+  /// The changes to make to buffers built by `to_buffers` with the default options, to bring them
+  /// up to date with the changes in the dirty set (see `take_dirty`). Vertices are laid out in order of id,
+  /// and new ones get higher ids, so usually the patch only rewrites the vertices which changed,
+  /// along with their neighbors, whose normals change too, and the indices from the first changed face on.
+  /// Removing vertices shifts the ones after them, so they are all rewritten, along with every index
+  pub fn to_buffers_patch(& self, dirty: & DirtySet) -> BuffersPatch {
+    let vert_ids = self.vertex_order();
+    let index = self.vertex_index();
+    let mut patch = BuffersPatch { num_vertices: vert_ids.len(), ..BuffersPatch::default() };
+
+    // Every vertex from the first one added or removed on has a new place in the buffers
+    let first_shifted = dirty.added_verts.iter().chain(dirty.removed_verts.iter()).min()
+      .map_or(vert_ids.len(), |min_id| vert_ids.partition_point(|id| id < min_id));
+
+    // Vertex normals come from the faces around them, so they change along with any face they're part of
+    let mut refresh: HashSet<u32> = HashSet::new();
+    let mut touched_faces: Vec<FaceRc> = dirty.added_faces.iter().chain(dirty.changed_faces.iter())
+      .filter_map(|id| self.faces.get(id).cloned())
+      .collect();
+    for id in & dirty.moved_verts {
+      if let Some(vert) = self.vertices.get(id) {
+        refresh.insert(*id);
+        touched_faces.extend(vert.borrow().adjacent_faces().filter_map(|f| f.upgrade()));
+      }
+    }
+    for face in & touched_faces {
+      refresh.extend(face.borrow().adjacent_verts().filter_map(|v| v.upgrade().map(|v| v.borrow().id)));
+    }
+
+    let mut updated: Vec<usize> = refresh.iter()
+      .filter_map(|id| index.get(id).cloned())
+      .filter(|&idx| idx < first_shifted)
+      .collect();
+    updated.sort();
+    updated.extend(first_shifted..vert_ids.len());
+    for idx in updated {
+      self.push_buffer_vert(&mut patch.vertices, vert_ids[idx]);
+      patch.vertex_indices.push(idx as u32);
+    }
+
+    // If vertices which were already there moved over, every index might be different
+    let renumbered = vert_ids[first_shifted..].iter().any(|id| !dirty.added_verts.contains(id));
+    let first_face_id = dirty.added_faces.iter().chain(dirty.changed_faces.iter()).chain(dirty.removed_faces.iter()).min();
+    if !renumbered && first_face_id.is_none() { return patch; }
+
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+    let first_face = match first_face_id {
+      Some(min_id) if !renumbered => face_ids.partition_point(|id| id < min_id),
+      _ => 0,
+    };
+    let mut indices: Vec<u32> = Vec::new();
+    for id in & face_ids[.. first_face] {
+      self.push_face_indices(&mut indices, & self.faces[id], & index);
+    }
+    patch.index_start = indices.len();
+    indices.clear();
+    for id in & face_ids[first_face ..] {
+      self.push_face_indices(&mut indices, & self.faces[id], & index);
+    }
+    patch.indices = Some(indices);
+
+    patch
+  }
+
+  /// Adds the vertex's data to the buffers
+  fn push_buffer_vert(& self, buffers: &mut MeshBuffers, id: u32) {
+    let vert_b = self.vertices[& id].borrow();
+    let pos = vert_b.get_pos();
+    let normal = vert_b.normal();
+    buffers.positions.push([pos.x, pos.y, pos.z]);
+    buffers.normals.push([normal.x, normal.y, normal.z]);
+    if !self.uvs.is_empty() {
+      buffers.uvs.push(self.uvs.get(& id).map_or([0.0, 0.0], |uv| [uv.x, uv.y]));
+    }
+    if !self.tangents.is_empty() {
+      buffers.tangents.push(self.tangents.get(& id).map_or([1.0, 0.0, 0.0, 1.0], |t| [t.x, t.y, t.z, t.w]));
+    }
+    self.attributes().write_vert(id, &mut buffers.attributes);
+    buffers.vert_ids.push(id);
+  }
+
+  /// Adds the face to the index buffer, as a triangle fan
+  fn push_face_indices(& self, indices: &mut Vec<u32>, face: & FaceRc, index: & HashMap<u32, usize>) {
+    let corners: Vec<u32> = face.borrow().adjacent_verts()
+      .filter_map(|v| v.upgrade().and_then(|v| index.get(& v.borrow().id).map(|&idx| idx as u32)))
+      .collect();
+    for idx in 1..corners.len().saturating_sub(1) {
+      indices.extend_from_slice(& [corners[0], corners[idx], corners[idx + 1]]);
+    }
+  }
+}
@@ -4,6 +4,7 @@ use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace};
 use approx::ApproxEq;
 
 use ptr::{Ptr, EdgePtr, EdgeRc, VertRc};
+use geom::Aabb;
 use iterators::*;
 
 // TODO: Better way of figuring out when to run compute_attrs
@@ -49,6 +50,41 @@ impl Face {
 
   pub fn num_vertices(& self) -> usize { self.adjacent_verts().count() }
 
+  /// Positions of the face's corner vertices, in *counterclockwise* order
+  pub fn vert_positions(& self) -> Vec<Point3<f32>> {
+    self.adjacent_verts().to_ptr_vec().iter()
+      .map(|v| v.borrow().get_pos())
+      .collect()
+  }
+
+  /// Area of the face, computed as a triangle fan around the first vertex,
+  /// so it's exact for triangles and planar convex polygons
+  pub fn area(& self) -> f32 {
+    let positions = self.vert_positions();
+
+    if positions.len() < 3 { return 0.0; }
+
+    let mut doubled = Vector3::new(0.0, 0.0, 0.0);
+    for idx in 1..(positions.len() - 1) {
+      doubled += (positions[idx] - positions[0]).cross(positions[idx + 1] - positions[0]);
+    }
+
+    doubled.magnitude() * 0.5
+  }
+
+  /// Corner positions of a triangular face, in counterclockwise order.
+  /// None if the face doesn't have exactly three vertices
+  pub fn triangle(& self) -> Option<[Point3<f32>; 3]> {
+    let positions = self.vert_positions();
+    if positions.len() != 3 { return None; }
+    Some([positions[0], positions[1], positions[2]])
+  }
+
+  /// Bounding box of the face's corner vertices
+  pub fn aabb(& self) -> Aabb {
+    Aabb::from_points(& self.vert_positions())
+  }
+
   /// Computes the attributes (normal and center) of this face
   /// Note: this only works when the edges and verts are properly connected
   /// So wait for the right time during initialization to run this
@@ -62,7 +98,7 @@ impl Face {
 
     let vert_list: Vec<VertRc> = self.adjacent_verts().to_ptr_vec();
 
-    debug_assert!(vert_list.len() == 3, "should have 3 adjacent vertices");
+    debug_assert!(vert_list.len() >= 3, "should have at least 3 adjacent vertices");
 
     for vert in &vert_list {
       let pos = vert.borrow().get_pos();
@@ -75,10 +111,16 @@ impl Face {
     // Average position of the corner points
     self.center = center / count;
 
+    // Sum of a triangle fan's normals, which for a triangle is just its normal,
+    // and for a polygon is an average weighted by area
     let vert_a = vert_list[0].borrow().get_pos();
-    let s1 = vert_list[1].borrow().get_pos() - vert_a;
-    let s2 = vert_list[2].borrow().get_pos() - vert_a;
-    self.normal = s1.cross(s2).normalize();
+    let mut normal = Vector3::new(0.0, 0.0, 0.0);
+    for idx in 1..(vert_list.len() - 1) {
+      let s1 = vert_list[idx].borrow().get_pos() - vert_a;
+      let s2 = vert_list[idx + 1].borrow().get_pos() - vert_a;
+      normal += s1.cross(s2);
+    }
+    self.normal = normal.normalize();
   }
 
   /// Iterates over the vertices which make up the face in *counterclockwise* order
@@ -96,6 +138,12 @@ impl Face {
     FaceAdjacentFaceIterator::new(self.edge.clone())
   }
 
+  /// Iterates breadth-first over the faces at most `rings` steps away across edges, not including this one.
+  /// Use `next_with_ring` or `into_rings` on the iterator to tell the rings apart
+  pub fn neighborhood(& self, rings: usize) -> FaceNeighborhoodIterator {
+    FaceNeighborhoodIterator::from_face(self, rings)
+  }
+
   pub fn distance_to(& self, point: & Point3<f32>) -> f32 {
     (point - self.center).magnitude()
   }
@@ -0,0 +1,252 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Point3, Vector3, InnerSpace};
+
+use mesh::{HalfEdgeMesh, vert_neighbor_ids};
+use face::Face;
+use ptr::EdgeRc;
+use selection::Selection;
+use iterators::ToPtrVec;
+
+/// The corners of a triangle with their vertex ids, counterclockwise. None for other faces
+fn triangle_with_ids(face: & Face) -> Option<([u32; 3], [Point3<f32>; 3])> {
+  let corners = face.triangle()?;
+  let ids: Vec<u32> = face.adjacent_verts().filter_map(|v| v.upgrade()).map(|v| v.borrow().id).collect();
+  if ids.len() != 3 { return None; }
+  Some(([ids[0], ids[1], ids[2]], corners))
+}
+
+/// Cotangent of the angle between the vectors
+fn cot(a: Vector3<f32>, b: Vector3<f32>) -> f32 {
+  let sin = a.cross(b).magnitude();
+  if sin > 0.0 { a.dot(b) / sin } else { 0.0 }
+}
+
+/// The field's value at both ends of the edge, origin first
+fn edge_values(edge: & EdgeRc, field: & dyn Fn(u32) -> Option<f32>) -> Option<(f32, f32)> {
+  let edge_b = edge.borrow();
+  let (origin, target) = (edge_b.get_origin()?, edge_b.get_target()?);
+  let (origin_value, target_value) = (field(origin.borrow().id)?, field(target.borrow().id)?);
+  Some((origin_value, target_value))
+}
+
+/// Where the field crosses the value along the edge. Measured from the lower end,
+/// so that both halves of an edge give exactly the same point
+fn crossing_point(edge: & EdgeRc, field: & dyn Fn(u32) -> Option<f32>, value: f32) -> Option<Point3<f32>> {
+  let (origin_value, target_value) = edge_values(edge, field)?;
+  let edge_b = edge.borrow();
+  let (origin, target) = (edge_b.get_origin()?.borrow().get_pos(), edge_b.get_target()?.borrow().get_pos());
+  let ((low, low_value), (high, high_value)) =
+    if origin_value <= target_value { ((origin, origin_value), (target, target_value)) } else { ((target, target_value), (origin, origin_value)) };
+  let t = if high_value > low_value { (value - low_value) / (high_value - low_value) } else { 0.0 };
+  Some(low + (high - low) * t)
+}
+
+/// Traces the isolines of the field at the value. Vertices right on the value count as above it.
+/// Each line goes along a chain of edges whose origin is above and target below, its "down" edges:
+/// across each face from the down edge to the next edge which comes back up, then over that edge's pair,
+/// which is the down edge of the next face. Lines which run into a border or a face with a missing value
+/// are open; the rest close up, and end with their first point again
+pub(crate) fn trace_isolines(mesh: & HalfEdgeMesh, field: & dyn Fn(u32) -> Option<f32>, value: f32) -> Vec<Vec<Point3<f32>>> {
+  let is_down = |edge: & EdgeRc| edge_values(edge, field).is_some_and(|(o, t)| o >= value && t < value);
+  let is_up = |edge: & EdgeRc| edge_values(edge, field).is_some_and(|(o, t)| o < value && t >= value);
+  // Faces with the field known at every corner. Lines only pass through these
+  let complete = |edge: & EdgeRc| -> bool {
+    let face = match edge.borrow().get_face() { Some(face) => face, None => return false };
+    let face_b = face.borrow();
+    face_b.adjacent_verts().all(|v| v.upgrade().is_some_and(|v| field(v.borrow().id).is_some()))
+  };
+  // The next face's down edge, over the pair of an up edge
+  let across = |up: & EdgeRc| up.borrow().get_pair().filter(|pair| complete(pair));
+
+  let mut edge_ids: Vec<u32> = mesh.edges.keys().cloned().collect();
+  edge_ids.sort();
+  let downs: Vec<EdgeRc> = edge_ids.iter().map(|id| mesh.edges[id].clone()).filter(|e| is_down(e) && complete(e)).collect();
+
+  let mut visited: HashSet<u32> = HashSet::new();
+  let mut lines: Vec<Vec<Point3<f32>>> = Vec::new();
+  let trace = |start: & EdgeRc, visited: &mut HashSet<u32>| -> Option<Vec<Point3<f32>>> {
+    let start_id = start.borrow().id;
+    let mut points: Vec<Point3<f32>> = vec![crossing_point(start, field, value)?];
+    let mut down = start.clone();
+    loop {
+      let down_id = down.borrow().id;
+      visited.insert(down_id);
+      let mut up = down.borrow().get_next()?;
+      while !is_up(& up) {
+        if up.borrow().id == down_id { return None; }
+        let next = up.borrow().get_next()?;
+        up = next;
+      }
+      let point = crossing_point(& up, field, value)?;
+      // Lines through a vertex on the value cross both of its edges at the vertex
+      if points.last() != Some(& point) { points.push(point); }
+
+      down = match across(& up) { Some(pair) => pair, None => break };
+      let next_id = down.borrow().id;
+      if next_id == start_id {
+        if points.first() != points.last() { points.push(points[0]); }
+        break;
+      }
+      if visited.contains(& next_id) { break; }
+    }
+    Some(points)
+  };
+
+  // Open lines first, from the down edges nothing leads into, then the closed loops left over
+  for start in downs.iter().filter(|d| d.borrow().get_pair().filter(|pair| complete(pair)).is_none()) {
+    if let Some(line) = trace(start, &mut visited) { lines.push(line); }
+  }
+  for start in & downs {
+    if visited.contains(& start.borrow().id) { continue; }
+    if let Some(line) = trace(start, &mut visited) { lines.push(line); }
+  }
+  lines.retain(|line| line.len() > 1);
+  lines
+}
+
+impl HalfEdgeMesh {
+  /// The gradient of a scalar field given at the vertices, on each triangle, by face id.
+  /// The field is linear over each triangle, so its gradient is constant there and lies in the triangle's plane.
+  /// Faces which aren't triangles, are degenerate, or have a corner missing from the field are left out
+  pub fn face_gradients(& self, field: & HashMap<u32, f32>) -> HashMap<u32, Vector3<f32>> {
+    let mut gradients: HashMap<u32, Vector3<f32>> = HashMap::with_capacity(self.faces.len());
+    for (&face_id, face) in & self.faces {
+      let (ids, corners) = match triangle_with_ids(& face.borrow()) { Some(t) => t, None => continue };
+      let values = match (field.get(& ids[0]), field.get(& ids[1]), field.get(& ids[2])) {
+        (Some(&a), Some(&b), Some(&c)) => [a, b, c],
+        _ => continue,
+      };
+      let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+      let double_area = normal.magnitude();
+      if double_area <= 0.0 { continue; }
+      let normal = normal / double_area;
+      // Each corner's value pushes the gradient toward it, across the edge opposite it
+      let gradient = (0..3).fold(Vector3::new(0.0, 0.0, 0.0), |sum, corner| {
+        let opposite = corners[(corner + 2) % 3] - corners[(corner + 1) % 3];
+        sum + normal.cross(opposite) * values[corner]
+      }) / double_area;
+      gradients.insert(face_id, gradient);
+    }
+    gradients
+  }
+
+  /// The divergence of a vector field given on the triangles, by face id, such as `face_gradients`,
+  /// at each vertex, by vertex id. This is the integrated divergence over the vertex's share of the area,
+  /// 1/2 the sum over its triangles of cot(θ1) (e1 · X) + cot(θ2) (e2 · X), for the two edges e1 and e2 out of
+  /// the vertex and the angles θ1 and θ2 opposite them. For the gradient of a field, it's minus the
+  /// `cotangent_laplacian` times the field. Faces missing from the vector field contribute nothing
+  pub fn vertex_divergence(& self, vectors: & HashMap<u32, Vector3<f32>>) -> HashMap<u32, f32> {
+    let mut divergence: HashMap<u32, f32> = self.vertices.keys().map(|&id| (id, 0.0)).collect();
+    for (face_id, face) in & self.faces {
+      let vector = match vectors.get(face_id) { Some(v) => *v, None => continue };
+      let (ids, corners) = match triangle_with_ids(& face.borrow()) { Some(t) => t, None => continue };
+      for corner in 0..3 {
+        let (j, k) = ((corner + 1) % 3, (corner + 2) % 3);
+        let to_j = corners[j] - corners[corner];
+        let to_k = corners[k] - corners[corner];
+        // The angle at k is opposite the edge to j, and the angle at j opposite the edge to k
+        let cot_k = cot(corners[corner] - corners[k], corners[j] - corners[k]);
+        let cot_j = cot(corners[corner] - corners[j], corners[k] - corners[j]);
+        *divergence.entry(ids[corner]).or_insert(0.0) += 0.5 * (cot_k * to_j.dot(vector) + cot_j * to_k.dot(vector));
+      }
+    }
+    divergence
+  }
+
+  /// The lines where a scalar field given at the vertices, by vertex id, equals the value, interpolating
+  /// linearly along the edges. Lines which meet a border, or a vertex missing from the field, are open;
+  /// closed lines end with their first point again. On a closed mesh with outward faces,
+  /// each line runs counterclockwise around the region above the value, seen from outside
+  pub fn isolines(& self, field: & HashMap<u32, f32>, value: f32) -> Vec<Vec<Point3<f32>>> {
+    trace_isolines(self, & |id| field.get(& id).cloned(), value)
+  }
+
+  /// The isolines of a field known at every vertex, at each of the values in turn, such as elevation contours
+  /// of a terrain with `&|id| mesh.vert(id).map_or(0.0, |v| v.borrow().get_pos().y)`. Marches across the faces,
+  /// interpolating the field along the edges, and follows pair links from face to face to chain the
+  /// crossings into ordered polylines. Lines which meet a border are open, and closed lines end with
+  /// their first point again, as with `isolines`. Values which aren't finite are skipped
+  pub fn extract_isolines(& self, field: & impl Fn(u32) -> f32, values: & [f32]) -> Vec<Vec<Point3<f32>>> {
+    values.iter()
+      .filter(|value| value.is_finite())
+      .flat_map(|&value| trace_isolines(self, & |id| Some(field(id)), value))
+      .collect()
+  }
+
+  /// Cuts the mesh along the isoline of a field known at every vertex, so that the line is made of actual
+  /// edges: each edge where the field crosses the value is split where it crosses, and each face the line
+  /// goes through is split between the two places it crosses. Triangles are split into triangles.
+  /// Faces where the line crosses more than twice, like saddles in a quad, are left whole.
+  /// Afterwards every face is entirely on one side of the line, which makes it easy to separate the regions
+  /// above and below, e.g. elevation bands. Returns the vertices and both halves of the edges along the line,
+  /// including any which were already exactly on the value
+  pub fn cut_along_isoline(&mut self, field: & impl Fn(u32) -> f32, value: f32) -> Result<Selection, &'static str> {
+    if !value.is_finite() { return Err("Isoline value has to be finite"); }
+    let values: HashMap<u32, f32> = self.vertices.keys().map(|&id| (id, field(id))).collect();
+    let mut on_line: HashSet<u32> = values.iter().filter(|&(_, &v)| v == value).map(|(&id, _)| id).collect();
+
+    // Edges the field crosses, each pair once, and how far along from the origin
+    let mut edge_ids: Vec<u32> = self.edges.keys().cloned().collect();
+    edge_ids.sort();
+    let mut seen: HashSet<u32> = HashSet::new();
+    let mut crossings: Vec<(EdgeRc, f32)> = Vec::new();
+    for id in edge_ids {
+      if !seen.insert(id) { continue; }
+      let edge = self.edges[& id].clone();
+      if let Some(pair) = edge.borrow().get_pair() { seen.insert(pair.borrow().id); }
+      let (origin_value, target_value) = match edge_values(& edge, & |id| values.get(& id).cloned()) { Some(v) => v, None => continue };
+      if (origin_value < value) != (target_value < value) && origin_value != value && target_value != value {
+        crossings.push((edge, (value - origin_value) / (target_value - origin_value)));
+      }
+    }
+    let num_crossings = crossings.len();
+    for (edge, tval) in crossings {
+      if tval > 0.0 && tval < 1.0 {
+        on_line.insert(self.split_edge(& edge, tval)?.borrow().id);
+      } else {
+        // Rounded onto an end, which is as good as on the line
+        let end = if tval <= 0.0 { edge.borrow().get_origin() } else { edge.borrow().get_target() };
+        if let Some(end) = end { on_line.insert(end.borrow().id); }
+      }
+    }
+
+    // Faces which still have corners on both sides, split between the two corners on the line where the sides change
+    let side = |id: u32| if on_line.contains(& id) { 0 } else if values[& id] < value { -1 } else { 1 };
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+    let mut num_split = 0;
+    for face_id in face_ids {
+      let face = self.faces[& face_id].clone();
+      let corners = face.borrow().adjacent_verts().to_ptr_vec();
+      let sides: Vec<i32> = corners.iter().map(|v| side(v.borrow().id)).collect();
+      if !sides.contains(& 1) || !sides.contains(& -1) { continue; }
+      let len = corners.len();
+      let changes: Vec<usize> = (0..len)
+        .filter(|&idx| sides[idx] == 0 && {
+          let before = (1..len).map(|back| sides[(idx + len - back) % len]).find(|&s| s != 0);
+          let after = (1..len).map(|ahead| sides[(idx + ahead) % len]).find(|&s| s != 0);
+          before != after
+        })
+        .collect();
+      if changes.len() != 2 { continue; }
+      let (va, vb) = (& corners[changes[0]], & corners[changes[1]]);
+      if changes[1] - changes[0] == 1 || (changes[0] == 0 && changes[1] == len - 1) { continue; }
+      if vert_neighbor_ids(va).contains(& vb.borrow().id) { continue; }
+      self.split_face(& face, va, vb)?;
+      num_split += 1;
+    }
+
+    let mut selection = Selection::empty();
+    for edge in self.edges.values() {
+      let edge_b = edge.borrow();
+      if let (Some(origin), Some(target)) = (edge_b.get_origin(), edge_b.get_target()) {
+        if on_line.contains(& origin.borrow().id) && on_line.contains(& target.borrow().id) { selection.edges.insert(edge_b.id); }
+      }
+    }
+    selection.verts = on_line;
+    trace!("cut_along_isoline: split {} edges and {} faces at {}", num_crossings, num_split, value);
+    self.check_after("cut_along_isoline");
+    Ok(selection)
+  }
+}
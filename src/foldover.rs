@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::fmt;
+
+use cgmath::{Point3, Vector3, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::VertRc;
+
+/// Why `HalfEdgeMesh::try_move_vert` refused a move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldoverError {
+  /// The vertex which would have moved
+  pub vert_id: u32,
+  /// Faces around it which would have been turned over or flattened, in order of id
+  pub face_ids: Vec<u32>,
+}
+
+impl fmt::Display for FoldoverError {
+  fn fmt(& self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Moving vertex {} would fold over faces {:?}", self.vert_id, self.face_ids)
+  }
+}
+
+impl Error for FoldoverError {}
+
+/// Sum of the normals of a triangle fan over the corners, twice the area of the polygon along its normal
+fn area_vector(corners: & [Point3<f32>]) -> Vector3<f32> {
+  let mut normal = Vector3::new(0.0, 0.0, 0.0);
+  for idx in 1..corners.len().saturating_sub(1) {
+    normal += (corners[idx] - corners[0]).cross(corners[idx + 1] - corners[0]);
+  }
+  normal
+}
+
+impl HalfEdgeMesh {
+  /// Faces around the vertex which would turn over, or be flattened to nothing, if it were at the position:
+  /// those whose normal would point away from, or at right angles to, where it points now.
+  /// Faces which already have no area can't be turned over, so are left out
+  pub fn foldover_faces(& self, vert: & VertRc, pos: Point3<f32>) -> Vec<u32> {
+    let vert_id = vert.borrow().id;
+    let mut folded: Vec<u32> = Vec::new();
+    for face in vert.borrow().adjacent_faces().filter_map(|f| f.upgrade()) {
+      let face_b = face.borrow();
+      let corners: Vec<VertRc> = face_b.adjacent_verts().filter_map(|v| v.upgrade()).collect();
+      let before: Vec<Point3<f32>> = corners.iter().map(|v| v.borrow().get_pos()).collect();
+      let after: Vec<Point3<f32>> = corners.iter().zip(& before)
+        .map(|(v, &old)| if v.borrow().id == vert_id { pos } else { old })
+        .collect();
+      let old_normal = area_vector(& before);
+      if old_normal.magnitude2() == 0.0 { continue; }
+      if area_vector(& after).dot(old_normal) <= 0.0 { folded.push(face_b.id); }
+    }
+    folded.sort();
+    folded.dedup();
+    folded
+  }
+
+  /// Moves the vertex, as `move_vert` does, unless that would turn over or flatten any of the faces around it,
+  /// which is what leaves a mesh tangled after smoothing or optimizing it. The faces around the vertex are
+  /// brought up to date after the move. Use `move_vert` to move it regardless
+  pub fn try_move_vert(&mut self, vert: & VertRc, pos: Point3<f32>) -> Result<(), FoldoverError> {
+    let face_ids = self.foldover_faces(vert, pos);
+    let vert_id = vert.borrow().id;
+    if !face_ids.is_empty() {
+      trace!("try_move_vert: refused to move vertex {}, folding faces {:?}", vert_id, face_ids);
+      return Err(FoldoverError { vert_id, face_ids });
+    }
+
+    self.move_vert(vert, pos);
+    for face in vert.borrow().adjacent_faces().filter_map(|f| f.upgrade()) { face.borrow_mut().compute_attrs(); }
+    Ok(())
+  }
+}
@@ -0,0 +1,175 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+
+use iterators::ToPtrVec;
+use ptr::{FaceRc, VertRc};
+
+/// Something a `Frontier` can walk over: faces, through the edges they share, or vertices, along their edges
+pub trait FrontierNode: Clone {
+  fn node_id(& self) -> u32;
+  fn neighbors(& self) -> Vec<Self>;
+}
+
+impl FrontierNode for FaceRc {
+  fn node_id(& self) -> u32 { self.borrow().id }
+
+  fn neighbors(& self) -> Vec<FaceRc> {
+    self.borrow().adjacent_edges().to_ptr_vec().iter()
+      .filter_map(|e| e.borrow().get_pair_face())
+      .collect()
+  }
+}
+
+impl FrontierNode for VertRc {
+  fn node_id(& self) -> u32 { self.borrow().id }
+
+  /// Along outgoing edges, and incoming border edges, which have no outgoing half
+  fn neighbors(& self) -> Vec<VertRc> {
+    self.borrow().neighbor_edges().into_iter().map(|(_, vert)| vert).collect()
+  }
+}
+
+/// Which node a `Frontier` visits next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontierOrder {
+  /// The one reached first, so the region grows in rings around the seeds
+  BreadthFirst,
+  /// The one reached last, so the region runs off as far as it can before backing up
+  DepthFirst,
+  /// The one with the lowest cost, as given by `Frontier::cost`, so the region grows like Dijkstra's algorithm
+  LowestCost,
+}
+
+/// Heap entry for `FrontierOrder::LowestCost`. Ordered so the cheapest is popped first, and among equals the oldest
+struct FrontierEntry<N> {
+  cost: f32,
+  seq: usize,
+  node: N,
+}
+
+impl<N> PartialEq for FrontierEntry<N> {
+  fn eq(& self, other: & FrontierEntry<N>) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl<N> Eq for FrontierEntry<N> {}
+
+impl<N> PartialOrd for FrontierEntry<N> {
+  fn partial_cmp(& self, other: & FrontierEntry<N>) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl<N> Ord for FrontierEntry<N> {
+  fn cmp(& self, other: & FrontierEntry<N>) -> Ordering {
+    other.cost.partial_cmp(& self.cost).unwrap_or(Ordering::Equal)
+      .then_with(|| other.seq.cmp(& self.seq))
+  }
+}
+
+type AcceptFn<'a, N> = Box<dyn FnMut(& N, & N) -> bool + 'a>;
+type BoundaryFn<'a, N> = Box<dyn FnMut(& N, & N) + 'a>;
+type CostFn<'a, N> = Box<dyn FnMut(& N, & N, f32) -> f32 + 'a>;
+
+/// Grows a region of faces or vertices outward from some seeds, the way `attach_point_for_faces` walks out to
+/// the horizon of the faces a point can see. Each time the region reaches a neighbor of a node in it, `accept`
+/// decides whether the neighbor joins; if not, `on_boundary` hears about it, so it sees every step across
+/// the edge of the region. Set the callbacks with the builder methods, then call `grow`:
+///
+/// `Frontier::new(FrontierOrder::BreadthFirst).accept(|_, face: & FaceRc| face.borrow().can_see(& point)).grow(& seeds)`
+pub struct Frontier<'a, N: FrontierNode> {
+  order: FrontierOrder,
+  accept: AcceptFn<'a, N>,
+  on_boundary: BoundaryFn<'a, N>,
+  cost: CostFn<'a, N>,
+}
+
+impl<'a, N: FrontierNode> Frontier<'a, N> {
+  /// A frontier which accepts every neighbor, and where every step costs 1
+  pub fn new(order: FrontierOrder) -> Frontier<'a, N> {
+    Frontier {
+      order,
+      accept: Box::new(|_, _| true),
+      on_boundary: Box::new(|_, _| {}),
+      cost: Box::new(|_, _, cost| cost + 1.0),
+    }
+  }
+
+  /// Called with a node in the region and a neighbor it reached, which joins the region if this returns true.
+  /// A neighbor which is turned down can still join later, from another node
+  pub fn accept<F>(mut self, accept: F) -> Frontier<'a, N> where F: FnMut(& N, & N) -> bool + 'a {
+    self.accept = Box::new(accept);
+    self
+  }
+
+  /// Called with a node in the region and a neighbor `accept` turned down
+  pub fn on_boundary<F>(mut self, on_boundary: F) -> Frontier<'a, N> where F: FnMut(& N, & N) + 'a {
+    self.on_boundary = Box::new(on_boundary);
+    self
+  }
+
+  /// For `FrontierOrder::LowestCost`, called with a node in the region, a neighbor, and the cost of the node,
+  /// to give the cost of the neighbor through it. Seeds cost 0. Costs shouldn't go down along the way,
+  /// or nodes may be visited before their cheapest route is found
+  pub fn cost<F>(mut self, cost: F) -> Frontier<'a, N> where F: FnMut(& N, & N, f32) -> f32 + 'a {
+    self.cost = Box::new(cost);
+    self
+  }
+
+  /// Grows the region from the seeds, which are always in it, and returns every node in it
+  /// in the order they were visited
+  pub fn grow(&mut self, seeds: & [N]) -> Vec<N> {
+    let mut visited: HashSet<u32> = HashSet::new();
+    let mut region: Vec<N> = Vec::new();
+    match self.order {
+      FrontierOrder::BreadthFirst | FrontierOrder::DepthFirst => {
+        // Breadth first, nodes are marked as they're queued, so each is only accepted once
+        let mut queued: HashSet<u32> = HashSet::new();
+        let mut queue: VecDeque<N> = VecDeque::new();
+        for seed in seeds {
+          if queued.insert(seed.node_id()) { queue.push_back(seed.clone()); }
+        }
+        loop {
+          let node = match if self.order == FrontierOrder::BreadthFirst { queue.pop_front() } else { queue.pop_back() } {
+            Some(node) => node,
+            None => break,
+          };
+          // Depth first, a node can be queued again from deeper in before it's reached
+          if !visited.insert(node.node_id()) { continue; }
+          for next in node.neighbors() {
+            let next_id = next.node_id();
+            if visited.contains(& next_id) { continue; }
+            if self.order == FrontierOrder::BreadthFirst && queued.contains(& next_id) { continue; }
+            if (self.accept)(& node, & next) {
+              queued.insert(next_id);
+              queue.push_back(next);
+            } else {
+              (self.on_boundary)(& node, & next);
+            }
+          }
+          region.push(node);
+        }
+      },
+      FrontierOrder::LowestCost => {
+        let mut heap: BinaryHeap<FrontierEntry<N>> = BinaryHeap::new();
+        let mut seq = 0;
+        for seed in seeds {
+          heap.push(FrontierEntry { cost: 0.0, seq, node: seed.clone() });
+          seq += 1;
+        }
+        while let Some(FrontierEntry { cost, node, .. }) = heap.pop() {
+          if !visited.insert(node.node_id()) { continue; }
+          for next in node.neighbors() {
+            if visited.contains(& next.node_id()) { continue; }
+            if (self.accept)(& node, & next) {
+              let next_cost = (self.cost)(& node, & next, cost);
+              heap.push(FrontierEntry { cost: next_cost, seq, node: next });
+              seq += 1;
+            } else {
+              (self.on_boundary)(& node, & next);
+            }
+          }
+          region.push(node);
+        }
+      },
+    }
+    region
+  }
+}
@@ -0,0 +1,629 @@
+use std::collections::HashSet;
+
+use cgmath::{Point2, Point3, Vector3, Vector4, Matrix4, EuclideanSpace, InnerSpace};
+use approx::ApproxEq;
+
+/// Axis-aligned bounding box.
+/// An empty box (containing no points) is represented by min > max on every axis,
+/// so that extending it by any point produces a box containing only that point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+  pub min: Point3<f32>,
+  pub max: Point3<f32>,
+}
+
+impl Aabb {
+  pub fn new(min: Point3<f32>, max: Point3<f32>) -> Aabb {
+    Aabb { min, max }
+  }
+
+  /// A box containing nothing
+  pub fn empty() -> Aabb {
+    Aabb {
+      min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+      max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    }
+  }
+
+  /// The smallest box containing all of the points
+  pub fn from_points<'a, I>(points: I) -> Aabb where I: IntoIterator<Item=&'a Point3<f32>> {
+    let mut aabb = Aabb::empty();
+    for point in points {
+      aabb.extend(point);
+    }
+    aabb
+  }
+
+  pub fn is_empty(& self) -> bool {
+    self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+  }
+
+  /// Grow the box so that it contains the point
+  pub fn extend(&mut self, point: & Point3<f32>) {
+    self.min.x = self.min.x.min(point.x);
+    self.min.y = self.min.y.min(point.y);
+    self.min.z = self.min.z.min(point.z);
+    self.max.x = self.max.x.max(point.x);
+    self.max.y = self.max.y.max(point.y);
+    self.max.z = self.max.z.max(point.z);
+  }
+
+  /// The smallest box containing both boxes
+  pub fn union(& self, other: & Aabb) -> Aabb {
+    Aabb {
+      min: Point3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+      max: Point3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+    }
+  }
+
+  /// The box where both boxes overlap, or None if they don't overlap
+  pub fn intersection(& self, other: & Aabb) -> Option<Aabb> {
+    let overlap = Aabb {
+      min: Point3::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y), self.min.z.max(other.min.z)),
+      max: Point3::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y), self.max.z.min(other.max.z)),
+    };
+    if overlap.is_empty() { None } else { Some(overlap) }
+  }
+
+  /// Checks if the boxes overlap. Boxes which only touch at their surfaces count as overlapping
+  pub fn intersects(& self, other: & Aabb) -> bool {
+    self.min.x <= other.max.x && other.min.x <= self.max.x &&
+    self.min.y <= other.max.y && other.min.y <= self.max.y &&
+    self.min.z <= other.max.z && other.min.z <= self.max.z
+  }
+
+  /// Checks if the point is inside the box, or on its surface
+  pub fn contains(& self, point: & Point3<f32>) -> bool {
+    self.min.x <= point.x && point.x <= self.max.x &&
+    self.min.y <= point.y && point.y <= self.max.y &&
+    self.min.z <= point.z && point.z <= self.max.z
+  }
+
+  /// Checks if the other box is entirely inside this one
+  pub fn contains_aabb(& self, other: & Aabb) -> bool {
+    other.is_empty() || (self.contains(& other.min) && self.contains(& other.max))
+  }
+
+  pub fn center(& self) -> Point3<f32> {
+    Point3::new((self.min.x + self.max.x) * 0.5, (self.min.y + self.max.y) * 0.5, (self.min.z + self.max.z) * 0.5)
+  }
+
+  /// The extent of the box along each axis
+  pub fn size(& self) -> Vector3<f32> {
+    if self.is_empty() { Vector3::new(0.0, 0.0, 0.0) } else { self.max - self.min }
+  }
+
+  /// Distance from the point to the nearest point of the box. Zero inside the box,
+  /// infinite for an empty box
+  pub fn distance_to_point(& self, point: & Point3<f32>) -> f32 {
+    if self.is_empty() { return f32::INFINITY; }
+    let mut dist2 = 0.0;
+    for axis in 0..3 {
+      let outside = (self.min[axis] - point[axis]).max(point[axis] - self.max[axis]).max(0.0);
+      dist2 += outside * outside;
+    }
+    dist2.sqrt()
+  }
+}
+
+/// Oriented bounding box: a box around `center`, turned to line up with `axes`, which are unit length and
+/// at right angles to each other, reaching `half_extents[i]` along `axes[i]` either way
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obb {
+  pub center: Point3<f32>,
+  pub axes: [Vector3<f32>; 3],
+  pub half_extents: Vector3<f32>,
+}
+
+impl Obb {
+  /// The smallest box lined up with the axes which contains all of the points, or None if there are none
+  pub fn from_points_with_axes<'a, I>(points: I, axes: [Vector3<f32>; 3]) -> Option<Obb> where I: IntoIterator<Item=&'a Point3<f32>> {
+    let mut local = Aabb::empty();
+    for point in points {
+      let p = point.to_vec();
+      local.extend(& Point3::new(p.dot(axes[0]), p.dot(axes[1]), p.dot(axes[2])));
+    }
+    if local.is_empty() { return None; }
+    let mid = local.center();
+    let center = Point3::from_vec(axes[0] * mid.x + axes[1] * mid.y + axes[2] * mid.z);
+    Some(Obb { center, axes, half_extents: local.size() / 2.0 })
+  }
+
+  pub fn volume(& self) -> f32 {
+    8.0 * self.half_extents.x * self.half_extents.y * self.half_extents.z
+  }
+
+  /// Checks if the point is inside the box, or on its surface, within a tolerance
+  /// for the rounding in turning points into and out of the box's frame
+  pub fn contains(& self, point: & Point3<f32>) -> bool {
+    let offset = point - self.center;
+    let tolerance = 4.0 * f32::default_epsilon() * (1.0 + self.center.to_vec().magnitude() + self.half_extents.magnitude());
+    (0..3).all(|axis| offset.dot(self.axes[axis]).abs() <= self.half_extents[axis] + tolerance)
+  }
+
+  /// The eight corners of the box. Bit i of the index picks the positive end of axis i
+  pub fn corners(& self) -> [Point3<f32>; 8] {
+    let mut corners = [self.center; 8];
+    for (idx, corner) in corners.iter_mut().enumerate() {
+      for axis in 0..3 {
+        let sign = if idx & (1 << axis) != 0 { 1.0 } else { -1.0 };
+        *corner += self.axes[axis] * (sign * self.half_extents[axis]);
+      }
+    }
+    corners
+  }
+
+  /// The axis-aligned box around this one
+  pub fn aabb(& self) -> Aabb {
+    Aabb::from_points(self.corners().iter())
+  }
+}
+
+/// Eigenvalues and unit eigenvectors of the symmetric matrix, by cyclic Jacobi rotations,
+/// sorted from the largest eigenvalue to the smallest. The eigenvectors form a right-handed frame
+pub fn symmetric_eigen(matrix: & [[f32; 3]; 3]) -> ([f32; 3], [Vector3<f32>; 3]) {
+  let mut a: [[f64; 3]; 3] = [[0.0; 3]; 3];
+  for row in 0..3 {
+    for col in 0..3 { a[row][col] = f64::from(matrix[row][col] + matrix[col][row]) / 2.0; }
+  }
+  let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+  for _ in 0..50 {
+    let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+    if off < 1e-24 { break; }
+    for &(p, q) in & [(0, 1), (0, 2), (1, 2)] {
+      if a[p][q].abs() < 1e-30 { continue; }
+      // The rotation in the p-q plane which zeroes a[p][q]
+      let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+      let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+      let t = if theta == 0.0 { 1.0 } else { t };
+      let c = 1.0 / (t * t + 1.0).sqrt();
+      let s = t * c;
+      for row in a.iter_mut() {
+        let (akp, akq) = (row[p], row[q]);
+        row[p] = c * akp - s * akq;
+        row[q] = s * akp + c * akq;
+      }
+      let (row_p, row_q) = (a[p], a[q]);
+      for (k, (apk, aqk)) in row_p.iter().zip(& row_q).enumerate() {
+        a[p][k] = c * apk - s * aqk;
+        a[q][k] = s * apk + c * aqk;
+      }
+      for row in v.iter_mut() {
+        let (vp, vq) = (row[p], row[q]);
+        row[p] = c * vp - s * vq;
+        row[q] = s * vp + c * vq;
+      }
+    }
+  }
+
+  let mut order = [0, 1, 2];
+  order.sort_by(|&i, &j| a[j][j].total_cmp(& a[i][i]));
+  let values = order.map(|i| a[i][i] as f32);
+  let mut vectors = order.map(|i| Vector3::new(v[0][i] as f32, v[1][i] as f32, v[2][i] as f32).normalize());
+  vectors[2] = vectors[0].cross(vectors[1]).normalize();
+  (values, vectors)
+}
+
+/// Moller-Trumbore intersection of the line origin + t * dir with a triangle.
+/// Returns (t, u, v), where u and v are the barycentric weights of the second and third corners
+fn line_triangle(origin: Point3<f32>, dir: Vector3<f32>, tri: & [Point3<f32>; 3]) -> Option<(f32, f32, f32)> {
+  let edge_1 = tri[1] - tri[0];
+  let edge_2 = tri[2] - tri[0];
+  let p_vec = dir.cross(edge_2);
+  let det = edge_1.dot(p_vec);
+
+  // Line is parallel to the triangle
+  if det.abs() < f32::default_epsilon() { return None; }
+
+  let inv_det = 1.0 / det;
+  let t_vec = origin - tri[0];
+  let u = t_vec.dot(p_vec) * inv_det;
+  if !(0.0..=1.0).contains(& u) { return None; }
+
+  let q_vec = t_vec.cross(edge_1);
+  let v = dir.dot(q_vec) * inv_det;
+  if v < 0.0 || u + v > 1.0 { return None; }
+
+  Some((edge_2.dot(q_vec) * inv_det, u, v))
+}
+
+/// Slab test of the line origin + t * dir against a box.
+/// Returns the entry and exit parameters, which may be negative
+fn line_aabb(origin: Point3<f32>, dir: Vector3<f32>, aabb: & Aabb) -> Option<(f32, f32)> {
+  if aabb.is_empty() { return None; }
+
+  let mut t_enter = f32::NEG_INFINITY;
+  let mut t_exit = f32::INFINITY;
+
+  for axis in 0..3 {
+    if dir[axis] == 0.0 {
+      // Parallel to this slab, so the origin must be between the planes
+      if origin[axis] < aabb.min[axis] || origin[axis] > aabb.max[axis] { return None; }
+    } else {
+      let inv = 1.0 / dir[axis];
+      let t_a = (aabb.min[axis] - origin[axis]) * inv;
+      let t_b = (aabb.max[axis] - origin[axis]) * inv;
+      t_enter = t_enter.max(t_a.min(t_b));
+      t_exit = t_exit.min(t_a.max(t_b));
+    }
+  }
+
+  if t_enter <= t_exit { Some((t_enter, t_exit)) } else { None }
+}
+
+/// An infinite plane, made up of the points p where normal . p == offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+  pub normal: Vector3<f32>,
+  pub offset: f32,
+}
+
+impl Plane {
+  /// The normal is normalized here, so it doesn't need to be passed in normalized, but it mustn't be zero
+  pub fn new(normal: Vector3<f32>, offset: f32) -> Plane {
+    let length = normal.magnitude();
+    debug_assert!(length > 0.0, "Plane normal is zero");
+    Plane { normal: normal / length, offset: offset / length }
+  }
+
+  /// The normal needn't be normalized, but it mustn't be zero
+  pub fn from_point_normal(point: Point3<f32>, normal: Vector3<f32>) -> Plane {
+    debug_assert!(normal.magnitude2() > 0.0, "Plane normal is zero");
+    let unit = normal.normalize();
+    Plane { normal: unit, offset: unit.dot(point.to_vec()) }
+  }
+
+  /// Plane through three points, which mustn't be in a line. The normal faces the side from which
+  /// the points appear in counterclockwise order
+  pub fn from_points(a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> Plane {
+    Plane::from_point_normal(a, (b - a).cross(c - a))
+  }
+
+  /// Positive in front of the plane (the side the normal points to), negative behind it
+  pub fn signed_distance(& self, point: & Point3<f32>) -> f32 {
+    self.normal.dot(point.to_vec()) - self.offset
+  }
+
+  /// Checks if the point is on the plane, within a small tolerance
+  pub fn contains(& self, point: & Point3<f32>) -> bool {
+    self.signed_distance(point).abs() <= f32::default_epsilon()
+  }
+
+  /// The closest point on the plane to the point
+  pub fn project(& self, point: & Point3<f32>) -> Point3<f32> {
+    point - self.normal * self.signed_distance(point)
+  }
+
+  /// The same plane, facing the other way
+  pub fn flipped(& self) -> Plane {
+    Plane { normal: -self.normal, offset: -self.offset }
+  }
+
+  /// The segment where the plane cuts through the triangle, if it does.
+  /// Triangles lying in the plane don't produce a segment
+  pub fn intersect_triangle(& self, tri: & [Point3<f32>; 3]) -> Option<Segment> {
+    let dists = [self.signed_distance(& tri[0]), self.signed_distance(& tri[1]), self.signed_distance(& tri[2])];
+    let mut crossings: Vec<Point3<f32>> = Vec::new();
+
+    for idx in 0..3 {
+      let next = (idx + 1) % 3;
+      let (d_a, d_b) = (dists[idx], dists[next]);
+      if d_a == 0.0 {
+        crossings.push(tri[idx]);
+      } else if (d_a < 0.0 && d_b > 0.0) || (d_a > 0.0 && d_b < 0.0) {
+        let t = d_a / (d_a - d_b);
+        crossings.push(tri[idx] + (tri[next] - tri[idx]) * t);
+      }
+    }
+
+    if crossings.len() == 2 { Some(Segment::new(crossings[0], crossings[1])) } else { None }
+  }
+
+  /// Checks if the plane passes through the box
+  pub fn intersects_aabb(& self, aabb: & Aabb) -> bool {
+    if aabb.is_empty() { return false; }
+    let half = aabb.size() * 0.5;
+    // Projected "radius" of the box onto the plane normal
+    let radius = half.x * self.normal.x.abs() + half.y * self.normal.y.abs() + half.z * self.normal.z.abs();
+    self.signed_distance(& aabb.center()).abs() <= radius
+  }
+}
+
+/// A half-infinite line, starting at origin and extending along dir
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+  pub origin: Point3<f32>,
+  pub dir: Vector3<f32>,
+}
+
+impl Ray {
+  /// The direction is normalized here, so ray parameters are distances
+  pub fn new(origin: Point3<f32>, dir: Vector3<f32>) -> Ray {
+    Ray { origin, dir: dir.normalize() }
+  }
+
+  /// Ray through a point on the screen, for picking with a mouse cursor.
+  /// ndc_x and ndc_y are normalized device coordinates (-1 to 1, with y pointing up),
+  /// and the matrix is the inverse of the camera's projection * view matrix.
+  /// The ray starts on the near plane and points toward the far plane
+  pub fn from_screen(ndc_x: f32, ndc_y: f32, inv_view_proj: & Matrix4<f32>) -> Ray {
+    let unproject = |ndc_z: f32| {
+      let clip = inv_view_proj * Vector4::new(ndc_x, ndc_y, ndc_z, 1.0);
+      Point3::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+    };
+    let near = unproject(-1.0);
+    let far = unproject(1.0);
+    Ray::new(near, far - near)
+  }
+
+  /// The point at distance t along the ray
+  pub fn at(& self, t: f32) -> Point3<f32> {
+    self.origin + self.dir * t
+  }
+
+  /// Closest point on the ray to the point
+  pub fn closest_point(& self, point: & Point3<f32>) -> Point3<f32> {
+    self.at((point - self.origin).dot(self.dir).max(0.0))
+  }
+
+  pub fn distance_to_point(& self, point: & Point3<f32>) -> f32 {
+    (point - self.closest_point(point)).magnitude()
+  }
+
+  /// Distance along the ray to the plane, if the ray hits it
+  pub fn intersect_plane(& self, plane: & Plane) -> Option<f32> {
+    let denom = plane.normal.dot(self.dir);
+    if denom.abs() < f32::default_epsilon() { return None; }
+    let t = -plane.signed_distance(& self.origin) / denom;
+    if t >= 0.0 { Some(t) } else { None }
+  }
+
+  /// Distance along the ray to the triangle, if the ray hits it.
+  /// Hits either side of the triangle
+  pub fn intersect_triangle(& self, tri: & [Point3<f32>; 3]) -> Option<f32> {
+    self.intersect_triangle_bary(tri).map(|(t, _)| t)
+  }
+
+  /// Like `intersect_triangle`, but also gives the barycentric coordinates of the hit point
+  pub fn intersect_triangle_bary(& self, tri: & [Point3<f32>; 3]) -> Option<(f32, [f32; 3])> {
+    line_triangle(self.origin, self.dir, tri)
+      .and_then(|(t, u, v)| if t >= 0.0 { Some((t, [1.0 - u - v, u, v])) } else { None })
+  }
+
+  /// Entry and exit distances of the ray through the box. If the ray starts inside
+  /// the box, the entry distance is 0
+  pub fn intersect_aabb(& self, aabb: & Aabb) -> Option<(f32, f32)> {
+    line_aabb(self.origin, self.dir, aabb)
+      .and_then(|(t_enter, t_exit)| if t_exit >= 0.0 { Some((t_enter.max(0.0), t_exit)) } else { None })
+  }
+}
+
+/// A finite line segment between two points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+  pub start: Point3<f32>,
+  pub end: Point3<f32>,
+}
+
+impl Segment {
+  pub fn new(start: Point3<f32>, end: Point3<f32>) -> Segment {
+    Segment { start, end }
+  }
+
+  pub fn length(& self) -> f32 {
+    (self.end - self.start).magnitude()
+  }
+
+  /// Point at parameter t, where 0 is the start and 1 is the end
+  pub fn at(& self, t: f32) -> Point3<f32> {
+    self.start + (self.end - self.start) * t
+  }
+
+  /// Parameter of the closest point on the segment to the point
+  pub fn closest_param(& self, point: & Point3<f32>) -> f32 {
+    let dir = self.end - self.start;
+    let len2 = dir.magnitude2();
+    if len2 == 0.0 { return 0.0; }
+    ((point - self.start).dot(dir) / len2).clamp(0.0, 1.0)
+  }
+
+  pub fn closest_point(& self, point: & Point3<f32>) -> Point3<f32> {
+    self.at(self.closest_param(point))
+  }
+
+  pub fn distance_to_point(& self, point: & Point3<f32>) -> f32 {
+    (point - self.closest_point(point)).magnitude()
+  }
+
+  /// Point where the segment crosses the plane, if it does
+  pub fn intersect_plane(& self, plane: & Plane) -> Option<Point3<f32>> {
+    let d_start = plane.signed_distance(& self.start);
+    let d_end = plane.signed_distance(& self.end);
+    if (d_start > 0.0 && d_end > 0.0) || (d_start < 0.0 && d_end < 0.0) || d_start == d_end { return None; }
+    Some(self.at(d_start / (d_start - d_end)))
+  }
+
+  /// Point where the segment passes through the triangle, if it does
+  pub fn intersect_triangle(& self, tri: & [Point3<f32>; 3]) -> Option<Point3<f32>> {
+    line_triangle(self.start, self.end - self.start, tri)
+      .and_then(|(t, _, _)| if (0.0..=1.0).contains(& t) { Some(self.at(t)) } else { None })
+  }
+
+  /// Checks if any part of the segment is inside the box
+  pub fn intersects_aabb(& self, aabb: & Aabb) -> bool {
+    line_aabb(self.start, self.end - self.start, aabb)
+      .is_some_and(|(t_enter, t_exit)| t_enter <= 1.0 && t_exit >= 0.0)
+  }
+}
+
+/// A closed polygon in the plane, as its corners in order. The last corner connects back to the first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Polygon2 {
+  pub points: Vec<Point2<f32>>,
+}
+
+impl Polygon2 {
+  pub fn new(points: Vec<Point2<f32>>) -> Polygon2 {
+    Polygon2 { points }
+  }
+
+  /// Area inside the polygon, positive if the corners run counterclockwise and negative if clockwise
+  pub fn signed_area(& self) -> f32 {
+    let count = self.points.len();
+    let mut doubled = 0.0;
+    for idx in 0..count {
+      let (a, b) = (self.points[idx], self.points[(idx + 1) % count]);
+      doubled += a.x * b.y - b.x * a.y;
+    }
+    doubled / 2.0
+  }
+
+  /// Even-odd test, so points inside holes of a self-overlapping polygon are outside
+  pub fn contains(& self, point: & Point2<f32>) -> bool {
+    let count = self.points.len();
+    let mut inside = false;
+    for idx in 0..count {
+      let (a, b) = (self.points[idx], self.points[(idx + count - 1) % count]);
+      if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (b.x - a.x) * (point.y - a.y) / (b.y - a.y) {
+        inside = !inside;
+      }
+    }
+    inside
+  }
+}
+
+/// Smallest angle of the triangle, in radians
+fn min_angle(a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> f32 {
+  let angle = |at: Point3<f32>, p: Point3<f32>, q: Point3<f32>| (p - at).angle(q - at).0;
+  angle(a, b, c).min(angle(b, c, a)).min(angle(c, a, b))
+}
+
+/// Triangulates the polygon by cutting off ears: corners which stick out, and don't have another corner
+/// inside the triangle they make with their neighbors. `ring` is indices into the points, counterclockwise
+/// around the polygon's Newell normal, so it works for polygons which aren't flat or convex. Corners may repeat,
+/// as where holes have been bridged in. With `best_ear` every corner is tried, and the ear with the widest
+/// smallest angle is cut first; otherwise the first ear found is, which is quicker for big polygons.
+/// If no corner makes a proper ear, as can happen for a very twisted polygon, it settles for the best convex
+/// corner, or failing that any corner. `allowed(a, c)` can rule out the cut from corner a to corner c,
+/// and the same cut is never made twice. Triangles are (before, ear, after), or None if every cut is ruled out
+pub(crate) fn ear_clip<F>(points: & [Point3<f32>], mut ring: Vec<usize>, best_ear: bool, mut allowed: F) -> Option<Vec<[usize; 3]>>
+  where F: FnMut(usize, usize) -> bool {
+  let mut tris: Vec<[usize; 3]> = Vec::new();
+  let mut cut: HashSet<(usize, usize)> = HashSet::new();
+  while ring.len() > 3 {
+    let len = ring.len();
+    // Newell's normal, of what's left of the polygon
+    let normal = (0..len).fold(Vector3::new(0.0, 0.0, 0.0), |sum, idx| {
+      let (a, b) = (points[ring[idx]], points[ring[(idx + 1) % len]]);
+      sum + Vector3::new((a.y - b.y) * (a.z + b.z), (a.z - b.z) * (a.x + b.x), (a.x - b.x) * (a.y + b.y))
+    });
+
+    let mut best: Option<(bool, bool, f32, usize)> = None;
+    for idx in 0..len {
+      let (prev, corner, next) = (ring[(idx + len - 1) % len], ring[idx], ring[(idx + 1) % len]);
+      if cut.contains(& (prev, next)) || !allowed(prev, next) { continue; }
+      let (a, b, c) = (points[prev], points[corner], points[next]);
+      let convex = (b - a).cross(c - b).dot(normal) > 0.0;
+      let inside = |p: Point3<f32>| {
+        (b - a).cross(p - a).dot(normal) >= 0.0 && (c - b).cross(p - b).dot(normal) >= 0.0 && (a - c).cross(p - c).dot(normal) >= 0.0
+      };
+      let is_ear = convex && ring.iter().all(|&other| other == prev || other == corner || other == next || !inside(points[other]));
+      let score = min_angle(a, b, c);
+      if best.is_none_or(|(best_ear, best_convex, best_score, _)| (is_ear, convex, score) > (best_ear, best_convex, best_score)) {
+        best = Some((is_ear, convex, score, idx));
+      }
+      if is_ear && !best_ear { break; }
+    }
+
+    let idx = best?.3;
+    let (prev, next) = (ring[(idx + len - 1) % len], ring[(idx + 1) % len]);
+    cut.insert((prev, next));
+    cut.insert((next, prev));
+    tris.push([prev, ring[idx], next]);
+    ring.remove(idx);
+  }
+  if ring.len() == 3 { tris.push([ring[0], ring[1], ring[2]]); }
+  Some(tris)
+}
+
+/// Checks if two triangles intersect, by checking whether any edge of
+/// either triangle passes through the other one
+pub fn triangles_intersect(tri_a: & [Point3<f32>; 3], tri_b: & [Point3<f32>; 3]) -> bool {
+  let crosses = |tri_l: & [Point3<f32>; 3], tri_r: & [Point3<f32>; 3]| {
+    (0..3).any(|idx| Segment::new(tri_l[idx], tri_l[(idx + 1) % 3]).intersect_triangle(tri_r).is_some())
+  };
+
+  crosses(tri_a, tri_b) || crosses(tri_b, tri_a)
+}
+
+/// Closest point on a triangle to a point (Ericson, Real-Time Collision Detection 5.1.5),
+/// along with its barycentric weights for the triangle's corners
+pub fn closest_point_on_triangle(point: & Point3<f32>, tri: & [Point3<f32>; 3]) -> (Point3<f32>, [f32; 3]) {
+  let (a, b, c) = (tri[0], tri[1], tri[2]);
+  let ab = b - a;
+  let ac = c - a;
+
+  // Corner regions, then edge regions, then the face itself
+  let ap = point - a;
+  let (d_1, d_2) = (ab.dot(ap), ac.dot(ap));
+  if d_1 <= 0.0 && d_2 <= 0.0 { return (a, [1.0, 0.0, 0.0]); }
+
+  let bp = point - b;
+  let (d_3, d_4) = (ab.dot(bp), ac.dot(bp));
+  if d_3 >= 0.0 && d_4 <= d_3 { return (b, [0.0, 1.0, 0.0]); }
+
+  let cp = point - c;
+  let (d_5, d_6) = (ab.dot(cp), ac.dot(cp));
+  if d_6 >= 0.0 && d_5 <= d_6 { return (c, [0.0, 0.0, 1.0]); }
+
+  let v_c = d_1 * d_4 - d_3 * d_2;
+  if v_c <= 0.0 && d_1 >= 0.0 && d_3 <= 0.0 {
+    let t = d_1 / (d_1 - d_3);
+    return (a + ab * t, [1.0 - t, t, 0.0]);
+  }
+
+  let v_b = d_5 * d_2 - d_1 * d_6;
+  if v_b <= 0.0 && d_2 >= 0.0 && d_6 <= 0.0 {
+    let t = d_2 / (d_2 - d_6);
+    return (a + ac * t, [1.0 - t, 0.0, t]);
+  }
+
+  let v_a = d_3 * d_6 - d_5 * d_4;
+  if v_a <= 0.0 && (d_4 - d_3) >= 0.0 && (d_5 - d_6) >= 0.0 {
+    let t = (d_4 - d_3) / ((d_4 - d_3) + (d_5 - d_6));
+    return (b + (c - b) * t, [0.0, 1.0 - t, t]);
+  }
+
+  let denom = 1.0 / (v_a + v_b + v_c);
+  let (v, w) = (v_b * denom, v_c * denom);
+  (a + ab * v + ac * w, [1.0 - v - w, v, w])
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Point3, Vector3};
+
+  use super::{Plane, ear_clip};
+
+  #[test]
+  #[cfg(debug_assertions)]
+  #[should_panic(expected = "Plane normal is zero")]
+  fn plane_with_zero_normal_is_rejected() {
+    Plane::from_point_normal(Point3::new(1.0, 2.0, 3.0), Vector3::new(0.0, 0.0, 0.0));
+  }
+
+  #[test]
+  fn ear_clip_covers_a_concave_polygon() {
+    // An L, which has a corner sticking in
+    let points: Vec<Point3<f32>> = [(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)]
+      .iter().map(|&(x, y)| Point3::new(x, y, 0.0)).collect();
+    for &best_ear in & [true, false] {
+      let tris = ear_clip(& points, (0..points.len()).collect(), best_ear, |_, _| true).unwrap();
+      assert_eq!(tris.len(), points.len() - 2);
+      let areas: Vec<f32> = tris.iter().map(|t| (points[t[1]] - points[t[0]]).cross(points[t[2]] - points[t[0]]).z / 2.0).collect();
+      // All turned the same way, and adding up to the L, so none overlap
+      assert!(areas.iter().all(|&area| area > 0.0));
+      assert!((areas.iter().sum::<f32>() - 3.0).abs() < 1e-5);
+    }
+  }
+}
@@ -0,0 +1,83 @@
+use std::collections::{BTreeSet, HashMap};
+
+use mesh::HalfEdgeMesh;
+
+/// Which elements of a mesh are next to each other, as plain adjacency lists,
+/// for graph algorithms like coloring, matching, or partitioning
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MeshGraph {
+  /// Id of the mesh element behind each node, in ascending order
+  pub ids: Vec<u32>,
+  /// Neighbors of each node, as indices into `ids`, in ascending order
+  pub neighbors: Vec<Vec<usize>>,
+}
+
+impl MeshGraph {
+  fn from_links(ids: Vec<u32>, links: Vec<(u32, u32)>) -> MeshGraph {
+    let index: HashMap<u32, usize> = ids.iter().enumerate().map(|(idx, &id)| (id, idx)).collect();
+    let mut sets: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); ids.len()];
+    for (a, b) in links {
+      if let (Some(&a), Some(&b)) = (index.get(& a), index.get(& b)) {
+        if a == b { continue; }
+        sets[a].insert(b);
+        sets[b].insert(a);
+      }
+    }
+    MeshGraph { ids, neighbors: sets.into_iter().map(|set| set.into_iter().collect()).collect() }
+  }
+
+  pub fn node_count(& self) -> usize { self.ids.len() }
+
+  pub fn edge_count(& self) -> usize { self.neighbors.iter().map(|n| n.len()).sum::<usize>() / 2 }
+
+  /// Each edge of the graph once, as a pair of node indices with the smaller first
+  pub fn edges(& self) -> Vec<(usize, usize)> {
+    self.neighbors.iter().enumerate()
+      .flat_map(|(a, around)| around.iter().filter(move |&&b| a < b).map(move |&b| (a, b)))
+      .collect()
+  }
+
+  /// Converts to a petgraph graph, with the element ids as node weights.
+  /// Node indices match the indices here
+  #[cfg(feature = "petgraph")]
+  pub fn to_petgraph(& self) -> ::petgraph::graph::UnGraph<u32, ()> {
+    let mut graph = ::petgraph::graph::UnGraph::with_capacity(self.node_count(), self.edge_count());
+    for &id in & self.ids { graph.add_node(id); }
+    for (a, b) in self.edges() {
+      graph.add_edge(::petgraph::graph::NodeIndex::new(a), ::petgraph::graph::NodeIndex::new(b), ());
+    }
+    graph
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Graph of the vertices, with a graph edge along each mesh edge
+  pub fn vertex_graph(& self) -> MeshGraph {
+    let links: Vec<(u32, u32)> = self.edges.values()
+      .filter_map(|e| {
+        let e_b = e.borrow();
+        match (e_b.get_origin(), e_b.get_target()) {
+          (Some(o), Some(t)) => Some((o.borrow().id, t.borrow().id)),
+          _ => None,
+        }
+      })
+      .collect();
+    MeshGraph::from_links(self.vertex_order(), links)
+  }
+
+  /// Graph of the faces, with a graph edge between faces which share a mesh edge (the dual graph)
+  pub fn face_graph(& self) -> MeshGraph {
+    let mut ids: Vec<u32> = self.faces.keys().cloned().collect();
+    ids.sort();
+    let links: Vec<(u32, u32)> = self.edges.values()
+      .filter_map(|e| {
+        let e_b = e.borrow();
+        match (e_b.get_face(), e_b.get_pair_face()) {
+          (Some(f), Some(p)) => Some((f.borrow().id, p.borrow().id)),
+          _ => None,
+        }
+      })
+      .collect();
+    MeshGraph::from_links(ids, links)
+  }
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+
+use cgmath::Point3;
+
+use mesh::HalfEdgeMesh;
+use units::LengthUnit;
+
+/// First bytes of every `.hem` file
+const HEM_MAGIC: [u8; 4] = *b"HEM\0";
+
+/// Version of the layout written by `to_hem_bytes`
+pub const HEM_VERSION: u32 = 1;
+
+/// Magic, version, units, and the vertex, face, and corner counts, each four bytes
+const HEADER_LEN: usize = 24;
+
+fn units_code(units: LengthUnit) -> u32 {
+  match units {
+    LengthUnit::Millimeter => 0,
+    LengthUnit::Centimeter => 1,
+    LengthUnit::Meter => 2,
+    LengthUnit::Inch => 3,
+  }
+}
+
+fn units_from_code(code: u32) -> Option<LengthUnit> {
+  match code {
+    0 => Some(LengthUnit::Millimeter),
+    1 => Some(LengthUnit::Centimeter),
+    2 => Some(LengthUnit::Meter),
+    3 => Some(LengthUnit::Inch),
+    _ => None,
+  }
+}
+
+fn read_u32(bytes: & [u8], at: usize) -> u32 {
+  u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// A `.hem` file's contents, read in place without copying them. Works just as well on a memory mapped file,
+/// so a large mesh can be walked, or loaded a piece at a time, without reading all of it.
+///
+/// The layout is little-endian, with every value four bytes:
+/// the magic `HEM\0`, the version, the units (millimeters, centimeters, meters, inches from 0),
+/// the vertex count V, the face count F, and the corner count C; then V positions as x, y, z floats;
+/// then F + 1 offsets, where face i's corners are entries offset[i] up to offset[i + 1] of the corners;
+/// then C vertex indices, counterclockwise around each face
+#[derive(Debug, Clone, Copy)]
+pub struct HemView<'a> {
+  bytes: &'a [u8],
+  units: LengthUnit,
+  num_vertices: usize,
+  num_faces: usize,
+}
+
+impl<'a> HemView<'a> {
+  /// Checks the header, and that the file is long enough for the counts in it.
+  /// The corner indices aren't checked until they're used
+  pub fn parse(bytes: &'a [u8]) -> Result<HemView<'a>, &'static str> {
+    if bytes.len() < HEADER_LEN || bytes[0..4] != HEM_MAGIC { return Err("Not a .hem file"); }
+    if read_u32(bytes, 4) != HEM_VERSION { return Err("Unsupported .hem version"); }
+    let units = units_from_code(read_u32(bytes, 8)).ok_or("Unknown units in .hem file")?;
+    let (num_vertices, num_faces, num_corners) = (read_u32(bytes, 12) as usize, read_u32(bytes, 16) as usize, read_u32(bytes, 20) as usize);
+
+    let expected = (num_vertices as u64 * 3 + num_faces as u64 + 1 + num_corners as u64) * 4 + HEADER_LEN as u64;
+    if (bytes.len() as u64) < expected { return Err(".hem file is cut short"); }
+    let view = HemView { bytes, units, num_vertices, num_faces };
+    if view.offset(num_faces) != num_corners { return Err(".hem face offsets don't match the corner count"); }
+    Ok(view)
+  }
+
+  pub fn units(& self) -> LengthUnit { self.units }
+
+  pub fn num_vertices(& self) -> usize { self.num_vertices }
+
+  pub fn num_faces(& self) -> usize { self.num_faces }
+
+  fn positions_start(& self) -> usize { HEADER_LEN }
+
+  fn offsets_start(& self) -> usize { self.positions_start() + self.num_vertices * 12 }
+
+  fn corners_start(& self) -> usize { self.offsets_start() + (self.num_faces + 1) * 4 }
+
+  fn offset(& self, face: usize) -> usize { read_u32(self.bytes, self.offsets_start() + face * 4) as usize }
+
+  /// Position of the vertex at the index. Panics if it's out of range
+  pub fn position(& self, vert: usize) -> Point3<f32> {
+    assert!(vert < self.num_vertices, "Vertex index out of range");
+    let at = self.positions_start() + vert * 12;
+    let coord = |idx: usize| f32::from_bits(read_u32(self.bytes, at + idx * 4));
+    Point3::new(coord(0), coord(1), coord(2))
+  }
+
+  /// Vertex indices of the face at the index. Fails if its offsets are out of order,
+  /// or it refers to a vertex which isn't there. Panics if the face index is out of range
+  pub fn face(& self, face: usize) -> Result<Vec<usize>, &'static str> {
+    assert!(face < self.num_faces, "Face index out of range");
+    let (from, to) = (self.offset(face), self.offset(face + 1));
+    if from > to || to > self.offset(self.num_faces) { return Err(".hem face offsets are out of order"); }
+    (from..to)
+      .map(|corner| {
+        let vert = read_u32(self.bytes, self.corners_start() + corner * 4) as usize;
+        if vert < self.num_vertices { Ok(vert) } else { Err(".hem face refers to a vertex which isn't there") }
+      })
+      .collect()
+  }
+
+  /// Builds the whole mesh
+  pub fn to_mesh(& self) -> Result<HalfEdgeMesh, &'static str> {
+    let positions: Vec<Point3<f32>> = (0..self.num_vertices).map(|idx| self.position(idx)).collect();
+    let faces: Vec<Vec<usize>> = (0..self.num_faces).map(|idx| self.face(idx)).collect::<Result<_, _>>()?;
+    let mut mesh = HalfEdgeMesh::from_polygons(& positions, & faces);
+    mesh.set_units(self.units);
+    Ok(mesh)
+  }
+}
+
+/// A memory mapped `.hem` file, whose contents are only read from disk as they're used
+#[cfg(feature = "mmap")]
+pub struct HemFile {
+  map: ::memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl HemFile {
+  /// Maps the file into memory, and checks its header. The file mustn't change while it's open
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<HemFile, &'static str> {
+    let file = fs::File::open(path).map_err(|_| "Couldn't open the .hem file")?;
+    // Safe as long as nothing else writes to the file while it's mapped, which `open` asks of the caller
+    let map = unsafe { ::memmap2::Mmap::map(& file) }.map_err(|_| "Couldn't map the .hem file into memory")?;
+    HemView::parse(& map)?;
+    Ok(HemFile { map })
+  }
+
+  pub fn view(& self) -> HemView<'_> {
+    HemView::parse(& self.map).expect("Header was checked when the file was opened")
+  }
+}
+
+impl HalfEdgeMesh {
+  /// The mesh in the `.hem` binary format (see `HemView`), with vertices and faces in id order, like `to_polygons`.
+  /// Much faster to write and read than a text format, and compact: a triangle mesh takes about
+  /// 44 bytes per vertex, since it has about twice as many faces
+  pub fn to_hem_bytes(& self) -> Vec<u8> {
+    let (positions, polygons) = self.to_polygons();
+    let num_corners: usize = polygons.iter().map(|p| p.len()).sum();
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(HEADER_LEN + (positions.len() * 3 + polygons.len() + 1 + num_corners) * 4);
+    bytes.extend_from_slice(& HEM_MAGIC);
+    for value in & [HEM_VERSION, units_code(self.units()), positions.len() as u32, polygons.len() as u32, num_corners as u32] {
+      bytes.extend_from_slice(& value.to_le_bytes());
+    }
+    for pos in & positions {
+      for coord in & [pos.x, pos.y, pos.z] { bytes.extend_from_slice(& coord.to_le_bytes()); }
+    }
+    let mut offset = 0u32;
+    bytes.extend_from_slice(& offset.to_le_bytes());
+    for polygon in & polygons {
+      offset += polygon.len() as u32;
+      bytes.extend_from_slice(& offset.to_le_bytes());
+    }
+    for &vert in polygons.iter().flatten() { bytes.extend_from_slice(& (vert as u32).to_le_bytes()); }
+    bytes
+  }
+
+  /// Reads a mesh from bytes in the `.hem` format
+  pub fn from_hem_bytes(bytes: & [u8]) -> Result<HalfEdgeMesh, &'static str> {
+    HemView::parse(bytes)?.to_mesh()
+  }
+
+  /// Writes the mesh to a `.hem` file, see `to_hem_bytes`
+  pub fn save_hem<P: AsRef<Path>>(& self, path: P) -> Result<(), &'static str> {
+    fs::write(path, self.to_hem_bytes()).map_err(|_| "Couldn't write the .hem file")
+  }
+
+  /// Reads a mesh from a `.hem` file. With the `mmap` feature, `HemFile` reads it in place instead
+  pub fn load_hem<P: AsRef<Path>>(path: P) -> Result<HalfEdgeMesh, &'static str> {
+    let bytes = fs::read(path).map_err(|_| "Couldn't read the .hem file")?;
+    HalfEdgeMesh::from_hem_bytes(& bytes)
+  }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::FaceRc;
+
+impl HalfEdgeMesh {
+  /// Builds the convex hull of the points, by starting from a tetrahedron of extreme points
+  /// and attaching every point outside the hull so far, farthest first.
+  /// Faces are triangles facing outward. Fails if the points are all (nearly) coplanar
+  pub fn convex_hull(points: & [Point3<f32>]) -> Result<HalfEdgeMesh, &'static str> {
+    if points.len() < 4 { return Err("A convex hull needs at least 4 points"); }
+
+    let by_x = |a: &&Point3<f32>, b: &&Point3<f32>| a.x.partial_cmp(& b.x).unwrap_or(::std::cmp::Ordering::Equal);
+    let p1 = *points.iter().min_by(by_x).ok_or("No points")?;
+    let p2 = *points.iter().max_by(by_x).ok_or("No points")?;
+    let size = p1.distance(p2).max(points.iter().map(|p| p.distance(p1)).fold(0.0, f32::max));
+    if size <= 0.0 { return Err("Points are all in the same place"); }
+    let eps = size * 1e-5;
+
+    let line = p2 - p1;
+    let line_dist = |p: & Point3<f32>| (p - p1).cross(line).magnitude() / line.magnitude().max(f32::MIN_POSITIVE);
+    let p3 = *points.iter().max_by(|a, b| line_dist(a).partial_cmp(& line_dist(b)).unwrap_or(::std::cmp::Ordering::Equal)).ok_or("No points")?;
+    if line_dist(& p3) <= eps { return Err("Points are all on a line"); }
+
+    let normal = line.cross(p3 - p1).normalize();
+    let plane_dist = |p: & Point3<f32>| (p - p1).dot(normal);
+    let p4 = *points.iter().max_by(|a, b| plane_dist(a).abs().partial_cmp(& plane_dist(b).abs()).unwrap_or(::std::cmp::Ordering::Equal)).ok_or("No points")?;
+    if plane_dist(& p4).abs() <= eps { return Err("Points are all in a plane"); }
+
+    // The tetrahedron's faces have to point away from its center
+    let center = Point3::centroid(& [p1, p2, p3, p4]);
+    let mut hull = HalfEdgeMesh::from_tetrahedron_pts(p4, p1, p2, p3);
+    if hull.faces.values().any(|f| f.borrow().directed_distance_to(& center) > 0.0) {
+      hull = HalfEdgeMesh::from_tetrahedron_pts(p4, p1, p3, p2);
+    }
+
+    let mut remaining: Vec<Point3<f32>> = points.to_vec();
+    loop {
+      // The farthest point outside of any face is certain to be on the hull
+      let mut farthest: Option<(f32, usize)> = None;
+      remaining.retain(|p| hull.faces.values().any(|f| f.borrow().directed_distance_to(p) > eps));
+      for (idx, point) in remaining.iter().enumerate() {
+        let dist = hull.faces.values().map(|f| f.borrow().directed_distance_to(point)).fold(f32::NEG_INFINITY, f32::max);
+        if farthest.is_none_or(|(d, _)| dist > d) { farthest = Some((dist, idx)); }
+      }
+
+      let point = match farthest { Some((_, idx)) => remaining.swap_remove(idx), None => break };
+      let visible: Vec<FaceRc> = hull.faces.values()
+        .filter(|f| f.borrow().directed_distance_to(& point) > eps)
+        .cloned()
+        .collect();
+      hull.attach_point_for_faces(point, & visible)?;
+    }
+
+    Ok(hull)
+  }
+
+  /// Distance from the point to the surface of a convex mesh, for a point inside it.
+  /// Negative for a point outside
+  pub fn convex_depth(& self, point: & Point3<f32>) -> f32 {
+    self.faces.values().map(|f| -f.borrow().directed_distance_to(point)).fold(f32::INFINITY, f32::min)
+  }
+
+  /// Expands a convex mesh by `margin` in every direction, for a physics collision margin.
+  /// Every vertex is copied outward along the normal of each face around it, and the hull
+  /// of the copies is returned: each face moves out by exactly `margin`, and the edges and corners
+  /// in between are beveled, staying inside the exact rounded Minkowski sum
+  pub fn inflate_convex(& self, margin: f32) -> Result<HalfEdgeMesh, &'static str> {
+    if margin < 0.0 { return Err("Margin can't be negative"); }
+
+    let mut points: Vec<Point3<f32>> = Vec::new();
+    for vert in self.vertices.values() {
+      let pos = vert.borrow().get_pos();
+      for out_edge in vert.borrow().outgoing_edges() {
+        if let Some(face) = out_edge.borrow().get_face() { points.push(pos + face.borrow().normal * margin); }
+      }
+    }
+
+    HalfEdgeMesh::convex_hull(& points)
+  }
+
+  /// Voronoi diagram on the unit sphere: a closed mesh whose faces are the cells of the points, each cell being
+  /// the part of the sphere closer to its point than to any other. Points are projected onto the sphere first.
+  /// The Delaunay triangulation of points on a sphere is their convex hull, so the cells are its dual:
+  /// each hull face gives a cell corner, at the center of its circumcircle, and each point's cell runs around
+  /// the hull faces at it. Cell edges are straight, so they cut a little inside the sphere between corners.
+  /// Points which (nearly) coincide with others, or lie (nearly) on the hull of the rest, get no cell.
+  /// Fails like `convex_hull`, e.g. if the points are all in one plane
+  pub fn spherical_voronoi(points: & [Point3<f32>]) -> Result<HalfEdgeMesh, &'static str> {
+    let on_sphere: Vec<Point3<f32>> = points.iter()
+      .filter(|p| p.to_vec().magnitude2() > 0.0)
+      .map(|p| Point3::from_vec(p.to_vec().normalize()))
+      .collect();
+    let hull = HalfEdgeMesh::convex_hull(& on_sphere)?;
+
+    // Neighboring faces through the same circle, like the two halves of a square, share a corner
+    let mut merged: HashMap<u32, u32> = hull.faces.keys().map(|&id| (id, id)).collect();
+    fn root(merged: & HashMap<u32, u32>, mut id: u32) -> u32 {
+      while merged[& id] != id { id = merged[& id]; }
+      id
+    }
+    let mut edge_ids: Vec<u32> = hull.edges.keys().cloned().collect();
+    edge_ids.sort();
+    for id in edge_ids {
+      let edge_b = hull.edges[& id].borrow();
+      if let (Some(face), Some(other)) = (edge_b.get_face(), edge_b.get_pair_face()) {
+        if face.borrow().normal.dot(other.borrow().normal) < 1.0 - 1e-6 { continue; }
+        let (a, b) = (root(& merged, face.borrow().id), root(& merged, other.borrow().id));
+        if a != b { merged.insert(a.max(b), a.min(b)); }
+      }
+    }
+
+    let mut corner_index: HashMap<u32, usize> = HashMap::new();
+    let mut corners: Vec<Point3<f32>> = Vec::new();
+    let mut face_ids: Vec<u32> = hull.faces.keys().cloned().collect();
+    face_ids.sort();
+    for id in face_ids {
+      let rep = root(& merged, id);
+      corner_index.entry(rep).or_insert_with(|| {
+        corners.push(Point3::from_vec(hull.faces[& rep].borrow().normal.normalize()));
+        corners.len() - 1
+      });
+    }
+
+    let mut vert_ids: Vec<u32> = hull.vertices.keys().cloned().collect();
+    vert_ids.sort();
+    let mut cells: Vec<Vec<usize>> = Vec::new();
+    for id in vert_ids {
+      let vert_b = hull.vertices[& id].borrow();
+      let mut cell: Vec<usize> = vert_b.outgoing_edges().iter()
+        .filter_map(|e| e.borrow().get_face())
+        .map(|f| corner_index[& root(& merged, f.borrow().id)])
+        .collect();
+      cell.dedup();
+      while cell.len() > 1 && cell.first() == cell.last() { cell.pop(); }
+      if cell.len() < 3 { continue; }
+
+      // The walk around a vertex goes clockwise, but check rather than rely on it
+      let pos = vert_b.get_pos().to_vec();
+      let winding: Vector3<f32> = (0..cell.len())
+        .map(|idx| corners[cell[idx]].to_vec().cross(corners[cell[(idx + 1) % cell.len()]].to_vec()))
+        .sum();
+      if winding.dot(pos) < 0.0 { cell.reverse(); }
+      cells.push(cell);
+    }
+
+    Ok(HalfEdgeMesh::from_polygons(& corners, & cells))
+  }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, Vector3, InnerSpace, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use kernel::EditableMesh;
+use util::OpTimer;
+
+impl HalfEdgeMesh {
+  /// Blows the mesh up like a balloon, with a little position-based physics: the edges are springs, which
+  /// want to stay the length they were at the start, and every face pushes its corners out along its normal,
+  /// harder the bigger it is, by `pressure` per unit area each step (negative to let the air out).
+  /// After each push, the springs pull each edge back toward its rest length by `stiffness`, from 0 for rubber
+  /// that stretches freely to 1 for an edge which snaps right back, so a stiffer balloon levels off smaller.
+  /// Border vertices are held in place, like the neck of a balloon. Runs for `steps` steps
+  pub fn inflate(&mut self, pressure: f32, stiffness: f32, steps: usize) {
+    inflate(self, pressure, stiffness, steps)
+  }
+}
+
+/// `HalfEdgeMesh::inflate` for any mesh kernel
+pub fn inflate<M: EditableMesh>(mesh: &mut M, pressure: f32, stiffness: f32, steps: usize) {
+  let start = OpTimer::start();
+  let stiffness = stiffness.clamp(0.0, 1.0);
+  let vert_ids = mesh.vert_ids();
+  let pinned: Vec<bool> = vert_ids.iter().map(|&id| mesh.is_boundary_vert(id)).collect();
+  let index: HashMap<u32, usize> = vert_ids.iter().enumerate().map(|(idx, &id)| (id, idx)).collect();
+
+  // Each spring once, with its rest length
+  let mut springs: Vec<(usize, usize, f32)> = Vec::new();
+  for edge in mesh.edge_ids() {
+    if let Some((origin, target)) = mesh.edge_verts(edge) {
+      let (a, b) = (index[& origin], index[& target]);
+      if a < b || mesh.edge_pair(edge).is_none() {
+        springs.push((a, b, mesh.vert_pos(origin).distance(mesh.vert_pos(target))));
+      }
+    }
+  }
+  let faces: Vec<Vec<usize>> = mesh.face_ids().into_iter()
+    .map(|f| mesh.face_verts(f).into_iter().map(|v| index[& v]).collect())
+    .collect();
+
+  let mut positions: Vec<Point3<f32>> = vert_ids.iter().map(|&id| mesh.vert_pos(id)).collect();
+  for _ in 0..steps {
+    // Pressure: each face's area-weighted normal, shared between its corners
+    let mut push: Vec<Vector3<f32>> = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for face in & faces {
+      if face.len() < 3 { continue; }
+      let area_normal = (1..face.len() - 1).fold(Vector3::new(0.0, 0.0, 0.0), |sum, idx| {
+        sum + (positions[face[idx]] - positions[face[0]]).cross(positions[face[idx + 1]] - positions[face[0]]) / 2.0
+      });
+      for &corner in face { push[corner] += area_normal * (pressure / face.len() as f32); }
+    }
+    for (idx, pos) in positions.iter_mut().enumerate() {
+      if !pinned[idx] { *pos += push[idx]; }
+    }
+
+    // Springs: every edge moves both its ends halfway toward its rest length, averaged over each vertex's edges
+    let mut pull: Vec<(Vector3<f32>, usize)> = vec![(Vector3::new(0.0, 0.0, 0.0), 0); positions.len()];
+    for &(a, b, rest) in & springs {
+      let along = positions[b] - positions[a];
+      let length = along.magnitude();
+      if length <= 0.0 { continue; }
+      let correction = along * (stiffness * (length - rest) / length / 2.0);
+      pull[a].0 += correction;
+      pull[b].0 -= correction;
+      pull[a].1 += 1;
+      pull[b].1 += 1;
+    }
+    for (idx, pos) in positions.iter_mut().enumerate() {
+      if !pinned[idx] && pull[idx].1 > 0 { *pos += pull[idx].0 / pull[idx].1 as f32; }
+    }
+  }
+
+  for (idx, &id) in vert_ids.iter().enumerate() { mesh.set_vert_pos(id, positions[idx]); }
+  debug!("inflate: {} steps over {} vertices, took {:?}", steps, vert_ids.len(), start.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Point3, InnerSpace};
+
+  use mesh::HalfEdgeMesh;
+  use kernel::{MeshTopology, MeshGeometry};
+  use super::inflate;
+
+  #[test]
+  fn pressure_pushes_the_middle_of_a_sheet_out() {
+    let positions: Vec<Point3<f32>> = (0..9).map(|idx| Point3::new((idx % 3) as f32, (idx / 3) as f32, 0.0)).collect();
+    let polygons = vec![vec![0, 1, 4, 3], vec![1, 2, 5, 4], vec![3, 4, 7, 6], vec![4, 5, 8, 7]];
+    let mut mesh = HalfEdgeMesh::from_polygons(& positions, & polygons);
+
+    inflate(&mut mesh, 0.05, 0.5, 20);
+    assert!(mesh.validate().is_ok());
+    for (idx, id) in mesh.vert_ids().into_iter().enumerate() {
+      let (before, after) = (positions[idx], mesh.vert_pos(id));
+      if mesh.is_boundary_vert(id) {
+        assert!((after - before).magnitude() < 1e-6);
+      } else {
+        assert!(after.z > 0.0);
+      }
+    }
+  }
+}
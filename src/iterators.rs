@@ -1,12 +1,15 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
 
 use edge::Edge;
+use vert::Vert;
+use face::Face;
 use ptr::{
   Ptr,
   EdgePtr, EdgeRc,
   VertPtr, VertRc,
-  FacePtr, /*FaceRc,*/
+  FacePtr, FaceRc,
 };
 
 
@@ -174,15 +177,13 @@ impl<'a> Iterator for EdgeAdjacentFaceIterator<'a> {
 // VertIterators
 
 pub struct VertAdjacentVertIterator {
-  start: EdgePtr,
-  current: Option<EdgePtr>,
+  edge_iter: VertAdjacentEdgeIterator,
 }
 
 impl VertAdjacentVertIterator {
   pub fn new(edge: EdgePtr) -> VertAdjacentVertIterator {
     VertAdjacentVertIterator {
-      start: edge,
-      current: None,
+      edge_iter: VertAdjacentEdgeIterator::new(edge),
     }
   }
 }
@@ -191,30 +192,14 @@ impl Iterator for VertAdjacentVertIterator {
   type Item = VertPtr;
 
   fn next(&mut self) -> Option<VertPtr> {
-    // edge.pair.origin
+    // edge.next.origin
     // edge -> edge.pair.next
-    match self.current.clone() {
-      Some(cur_weak) => cur_weak.upgrade()
-        .and_then(|cur_rc: EdgeRc| cur_rc.borrow().pair.upgrade())
-        .and_then(|pair_rc: EdgeRc| {
-          let next_weak: EdgePtr = pair_rc.borrow().next.clone();
-          Ptr::merge_upgrade(& next_weak, & self.start)
-            .and_then(|(next_rc, start_rc)| {
-              if next_rc == start_rc {
-                None
-              } else {
-                self.current = Some(next_weak);
-                Some(pair_rc.borrow().origin.clone())
-              }
-            })
-        }),
-      None => self.start.upgrade()
-        .and_then(|cur_rc: EdgeRc| cur_rc.borrow().pair.upgrade())
-        .map(|pair_rc: EdgeRc| {
-          self.current = Some(self.start.clone());
-          pair_rc.borrow().origin.clone()
-        }),
-    }
+    // Uses the target of each outgoing edge rather than edge.pair.origin,
+    // so that the last edge around a boundary vertex still yields its vertex
+    self.edge_iter.next()
+      .and_then(|edge_ptr: EdgePtr| edge_ptr.upgrade())
+      .and_then(|edge_rc: EdgeRc| edge_rc.borrow().next.upgrade())
+      .map(|next_rc: EdgeRc| next_rc.borrow().origin.clone())
   }
 }
 
@@ -439,3 +424,88 @@ impl Iterator for FaceAdjacentFaceIterator {
     }
   }
 }
+
+// Neighborhood iterators
+
+/// Breadth-first walk over everything within a number of topological rings of a starting entity,
+/// nearest ring first. The starting entity itself is not included
+pub struct NeighborhoodIterator<T> {
+  queue: VecDeque<(Rc<RefCell<T>>, usize)>,
+  visited: HashSet<u32>,
+  rings: usize,
+  neighbors: fn(& T) -> Vec<Rc<RefCell<T>>>,
+  id: fn(& T) -> u32,
+}
+
+pub type VertNeighborhoodIterator = NeighborhoodIterator<Vert>;
+pub type FaceNeighborhoodIterator = NeighborhoodIterator<Face>;
+
+impl<T> NeighborhoodIterator<T> {
+  fn new(start: & T, rings: usize, neighbors: fn(& T) -> Vec<Rc<RefCell<T>>>, id: fn(& T) -> u32) -> NeighborhoodIterator<T> {
+    let mut iter = NeighborhoodIterator {
+      queue: VecDeque::new(),
+      visited: HashSet::new(),
+      rings,
+      neighbors,
+      id,
+    };
+    iter.visited.insert(id(start));
+    if rings > 0 { iter.enqueue(start, 1); }
+    iter
+  }
+
+  fn enqueue(&mut self, from: & T, ring: usize) {
+    for neighbor in (self.neighbors)(from) {
+      if self.visited.insert((self.id)(& neighbor.borrow())) {
+        self.queue.push_back((neighbor, ring));
+      }
+    }
+  }
+
+  /// Like `next`, but also gives the ring the entity is in, starting from 1 for direct neighbors
+  pub fn next_with_ring(&mut self) -> Option<(Rc<RefCell<T>>, usize)> {
+    let (current, ring) = self.queue.pop_front()?;
+    if ring < self.rings { self.enqueue(& current.borrow(), ring + 1); }
+    Some((current, ring))
+  }
+
+  /// Collects the rest of the walk, grouped by ring. Entry 0 holds ring 1
+  pub fn into_rings(mut self) -> Vec<Vec<Rc<RefCell<T>>>> {
+    let mut groups: Vec<Vec<Rc<RefCell<T>>>> = Vec::new();
+    while let Some((entity, ring)) = self.next_with_ring() {
+      while groups.len() < ring { groups.push(Vec::new()); }
+      groups[ring - 1].push(entity);
+    }
+    groups
+  }
+}
+
+impl<T> Iterator for NeighborhoodIterator<T> {
+  type Item = Ptr<T>;
+
+  fn next(&mut self) -> Option<Ptr<T>> {
+    self.next_with_ring().map(|(entity, _)| Ptr::new(& entity))
+  }
+}
+
+/// Vertices connected to the vertex by an edge, including the start of an incoming border edge
+fn vert_neighbors(vert: & Vert) -> Vec<VertRc> {
+  vert.neighbor_edges().into_iter().map(|(_, neighbor)| neighbor).collect()
+}
+
+/// Faces sharing an edge with the face
+fn face_neighbors(face: & Face) -> Vec<FaceRc> {
+  face.adjacent_faces().to_ptr_vec()
+}
+
+impl VertNeighborhoodIterator {
+  pub fn from_vert(start: & Vert, rings: usize) -> VertNeighborhoodIterator {
+    NeighborhoodIterator::new(start, rings, vert_neighbors, |v| v.id)
+  }
+}
+
+impl FaceNeighborhoodIterator {
+  pub fn from_face(start: & Face, rings: usize) -> FaceNeighborhoodIterator {
+    NeighborhoodIterator::new(start, rings, face_neighbors, |f| f.id)
+  }
+}
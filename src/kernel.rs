@@ -0,0 +1,208 @@
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::{HalfEdgeMesh, vert_neighbor_ids};
+use ptr::{EdgeRc, FaceRc, VertRc};
+use iterators::ToPtrVec;
+
+/// Connectivity of a mesh kernel, by element id. This, `MeshGeometry`, and `EditableMesh` are what the
+/// generic algorithms (`simplify::decimate`, `subdivide::subdivide_adaptive`, `project::shrinkwrap`,
+/// `smoothing::bilateral_denoise`, `remesh::relax_tangential`, `inflate::inflate`) need,
+/// so that they can run on any half-edge structure, not just `HalfEdgeMesh`.
+/// Edges are half-edges, each running from its origin to its target along one face
+pub trait MeshTopology {
+  /// Every vertex id, in increasing order
+  fn vert_ids(& self) -> Vec<u32>;
+  /// Every half-edge id, in increasing order
+  fn edge_ids(& self) -> Vec<u32>;
+  /// Every face id, in increasing order
+  fn face_ids(& self) -> Vec<u32>;
+
+  fn num_faces(& self) -> usize { self.face_ids().len() }
+
+  /// Corners of the face, in counterclockwise order
+  fn face_verts(& self, face: u32) -> Vec<u32>;
+  /// Origin and target of the half-edge
+  fn edge_verts(& self, edge: u32) -> Option<(u32, u32)>;
+  /// The half-edge running the other way, None on the border
+  fn edge_pair(& self, edge: u32) -> Option<u32>;
+  /// Faces on either side of the edge
+  fn edge_faces(& self, edge: u32) -> Vec<u32>;
+  /// Half-edges leaving the vertex, and the ones arriving at it
+  fn vert_edges(& self, vert: u32) -> Vec<u32>;
+  /// Faces around the vertex
+  fn vert_faces(& self, vert: u32) -> Vec<u32>;
+  /// Vertices sharing an edge with the vertex
+  fn vert_neighbors(& self, vert: u32) -> Vec<u32>;
+
+  fn is_boundary_vert(& self, vert: u32) -> bool;
+  fn is_boundary_edge(& self, edge: u32) -> bool { self.edge_pair(edge).is_none() }
+}
+
+/// Vertex positions, and the face attributes which follow from them. Kernels which cache
+/// face attributes, like `HalfEdgeMesh`, can hand those back instead of computing them
+pub trait MeshGeometry: MeshTopology {
+  fn vert_pos(& self, vert: u32) -> Point3<f32>;
+
+  /// Unit normal of the face, from the sum of its triangle fan's normals
+  fn face_normal(& self, face: u32) -> Vector3<f32> {
+    let corners: Vec<Point3<f32>> = self.face_verts(face).into_iter().map(|v| self.vert_pos(v)).collect();
+    let mut normal = Vector3::new(0.0, 0.0, 0.0);
+    for idx in 1..corners.len().saturating_sub(1) {
+      normal += (corners[idx] - corners[0]).cross(corners[idx + 1] - corners[0]);
+    }
+    if normal.magnitude2() > 0.0 { normal.normalize() } else { normal }
+  }
+
+  /// Average position of the face's corners
+  fn face_center(& self, face: u32) -> Point3<f32> {
+    let corners: Vec<Point3<f32>> = self.face_verts(face).into_iter().map(|v| self.vert_pos(v)).collect();
+    Point3::centroid(& corners)
+  }
+
+  fn face_area(& self, face: u32) -> f32 {
+    let corners: Vec<Point3<f32>> = self.face_verts(face).into_iter().map(|v| self.vert_pos(v)).collect();
+    let mut doubled = Vector3::new(0.0, 0.0, 0.0);
+    for idx in 1..corners.len().saturating_sub(1) {
+      doubled += (corners[idx] - corners[0]).cross(corners[idx + 1] - corners[0]);
+    }
+    doubled.magnitude() / 2.0
+  }
+
+  /// Average of the normals of the faces around the vertex, weighted by area
+  fn vert_normal(& self, vert: u32) -> Vector3<f32> {
+    let sum = self.vert_faces(vert).into_iter()
+      .fold(Vector3::new(0.0, 0.0, 0.0), |acc, f| acc + self.face_normal(f) * self.face_area(f));
+    if sum.magnitude2() > 0.0 { sum.normalize() } else { sum }
+  }
+}
+
+/// The edits the generic algorithms make
+pub trait EditableMesh: MeshGeometry {
+  /// Moves the vertex. Cached face attributes have to be brought up to date
+  fn set_vert_pos(&mut self, vert: u32, pos: Point3<f32>);
+
+  /// Whether collapsing the edge would keep the mesh manifold
+  fn can_collapse(& self, edge: u32) -> bool;
+
+  /// Merges the edge's target into its origin, which is moved to `pos`, removing the faces along the edge.
+  /// Returns the id of the vertex left, or None if the edge can't be collapsed
+  fn collapse(&mut self, edge: u32, pos: Point3<f32>) -> Option<u32>;
+
+  /// A new mesh made of polygons, given as counterclockwise indices into the positions.
+  /// The ids of the faces increase in the order of the polygons
+  fn with_polygons(positions: & [Point3<f32>], polygons: & [Vec<usize>]) -> Self where Self: Sized;
+
+  /// Replaces everything in the mesh with the polygons, as in `with_polygons`. Data kept per vertex can only
+  /// follow the vertices built where old ones were: `HalfEdgeMesh` keeps their texture coordinates, registered
+  /// attributes, and the weights of edges between them, and drops tangents
+  fn rebuild(&mut self, positions: & [Point3<f32>], polygons: & [Vec<usize>]);
+}
+
+fn vert_id(vert: & VertRc) -> u32 { vert.borrow().id }
+
+fn face_id(face: & FaceRc) -> u32 { face.borrow().id }
+
+fn edge_id(edge: & EdgeRc) -> u32 { edge.borrow().id }
+
+fn sorted_keys<T>(map: & ::std::collections::HashMap<u32, T>) -> Vec<u32> {
+  let mut ids: Vec<u32> = map.keys().cloned().collect();
+  ids.sort();
+  ids
+}
+
+impl MeshTopology for HalfEdgeMesh {
+  fn vert_ids(& self) -> Vec<u32> { sorted_keys(& self.vertices) }
+
+  fn edge_ids(& self) -> Vec<u32> { sorted_keys(& self.edges) }
+
+  fn face_ids(& self) -> Vec<u32> { sorted_keys(& self.faces) }
+
+  fn num_faces(& self) -> usize { self.faces.len() }
+
+  fn face_verts(& self, face: u32) -> Vec<u32> {
+    self.faces.get(& face)
+      .map_or_else(Vec::new, |f| f.borrow().adjacent_verts().to_ptr_vec().iter().map(vert_id).collect())
+  }
+
+  fn edge_verts(& self, edge: u32) -> Option<(u32, u32)> {
+    let edge_b = self.edges.get(& edge)?.borrow();
+    Some((vert_id(& edge_b.get_origin()?), vert_id(& edge_b.get_target()?)))
+  }
+
+  fn edge_pair(& self, edge: u32) -> Option<u32> {
+    self.edges.get(& edge)?.borrow().get_pair().map(|p| edge_id(& p))
+  }
+
+  fn edge_faces(& self, edge: u32) -> Vec<u32> {
+    self.edges.get(& edge)
+      .map_or_else(Vec::new, |e| e.borrow().adjacent_faces().to_ptr_vec().iter().map(face_id).collect())
+  }
+
+  fn vert_edges(& self, vert: u32) -> Vec<u32> {
+    let vert = match self.vertices.get(& vert) { Some(v) => v, None => return Vec::new() };
+    let mut ids: Vec<u32> = Vec::new();
+    for out_edge in vert.borrow().outgoing_edges() {
+      ids.push(edge_id(& out_edge));
+      if let Some(incoming) = out_edge.borrow().get_prev() { ids.push(edge_id(& incoming)); }
+    }
+    ids
+  }
+
+  fn vert_faces(& self, vert: u32) -> Vec<u32> {
+    self.vertices.get(& vert).map_or_else(Vec::new, |v| {
+      v.borrow().outgoing_edges().iter().filter_map(|e| e.borrow().get_face()).map(|f| face_id(& f)).collect()
+    })
+  }
+
+  fn vert_neighbors(& self, vert: u32) -> Vec<u32> {
+    self.vertices.get(& vert).map_or_else(Vec::new, |v| vert_neighbor_ids(v).into_iter().collect())
+  }
+
+  fn is_boundary_vert(& self, vert: u32) -> bool {
+    self.vertices.get(& vert).is_some_and(|v| v.borrow().is_boundary())
+  }
+
+  fn is_boundary_edge(& self, edge: u32) -> bool {
+    self.edges.get(& edge).is_some_and(|e| e.borrow().is_boundary())
+  }
+}
+
+/// Face attributes come from the cache, which the mesh keeps up to date
+impl MeshGeometry for HalfEdgeMesh {
+  fn vert_pos(& self, vert: u32) -> Point3<f32> { self.vertices[& vert].borrow().get_pos() }
+
+  fn face_normal(& self, face: u32) -> Vector3<f32> { self.faces[& face].borrow().normal }
+
+  fn face_center(& self, face: u32) -> Point3<f32> { self.faces[& face].borrow().center }
+
+  fn face_area(& self, face: u32) -> f32 { self.faces[& face].borrow().area() }
+
+  fn vert_normal(& self, vert: u32) -> Vector3<f32> { self.vertices[& vert].borrow().normal() }
+}
+
+impl EditableMesh for HalfEdgeMesh {
+  fn set_vert_pos(&mut self, vert: u32, pos: Point3<f32>) {
+    let vert = match self.vertices.get(& vert) { Some(v) => v.clone(), None => return };
+    self.move_vert(& vert, pos);
+    for out_edge in vert.borrow().outgoing_edges() {
+      if let Some(face) = out_edge.borrow().get_face() { face.borrow_mut().compute_attrs(); }
+    }
+  }
+
+  fn can_collapse(& self, edge: u32) -> bool {
+    self.edges.get(& edge).is_some_and(|e| self.can_collapse_edge(e))
+  }
+
+  fn collapse(&mut self, edge: u32, pos: Point3<f32>) -> Option<u32> {
+    let edge = self.edges.get(& edge)?.clone();
+    self.collapse_edge(& edge, pos).ok().map(|v| vert_id(& v))
+  }
+
+  fn with_polygons(positions: & [Point3<f32>], polygons: & [Vec<usize>]) -> HalfEdgeMesh {
+    HalfEdgeMesh::from_polygons(positions, polygons)
+  }
+
+  fn rebuild(&mut self, positions: & [Point3<f32>], polygons: & [Vec<usize>]) {
+    self.replace_with_polygons(positions, polygons);
+  }
+}
@@ -2,6 +2,13 @@
 
 extern crate cgmath;
 extern crate approx;
+extern crate rand;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "petgraph")]
+extern crate petgraph;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
 
 pub mod ptr;
 pub mod edge;
@@ -10,11 +17,108 @@ pub mod face;
 pub mod iterators;
 pub mod mesh;
 pub mod util;
+pub mod geom;
+pub mod selection;
+pub mod simplify;
+pub mod bvh;
+pub mod bake;
+pub mod sampling;
+pub mod report;
+pub mod surface;
+pub mod operators;
+pub mod parametrize;
+pub mod quads;
+pub mod export;
+pub mod tangents;
+pub mod morph;
+pub mod subdivide;
+pub mod terrain;
+pub mod project;
+pub mod hull;
+pub mod decompose;
+pub mod support;
+pub mod repair;
+pub mod paths;
+pub mod loops;
+pub mod observer;
+pub mod dirty;
+pub mod kernel;
+pub mod primitives;
+pub mod slice;
+pub mod convention;
+pub mod units;
+pub mod snapshot;
+pub mod validate;
+pub mod reorder;
+pub mod wireframe;
+pub mod planar;
+pub mod delaunay;
+pub mod graph;
+pub mod partition;
+pub mod hem;
+#[cfg(feature = "compression")]
+pub mod compress;
+pub mod quantize;
+pub mod remesh;
+pub mod smoothing;
+pub mod inflate;
+pub mod sim;
+pub mod fields;
+pub mod frontier;
+pub mod views;
+pub mod nav;
+pub mod openmesh_compat;
+pub mod align;
+pub mod shape;
+pub mod brush;
+pub mod sculpt;
+pub mod attributes;
+pub mod foldover;
+pub mod exact;
+pub mod bisect;
 
 pub use self::mesh::HalfEdgeMesh;
 pub use self::edge::Edge;
 pub use self::vert::Vert;
 pub use self::face::Face;
+pub use self::report::PrintabilityReport;
+pub use self::geom::{Aabb, Obb, Plane, Ray, Segment, Polygon2};
+pub use self::selection::{Selection, PickResult};
+pub use self::simplify::DecimateOptions;
+pub use self::bvh::{Bvh, BvhHit};
+pub use self::surface::SurfacePoint;
+pub use self::operators::SparseMatrix;
+pub use self::parametrize::{BoundaryLoop, Chart};
+pub use self::export::{BufferOptions, MeshBuffers, BuffersPatch};
+pub use self::bake::Image;
+pub use self::decompose::DecomposeOptions;
+pub use self::support::SupportMap;
+pub use self::paths::EdgeWeight;
+pub use self::observer::MeshObserver;
+pub use self::dirty::DirtySet;
+pub use self::kernel::{MeshTopology, MeshGeometry, EditableMesh};
+pub use self::convention::{CoordinateConvention, UpAxis, Handedness};
+pub use self::units::{LengthUnit, ImportOptions};
+pub use self::snapshot::{MeshSnapshot, SnapshotCounts};
+pub use self::reorder::SpatialOrder;
+pub use self::planar::{HalfEdgeMesh2, Vert2, Edge2, Face2};
+pub use self::graph::MeshGraph;
+pub use self::hem::HemView;
+#[cfg(feature = "mmap")]
+pub use self::hem::HemFile;
+#[cfg(feature = "compression")]
+pub use self::compress::CompressOptions;
+pub use self::quantize::QuantizedBuffers;
+pub use self::sim::{ClothSim, ClothOptions};
+pub use self::frontier::{Frontier, FrontierNode, FrontierOrder};
+pub use self::views::{VertView, EdgeView, FaceView};
+pub use self::nav::{HalfEdgeNav, VertNav, FaceNav};
+pub use self::align::IcpOptions;
+pub use self::brush::{Falloff, BrushMetric, VertexData};
+pub use self::sculpt::SculptBrush;
+pub use self::attributes::{AttributeRegistry, AttributeSemantic, AttributeValue, BufferAttribute, Interpolate};
+pub use self::foldover::FoldoverError;
+pub use self::exact::SnapGrid;
 
 // Export the pointer types too, in case you need them
 pub use self::ptr::*;
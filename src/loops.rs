@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+
+use mesh::HalfEdgeMesh;
+use ptr::{EdgeRc, FaceRc, VertRc};
+
+fn vert_id(vert: Option<VertRc>) -> Option<u32> { vert.map(|v| v.borrow().id) }
+
+/// Continues an edge loop which reached `vert` from `prev`. Returns an edge between `vert` and the next
+/// vertex of the loop, along with that vertex. Inside the mesh the loop goes straight through vertices
+/// with four edges, taking the edge opposite the one it came in on. Along the border it goes through
+/// border vertices with three edges (the ones along a straight border of a quad grid). Anywhere else it stops
+fn loop_step(prev_id: u32, vert: & VertRc, on_border: bool) -> Option<(EdgeRc, VertRc)> {
+  let outgoing = vert.borrow().outgoing_edges();
+
+  if on_border {
+    if vert.borrow().valence() != 3 { return None; }
+    let leaving = outgoing.iter().find(|e| e.borrow().is_boundary()).cloned();
+    let arriving = outgoing.first().and_then(|e| e.borrow().get_prev()).filter(|p| p.borrow().is_boundary());
+    if let Some(edge) = leaving {
+      let target = edge.borrow().get_target()?;
+      if target.borrow().id != prev_id { return Some((edge, target)); }
+    }
+    let edge = arriving?;
+    let origin = edge.borrow().get_origin()?;
+    if origin.borrow().id == prev_id { return None; }
+    return Some((edge, origin));
+  }
+
+  if outgoing.len() != 4 || vert.borrow().is_boundary() { return None; }
+  let idx = outgoing.iter().position(|e| vert_id(e.borrow().get_target()) == Some(prev_id))?;
+  let edge = outgoing[(idx + 2) % 4].clone();
+  let target = edge.borrow().get_target()?;
+  Some((edge, target))
+}
+
+/// Ids of both halves of an edge
+fn edge_ids(edge: & EdgeRc) -> (u32, Option<u32>) {
+  let edge_b = edge.borrow();
+  (edge_b.id, edge_b.get_pair().map(|p| p.borrow().id))
+}
+
+fn same_edge(a: & EdgeRc, b: & EdgeRc) -> bool {
+  let (a_id, a_pair) = edge_ids(a);
+  let b_id = b.borrow().id;
+  a_id == b_id || a_pair == Some(b_id)
+}
+
+/// Quad faces crossed by walking from the edge's face over opposite edges,
+/// leaving out the ones already seen
+fn quad_strip(start: Option<EdgeRc>, seen: &mut HashSet<u32>) -> Vec<FaceRc> {
+  let mut faces: Vec<FaceRc> = Vec::new();
+  let mut current = start;
+  while let Some(edge) = current {
+    let face = match edge.borrow().get_face() { Some(f) => f, None => break };
+    let opposite = match opposite_in_quad(& edge) { Some(o) => o, None => break };
+    if !seen.insert(face.borrow().id) { break; }
+    faces.push(face);
+    current = opposite.borrow().get_pair();
+  }
+  faces
+}
+
+/// Opposite edge of a quad face, or None if the face isn't a quad
+fn opposite_in_quad(edge: & EdgeRc) -> Option<EdgeRc> {
+  let face = edge.borrow().get_face()?;
+  if face.borrow().num_vertices() != 4 { return None; }
+  edge.borrow().get_next_next()
+}
+
+impl HalfEdgeMesh {
+  /// The edge loop through the edge: the run of edges continuing straight on from it
+  /// in both directions, as in a modeling tool. It passes through vertices with four edges,
+  /// and stops at any other vertex (a pole, or the corner of a triangle fan) or when it meets the border.
+  /// A border edge's loop follows the border instead, and stops at its corners.
+  /// The edges are in order along the loop, and include the starting edge.
+  /// Each is the half running along the loop, where there is one
+  pub fn edge_loop(& self, edge: & EdgeRc) -> Vec<EdgeRc> {
+    let on_border = edge.borrow().is_boundary();
+    let (origin, target) = match (edge.borrow().get_origin(), edge.borrow().get_target()) {
+      (Some(o), Some(t)) => (o, t),
+      _ => return vec![edge.clone()],
+    };
+
+    let mut forward: Vec<EdgeRc> = vec![edge.clone()];
+    let (mut prev_id, mut current) = (origin.borrow().id, target.clone());
+    let mut closed = false;
+    while let Some((next_edge, next_vert)) = loop_step(prev_id, & current, on_border) {
+      if same_edge(& next_edge, edge) { closed = true; break; }
+      // A loop can end up in a cycle which doesn't include the start, around a pole
+      if forward.iter().any(|e| same_edge(e, & next_edge)) { break; }
+      forward.push(next_edge);
+      prev_id = current.borrow().id;
+      current = next_vert;
+    }
+    if closed { return forward; }
+
+    let mut backward: Vec<EdgeRc> = Vec::new();
+    let (mut prev_id, mut current) = (target.borrow().id, origin);
+    while let Some((next_edge, next_vert)) = loop_step(prev_id, & current, on_border) {
+      if forward.iter().chain(backward.iter()).any(|e| same_edge(e, & next_edge)) { break; }
+      // Walking backward, the half running along the loop is the pair
+      let along = next_edge.borrow().get_pair().unwrap_or_else(|| next_edge.clone());
+      backward.push(along);
+      prev_id = current.borrow().id;
+      current = next_vert;
+    }
+
+    backward.reverse();
+    backward.append(&mut forward);
+    backward
+  }
+
+  /// The edge ring through the edge: the edges across from each other in a strip of quads,
+  /// starting from the faces on both sides of the edge. The ring stops at a face which
+  /// isn't a quad, and at the border. The edges are in order across the strip,
+  /// and include the starting edge. Each is the half running the same way as the starting edge,
+  /// except for a border edge at the end of the ring, which only has the other half
+  pub fn edge_ring(& self, edge: & EdgeRc) -> Vec<EdgeRc> {
+    let mut forward: Vec<EdgeRc> = vec![edge.clone()];
+    let mut current = edge.clone();
+    let mut closed = false;
+    while let Some(opposite) = opposite_in_quad(& current) {
+      let next = match opposite.borrow().get_pair() { Some(p) => p, None => { forward.push(opposite.clone()); break; } };
+      if same_edge(& next, edge) { closed = true; break; }
+      if forward.iter().any(|e| same_edge(e, & next)) { break; }
+      forward.push(next.clone());
+      current = next;
+    }
+    if closed { return forward; }
+
+    let mut backward: Vec<EdgeRc> = Vec::new();
+    let mut current = match edge.borrow().get_pair() { Some(p) => p, None => return forward };
+    while let Some(opposite) = opposite_in_quad(& current) {
+      if forward.iter().chain(backward.iter()).any(|e| same_edge(e, & opposite)) { break; }
+      // The opposite edge in the face runs the same way as the starting edge
+      backward.push(opposite.clone());
+      current = match opposite.borrow().get_pair() { Some(p) => p, None => break };
+    }
+
+    backward.reverse();
+    backward.append(&mut forward);
+    backward
+  }
+
+  /// The loop of quads crossed by the edge's ring: starting from the faces on both sides of the edge,
+  /// it walks across opposite edges of quads until it reaches a face which isn't a quad, the border,
+  /// or comes back around to the start. The faces are in order along the loop
+  pub fn face_loop(& self, edge: & EdgeRc) -> Vec<FaceRc> {
+    let mut seen: HashSet<u32> = HashSet::new();
+    let forward = quad_strip(Some(edge.clone()), &mut seen);
+    let mut faces = quad_strip(edge.borrow().get_pair(), &mut seen);
+    faces.reverse();
+    faces.extend(forward);
+    faces
+  }
+}
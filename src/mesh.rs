@@ -1,7 +1,8 @@
-use cgmath::Point3;
+use cgmath::{Point3, Vector2, Vector4, InnerSpace};
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::rc::Rc;
 
 use edge::Edge;
 use vert::Vert;
@@ -9,13 +10,24 @@ use face::Face;
 use ptr::{Ptr, EdgeRc, VertRc, FaceRc, EdgePtr, VertPtr, FacePtr};
 use iterators::ToPtrVec;
 use util::*;
+use geom::{Aabb, ear_clip};
+use observer::MeshObserver;
+use dirty::DirtySet;
+use units::LengthUnit;
+use attributes::{AttributeRegistry, interpolate_vert};
+use surface::{barycentric, clamp_bary};
+
+/// Smallest share of the face each new triangle can have in `triangulate_face`, so none of them are slivers
+const MIN_TRIANGULATE_SHARE: f32 = 1e-6;
 
 /// Half-Edge Mesh data structure
 /// While it's possible to create non-triangular faces, this code assumes
 /// triangular faces in several locations
-/// mesh.edges, mesh.vertices, and mesh.faces are `HashMaps` containing reference-counted Pointers
+/// The mesh holds its edges, vertices, and faces in maps by id, of reference-counted Pointers
 /// to the mesh contents. Usually, these Rc values are the last values to exist. When they
-/// are destroyed, the pointed-to contents are destroyed as well.
+/// are destroyed, the pointed-to contents are destroyed as well. The maps are private, so that
+/// their representation can change: look elements up with `vert`, `edge`, and `face`, walk them with
+/// `verts`, `edges`, and `faces`, and add and remove them with the `push_` and `drop_` methods.
 /// Vertex, edge, and face ids are mesh-specific and unique only within a certain mesh
 /// Integer overflow is undefined in Rust, but checked in debug builds. I think this means
 /// that it's possible to generate the same id twice, after 2^32-1 ids have been made.
@@ -25,12 +37,25 @@ use util::*;
 /// Probably should do it whenever faces are added or a vertex is modified ?
 /// TODO: Better way of updating face-specific data like center and normals
 pub struct HalfEdgeMesh {
-  pub edges: HashMap<u32, EdgeRc>,
-  pub vertices: HashMap<u32, VertRc>,
-  pub faces: HashMap<u32, FaceRc>,
+  pub(crate) edges: HashMap<u32, EdgeRc>,
+  pub(crate) vertices: HashMap<u32, VertRc>,
+  pub(crate) faces: HashMap<u32, FaceRc>,
+  /// Texture coordinates, by vertex id. Empty unless the mesh has been textured
+  pub(crate) uvs: HashMap<u32, Vector2<f32>>,
+  /// Tangents by vertex id, as filled in by `compute_tangents`. The w component is
+  /// the handedness: bitangent = w * cross(normal, tangent)
+  pub(crate) tangents: HashMap<u32, Vector4<f32>>,
+  attributes: AttributeRegistry,
+  /// Weights of edges which have one, by half-edge id, kept the same on both halves. See `set_edge_weight`.
+  /// Not in `attributes`, which is per vertex: its values are blended at new vertices and written out as vertex
+  /// buffers, while a weight follows its edge through splits, flips, and collapses, and isn't exported
+  pub(crate) edge_weights: HashMap<u32, f32>,
   cur_edge_id: u32,
   cur_vert_id: u32,
   cur_face_id: u32,
+  observer: Option<Box<dyn MeshObserver>>,
+  dirty: DirtySet,
+  units: LengthUnit,
 }
 
 impl HalfEdgeMesh {
@@ -40,9 +65,16 @@ impl HalfEdgeMesh {
       edges: HashMap::new(),
       vertices: HashMap::new(),
       faces: HashMap::new(),
+      uvs: HashMap::new(),
+      tangents: HashMap::new(),
+      attributes: AttributeRegistry::default(),
+      edge_weights: HashMap::new(),
       cur_edge_id: 0,
       cur_vert_id: 0,
       cur_face_id: 0,
+      observer: None,
+      dirty: DirtySet::default(),
+      units: LengthUnit::default(),
     }
   }
 
@@ -73,7 +105,7 @@ impl HalfEdgeMesh {
 
     mesh.move_verts(vec![v1, v2, v3, v4]);
 
-    report_connect_err(connect_pairs(&mut mesh));
+    report_connect_err(mesh.connect_all_pairs());
 
     mesh
   }
@@ -111,7 +143,7 @@ impl HalfEdgeMesh {
 
     mesh.move_verts(vec![v1, v2, v3, v4, v5, v6]);
 
-    report_connect_err(connect_pairs(&mut mesh));
+    report_connect_err(mesh.connect_all_pairs());
 
     mesh
   }
@@ -119,8 +151,24 @@ impl HalfEdgeMesh {
   /// Construct a half edge mesh from a Vec of vertices and a Vec of triplets of indices into
   /// the Vec of vertices.
   pub fn from_face_vertex_mesh(vertices: &[Point3<f32>], indices: &[[usize; 3]]) -> HalfEdgeMesh {
+    HalfEdgeMesh::from_polygons(vertices, indices)
+  }
+
+  /// Construct a half edge mesh with polygonal faces from a Vec of vertices and a list of faces,
+  /// each of which is a list of indices into the Vec of vertices, in counterclockwise order
+  pub fn from_polygons<F>(vertices: &[Point3<f32>], indices: &[F]) -> HalfEdgeMesh where F: AsRef<[usize]> {
+    let mut mesh = HalfEdgeMesh::from_polygons_unpaired(vertices, indices);
+    report_connect_err(mesh.connect_all_pairs());
+    mesh.check_after("from_polygons");
+    mesh
+  }
+
+  /// Builds the faces for `from_polygons`, without connecting edge pairs
+  pub(crate) fn from_polygons_unpaired<F>(vertices: &[Point3<f32>], indices: &[F]) -> HalfEdgeMesh where F: AsRef<[usize]> {
     let mut mesh = HalfEdgeMesh::empty();
-    let mut id_map: HashMap<usize, u32> = HashMap::new(); // Maps indices to ids
+    let num_corners = indices.iter().map(|polygon| polygon.as_ref().len()).sum();
+    mesh.reserve(vertices.len(), num_corners, indices.len());
+    let mut id_map: HashMap<usize, u32> = HashMap::with_capacity(vertices.len()); // Maps indices to ids
 
     for (idx, pos) in vertices.iter().enumerate() {
       let vert = Ptr::new_rc(Vert::empty(mesh.new_vert_id(), *pos));
@@ -128,11 +176,11 @@ impl HalfEdgeMesh {
       mesh.push_vert(vert);
     }
 
-    for tri in indices.iter() {
+    for polygon in indices.iter() {
       let face = Ptr::new_rc(Face::empty(mesh.new_face_id()));
       let mut new_edges: Vec<EdgeRc> = Vec::new();
 
-      for idx in tri {
+      for idx in polygon.as_ref() {
         if let Some(vert_id) = id_map.get(idx) {
           if mesh.vertices.contains_key(vert_id) {
             let new_edge_id = mesh.new_edge_id();
@@ -162,11 +210,28 @@ impl HalfEdgeMesh {
       mesh.push_face(face);
     }
 
-    report_connect_err(connect_pairs(&mut mesh));
-
     mesh
   }
 
+  /// The reverse of `from_polygons`: returns the vertex positions, and the faces as lists of
+  /// indices into them. Vertices and faces are ordered by id
+  pub fn to_polygons(& self) -> (Vec<Point3<f32>>, Vec<Vec<usize>>) {
+    let mut vert_ids: Vec<u32> = self.vertices.keys().cloned().collect();
+    vert_ids.sort();
+    let index: HashMap<u32, usize> = vert_ids.iter().enumerate().map(|(idx, &id)| (id, idx)).collect();
+    let positions = vert_ids.iter().map(|id| self.vertices[id].borrow().get_pos()).collect();
+
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+    let polygons = face_ids.iter().map(|id| {
+      self.faces[id].borrow().adjacent_verts()
+        .filter_map(|v| v.upgrade().and_then(|v| index.get(& v.borrow().id).cloned()))
+        .collect()
+    }).collect();
+
+    (positions, polygons)
+  }
+
   pub fn new_edge_id(&mut self) -> u32 {
     self.cur_edge_id += 1; self.cur_edge_id
   }
@@ -179,41 +244,55 @@ impl HalfEdgeMesh {
     self.cur_face_id += 1; self.cur_face_id
   }
 
+  /// Attaches an observer, which is called back for every element added, removed, or moved
+  /// from now on. Replaces the previous observer, if there was one
+  pub fn set_observer<O: MeshObserver + 'static>(&mut self, observer: O) {
+    self.observer = Some(Box::new(observer));
+  }
+
+  /// Detaches the observer and hands it back
+  pub fn take_observer(&mut self) -> Option<Box<dyn MeshObserver>> {
+    self.observer.take()
+  }
+
+  fn notify<F: FnOnce(&mut dyn MeshObserver)>(&mut self, callback: F) {
+    if let Some(ref mut observer) = self.observer { callback(observer.as_mut()); }
+  }
+
   pub fn push_edge(&mut self, edge: EdgeRc) {
     let key = edge.borrow().id;
     self.edges.insert(key, edge);
+    self.notify(|o| o.edge_added(key));
   }
 
   pub fn extend_edges(&mut self, edges: & [EdgeRc]) {
     for edge in edges {
-      let key = edge.borrow().id;
-      self.edges.insert(key, edge.clone());
+      self.push_edge(edge.clone());
     }
   }
 
   pub fn move_edges(&mut self, edges: Vec<EdgeRc>) {
     for edge in edges {
-      let key = edge.borrow().id;
-      self.edges.insert(key, edge);
+      self.push_edge(edge);
     }
   }
 
   pub fn push_vert(&mut self, vert: VertRc) {
-    let key = vert.borrow().id;
+    let (key, pos) = (vert.borrow().id, vert.borrow().get_pos());
     self.vertices.insert(key, vert);
+    self.dirty.add_vert(key);
+    self.notify(|o| o.vert_added(key, pos));
   }
 
   pub fn extend_verts(&mut self, verts: & [VertRc]) {
     for vert in verts {
-      let key = vert.borrow().id;
-      self.vertices.insert(key, vert.clone());
+      self.push_vert(vert.clone());
     }
   }
 
   pub fn move_verts(&mut self, verts: Vec<VertRc>) {
     for vert in verts {
-      let key = vert.borrow().id;
-      self.vertices.insert(key, vert);
+      self.push_vert(vert);
     }
   }
 
@@ -222,40 +301,330 @@ impl HalfEdgeMesh {
     face.borrow_mut().compute_attrs();
     let key = face.borrow().id;
     self.faces.insert(key, face);
+    self.dirty.add_face(key);
+    self.notify(|o| o.face_added(key));
   }
 
   pub fn extend_faces(&mut self, faces: & [FaceRc]) {
     for face in faces {
-      face.borrow_mut().compute_attrs();
-      let key = face.borrow().id;
-      self.faces.insert(key, face.clone());
+      self.push_face(face.clone());
     }
   }
 
   pub fn move_faces(&mut self, faces: Vec<FaceRc>) {
     for face in faces {
+      self.push_face(face);
+    }
+  }
+
+  /// Removes the edge from the mesh, without touching anything linked to it
+  pub fn drop_edge(&mut self, id: u32) -> Option<EdgeRc> {
+    let removed = self.edges.remove(& id);
+    if removed.is_some() {
+      self.edge_weights.remove(& id);
+      self.notify(|o| o.edge_removed(id));
+    }
+    removed
+  }
+
+  /// Removes the vertex from the mesh, without touching anything linked to it
+  pub fn drop_vert(&mut self, id: u32) -> Option<VertRc> {
+    let removed = self.vertices.remove(& id);
+    if removed.is_some() {
+      self.uvs.remove(& id);
+      self.tangents.remove(& id);
+      self.attributes.remove_vert(id);
+      self.dirty.remove_vert(id);
+      self.notify(|o| o.vert_removed(id));
+    }
+    removed
+  }
+
+  /// Removes the face from the mesh, without touching anything linked to it
+  pub fn drop_face(&mut self, id: u32) -> Option<FaceRc> {
+    let removed = self.faces.remove(& id);
+    if removed.is_some() {
+      self.dirty.remove_face(id);
+      self.notify(|o| o.face_removed(id));
+    }
+    removed
+  }
+
+  /// Moves the vertex, letting the observer know. The attributes of the faces around it
+  /// are left for the caller to recompute, since usually many vertices are moved at once
+  pub fn move_vert(&mut self, vert: & VertRc, pos: Point3<f32>) {
+    let (id, from) = (vert.borrow().id, vert.borrow().get_pos());
+    vert.borrow_mut().move_to(pos);
+    self.dirty.move_vert(id);
+    self.notify(|o| o.vert_moved(id, from, pos));
+  }
+
+  /// Records that the corners of the face changed, for code which relinks its edges directly,
+  /// and tells the observer
+  pub fn mark_face_changed(&mut self, id: u32) {
+    self.dirty.change_face(id);
+    self.notify(|o| o.face_changed(id));
+  }
+
+  /// Hands back the vertices and faces changed since the last call, and starts over
+  pub fn take_dirty(&mut self) -> DirtySet {
+    ::std::mem::take(&mut self.dirty)
+  }
+
+  pub fn num_verts(& self) -> usize { self.vertices.len() }
+
+  /// Number of half-edges, which is twice the number of edges in a closed mesh
+  pub fn num_edges(& self) -> usize { self.edges.len() }
+
+  pub fn num_faces(& self) -> usize { self.faces.len() }
+
+  /// Makes room for at least this many more vertices, half-edges, and faces, so that building a large mesh
+  /// one element at a time doesn't keep growing the maps. A triangle mesh has about twice as many faces
+  /// as vertices, and three half-edges per face
+  pub fn reserve(&mut self, verts: usize, edges: usize, faces: usize) {
+    self.vertices.reserve(verts);
+    self.edges.reserve(edges);
+    self.faces.reserve(faces);
+  }
+
+  /// Every vertex, in no particular order. `vertex_order` gives the ids sorted
+  pub fn verts(& self) -> impl Iterator<Item = & VertRc> + '_ { self.vertices.values() }
+
+  /// Every half-edge, in no particular order
+  pub fn edges(& self) -> impl Iterator<Item = & EdgeRc> + '_ { self.edges.values() }
+
+  /// Every face, in no particular order
+  pub fn faces(& self) -> impl Iterator<Item = & FaceRc> + '_ { self.faces.values() }
+
+  /// Texture coordinates, by vertex id. Empty unless the mesh has been textured
+  pub fn uvs(& self) -> & HashMap<u32, Vector2<f32>> { & self.uvs }
+
+  /// Gives the vertex texture coordinates. Fails if there's no such vertex
+  pub fn set_uv(&mut self, vert_id: u32, uv: Vector2<f32>) -> Result<(), &'static str> {
+    if !self.vertices.contains_key(& vert_id) { return Err("No vertex with that id"); }
+    self.uvs.insert(vert_id, uv);
+    Ok(())
+  }
+
+  /// Removes the texture coordinates of every vertex, and the tangents which follow them
+  pub fn clear_uvs(&mut self) {
+    self.uvs.clear();
+    self.tangents.clear();
+  }
+
+  /// Tangents by vertex id, as filled in by `compute_tangents`. The w component is
+  /// the handedness: bitangent = w * cross(normal, tangent)
+  pub fn tangents(& self) -> & HashMap<u32, Vector4<f32>> { & self.tangents }
+
+  /// Removes every tangent
+  pub fn clear_tangents(&mut self) { self.tangents.clear(); }
+
+  /// Calls the function on every face in id order, borrowed mutably, then recomputes the face's normal and
+  /// center and marks it changed, as its links may have been changed
+  pub fn faces_mut_with<F>(&mut self, mut f: F) where F: FnMut(&mut Face) {
+    let mut ids: Vec<u32> = self.faces.keys().cloned().collect();
+    ids.sort();
+    for id in ids {
+      let face = self.faces[& id].clone();
+      f(&mut face.borrow_mut());
       face.borrow_mut().compute_attrs();
-      let key = face.borrow().id;
-      self.faces.insert(key, face);
+      self.mark_face_changed(id);
     }
   }
 
-  /// Adds a tuple of (face, edge, edge, edge) to the mesh
-  pub fn add_triangle(&mut self, triangle: (FaceRc, EdgeRc, EdgeRc, EdgeRc)) {
-    let mut key: u32;
+  /// The vertex with the id, if it's in the mesh
+  pub fn vert(& self, id: u32) -> Option<& VertRc> { self.vertices.get(& id) }
 
-    triangle.0.borrow_mut().compute_attrs();
-    key = triangle.0.borrow().id;
-    self.faces.insert(key, triangle.0);
+  /// The half-edge with the id, if it's in the mesh
+  pub fn edge(& self, id: u32) -> Option<& EdgeRc> { self.edges.get(& id) }
 
-    key = triangle.1.borrow().id;
-    self.edges.insert(key, triangle.1);
+  /// The face with the id, if it's in the mesh
+  pub fn face(& self, id: u32) -> Option<& FaceRc> { self.faces.get(& id) }
 
-    key = triangle.2.borrow().id;
-    self.edges.insert(key, triangle.2);
+  /// Units the mesh's coordinates are in. Meters unless set otherwise
+  pub fn units(& self) -> LengthUnit { self.units }
 
-    key = triangle.3.borrow().id;
-    self.edges.insert(key, triangle.3);
+  /// Records which units the coordinates are in, without changing them. Use `convert_units` to rescale the mesh
+  pub fn set_units(&mut self, units: LengthUnit) { self.units = units; }
+
+  /// Named per-vertex attributes registered on the mesh, which `to_buffers` writes out along with the rest
+  pub fn attributes(& self) -> & AttributeRegistry { & self.attributes }
+
+  /// Named per-vertex attributes, for registering attributes and setting their values
+  pub fn attributes_mut(&mut self) -> &mut AttributeRegistry { &mut self.attributes }
+
+  /// Gives the vertex `into` texture coordinates and registered attributes blended from vertex a's and b's,
+  /// a fraction t of the way from a to b, for editing operations which add or merge vertices
+  pub(crate) fn interpolate_vert_data(&mut self, a: u32, b: u32, t: f32, into: u32) {
+    interpolate_vert(&mut self.uvs, a, b, t, into);
+    self.attributes.interpolate_vert(a, b, t, into);
+  }
+
+  /// Gives the vertex `into` texture coordinates and registered attributes blended from the three vertices',
+  /// by barycentric weights, which should add up to 1
+  pub(crate) fn blend_vert_data(&mut self, corners: [u32; 3], weights: (f32, f32, f32), into: u32) {
+    let (w_0, w_1, w_2) = weights;
+    let first_two = w_0 + w_1;
+    self.interpolate_vert_data(corners[0], corners[1], if first_two > 0.0 { w_1 / first_two } else { 0.0 }, into);
+    self.interpolate_vert_data(into, corners[2], w_2, into);
+  }
+
+  /// Gives each edge the weight of the edge of `from` between the same vertices, if it had one,
+  /// where `verts` maps `from`'s vertex ids to this mesh's
+  pub(crate) fn carry_edge_weights(&mut self, from: & HalfEdgeMesh, verts: & HashMap<u32, u32>) {
+    if from.edge_weights.is_empty() { return; }
+    let mut by_ends: HashMap<(u32, u32), u32> = HashMap::new();
+    for edge in self.edges.values() {
+      let edge_b = edge.borrow();
+      if let (Some(origin), Some(target)) = (edge_b.get_origin(), edge_b.get_target()) {
+        by_ends.insert((origin.borrow().id, target.borrow().id), edge_b.id);
+      }
+    }
+    for (old_id, &weight) in & from.edge_weights {
+      let edge_b = match from.edges.get(old_id) { Some(e) => e.borrow(), None => continue };
+      let ends = match (edge_b.get_origin(), edge_b.get_target()) {
+        (Some(origin), Some(target)) => (verts.get(& origin.borrow().id), verts.get(& target.borrow().id)),
+        _ => continue,
+      };
+      if let (Some(&origin), Some(&target)) = ends {
+        if let Some(&new_id) = by_ends.get(& (origin, target)) { self.edge_weights.insert(new_id, weight); }
+      }
+    }
+  }
+
+  /// Moves everything out of the mesh into a new one, leaving this one empty.
+  /// The observer and the dirty set stay with this mesh, and are told that everything was removed.
+  /// Both meshes keep the units
+  pub(crate) fn take_contents(&mut self) -> HalfEdgeMesh {
+    let mut taken = HalfEdgeMesh::empty();
+    ::std::mem::swap(self, &mut taken);
+    self.observer = taken.observer.take();
+    self.dirty = ::std::mem::take(&mut taken.dirty);
+    self.units = taken.units;
+    let mut ids: Vec<u32> = taken.faces.keys().cloned().collect();
+    ids.sort();
+    for id in ids {
+      self.dirty.remove_face(id);
+      self.notify(|o| o.face_removed(id));
+    }
+    ids = taken.edges.keys().cloned().collect();
+    ids.sort();
+    for id in ids { self.notify(|o| o.edge_removed(id)); }
+    ids = taken.vertices.keys().cloned().collect();
+    ids.sort();
+    for id in ids {
+      self.dirty.remove_vert(id);
+      self.notify(|o| o.vert_removed(id));
+    }
+    taken
+  }
+
+  /// Replaces the contents of the mesh with another mesh's, keeping the units, the observer, and the dirty set,
+  /// which are told that all of the old elements were removed and all of the new ones added
+  pub(crate) fn replace_with(&mut self, mut mesh: HalfEdgeMesh) {
+    self.take_contents();
+    mesh.observer = self.observer.take();
+    mesh.dirty = ::std::mem::take(&mut self.dirty);
+    mesh.units = self.units;
+    *self = mesh;
+    let mut verts: Vec<(u32, Point3<f32>)> = self.vertices.iter().map(|(&id, v)| (id, v.borrow().get_pos())).collect();
+    verts.sort_by_key(|v| v.0);
+    for (id, pos) in verts {
+      self.dirty.add_vert(id);
+      self.notify(|o| o.vert_added(id, pos));
+    }
+    let mut ids: Vec<u32> = self.edges.keys().cloned().collect();
+    ids.sort();
+    for id in ids { self.notify(|o| o.edge_added(id)); }
+    ids = self.faces.keys().cloned().collect();
+    ids.sort();
+    for id in ids {
+      self.dirty.add_face(id);
+      self.notify(|o| o.face_added(id));
+    }
+  }
+
+  /// Replaces the contents of the mesh with the polygons, as `replace_with` does. A vertex built at exactly the
+  /// position of an old one stands in for it, taking its texture coordinates and registered attributes, and edges
+  /// between two such vertices keep their weights. Other vertices get none, and tangents are dropped
+  pub(crate) fn replace_with_polygons(&mut self, positions: & [Point3<f32>], polygons: & [Vec<usize>]) {
+    let mut mesh = HalfEdgeMesh::from_polygons(positions, polygons);
+    let key = |pos: Point3<f32>| [pos.x.to_bits(), pos.y.to_bits(), pos.z.to_bits()];
+    let new_at: HashMap<[u32; 3], u32> = mesh.vertices.iter().map(|(&id, v)| (key(v.borrow().get_pos()), id)).collect();
+    let stand_ins: HashMap<u32, u32> = self.vertices.iter()
+      .filter_map(|(&id, v)| new_at.get(& key(v.borrow().get_pos())).map(|&new| (id, new)))
+      .collect();
+    mesh.carry_edge_weights(self, & stand_ins);
+    mesh.uvs = self.uvs.iter().filter_map(|(old, uv)| stand_ins.get(old).map(|&new| (new, *uv))).collect();
+    let mut attributes = self.attributes.clone();
+    attributes.renumber(& stand_ins);
+    mesh.attributes = attributes;
+    self.replace_with(mesh);
+  }
+
+  /// Gives the vertices and faces new ids, counting up from 1 like a new mesh does, in the orders given, which have to list
+  /// every vertex and face id once. Each face's edges get consecutive ids, in the order of the faces.
+  /// Texture coordinates and tangents follow their vertices, and weights their edges. The observer and the dirty set are told
+  /// that everything was removed and added again
+  pub(crate) fn renumber(&mut self, vert_order: & [u32], face_order: & [u32]) {
+    debug_assert!(vert_order.len() == self.vertices.len() && face_order.len() == self.faces.len());
+    let mut mesh = self.take_contents();
+    let vert_ids: HashMap<u32, u32> = vert_order.iter().enumerate().map(|(new, &old)| (old, new as u32 + 1)).collect();
+
+    let mut vertices: HashMap<u32, VertRc> = HashMap::new();
+    for (old, vert) in mesh.vertices.drain() {
+      let new = vert_ids[& old];
+      vert.borrow_mut().id = new;
+      vertices.insert(new, vert);
+    }
+
+    let mut faces: HashMap<u32, FaceRc> = HashMap::new();
+    let mut edges: HashMap<u32, EdgeRc> = HashMap::new();
+    let mut edge_ids: HashMap<u32, u32> = HashMap::new();
+    for old in face_order {
+      let face = match mesh.faces.remove(old) { Some(f) => f, None => continue };
+      let new = faces.len() as u32 + 1;
+      face.borrow_mut().id = new;
+      for edge in face.borrow().adjacent_edges().to_ptr_vec() {
+        let old_edge = edge.borrow().id;
+        if mesh.edges.remove(& old_edge).is_none() { continue; }
+        let new_edge = edges.len() as u32 + 1;
+        edge_ids.insert(old_edge, new_edge);
+        edge.borrow_mut().id = new_edge;
+        edges.insert(new_edge, edge);
+      }
+      faces.insert(new, face);
+    }
+    // Edges which somehow aren't in a face loop go last
+    let mut stray: Vec<u32> = mesh.edges.keys().cloned().collect();
+    stray.sort();
+    for old in stray {
+      let edge = mesh.edges[& old].clone();
+      let new_edge = edges.len() as u32 + 1;
+      edge_ids.insert(old, new_edge);
+      edge.borrow_mut().id = new_edge;
+      edges.insert(new_edge, edge);
+    }
+
+    mesh.uvs = mesh.uvs.drain().filter_map(|(old, uv)| vert_ids.get(& old).map(|&new| (new, uv))).collect();
+    mesh.tangents = mesh.tangents.drain().filter_map(|(old, t)| vert_ids.get(& old).map(|&new| (new, t))).collect();
+    mesh.attributes.renumber(& vert_ids);
+    mesh.edge_weights = mesh.edge_weights.drain().filter_map(|(old, w)| edge_ids.get(& old).map(|&new| (new, w))).collect();
+    mesh.cur_vert_id = vertices.len() as u32;
+    mesh.cur_edge_id = edges.len() as u32;
+    mesh.cur_face_id = faces.len() as u32;
+    mesh.vertices = vertices;
+    mesh.edges = edges;
+    mesh.faces = faces;
+    self.replace_with(mesh);
+  }
+
+  /// Adds a tuple of (face, edge, edge, edge) to the mesh
+  pub fn add_triangle(&mut self, triangle: (FaceRc, EdgeRc, EdgeRc, EdgeRc)) {
+    self.push_face(triangle.0);
+    self.push_edge(triangle.1);
+    self.push_edge(triangle.2);
+    self.push_edge(triangle.3);
   }
 
   /// Takes three `Rc<RefCell<Vert>>`,
@@ -294,6 +663,108 @@ impl HalfEdgeMesh {
     (f1, e1, e2, e3)
   }
 
+  /// Bounding box of all of the vertices in the mesh.
+  /// Empty if the mesh has no vertices
+  pub fn aabb(& self) -> Aabb {
+    let mut aabb = Aabb::empty();
+    for vert in self.vertices.values() {
+      aabb.extend(& vert.borrow().get_pos());
+    }
+    aabb
+  }
+
+  /// Total area of all of the faces
+  pub fn surface_area(& self) -> f32 {
+    self.faces.values().map(|f| f.borrow().area()).sum()
+  }
+
+  /// Finds the edges where the surface bends by more than `angle_threshold` (radians)
+  /// and sets their `sharp` flag. Every other edge has its flag cleared.
+  /// Both halves of each sharp edge are flagged, and both are returned
+  pub fn detect_feature_edges(& self, angle_threshold: f32) -> Vec<EdgeRc> {
+    let mut sharp_edges: Vec<EdgeRc> = Vec::new();
+
+    for edge in self.edges.values() {
+      let is_sharp = edge.borrow().dihedral_angle().is_some_and(|angle| angle > angle_threshold);
+      edge.borrow_mut().sharp = is_sharp;
+      if is_sharp { sharp_edges.push(edge.clone()); }
+    }
+
+    sharp_edges
+  }
+
+  /// Finds the edges on the outline of the mesh as seen from `eye`: edges where the face
+  /// on one side can see the eye and the face on the other side can't.
+  /// Only the half of each pair belonging to the visible face is returned, so the
+  /// edges of a silhouette loop all run in the same direction
+  pub fn silhouette_edges(& self, eye: Point3<f32>) -> Vec<EdgeRc> {
+    self.edges.values()
+      .filter(|edge| {
+        let edge_b = edge.borrow();
+        match (edge_b.get_face(), edge_b.get_pair_face()) {
+          (Some(face), Some(pair_face)) => face.borrow().can_see(& eye) && !pair_face.borrow().can_see(& eye),
+          _ => false,
+        }
+      })
+      .cloned()
+      .collect()
+  }
+
+  /// Builds a closed shadow volume for a point light. The faces which can see the light form the
+  /// front cap, copies of them pushed `extrusion` units directly away from the light form the back cap,
+  /// and the silhouette edges (plus any border edges of lit faces) are extruded into the side walls.
+  /// The result is consistently oriented with outward-facing normals. Assumes triangular faces
+  pub fn shadow_volume(& self, light: Point3<f32>, extrusion: f32) -> HalfEdgeMesh {
+    let mut positions: Vec<Point3<f32>> = Vec::new();
+    // Maps vertex ids to the indices of the vertex and its extruded copy
+    let mut index_map: HashMap<u32, (usize, usize)> = HashMap::new();
+    let mut indices: Vec<[usize; 3]> = Vec::new();
+
+    let mut index_of = |vert: & VertRc, positions: &mut Vec<Point3<f32>>| -> (usize, usize) {
+      let vert_b = vert.borrow();
+      *index_map.entry(vert_b.id).or_insert_with(|| {
+        let pos = vert_b.get_pos();
+        let away = pos - light;
+        let extruded = if away.magnitude2() > 0.0 { pos + away.normalize() * extrusion } else { pos };
+        positions.push(pos);
+        positions.push(extruded);
+        (positions.len() - 2, positions.len() - 1)
+      })
+    };
+
+    let mut lit_faces: Vec<& FaceRc> = self.faces.values().filter(|f| f.borrow().can_see(& light)).collect();
+    lit_faces.sort_by_key(|f| f.borrow().id);
+
+    for face in & lit_faces {
+      let corners: Vec<(usize, usize)> = face.borrow().adjacent_verts().to_ptr_vec().iter()
+        .map(|v| index_of(v, &mut positions))
+        .collect();
+      if corners.len() != 3 { continue; }
+      // Front cap keeps the face's winding, back cap reverses it
+      indices.push([corners[0].0, corners[1].0, corners[2].0]);
+      indices.push([corners[2].1, corners[1].1, corners[0].1]);
+    }
+
+    let mut wall_edges = self.silhouette_edges(light);
+    for face in & lit_faces {
+      wall_edges.extend(face.borrow().adjacent_edges().to_ptr_vec().into_iter().filter(|e| e.borrow().is_boundary()));
+    }
+
+    for edge in & wall_edges {
+      let (origin, target) = match (edge.borrow().get_origin(), edge.borrow().get_target()) {
+        (Some(o), Some(t)) => (o, t),
+        _ => continue,
+      };
+      let (a, a_ext) = index_of(& origin, &mut positions);
+      let (b, b_ext) = index_of(& target, &mut positions);
+      // The wall runs along the edge in the opposite direction to the front cap
+      indices.push([b, a, a_ext]);
+      indices.push([b, a_ext, b_ext]);
+    }
+
+    HalfEdgeMesh::from_face_vertex_mesh(& positions, & indices)
+  }
+
   /// Checks if two faces are adjacent by looking for a shared edge
   pub fn are_faces_adjacent(& self, face_l: & FaceRc, face_r: & FaceRc) -> bool {
     face_l.borrow().adjacent_edges()
@@ -312,10 +783,38 @@ impl HalfEdgeMesh {
     }
   }
 
-  /// Replace a face with three faces, each connected to the new point
-  /// And one of the face's previous vertices
-  /// TODO: Make all of these mesh-manipulation functions return a Result<(), &str> to check that manipulation was completed
-  pub fn triangulate_face(&mut self, point: Point3<f32>, target_face: & FaceRc) {
+  /// Like `are_faces_adjacent`, for the faces with these ids. False if either isn't in the mesh
+  pub fn are_faces_adjacent_ids(& self, face_l: u32, face_r: u32) -> bool {
+    match (self.face(face_l), self.face(face_r)) {
+      (Some(l_rc), Some(r_rc)) => self.are_faces_adjacent(l_rc, r_rc),
+      _ => false,
+    }
+  }
+
+  /// Replaces a triangle with three, each joining one of its edges to a new vertex at the point,
+  /// and returns the new vertex. Fails without changing anything if the face isn't a triangle, or if any of
+  /// the new triangles would be degenerate or face the other way from the original. That's the same as
+  /// the point having to be inside the triangle, seen along its normal, though it can be off its plane
+  pub fn triangulate_face(&mut self, point: Point3<f32>, target_face: & FaceRc) -> Result<VertRc, &'static str> {
+    let corners = target_face.borrow().triangle().ok_or("Face isn't a triangle")?;
+    let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+    let norm2 = normal.magnitude2();
+    if norm2.is_nan() || norm2 <= 0.0 { return Err("Face is degenerate"); }
+    // Each new triangle's area along the original normal, over the original's, is a barycentric coordinate of the point
+    for idx in 0..3 {
+      let (a, b) = (corners[idx], corners[(idx + 1) % 3]);
+      let share = (b - a).cross(point - a).dot(normal) / norm2;
+      if share.is_nan() || share <= MIN_TRIANGULATE_SHARE { return Err("Point would make a degenerate or flipped triangle"); }
+    }
+    Ok(self.triangulate_face_unchecked(point, target_face))
+  }
+
+  /// Replaces a face with three faces, each connected to the new point and one of the face's edges,
+  /// and returns the new vertex. Doesn't check anything, so the new faces can come out flipped or degenerate
+  /// if the point isn't in the right place, which is up to the caller. The face has to be a triangle.
+  /// The new vertex gets texture coordinates and registered attributes blended from the corners', by where
+  /// the point is in the triangle, or the closest place in it
+  pub fn triangulate_face_unchecked(&mut self, point: Point3<f32>, target_face: & FaceRc) -> VertRc {
     // get face edges
     let face_edges = target_face.borrow().adjacent_edges().to_ptr_vec();
     // get face vertexes, assumed to be counter-clockwise
@@ -359,7 +858,11 @@ impl HalfEdgeMesh {
     }
 
     // This step is pretty crucial
-    self.push_vert(apex_vert);
+    self.push_vert(apex_vert.clone());
+    let corner_pos = [face_vertices[0].borrow().get_pos(), face_vertices[1].borrow().get_pos(), face_vertices[2].borrow().get_pos()];
+    let (w_0, w_1, w_2) = barycentric(& corner_pos, & point);
+    let corner_ids = [face_vertices[0].borrow().id, face_vertices[1].borrow().id, face_vertices[2].borrow().id];
+    self.blend_vert_data(corner_ids, clamp_bary([w_0, w_1, w_2]), apex_vert.borrow().id);
 
     let trail_edge_len = new_trail_edges.len();
 
@@ -376,12 +879,17 @@ impl HalfEdgeMesh {
 
     // Remove the face and the edges from the mesh.
     // When the local pointer to this falls out of scope, it should be deallocated
-    self.faces.remove(& target_face.borrow().id);
+    let target_id = target_face.borrow().id;
+    self.drop_face(target_id);
+    trace!("triangulate_face: replaced face {} with {} faces", target_id, vertices_len);
+    self.check_after("triangulate_face");
+    apex_vert
   }
 
-  pub fn triangulate_face_ptr(&mut self, point: Point3<f32>, face: & FacePtr) {
-    if let Some(face_rc) = face.upgrade() {
-      self.triangulate_face(point, & face_rc)
+  pub fn triangulate_face_ptr(&mut self, point: Point3<f32>, face: & FacePtr) -> Result<VertRc, &'static str> {
+    match face.upgrade() {
+      Some(face_rc) => self.triangulate_face(point, & face_rc),
+      None => Err("Provided pointer was invalid"),
     }
   }
 
@@ -391,6 +899,7 @@ impl HalfEdgeMesh {
   /// The programmer is responsible for ensuring that there are no holes in the passed
   /// set of faces. Returns Pointers to the new faces in the result, if successful
   pub fn attach_point_for_faces(&mut self, point: Point3<f32>, remove_faces: &[FaceRc]) -> Result<Vec<FaceRc>, &'static str> {
+    let start = OpTimer::start();
     // collect a set of face ids to be removed, for later reference
     let outgoing_face_ids: HashSet<u32> = remove_faces.iter().map(|f| f.borrow().id).collect();
     let mut horizon_edges: HashMap<u32, EdgeRc> = HashMap::new();
@@ -492,15 +1001,16 @@ impl HalfEdgeMesh {
     // Do this after all other data structures have been set up, because a valid mesh is required
     // for some steps, for example finding a horizon edge's next edge
     for out_face in remove_faces {
-      self.faces.remove(&out_face.borrow().id);
+      let out_face_id = out_face.borrow().id;
+      self.drop_face(out_face_id);
     }
 
     for out_vert_id in &remove_verts {
-      self.vertices.remove(out_vert_id);
+      self.drop_vert(*out_vert_id);
     }
 
     for out_edge_id in &remove_edges {
-      self.edges.remove(out_edge_id);
+      self.drop_edge(*out_edge_id);
     }
 
     // create a new vertex for the point
@@ -560,6 +1070,11 @@ impl HalfEdgeMesh {
       }
     }
 
+    let mut removed_face_ids: Vec<u32> = outgoing_face_ids.into_iter().collect();
+    removed_face_ids.sort();
+    debug!("attach_point_for_faces: replaced faces {:?} with {} faces, took {:?}",
+      removed_face_ids, return_faces.len(), start.elapsed());
+    self.check_after("attach_point_for_faces");
     Ok(return_faces)
   }
 
@@ -568,20 +1083,30 @@ impl HalfEdgeMesh {
     self.attach_point_for_faces(point, &face_ptrs)
   }
 
-  /// This function should only work if the vertex has exactly three adjacent edges.
-  /// Therefore, it has three adjacent faces.
-  /// The vertices connected to those edges form a new face, and the faces and edges connected
-  /// to the removed vertex are also removed
+  /// Removes an interior vertex, along with its edges and faces, which have to be triangles, and fills the hole
+  /// with triangles between its neighbors. With three neighbors that's a single triangle, which is quick.
+  /// With more, the hole is cut up by ear clipping: the corners of the hole which stick out, and don't have
+  /// another corner inside the triangle they make with their neighbors, are cut off one at a time,
+  /// widest smallest angle first, until there's a triangle left. The cuts are worked out before anything is
+  /// removed, so it fails without changing the mesh
   pub fn remove_vert(&mut self, vert: &VertRc) -> Result<(), &'static str> {
-    let vert_b = vert.borrow();
-    let mut edges = vert_b.adjacent_edges().to_ptr_vec(); // get e for e in v.edges
-    // Edges are iterated in clockwise order, but we need counter-clockwise order
-    // to establish correct .next links
-    edges.reverse();
+    let edges = removable_vert_edges(vert)?;
+    let ring: Vec<VertRc> = edges.iter().filter_map(|e| e.borrow().get_target()).collect();
+    let diagonals = ear_clip_diagonals(& ring)?;
+    let mut face = self.merge_faces_around(vert)?;
+    for (a, b) in diagonals {
+      // The original face keeps the ear, from a round to b, and the new face has the rest
+      face = self.split_face(& face, & a, & b)?.1;
+    }
+    Ok(())
+  }
 
-    // Must have 3 edges, so that the surrounding faces can be combined to a triangle
-    if edges.len() != 3 { return Err("Vertex must have exactly 3 connecting edges"); }
+  /// Removes the vertex and its edges, and joins the faces around it into one, which is returned
+  fn merge_faces_around(&mut self, vert: &VertRc) -> Result<FaceRc, &'static str> {
+    let edges = removable_vert_edges(vert)?;
+    let vert_b = vert.borrow();
 
+    let old_face_ids: Vec<u32> = edges.iter().filter_map(|e| e.borrow().get_face()).map(|f| f.borrow().id).collect();
     let new_face = Ptr::new_rc(Face::empty(self.new_face_id())); // n_f
 
     for (idx, edge) in edges.iter().enumerate() {
@@ -596,20 +1121,24 @@ impl HalfEdgeMesh {
             .map(|o: VertRc| o.borrow_mut().set_edge_rc(& next)); // e.n.o.e = e.n
         });
 
-      edge_b.pair.upgrade()
-        .map(|p: EdgeRc| self.edges.remove(& p.borrow().id)); // del e.p
-      self.edges.remove(& edge_b.id); // del e
+      if let Some(p) = edge_b.pair.upgrade() {
+        let pair_id = p.borrow().id;
+        self.drop_edge(pair_id); // del e.p
+      }
+      self.drop_edge(edge_b.id); // del e
     }
 
-    self.push_face(new_face); // add n_f
+    self.push_face(new_face.clone()); // add n_f
 
-    for face in vert_b.adjacent_faces() {
-      face.upgrade().map(|f: FaceRc| self.faces.remove(& f.borrow().id)); // del f for f in v.faces
+    for face_id in old_face_ids {
+      self.drop_face(face_id); // del f for f in v.faces
     }
 
-    self.vertices.remove(& vert_b.id); // del v
+    self.drop_vert(vert_b.id); // del v
+    trace!("remove_vert: removed vertex {}", vert_b.id);
+    self.check_after("remove_vert");
 
-    Ok(())
+    Ok(new_face)
   }
 
   pub fn remove_vert_ptr(&mut self, point: &VertPtr) -> Result<(), &'static str> {
@@ -621,26 +1150,773 @@ impl HalfEdgeMesh {
 
   /// flips an edge between two faces so that the faces are each split by
   /// the other diagonal of the parallelogram they form.
-  pub fn flip_edge(&mut self, _edge: &EdgeRc) {
-    unimplemented!();
+  /// The edge keeps its id, and runs between the corners opposite it. Fails if it's on the border, either face
+  /// isn't a triangle, the opposite corners are already joined by an edge, or it has a weight, which would
+  /// otherwise end up on the other diagonal (clear it first with `clear_edge_weight`). Doesn't check that the
+  /// faces stay the right way up
+  pub fn flip_edge(&mut self, edge: &EdgeRc) -> Result<(), &'static str> {
+    let pair = edge.borrow().get_pair().ok_or("Can't flip an edge on the border")?;
+    if self.edge_weights.contains_key(& edge.borrow().id) { return Err("Can't flip an edge with a weight"); }
+    let (face, pair_face) = (edge.borrow().get_face().ok_or("Edge has no face")?, pair.borrow().get_face().ok_or("Edge has no face")?);
+    if face.borrow().num_vertices() != 3 || pair_face.borrow().num_vertices() != 3 { return Err("Can only flip an edge between two triangles"); }
+
+    // edge: a -> b -> c, pair: b -> a -> d
+    let (edge_next, pair_next) = (edge.borrow().get_next().ok_or("Face loop is broken")?, pair.borrow().get_next().ok_or("Face loop is broken")?);
+    let (edge_prev, pair_prev) = (edge_next.borrow().get_next().ok_or("Face loop is broken")?, pair_next.borrow().get_next().ok_or("Face loop is broken")?);
+    let (vert_a, vert_b) = (edge.borrow().get_origin().ok_or("Edge has no origin vertex")?, pair.borrow().get_origin().ok_or("Edge has no origin vertex")?);
+    let (vert_c, vert_d) = (edge_prev.borrow().get_origin().ok_or("Edge has no origin vertex")?, pair_prev.borrow().get_origin().ok_or("Edge has no origin vertex")?);
+    if Rc::ptr_eq(& vert_c, & vert_d) || vert_neighbor_ids(& vert_c).contains(& vert_d.borrow().id) {
+      return Err("Corners opposite the edge are already joined");
+    }
+
+    // Now edge: d -> c -> a, pair: c -> d -> b
+    edge.borrow_mut().set_origin_rc(& vert_d);
+    pair.borrow_mut().set_origin_rc(& vert_c);
+    edge.borrow_mut().set_next_rc(& edge_prev);
+    edge_prev.borrow_mut().set_next_rc(& pair_next);
+    pair_next.borrow_mut().set_next_rc(edge);
+    pair.borrow_mut().set_next_rc(& pair_prev);
+    pair_prev.borrow_mut().set_next_rc(& edge_next);
+    edge_next.borrow_mut().set_next_rc(& pair);
+    pair_next.borrow_mut().set_face_rc(& face);
+    edge_next.borrow_mut().set_face_rc(& pair_face);
+    face.borrow_mut().set_edge_rc(edge);
+    pair_face.borrow_mut().set_edge_rc(& pair);
+    vert_a.borrow_mut().set_edge_rc(& pair_next);
+    vert_b.borrow_mut().set_edge_rc(& edge_next);
+
+    for changed in & [face, pair_face] {
+      changed.borrow_mut().compute_attrs();
+      let face_id = changed.borrow().id;
+      self.mark_face_changed(face_id);
+    }
+    trace!("flip_edge: flipped edge {}", edge.borrow().id);
+    self.check_after("flip_edge");
+    Ok(())
   }
 
-  pub fn flip_edge_ptr(&mut self, edge: &EdgePtr) {
-    if let Some(edge_rc) = edge.upgrade() {
-       self.flip_edge(&edge_rc)
+  pub fn flip_edge_ptr(&mut self, edge: &EdgePtr) -> Result<(), &'static str> {
+    match edge.upgrade() {
+      Some(edge_rc) => self.flip_edge(&edge_rc),
+      None => Err("Provided pointer was invalid"),
     }
   }
 
   /// Inserts a vertex at the position, specified by tval, along edge.origin -> edge.next.origin
   /// The edge's two neighboring faces are each split into two faces.
-  /// All four new faces include the new vertex
-  pub fn split_edge(&mut self, _edge: &EdgeRc, _tval: f32) {
-    unimplemented!();
+  /// All four new faces include the new vertex. Faces which aren't triangles just gain the vertex as a corner.
+  /// Fails if tval isn't strictly between 0 and 1
+  pub fn split_edge(&mut self, edge: &EdgeRc, tval: f32) -> Result<VertRc, &'static str> {
+    if !(tval > 0.0 && tval < 1.0) { return Err("Split has to be strictly between the ends of the edge"); }
+    let (origin, target) = edge_ends(edge)?;
+    let point = origin + (target - origin) * tval;
+    self.split_edge_at(edge, & [point]).map(|mut verts| verts.remove(0))
+  }
+
+  pub fn split_edge_rc(&mut self, edge: &EdgePtr, tval: f32) -> Result<VertRc, &'static str> {
+    match edge.upgrade() {
+      Some(edge_rc) => self.split_edge(& edge_rc, tval),
+      None => Err("Provided pointer was invalid"),
+    }
   }
 
-  pub fn split_edge_rc(&mut self, edge: &EdgePtr, tval: f32) {
-    if let Some(edge_rc) = edge.upgrade() {
-       self.split_edge(& edge_rc, tval)
+  /// Like `split_edge`, but puts the new vertex exactly at the point, which needn't be on the edge,
+  /// for example an intersection computed some other way. Fails if the point isn't beside the edge,
+  /// somewhere strictly between its ends
+  pub fn split_edge_at_point(&mut self, edge: &EdgeRc, point: Point3<f32>) -> Result<VertRc, &'static str> {
+    let (origin, target) = edge_ends(edge)?;
+    let along = target - origin;
+    let tval = (point - origin).dot(along) / along.magnitude2();
+    if !(tval > 0.0 && tval < 1.0) { return Err("Split has to be strictly between the ends of the edge"); }
+    self.split_edge_at(edge, & [point]).map(|mut verts| verts.remove(0))
+  }
+
+  /// Splits the edge into n + 1 equal pieces with n new vertices, returned in order from the edge's origin.
+  /// Each triangle beside the edge is split into a fan of n + 1 triangles around its opposite corner,
+  /// all in one go rather than one split at a time. Faces which aren't triangles just gain the vertices as corners
+  pub fn split_edge_n(&mut self, edge: &EdgeRc, n: usize) -> Result<Vec<VertRc>, &'static str> {
+    let (origin, target) = edge_ends(edge)?;
+    let points: Vec<Point3<f32>> = (1..n + 1).map(|idx| origin + (target - origin) * (idx as f32 / (n + 1) as f32)).collect();
+    self.split_edge_at(edge, & points)
+  }
+
+  /// Inserts new vertices at the points, which run from the edge's origin to its target, into the edge and its pair,
+  /// and fans out the triangles on either side
+  fn split_edge_at(&mut self, edge: &EdgeRc, points: & [Point3<f32>]) -> Result<Vec<VertRc>, &'static str> {
+    if points.is_empty() { return Ok(Vec::new()); }
+    let pair = edge.borrow().get_pair();
+    let sides: Vec<EdgeRc> = Some(edge.clone()).into_iter().chain(pair).collect();
+    let was_triangle: Vec<bool> = sides.iter()
+      .map(|side| side.borrow().get_face().is_some_and(|f| f.borrow().num_vertices() == 3))
+      .collect();
+    for side in & sides { side.borrow().get_next().ok_or("Face loop is broken")?; }
+
+    let new_verts: Vec<VertRc> = points.iter().map(|point| Ptr::new_rc(Vert::empty(self.new_vert_id(), *point))).collect();
+    let origin = edge.borrow().get_origin().ok_or("Edge has no origin vertex")?;
+    let target = edge.borrow().get_target().ok_or("Edge has no target vertex")?;
+
+    // Each side keeps its first piece, and the rest are chained on after it. The pair side runs the other way
+    let mut chains: Vec<Vec<EdgeRc>> = Vec::new();
+    for (side_idx, side) in sides.iter().enumerate() {
+      let face = side.borrow().get_face().ok_or("Edge has no face")?;
+      let after = side.borrow().get_next().ok_or("Face loop is broken")?;
+      let mut chain: Vec<EdgeRc> = vec![side.clone()];
+      for idx in 0..new_verts.len() {
+        let start = if side_idx == 0 { & new_verts[idx] } else { & new_verts[new_verts.len() - 1 - idx] };
+        let piece = Ptr::new_rc(Edge::with_origin(self.new_edge_id(), Ptr::new(start)));
+        piece.borrow_mut().set_face_rc(& face);
+        chain.last().ok_or("Edge chain is empty")?.borrow_mut().set_next_rc(& piece);
+        if side_idx == 0 { start.borrow_mut().set_edge_rc(& piece); }
+        chain.push(piece);
+      }
+      chain.last().ok_or("Edge chain is empty")?.borrow_mut().set_next_rc(& after);
+      chains.push(chain);
+    }
+
+    // Piece i of one side runs between the same vertices as piece n - i of the other
+    if chains.len() == 2 {
+      let count = chains[0].len();
+      for idx in 0..count {
+        let (forward, backward) = (& chains[0][idx], & chains[1][count - 1 - idx]);
+        forward.borrow_mut().set_pair_rc(backward);
+        backward.borrow_mut().set_pair_rc(forward);
+      }
+    }
+
+    // Pieces of a weighted edge keep its weight
+    if let Some(&weight) = self.edge_weights.get(& edge.borrow().id) {
+      for piece in chains.iter().flat_map(|chain| chain[1..].iter()) { self.edge_weights.insert(piece.borrow().id, weight); }
+    }
+    for vert in & new_verts {
+      self.push_vert(vert.clone());
+      let t = segment_param(& origin.borrow().get_pos(), & target.borrow().get_pos(), & vert.borrow().get_pos());
+      self.interpolate_vert_data(origin.borrow().id, target.borrow().id, t, vert.borrow().id);
+    }
+    for chain in & chains {
+      for piece in & chain[1..] { self.push_edge(piece.clone()); }
+    }
+
+    for (side_idx, chain) in chains.iter().enumerate() {
+      if was_triangle[side_idx] {
+        self.fan_split_edge_chain(chain)?;
+      } else if let Some(face) = chain[0].borrow().get_face() {
+        face.borrow_mut().compute_attrs();
+        let face_id = face.borrow().id;
+        self.mark_face_changed(face_id);
+      }
+    }
+
+    trace!("split_edge: added {} vertices to edge {}", new_verts.len(), edge.borrow().id);
+    self.check_after("split_edge");
+    Ok(new_verts)
+  }
+
+  /// Splits what was a triangle, whose first side is now the chain of edges, into a fan of triangles
+  /// from its opposite corner, one on each edge of the chain. The first keeps the original face
+  fn fan_split_edge_chain(&mut self, chain: & [EdgeRc]) -> Result<(), &'static str> {
+    let face = chain[0].borrow().get_face().ok_or("Edge has no face")?;
+    let last_next = chain[chain.len() - 1].borrow().get_next().ok_or("Face loop is broken")?;
+    let closing = last_next.borrow().get_next().ok_or("Face loop is broken")?;
+    let apex = closing.borrow().get_origin().ok_or("Edge has no origin vertex")?;
+
+    // Spokes between each new vertex and the apex, one each way
+    let mut to_apex: Vec<EdgeRc> = Vec::new();
+    let mut from_apex: Vec<EdgeRc> = Vec::new();
+    for piece in & chain[1..] {
+      let vert = piece.borrow().get_origin().ok_or("Edge has no origin vertex")?;
+      let inward = Ptr::new_rc(Edge::with_origin(self.new_edge_id(), Ptr::new(& vert)));
+      let outward = Ptr::new_rc(Edge::with_origin(self.new_edge_id(), Ptr::new(& apex)));
+      inward.borrow_mut().set_pair_rc(& outward);
+      outward.borrow_mut().set_pair_rc(& inward);
+      to_apex.push(inward);
+      from_apex.push(outward);
+    }
+
+    let mut new_faces: Vec<FaceRc> = Vec::new();
+    for (idx, piece) in chain.iter().enumerate() {
+      let tri_face = if idx == 0 { face.clone() } else { Ptr::new_rc(Face::with_edge(self.new_face_id(), Ptr::new(piece))) };
+      let second = if idx + 1 < chain.len() { to_apex[idx].clone() } else { last_next.clone() };
+      let third = if idx > 0 { from_apex[idx - 1].clone() } else { closing.clone() };
+      for side in & [piece, & second, & third] { side.borrow_mut().set_face_rc(& tri_face); }
+      piece.borrow_mut().set_next_rc(& second);
+      second.borrow_mut().set_next_rc(& third);
+      third.borrow_mut().set_next_rc(piece);
+      if idx > 0 { new_faces.push(tri_face); }
+    }
+    face.borrow_mut().set_edge_rc(& chain[0]);
+
+    for spoke in to_apex.into_iter().chain(from_apex) { self.push_edge(spoke); }
+    for new_face in new_faces { self.push_face(new_face); }
+    face.borrow_mut().compute_attrs();
+    let face_id = face.borrow().id;
+    self.mark_face_changed(face_id);
+    Ok(())
+  }
+
+  /// Splits the face in two with a new edge between two of its corners, which mustn't be next to each other,
+  /// or already joined by an edge. Returns the original face, which keeps the corners from `va` round to `vb`,
+  /// the new face, which has the ones from `vb` round to `va`, and the new edge in the original face,
+  /// running from `vb` to `va`. Its pair is in the new face
+  pub fn split_face(&mut self, face: & FaceRc, va: & VertRc, vb: & VertRc) -> Result<(FaceRc, FaceRc, EdgeRc), &'static str> {
+    let face_edges = face.borrow().adjacent_edges().to_ptr_vec();
+    let starts_at = |vert: & VertRc| face_edges.iter()
+      .position(|e| e.borrow().get_origin().is_some_and(|o| Rc::ptr_eq(& o, vert)))
+      .ok_or("Vertex isn't a corner of the face");
+    let (pos_a, pos_b) = (starts_at(va)?, starts_at(vb)?);
+    let len = face_edges.len();
+    if pos_a == pos_b { return Err("Can't split a face between a vertex and itself"); }
+    if (pos_a + 1) % len == pos_b || (pos_b + 1) % len == pos_a { return Err("Vertices are next to each other in the face"); }
+    if vert_neighbor_ids(va).contains(& vb.borrow().id) { return Err("Vertices are already joined by an edge"); }
+
+    let (edge_a, edge_b) = (face_edges[pos_a].clone(), face_edges[pos_b].clone());
+    let (before_a, before_b) = (face_edges[(pos_a + len - 1) % len].clone(), face_edges[(pos_b + len - 1) % len].clone());
+    let new_face = Ptr::new_rc(Face::with_edge(self.new_face_id(), Ptr::new(& edge_b)));
+    let closing_a = Ptr::new_rc(Edge::with_origin(self.new_edge_id(), Ptr::new(vb)));
+    let closing_b = Ptr::new_rc(Edge::with_origin(self.new_edge_id(), Ptr::new(va)));
+
+    closing_a.borrow_mut().set_pair_rc(& closing_b);
+    closing_b.borrow_mut().set_pair_rc(& closing_a);
+    before_b.borrow_mut().set_next_rc(& closing_a);
+    closing_a.borrow_mut().set_next_rc(& edge_a);
+    before_a.borrow_mut().set_next_rc(& closing_b);
+    closing_b.borrow_mut().set_next_rc(& edge_b);
+
+    closing_a.borrow_mut().set_face_rc(face);
+    face.borrow_mut().set_edge_rc(& edge_a);
+    let mut idx = pos_b;
+    while idx != pos_a {
+      face_edges[idx].borrow_mut().set_face_rc(& new_face);
+      idx = (idx + 1) % len;
+    }
+    closing_b.borrow_mut().set_face_rc(& new_face);
+
+    self.push_edge(closing_a.clone());
+    self.push_edge(closing_b);
+    self.push_face(new_face.clone());
+    face.borrow_mut().compute_attrs();
+    let face_id = face.borrow().id;
+    self.mark_face_changed(face_id);
+
+    trace!("split_face: split face {} into it and {}", face_id, new_face.borrow().id);
+    self.check_after("split_face");
+    Ok((face.clone(), new_face, closing_a))
+  }
+
+  /// Checks if collapsing the edge would keep the mesh manifold (the "link condition").
+  /// The ends of the edge may only share the neighbors opposite the edge in its faces,
+  /// those opposite vertices must be left with enough edges to still form triangles,
+  /// and an interior edge can't join two separate parts of the mesh border.
+  /// Assumes triangular faces
+  pub fn can_collapse_edge(& self, edge: & EdgeRc) -> bool {
+    let edge_b = edge.borrow();
+    let (origin, target) = match (edge_b.get_origin(), edge_b.get_target()) {
+      (Some(o), Some(t)) => (o, t),
+      _ => return false,
+    };
+
+    // Vertices opposite the edge in its face, and in its pair's face
+    let mut opposite_ids: HashSet<u32> = HashSet::new();
+    let mut outer_boundary_count = 0;
+    for side in Some(edge.clone()).into_iter().chain(edge_b.get_pair()) {
+      let side_b = side.borrow();
+      if side_b.get_face().map_or(0, |f| f.borrow().num_vertices()) != 3 { return false; }
+      let side_next = match side_b.get_next() { Some(n) => n, None => return false };
+      let apex = match side_next.borrow().get_target() { Some(v) => v, None => return false };
+      let apex_b = apex.borrow();
+      // The apex loses one edge, and needs to keep at least two (on the border) or three (inside)
+      let min_valence = if apex_b.is_boundary() { 3 } else { 4 };
+      if apex_b.valence() < min_valence { return false; }
+      opposite_ids.insert(apex_b.id);
+      if side_next.borrow().is_boundary() { outer_boundary_count += 1; }
+      if side_next.borrow().get_next().is_none_or(|p| p.borrow().is_boundary()) { outer_boundary_count += 1; }
+    }
+
+    if edge_b.is_boundary() {
+      // A lone triangle would collapse to nothing
+      if outer_boundary_count == 2 { return false; }
+    } else if origin.borrow().is_boundary() && target.borrow().is_boundary() {
+      return false;
+    }
+
+    let origin_ids = vert_neighbor_ids(& origin);
+    let target_ids = vert_neighbor_ids(& target);
+    let shared: HashSet<u32> = origin_ids.intersection(& target_ids).cloned().collect();
+
+    shared == opposite_ids
+  }
+
+  /// Collapses the edge by merging its target vertex into its origin vertex, which is then moved to `pos`.
+  /// The faces on either side of the edge are removed, and their remaining edges are paired up, with the heavier
+  /// of their weights if they have any.
+  /// Returns the merged vertex. Fails without changing the mesh if `can_collapse_edge` is false.
+  /// Assumes triangular faces
+  pub fn collapse_edge(&mut self, edge: & EdgeRc, pos: Point3<f32>) -> Result<VertRc, &'static str> {
+    if !self.can_collapse_edge(edge) { return Err("Collapsing the edge would make the mesh non-manifold"); }
+
+    let keep_vert = edge.borrow().get_origin().ok_or("Edge has no origin vertex")?;
+    let gone_vert = edge.borrow().get_target().ok_or("Edge has no target vertex")?;
+
+    let keep_edges = keep_vert.borrow().outgoing_edges();
+    let moved_edges = gone_vert.borrow().outgoing_edges();
+
+    let mut dead_edge_ids: HashSet<u32> = HashSet::new();
+    let mut dead_face_ids: Vec<u32> = Vec::new();
+    let mut apex_verts: Vec<VertRc> = Vec::new();
+
+    let sides: Vec<EdgeRc> = Some(edge.clone()).into_iter().chain(edge.borrow().get_pair()).collect();
+    for side in & sides {
+      // side -> side_next -> side_prev is a triangle which disappears
+      let side_next = side.borrow().get_next().ok_or("Face loop is broken")?;
+      let side_prev = side_next.borrow().get_next().ok_or("Face loop is broken")?;
+      let outer_next = side_next.borrow().get_pair();
+      let outer_prev = side_prev.borrow().get_pair();
+
+      // The two outer edges will run between the same vertices, so they become pairs, and a weight on either
+      // side goes to both, the heavier one if both have one
+      let weight = [& side_next, & side_prev].iter().filter_map(|e| self.edge_weights.get(& e.borrow().id)).cloned().reduce(f32::max);
+      if let Some(weight) = weight {
+        for outer in outer_next.iter().chain(outer_prev.iter()) { self.edge_weights.insert(outer.borrow().id, weight); }
+      }
+      match (outer_next.as_ref(), outer_prev.as_ref()) {
+        (Some(o_n), Some(o_p)) => {
+          o_n.borrow_mut().set_pair_rc(o_p);
+          o_p.borrow_mut().set_pair_rc(o_n);
+        },
+        (Some(o), None) | (None, Some(o)) => { o.borrow_mut().take_pair(Ptr::empty()); },
+        (None, None) => {},
+      }
+
+      // The apex vertex might have pointed at the edge leaving it in the removed face
+      if let Some(apex) = side_prev.borrow().get_origin() {
+        let replacement = outer_next.clone().or_else(|| outer_prev.as_ref().and_then(|o| o.borrow().get_next()));
+        if let Some(new_edge) = replacement {
+          apex.borrow_mut().set_edge_rc(& new_edge);
+        }
+        apex_verts.push(apex);
+      }
+
+      dead_edge_ids.insert(side.borrow().id);
+      dead_edge_ids.insert(side_next.borrow().id);
+      dead_edge_ids.insert(side_prev.borrow().id);
+      if let Some(face) = side.borrow().get_face() { dead_face_ids.push(face.borrow().id); }
+    }
+
+    for moved in & moved_edges {
+      if !dead_edge_ids.contains(& moved.borrow().id) {
+        moved.borrow_mut().set_origin_rc(& keep_vert);
+      }
+    }
+
+    if let Some(live_edge) = keep_edges.iter().chain(moved_edges.iter()).find(|e| !dead_edge_ids.contains(& e.borrow().id)) {
+      keep_vert.borrow_mut().set_edge_rc(live_edge);
+    }
+
+    // The merged vertex's data is blended by where it ends up between the two, before the other one's is dropped
+    let gone_id = gone_vert.borrow().id;
+    let keep_id = keep_vert.borrow().id;
+    let t = segment_param(& keep_vert.borrow().get_pos(), & gone_vert.borrow().get_pos(), & pos);
+    self.interpolate_vert_data(keep_id, gone_id, t, keep_id);
+
+    for edge_id in & dead_edge_ids { self.drop_edge(*edge_id); }
+    for face_id in & dead_face_ids { self.drop_face(*face_id); }
+    self.drop_vert(gone_id);
+
+    self.move_vert(& keep_vert, pos);
+
+    anchor_boundary_vert(& keep_vert);
+    for apex in & apex_verts { anchor_boundary_vert(apex); }
+
+    for out_edge in keep_vert.borrow().outgoing_edges() {
+      if let Some(face) = out_edge.borrow().get_face() {
+        face.borrow_mut().compute_attrs();
+        let face_id = face.borrow().id;
+        self.mark_face_changed(face_id);
+      }
+    }
+
+    trace!("collapse_edge: merged vertex {} into {}, removed faces {:?}", gone_id, keep_vert.borrow().id, dead_face_ids);
+    self.check_after("collapse_edge");
+    Ok(keep_vert)
+  }
+}
+
+/// How far along the segment from a to b the point is, from 0 at a to 1 at b, for the closest point on it
+fn segment_param(a: & Point3<f32>, b: & Point3<f32>, point: & Point3<f32>) -> f32 {
+  let along = b - a;
+  if along.magnitude2() == 0.0 { return 0.5; }
+  ((point - a).dot(along) / along.magnitude2()).clamp(0.0, 1.0)
+}
+
+/// Positions of the edge's origin and target
+fn edge_ends(edge: & EdgeRc) -> Result<(Point3<f32>, Point3<f32>), &'static str> {
+  let edge_b = edge.borrow();
+  let origin = edge_b.get_origin().ok_or("Edge has no origin vertex")?.borrow().get_pos();
+  let target = edge_b.get_target().ok_or("Edge has no target vertex")?.borrow().get_pos();
+  Ok((origin, target))
+}
+
+/// The edges leaving a vertex which `remove_vert` can remove, counterclockwise, or why it can't be removed
+fn removable_vert_edges(vert: & VertRc) -> Result<Vec<EdgeRc>, &'static str> {
+  let vert_b = vert.borrow();
+  let mut edges = vert_b.adjacent_edges().to_ptr_vec(); // get e for e in v.edges
+  // Edges are iterated in clockwise order, but we need counter-clockwise order
+  // to establish correct .next links
+  edges.reverse();
+
+  // Must have at least 3 edges, so that the surrounding faces can be combined into a polygon
+  if edges.len() < 3 { return Err("Vertex must have at least 3 connecting edges"); }
+  if vert_b.is_boundary() { return Err("Can't remove a vertex on the border"); }
+  let faces_are_triangles = edges.iter().all(|e| e.borrow().get_face().is_some_and(|f| f.borrow().num_vertices() == 3));
+  if !faces_are_triangles { return Err("Faces around the vertex must be triangles"); }
+  if vert_neighbor_ids(vert).len() != edges.len() { return Err("Vertex's neighbors must all be different"); }
+  Ok(edges)
+}
+
+/// The cuts which take ears off the polygon, counterclockwise, until it's a triangle, as described for
+/// `remove_vert`, each from the corner before the ear to the one after it. Cuts never join two vertices
+/// which are already joined, or were by an earlier cut
+fn ear_clip_diagonals(corners: & [VertRc]) -> Result<Vec<(VertRc, VertRc)>, &'static str> {
+  let points: Vec<Point3<f32>> = corners.iter().map(|v| v.borrow().get_pos()).collect();
+  let joined = |a: usize, c: usize| vert_neighbor_ids(& corners[a]).contains(& corners[c].borrow().id);
+  let tris = ear_clip(& points, (0..corners.len()).collect(), true, |a, c| !joined(a, c))
+    .ok_or("Couldn't find a corner of the face to cut off")?;
+  // The last triangle is what's left over, not cut off
+  Ok(tris[.. tris.len() - 1].iter().map(|tri| (corners[tri[0]].clone(), corners[tri[2]].clone())).collect())
+}
+
+/// Ids of all the vertices connected to a vertex by an edge, including
+/// the vertex at the start of an incoming border edge
+pub(crate) fn vert_neighbor_ids(vert: & VertRc) -> HashSet<u32> {
+  vert.borrow().neighbor_edges().iter().map(|(_, neighbor)| neighbor.borrow().id).collect()
+}
+
+/// Deep copy, with the same ids for every vertex, edge, and face
+impl Clone for HalfEdgeMesh {
+  fn clone(& self) -> HalfEdgeMesh {
+    let mut mesh = HalfEdgeMesh::empty();
+    mesh.cur_edge_id = self.cur_edge_id;
+    mesh.cur_vert_id = self.cur_vert_id;
+    mesh.cur_face_id = self.cur_face_id;
+    mesh.units = self.units;
+    mesh.uvs = self.uvs.clone();
+    mesh.tangents = self.tangents.clone();
+    mesh.attributes = self.attributes.clone();
+    mesh.edge_weights = self.edge_weights.clone();
+
+    // Create everything first, then link it up by id
+    for (&id, vert) in & self.vertices {
+      mesh.vertices.insert(id, Ptr::new_rc(Vert::empty(id, vert.borrow().get_pos())));
+    }
+    for &id in self.edges.keys() {
+      mesh.edges.insert(id, Ptr::new_rc(Edge::empty(id)));
+    }
+    for (&id, face) in & self.faces {
+      let mut copy = Face::empty(id);
+      copy.normal = face.borrow().normal;
+      copy.center = face.borrow().center;
+      mesh.faces.insert(id, Ptr::new_rc(copy));
+    }
+
+    for (id, edge) in & self.edges {
+      let edge_b = edge.borrow();
+      let mut copy = mesh.edges[id].borrow_mut();
+      copy.sharp = edge_b.sharp;
+      if let Some(next) = edge_b.get_next().and_then(|e| mesh.edges.get(& e.borrow().id)) { copy.set_next_rc(next); }
+      if let Some(pair) = edge_b.get_pair().and_then(|e| mesh.edges.get(& e.borrow().id)) { copy.set_pair_rc(pair); }
+      if let Some(origin) = edge_b.get_origin().and_then(|v| mesh.vertices.get(& v.borrow().id)) { copy.set_origin_rc(origin); }
+      if let Some(face) = edge_b.get_face().and_then(|f| mesh.faces.get(& f.borrow().id)) { copy.set_face_rc(face); }
+    }
+    for (id, vert) in & self.vertices {
+      if let Some(edge) = vert.borrow().get_edge().and_then(|e| mesh.edges.get(& e.borrow().id)) {
+        mesh.vertices[id].borrow_mut().set_edge_rc(edge);
+      }
+    }
+    for (id, face) in & self.faces {
+      if let Some(edge) = face.borrow().get_edge().and_then(|e| mesh.edges.get(& e.borrow().id)) {
+        mesh.faces[id].borrow_mut().set_edge_rc(edge);
+      }
+    }
+
+    // Like any new mesh, the copy starts out with everything in it added
+    mesh.dirty.added_verts = mesh.vertices.keys().cloned().collect();
+    mesh.dirty.added_faces = mesh.faces.keys().cloned().collect();
+
+    mesh
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::{HashMap, HashSet};
+
+  use cgmath::{Point3, Vector2, InnerSpace};
+
+  use super::{HalfEdgeMesh, edge_ends};
+  use ptr::{EdgeRc, FaceRc};
+  use iterators::ToPtrVec;
+  use kernel::EditableMesh;
+
+  fn sphere() -> HalfEdgeMesh { HalfEdgeMesh::icosphere(Point3::new(0.0, 0.0, 0.0), 1.0, 1) }
+
+  /// An open, flat grid of `n` by `n` squares, each cut into two triangles
+  fn grid(n: usize) -> HalfEdgeMesh {
+    let mut positions: Vec<Point3<f32>> = Vec::new();
+    for y in 0..= n {
+      for x in 0..= n { positions.push(Point3::new(x as f32, y as f32, 0.0)); }
+    }
+    let mut polygons: Vec<Vec<usize>> = Vec::new();
+    for y in 0..n {
+      for x in 0..n {
+        let corner = y * (n + 1) + x;
+        polygons.push(vec![corner, corner + 1, corner + n + 2]);
+        polygons.push(vec![corner, corner + n + 2, corner + n + 1]);
+      }
+    }
+    HalfEdgeMesh::from_polygons(& positions, & polygons)
+  }
+
+  fn counts(mesh: & HalfEdgeMesh) -> (usize, usize, usize) { (mesh.num_verts(), mesh.num_edges(), mesh.num_faces()) }
+
+  /// The half-edge running from the vertex at `from` to the one at `to`
+  fn edge_between(mesh: & HalfEdgeMesh, from: (f32, f32), to: (f32, f32)) -> EdgeRc {
+    let at = |pos: Point3<f32>, (x, y): (f32, f32)| pos == Point3::new(x, y, 0.0);
+    mesh.edges()
+      .find(|e| {
+        let (origin, target) = edge_ends(e).unwrap();
+        at(origin, from) && at(target, to)
+      })
+      .unwrap()
+      .clone()
+  }
+
+  /// A copy of the mesh with the two triangles beside its first interior edge merged into a quad,
+  /// and that quad in the copy
+  fn with_quad(mesh: & HalfEdgeMesh) -> (HalfEdgeMesh, FaceRc) {
+    let edge = first_interior_edge(mesh);
+    let (face, pair_face) = (edge.borrow().get_face().unwrap(), edge.borrow().get_pair_face().unwrap());
+    let index: HashMap<u32, usize> = mesh.vertex_order().into_iter().enumerate().map(|(idx, id)| (id, idx)).collect();
+    let corners = |face: & FaceRc| -> Vec<usize> {
+      face.borrow().adjacent_verts().to_ptr_vec().iter().map(|v| index[& v.borrow().id]).collect()
+    };
+
+    // The triangles are (origin, target, c) and (target, origin, d), which together go round origin, d, target, c
+    let origin = index[& edge.borrow().get_origin().unwrap().borrow().id];
+    let target = index[& edge.borrow().get_target().unwrap().borrow().id];
+    let opposite = |corners: Vec<usize>| corners.into_iter().find(|&c| c != origin && c != target).unwrap();
+    let mut polygons: Vec<Vec<usize>> = vec![vec![origin, opposite(corners(& pair_face)), target, opposite(corners(& face))]];
+    let (face_id, pair_id) = (face.borrow().id, pair_face.borrow().id);
+    let mut face_ids: Vec<u32> = mesh.faces.keys().cloned().filter(|&id| id != face_id && id != pair_id).collect();
+    face_ids.sort();
+    polygons.extend(face_ids.iter().map(|id| corners(& mesh.faces[id])));
+
+    let positions: Vec<Point3<f32>> = mesh.vertex_order().iter().map(|id| mesh.vertices[id].borrow().get_pos()).collect();
+    let merged = HalfEdgeMesh::from_polygons(& positions, & polygons);
+    let quad = merged.faces().find(|f| f.borrow().num_vertices() == 4).unwrap().clone();
+    (merged, quad)
+  }
+
+  /// The interior half-edge with the lowest id
+  fn first_interior_edge(mesh: & HalfEdgeMesh) -> EdgeRc {
+    mesh.edges().filter(|e| e.borrow().get_pair().is_some()).min_by_key(|e| e.borrow().id).unwrap().clone()
+  }
+
+  #[test]
+  fn removed_vertices_take_their_texture_coordinates() {
+    let mut mesh = sphere();
+    for id in mesh.vertex_order() {
+      let pos = mesh.vertices[& id].borrow().get_pos();
+      mesh.set_uv(id, Vector2::new(pos.x, pos.y)).unwrap();
+    }
+    mesh.compute_tangents();
+    assert!(mesh.set_uv(10_000, Vector2::new(0.0, 0.0)).is_err());
+
+    let edge = mesh.edges().min_by_key(|e| e.borrow().id).unwrap().clone();
+    let pos = edge.borrow().get_origin().unwrap().borrow().get_pos();
+    mesh.collapse_edge(& edge, pos).unwrap();
+
+    let ids: HashSet<u32> = mesh.vertex_order().into_iter().collect();
+    assert_eq!(mesh.uvs().keys().cloned().collect::<HashSet<u32>>(), ids);
+    assert!(mesh.tangents().keys().all(|id| ids.contains(id)));
+  }
+
+  #[test]
+  fn remove_vert_fills_the_hole() {
+    let mut mesh = sphere();
+    let (verts, edges, faces) = counts(& mesh);
+    let vert = mesh.vertices[& 1].clone();
+    let valence = vert.borrow().valence();
+    mesh.remove_vert(& vert).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    // The hole of `valence` corners takes `valence - 3` diagonals, each two half-edges
+    assert_eq!(counts(& mesh), (verts - 1, edges - 2 * valence + 2 * (valence - 3), faces - 2));
+  }
+
+  #[test]
+  fn remove_vert_on_border_leaves_mesh_alone() {
+    let mut mesh = grid(3);
+    let before = counts(& mesh);
+    let corner = mesh.vertices[& 1].clone();
+    assert!(mesh.remove_vert(& corner).is_err());
+    assert_eq!(counts(& mesh), before);
+    assert_eq!(mesh.validate(), Ok(()));
+
+    let inner = mesh.vertices[& 6].clone();
+    mesh.remove_vert(& inner).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    assert_eq!(counts(& mesh), (before.0 - 1, before.1 - 6, before.2 - 2));
+  }
+
+  #[test]
+  fn split_edge_between_two_triangles() {
+    let mut mesh = sphere();
+    let (verts, edges, faces) = counts(& mesh);
+    let edge = first_interior_edge(& mesh);
+    mesh.split_edge(& edge, 0.5).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    // Each triangle is cut in two, and the edge in half, on both sides
+    assert_eq!(counts(& mesh), (verts + 1, edges + 6, faces + 2));
+
+    let mut mesh = grid(3);
+    let (verts, edges, faces) = counts(& mesh);
+    let edge = edge_between(& mesh, (1.0, 1.0), (2.0, 1.0));
+    mesh.split_edge(& edge, 0.25).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    assert_eq!(counts(& mesh), (verts + 1, edges + 6, faces + 2));
+  }
+
+  #[test]
+  fn split_edge_on_border() {
+    let mut mesh = grid(3);
+    let (verts, edges, faces) = counts(& mesh);
+    let edge = edge_between(& mesh, (1.0, 0.0), (2.0, 0.0));
+    assert!(mesh.split_edge(& edge, 1.0).is_err());
+    assert_eq!(counts(& mesh), (verts, edges, faces));
+
+    let vert = mesh.split_edge(& edge, 0.5).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    assert!(vert.borrow().is_boundary());
+    // Only the one triangle is cut, and there's no half-edge on the outside to split
+    assert_eq!(counts(& mesh), (verts + 1, edges + 3, faces + 1));
+  }
+
+  #[test]
+  fn split_face_across_a_quad() {
+    for mesh in [sphere(), grid(3)] {
+      let (mut mesh, quad) = with_quad(& mesh);
+      assert_eq!(mesh.validate(), Ok(()));
+      let (verts, edges, faces) = counts(& mesh);
+      let corners = quad.borrow().adjacent_verts().to_ptr_vec();
+
+      assert!(mesh.split_face(& quad, & corners[0], & corners[1]).is_err());
+      assert_eq!(counts(& mesh), (verts, edges, faces));
+
+      let (kept, new, edge) = mesh.split_face(& quad, & corners[1], & corners[3]).unwrap();
+      assert_eq!(mesh.validate(), Ok(()));
+      assert_eq!(counts(& mesh), (verts, edges + 2, faces + 1));
+      assert_eq!((kept.borrow().num_vertices(), new.borrow().num_vertices()), (3, 3));
+      assert_eq!(edge.borrow().get_pair_face().unwrap().borrow().id, new.borrow().id);
+    }
+  }
+
+  #[test]
+  fn flip_edge_turns_the_diagonal() {
+    let mut mesh = sphere();
+    let before = counts(& mesh);
+    let edge = first_interior_edge(& mesh);
+    let opposite = edge.borrow().get_next().unwrap().borrow().get_target().unwrap();
+    mesh.flip_edge(& edge).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    assert_eq!(counts(& mesh), before);
+    assert!(edge.borrow().adjacent_verts().to_ptr_vec().iter().any(|v| v.borrow().id == opposite.borrow().id));
+
+    let mut mesh = grid(3);
+    let before = counts(& mesh);
+    let edge = edge_between(& mesh, (1.0, 1.0), (2.0, 2.0));
+    mesh.flip_edge(& edge).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    assert_eq!(counts(& mesh), before);
+    assert_eq!(edge_ends(& edge).unwrap(), (Point3::new(2.0, 1.0, 0.0), Point3::new(1.0, 2.0, 0.0)));
+
+    let border = edge_between(& mesh, (1.0, 0.0), (2.0, 0.0));
+    assert!(mesh.flip_edge(& border).is_err());
+    assert_eq!(mesh.validate(), Ok(()));
+    assert_eq!(counts(& mesh), before);
+  }
+
+  #[test]
+  fn collapse_edge_removes_its_faces() {
+    let mut mesh = sphere();
+    let (verts, edges, faces) = counts(& mesh);
+    let edge = first_interior_edge(& mesh);
+    let pos = edge.borrow().get_origin().unwrap().borrow().get_pos();
+    mesh.collapse_edge(& edge, pos).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    // Both triangles go, and each one's other two sides become one edge
+    assert_eq!(counts(& mesh), (verts - 1, edges - 6, faces - 2));
+
+    let mut mesh = grid(3);
+    let (verts, edges, faces) = counts(& mesh);
+    let edge = edge_between(& mesh, (1.0, 1.0), (2.0, 1.0));
+    mesh.collapse_edge(& edge, Point3::new(1.5, 1.0, 0.0)).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    assert_eq!(counts(& mesh), (verts - 1, edges - 6, faces - 2));
+
+    let (verts, edges, faces) = counts(& mesh);
+    let edge = edge_between(& mesh, (2.0, 0.0), (3.0, 0.0));
+    mesh.collapse_edge(& edge, Point3::new(2.0, 0.0, 0.0)).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    // Only one triangle is beside a border edge
+    assert_eq!(counts(& mesh), (verts - 1, edges - 3, faces - 1));
+  }
+
+  #[test]
+  fn edge_weights_follow_their_edges() {
+    let mut mesh = grid(3);
+    let before = counts(& mesh);
+    let diagonal = edge_between(& mesh, (1.0, 1.0), (2.0, 2.0));
+    mesh.set_edge_weight(& diagonal, 4.0);
+    assert!(mesh.flip_edge(& diagonal).is_err());
+    assert_eq!(counts(& mesh), before);
+    assert_eq!(edge_ends(& diagonal).unwrap(), (Point3::new(1.0, 1.0, 0.0), Point3::new(2.0, 2.0, 0.0)));
+
+    // Collapsing (2, 1) into (1, 1) merges the weighted side (2, 1) - (2, 2) with the diagonal (2, 2) - (1, 1)
+    let side = edge_between(& mesh, (2.0, 1.0), (2.0, 2.0));
+    mesh.set_edge_weight(& side, 5.0);
+    let edge = edge_between(& mesh, (1.0, 1.0), (2.0, 1.0));
+    mesh.collapse_edge(& edge, Point3::new(1.5, 1.0, 0.0)).unwrap();
+    let merged = edge_between(& mesh, (1.5, 1.0), (2.0, 2.0));
+    assert_eq!(mesh.edge_weight(& merged), 5.0);
+    assert_eq!(mesh.edge_weight(& merged.borrow().get_pair().unwrap()), 5.0);
+
+    // Rebuilding from the same polygons keeps the weights on edges whose ends stay where they were
+    let (positions, polygons) = mesh.to_polygons();
+    mesh.rebuild(& positions, & polygons);
+    assert_eq!(mesh.edge_weight(& edge_between(& mesh, (2.0, 2.0), (1.5, 1.0))), 5.0);
+    assert_eq!(mesh.edge_weight(& edge_between(& mesh, (2.0, 2.0), (2.0, 3.0))), 1.0);
+  }
+
+  #[test]
+  fn new_vertices_get_blended_texture_coordinates() {
+    let mut mesh = grid(2);
+    for id in mesh.vertex_order() {
+      let pos = mesh.vertices[& id].borrow().get_pos();
+      mesh.set_uv(id, Vector2::new(pos.x / 2.0, pos.y / 2.0)).unwrap();
+    }
+
+    let face = mesh.faces().min_by_key(|f| f.borrow().id).unwrap().clone();
+    let corners = face.borrow().triangle().unwrap();
+    let center = Point3::new((corners[0].x + corners[1].x + corners[2].x) / 3.0, (corners[0].y + corners[1].y + corners[2].y) / 3.0, 0.0);
+    let apex = mesh.triangulate_face(center, & face).unwrap();
+    let uv = mesh.uvs()[& apex.borrow().id];
+    assert!((uv - Vector2::new(center.x / 2.0, center.y / 2.0)).magnitude() < 1e-5);
+
+    // Rebuilding keeps the data of vertices which stay where they were
+    let (positions, polygons) = mesh.to_polygons();
+    mesh.rebuild(& positions, & polygons);
+    assert_eq!(mesh.uvs().len(), mesh.num_verts());
+    for vert in mesh.verts() {
+      let pos = vert.borrow().get_pos();
+      assert!((mesh.uvs()[& vert.borrow().id] - Vector2::new(pos.x / 2.0, pos.y / 2.0)).magnitude() < 1e-5);
     }
   }
 }
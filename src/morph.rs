@@ -0,0 +1,52 @@
+use cgmath::{EuclideanSpace, Point3};
+
+use mesh::HalfEdgeMesh;
+use ptr::Ptr;
+
+/// Id of the element behind a pointer, if it's set
+fn ptr_id<T, F>(ptr: & Ptr<T>, id: F) -> Option<u32> where F: Fn(& T) -> u32 {
+  ptr.upgrade().map(|rc| id(& rc.borrow()))
+}
+
+impl HalfEdgeMesh {
+  /// Checks if two meshes have the same connectivity: the same vertex, edge, and face ids,
+  /// with every edge linked to the same next edge, pair, origin, and face.
+  /// Positions and other attributes aren't compared
+  pub fn is_topologically_equal(& self, other: & HalfEdgeMesh) -> bool {
+    if self.vertices.len() != other.vertices.len() || self.edges.len() != other.edges.len() || self.faces.len() != other.faces.len() {
+      return false;
+    }
+    if !self.vertices.keys().all(|id| other.vertices.contains_key(id)) { return false; }
+    if !self.faces.keys().all(|id| other.faces.contains_key(id)) { return false; }
+
+    self.edges.iter().all(|(id, edge)| {
+      let other_edge = match other.edges.get(id) { Some(e) => e, None => return false };
+      let (a, b) = (edge.borrow(), other_edge.borrow());
+      ptr_id(& a.next, |e| e.id) == ptr_id(& b.next, |e| e.id) &&
+        ptr_id(& a.pair, |e| e.id) == ptr_id(& b.pair, |e| e.id) &&
+        ptr_id(& a.origin, |v| v.id) == ptr_id(& b.origin, |v| v.id) &&
+        ptr_id(& a.face, |f| f.id) == ptr_id(& b.face, |f| f.id)
+    })
+  }
+
+  /// Linearly interpolates between two meshes with the same connectivity, for blend shapes and morphing.
+  /// At t = 0 the result matches this mesh, and at t = 1 it matches the other one.
+  /// The result is a copy of this mesh, with the same ids
+  pub fn blend(& self, other: & HalfEdgeMesh, t: f32) -> Result<HalfEdgeMesh, &'static str> {
+    if !self.is_topologically_equal(other) {
+      return Err("Meshes must have the same connectivity to be blended");
+    }
+
+    let blended = self.clone();
+    for (id, vert) in & blended.vertices {
+      let from = vert.borrow().get_pos();
+      let to = other.vertices[id].borrow().get_pos();
+      vert.borrow_mut().move_to(Point3::from_vec(from.to_vec() * (1.0 - t) + to.to_vec() * t));
+    }
+    for face in blended.faces.values() {
+      face.borrow_mut().compute_attrs();
+    }
+
+    Ok(blended)
+  }
+}
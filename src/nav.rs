@@ -0,0 +1,106 @@
+use cgmath::{Point3, Vector3};
+
+use mesh::HalfEdgeMesh;
+use views::{VertView, EdgeView, FaceView};
+
+/// A step in a walk over the half-edges of a mesh, started with `HalfEdgeMesh::halfedge`. Every step gives
+/// another navigator, so steps chain like `mesh.halfedge(id).next().pair().face()`; once a step leads
+/// nowhere, past the border or to a dead pointer, every later step leads nowhere too, and the end of the
+/// chain gives None instead of panicking. Finish the walk with `get`, `id`, or one of the value getters
+#[derive(Clone)]
+pub struct HalfEdgeNav<'a>(Option<EdgeView<'a>>);
+
+/// A vertex reached by a walk. See `HalfEdgeNav`
+#[derive(Clone)]
+pub struct VertNav<'a>(Option<VertView<'a>>);
+
+/// A face reached by a walk. See `HalfEdgeNav`
+#[derive(Clone)]
+pub struct FaceNav<'a>(Option<FaceView<'a>>);
+
+impl<'a> HalfEdgeNav<'a> {
+  pub fn get(self) -> Option<EdgeView<'a>> { self.0 }
+
+  pub fn id(& self) -> Option<u32> { self.0.as_ref().map(|e| e.id()) }
+
+  /// Whether every step so far led somewhere
+  pub fn is_valid(& self) -> bool { self.0.is_some() }
+
+  pub fn next(& self) -> HalfEdgeNav<'a> { HalfEdgeNav(self.0.as_ref().and_then(|e| e.next())) }
+
+  pub fn prev(& self) -> HalfEdgeNav<'a> { HalfEdgeNav(self.0.as_ref().and_then(|e| e.prev())) }
+
+  /// The other half of the edge. Leads nowhere on the border
+  pub fn pair(& self) -> HalfEdgeNav<'a> { HalfEdgeNav(self.0.as_ref().and_then(|e| e.pair())) }
+
+  /// The next edge out of the same origin, clockwise. Leads nowhere on the border
+  pub fn rotate_cw(& self) -> HalfEdgeNav<'a> { self.pair().next() }
+
+  /// The next edge out of the same origin, counterclockwise. Leads nowhere on the border
+  pub fn rotate_ccw(& self) -> HalfEdgeNav<'a> { self.prev().pair() }
+
+  pub fn origin(& self) -> VertNav<'a> { VertNav(self.0.as_ref().and_then(|e| e.origin())) }
+
+  pub fn target(& self) -> VertNav<'a> { VertNav(self.0.as_ref().and_then(|e| e.target())) }
+
+  pub fn face(& self) -> FaceNav<'a> { FaceNav(self.0.as_ref().and_then(|e| e.face())) }
+
+  /// The vector from the origin to the target
+  pub fn vector(& self) -> Option<Vector3<f32>> { self.0.as_ref().and_then(|e| e.vector()) }
+}
+
+impl<'a> VertNav<'a> {
+  pub fn get(self) -> Option<VertView<'a>> { self.0 }
+
+  pub fn id(& self) -> Option<u32> { self.0.as_ref().map(|v| v.id()) }
+
+  /// Whether every step so far led somewhere
+  pub fn is_valid(& self) -> bool { self.0.is_some() }
+
+  /// The edge the vertex points to, which starts at it
+  pub fn edge(& self) -> HalfEdgeNav<'a> { HalfEdgeNav(self.0.as_ref().and_then(|v| v.edge())) }
+
+  pub fn pos(& self) -> Option<Point3<f32>> { self.0.as_ref().map(|v| v.pos()) }
+}
+
+impl<'a> FaceNav<'a> {
+  pub fn get(self) -> Option<FaceView<'a>> { self.0 }
+
+  pub fn id(& self) -> Option<u32> { self.0.as_ref().map(|f| f.id()) }
+
+  /// Whether every step so far led somewhere
+  pub fn is_valid(& self) -> bool { self.0.is_some() }
+
+  /// The face's first edge
+  pub fn edge(& self) -> HalfEdgeNav<'a> { HalfEdgeNav(self.0.as_ref().and_then(|f| f.edge())) }
+
+  pub fn normal(& self) -> Option<Vector3<f32>> { self.0.as_ref().map(|f| f.normal()) }
+
+  pub fn center(& self) -> Option<Point3<f32>> { self.0.as_ref().map(|f| f.center()) }
+}
+
+impl<'a> EdgeView<'a> {
+  /// Starts a walk from the edge. See `HalfEdgeNav`
+  pub fn nav(& self) -> HalfEdgeNav<'a> { HalfEdgeNav(Some(self.clone())) }
+}
+
+impl<'a> VertView<'a> {
+  /// Starts a walk from the vertex. See `HalfEdgeNav`
+  pub fn nav(& self) -> VertNav<'a> { VertNav(Some(self.clone())) }
+}
+
+impl<'a> FaceView<'a> {
+  /// Starts a walk from the face. See `HalfEdgeNav`
+  pub fn nav(& self) -> FaceNav<'a> { FaceNav(Some(self.clone())) }
+}
+
+impl HalfEdgeMesh {
+  /// Starts a walk from the half-edge with the id, which leads nowhere if there isn't one. See `HalfEdgeNav`
+  pub fn halfedge(& self, id: u32) -> HalfEdgeNav<'_> { HalfEdgeNav(self.edge_view(id)) }
+
+  /// Starts a walk from the vertex with the id. See `HalfEdgeNav`
+  pub fn vertex_nav(& self, id: u32) -> VertNav<'_> { VertNav(self.vert_view(id)) }
+
+  /// Starts a walk from the face with the id. See `HalfEdgeNav`
+  pub fn face_nav(& self, id: u32) -> FaceNav<'_> { FaceNav(self.face_view(id)) }
+}
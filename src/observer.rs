@@ -0,0 +1,53 @@
+use cgmath::Point3;
+
+/// Receives a callback whenever a vertex, edge, or face is added to or removed from a mesh, a vertex
+/// is moved, or a face's corners change, e.g. to keep GPU buffers, spatial indices, or an undo log in sync
+/// without diffing the mesh after every operation. Attach one with `HalfEdgeMesh::set_observer`.
+/// Every method does nothing by default, so only the interesting ones need to be implemented.
+/// Callbacks happen while the mesh is being modified, so the entities are identified by id only,
+/// and an operation can report an element as removed before its replacements are added
+pub trait MeshObserver {
+  fn vert_added(&mut self, _id: u32, _pos: Point3<f32>) {}
+  fn vert_removed(&mut self, _id: u32) {}
+  /// The vertex was moved from `from` to `to`. The normals of the faces around it change too
+  fn vert_moved(&mut self, _id: u32, _from: Point3<f32>, _to: Point3<f32>) {}
+  fn edge_added(&mut self, _id: u32) {}
+  fn edge_removed(&mut self, _id: u32) {}
+  fn face_added(&mut self, _id: u32) {}
+  fn face_removed(&mut self, _id: u32) {}
+  /// The face is still there, but its edges were relinked, so its corners are different, e.g. by `flip_edge`
+  fn face_changed(&mut self, _id: u32) {}
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  use cgmath::Point3;
+
+  use mesh::HalfEdgeMesh;
+  use super::MeshObserver;
+
+  struct ChangedFaces(Rc<RefCell<Vec<u32>>>);
+
+  impl MeshObserver for ChangedFaces {
+    fn face_changed(&mut self, id: u32) { self.0.borrow_mut().push(id); }
+  }
+
+  #[test]
+  fn flip_reports_both_faces() {
+    let mut mesh = HalfEdgeMesh::icosphere(Point3::new(0.0, 0.0, 0.0), 1.0, 1);
+    let changed = Rc::new(RefCell::new(Vec::new()));
+    mesh.set_observer(ChangedFaces(changed.clone()));
+    let edge = mesh.edges().min_by_key(|e| e.borrow().id).unwrap().clone();
+    let (face, pair_face) = (edge.borrow().get_face().unwrap().borrow().id, edge.borrow().get_pair_face().unwrap().borrow().id);
+    mesh.flip_edge(& edge).unwrap();
+    let mut ids = changed.borrow().clone();
+    ids.sort();
+    ids.dedup();
+    let mut expected = vec![face, pair_face];
+    expected.sort();
+    assert_eq!(ids, expected);
+  }
+}
@@ -0,0 +1,283 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::vec::IntoIter;
+
+use cgmath::{Point3, Vector3};
+
+use mesh::HalfEdgeMesh;
+use kernel::MeshTopology;
+
+// Names from OpenMesh, mapped onto this crate, for porting code written against it.
+// The main differences to watch for when porting:
+//  - Handles are element ids, and there are no invalid handles: where OpenMesh would hand back an
+//    invalid handle, these give None.
+//  - There are no boundary half-edges. A half-edge on the border of an open mesh has no opposite,
+//    so `opposite_halfedge_handle` gives None there, and every half-edge has a face.
+//  - Properties live in a `Properties` store next to the mesh instead of inside it.
+
+/// Something which names an element of the mesh, by its id
+pub trait OmHandle: Copy {
+  fn idx(& self) -> u32;
+
+  /// Whether the element is on the border of the mesh, for `OpenMeshCompat::is_boundary`
+  fn is_boundary_in(self, mesh: & HalfEdgeMesh) -> bool;
+}
+
+/// A handle which points to a half-edge, for `OpenMeshCompat::halfedge_handle`
+pub trait HasHalfedge: OmHandle {
+  fn halfedge_in(self, mesh: & HalfEdgeMesh) -> Option<HalfedgeHandle>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VertexHandle(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HalfedgeHandle(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FaceHandle(pub u32);
+
+impl OmHandle for VertexHandle {
+  fn idx(& self) -> u32 { self.0 }
+
+  fn is_boundary_in(self, mesh: & HalfEdgeMesh) -> bool {
+    mesh.vert(self.0).is_some_and(|v| v.borrow().is_boundary())
+  }
+}
+
+impl OmHandle for HalfedgeHandle {
+  fn idx(& self) -> u32 { self.0 }
+
+  /// True if the half-edge has no opposite
+  fn is_boundary_in(self, mesh: & HalfEdgeMesh) -> bool {
+    mesh.edge(self.0).is_some_and(|e| e.borrow().is_boundary())
+  }
+}
+
+impl OmHandle for FaceHandle {
+  fn idx(& self) -> u32 { self.0 }
+
+  /// True if any of the face's edges is on the border
+  fn is_boundary_in(self, mesh: & HalfEdgeMesh) -> bool {
+    mesh.face_view(self.0).is_some_and(|f| f.edges().iter().any(|e| e.is_boundary()))
+  }
+}
+
+impl HasHalfedge for VertexHandle {
+  fn halfedge_in(self, mesh: & HalfEdgeMesh) -> Option<HalfedgeHandle> {
+    mesh.vertex_nav(self.0).edge().id().map(HalfedgeHandle)
+  }
+}
+
+impl HasHalfedge for FaceHandle {
+  fn halfedge_in(self, mesh: & HalfEdgeMesh) -> Option<HalfedgeHandle> {
+    mesh.face_nav(self.0).edge().id().map(HalfedgeHandle)
+  }
+}
+
+/// OpenMesh's names for getting around a `HalfEdgeMesh`. Bring the trait into scope to use them
+pub trait OpenMeshCompat {
+  fn n_vertices(& self) -> usize;
+  fn n_halfedges(& self) -> usize;
+  /// Number of whole edges, counting each pair of half-edges once
+  fn n_edges(& self) -> usize;
+  fn n_faces(& self) -> usize;
+
+  /// Every vertex, in increasing order of id
+  fn all_vertices(& self) -> Vec<VertexHandle>;
+  /// Every half-edge, in increasing order of id
+  fn all_halfedges(& self) -> Vec<HalfedgeHandle>;
+  /// Every face, in increasing order of id
+  fn all_faces(& self) -> Vec<FaceHandle>;
+
+  /// The outgoing half-edge of a vertex, or the first half-edge of a face
+  fn halfedge_handle<H: HasHalfedge>(& self, handle: H) -> Option<HalfedgeHandle>;
+  fn next_halfedge_handle(& self, heh: HalfedgeHandle) -> Option<HalfedgeHandle>;
+  fn prev_halfedge_handle(& self, heh: HalfedgeHandle) -> Option<HalfedgeHandle>;
+  /// None on the border, where OpenMesh would give a boundary half-edge
+  fn opposite_halfedge_handle(& self, heh: HalfedgeHandle) -> Option<HalfedgeHandle>;
+  /// The vertex the half-edge runs to
+  fn to_vertex_handle(& self, heh: HalfedgeHandle) -> Option<VertexHandle>;
+  /// The vertex the half-edge starts at
+  #[allow(clippy::wrong_self_convention)]
+  fn from_vertex_handle(& self, heh: HalfedgeHandle) -> Option<VertexHandle>;
+  fn face_handle(& self, heh: HalfedgeHandle) -> Option<FaceHandle>;
+
+  fn is_boundary<H: OmHandle>(& self, handle: H) -> bool;
+  fn valence(& self, vh: VertexHandle) -> usize;
+
+  fn point(& self, vh: VertexHandle) -> Option<Point3<f32>>;
+  /// Moves the vertex and updates the faces around it. Returns false if there's no such vertex
+  fn set_point(&mut self, vh: VertexHandle, pos: Point3<f32>) -> bool;
+  fn calc_face_normal(& self, fh: FaceHandle) -> Option<Vector3<f32>>;
+  fn calc_vertex_normal(& self, vh: VertexHandle) -> Option<Vector3<f32>>;
+
+  /// Vertices around the vertex, *clockwise*, like OpenMesh's `vv_cwiter`
+  fn vv_iter(& self, vh: VertexHandle) -> IntoIter<VertexHandle>;
+  /// Half-edges out of the vertex, *clockwise*
+  fn voh_iter(& self, vh: VertexHandle) -> IntoIter<HalfedgeHandle>;
+  /// Half-edges into the vertex, *clockwise*. Incoming border edges are included
+  fn vih_iter(& self, vh: VertexHandle) -> IntoIter<HalfedgeHandle>;
+  /// Faces around the vertex, *clockwise*
+  fn vf_iter(& self, vh: VertexHandle) -> IntoIter<FaceHandle>;
+  /// Corners of the face, counterclockwise
+  fn fv_iter(& self, fh: FaceHandle) -> IntoIter<VertexHandle>;
+  /// Half-edges of the face, counterclockwise
+  fn fh_iter(& self, fh: FaceHandle) -> IntoIter<HalfedgeHandle>;
+  /// Faces across the face's edges, counterclockwise, leaving out border edges
+  fn ff_iter(& self, fh: FaceHandle) -> IntoIter<FaceHandle>;
+}
+
+impl OpenMeshCompat for HalfEdgeMesh {
+  fn n_vertices(& self) -> usize { self.num_verts() }
+  fn n_halfedges(& self) -> usize { self.num_edges() }
+  fn n_edges(& self) -> usize {
+    self.edges().filter(|e| e.borrow().get_pair().is_none_or(|p| p.borrow().id > e.borrow().id)).count()
+  }
+  fn n_faces(& self) -> usize { self.num_faces() }
+
+  fn all_vertices(& self) -> Vec<VertexHandle> { self.vert_ids().into_iter().map(VertexHandle).collect() }
+  fn all_halfedges(& self) -> Vec<HalfedgeHandle> { self.edge_ids().into_iter().map(HalfedgeHandle).collect() }
+  fn all_faces(& self) -> Vec<FaceHandle> { self.face_ids().into_iter().map(FaceHandle).collect() }
+
+  fn halfedge_handle<H: HasHalfedge>(& self, handle: H) -> Option<HalfedgeHandle> { handle.halfedge_in(self) }
+  fn next_halfedge_handle(& self, heh: HalfedgeHandle) -> Option<HalfedgeHandle> {
+    self.halfedge(heh.0).next().id().map(HalfedgeHandle)
+  }
+  fn prev_halfedge_handle(& self, heh: HalfedgeHandle) -> Option<HalfedgeHandle> {
+    self.halfedge(heh.0).prev().id().map(HalfedgeHandle)
+  }
+  fn opposite_halfedge_handle(& self, heh: HalfedgeHandle) -> Option<HalfedgeHandle> {
+    self.halfedge(heh.0).pair().id().map(HalfedgeHandle)
+  }
+  fn to_vertex_handle(& self, heh: HalfedgeHandle) -> Option<VertexHandle> {
+    self.halfedge(heh.0).target().id().map(VertexHandle)
+  }
+  fn from_vertex_handle(& self, heh: HalfedgeHandle) -> Option<VertexHandle> {
+    self.halfedge(heh.0).origin().id().map(VertexHandle)
+  }
+  fn face_handle(& self, heh: HalfedgeHandle) -> Option<FaceHandle> {
+    self.halfedge(heh.0).face().id().map(FaceHandle)
+  }
+
+  fn is_boundary<H: OmHandle>(& self, handle: H) -> bool { handle.is_boundary_in(self) }
+  fn valence(& self, vh: VertexHandle) -> usize {
+    self.vert(vh.0).map_or(0, |v| v.borrow().valence())
+  }
+
+  fn point(& self, vh: VertexHandle) -> Option<Point3<f32>> { self.vertex_nav(vh.0).pos() }
+  fn set_point(&mut self, vh: VertexHandle, pos: Point3<f32>) -> bool {
+    let vert = match self.vert(vh.0) {
+      Some(vert) => vert.clone(),
+      None => return false,
+    };
+    self.move_vert(& vert, pos);
+    let faces: Vec<_> = vert.borrow().outgoing_edges().iter().filter_map(|e| e.borrow().get_face()).collect();
+    for face in faces { face.borrow_mut().compute_attrs(); }
+    true
+  }
+  fn calc_face_normal(& self, fh: FaceHandle) -> Option<Vector3<f32>> { self.face_nav(fh.0).normal() }
+  fn calc_vertex_normal(& self, vh: VertexHandle) -> Option<Vector3<f32>> {
+    self.vert_view(vh.0).map(|v| v.normal())
+  }
+
+  fn vv_iter(& self, vh: VertexHandle) -> IntoIter<VertexHandle> {
+    let neighbors = self.vert_view(vh.0).map(|v| v.neighbors()).unwrap_or_default();
+    neighbors.iter().map(|v| VertexHandle(v.id())).collect::<Vec<_>>().into_iter()
+  }
+  fn voh_iter(& self, vh: VertexHandle) -> IntoIter<HalfedgeHandle> {
+    let outgoing = self.vert_view(vh.0).map(|v| v.outgoing()).unwrap_or_default();
+    outgoing.iter().map(|e| HalfedgeHandle(e.id())).collect::<Vec<_>>().into_iter()
+  }
+  fn vih_iter(& self, vh: VertexHandle) -> IntoIter<HalfedgeHandle> {
+    let outgoing = self.vert_view(vh.0).map(|v| v.outgoing()).unwrap_or_default();
+    outgoing.iter().filter_map(|e| e.prev()).map(|e| HalfedgeHandle(e.id())).collect::<Vec<_>>().into_iter()
+  }
+  fn vf_iter(& self, vh: VertexHandle) -> IntoIter<FaceHandle> {
+    let faces = self.vert_view(vh.0).map(|v| v.faces()).unwrap_or_default();
+    faces.iter().map(|f| FaceHandle(f.id())).collect::<Vec<_>>().into_iter()
+  }
+  fn fv_iter(& self, fh: FaceHandle) -> IntoIter<VertexHandle> {
+    let verts = self.face_view(fh.0).map(|f| f.verts()).unwrap_or_default();
+    verts.iter().map(|v| VertexHandle(v.id())).collect::<Vec<_>>().into_iter()
+  }
+  fn fh_iter(& self, fh: FaceHandle) -> IntoIter<HalfedgeHandle> {
+    let edges = self.face_view(fh.0).map(|f| f.edges()).unwrap_or_default();
+    edges.iter().map(|e| HalfedgeHandle(e.id())).collect::<Vec<_>>().into_iter()
+  }
+  fn ff_iter(& self, fh: FaceHandle) -> IntoIter<FaceHandle> {
+    let faces = self.face_view(fh.0).map(|f| f.neighbors()).unwrap_or_default();
+    faces.iter().map(|f| FaceHandle(f.id())).collect::<Vec<_>>().into_iter()
+  }
+}
+
+/// Names a property in a `Properties` store, holding a `T` for each element of the kind `H` names
+pub struct PropHandle<H, T> {
+  idx: usize,
+  marker: PhantomData<(H, T)>,
+}
+
+// By hand, since deriving would ask for T: Copy
+impl<H, T> Clone for PropHandle<H, T> {
+  fn clone(& self) -> PropHandle<H, T> { *self }
+}
+
+impl<H, T> Copy for PropHandle<H, T> {}
+
+pub type VPropHandle<T> = PropHandle<VertexHandle, T>;
+pub type HPropHandle<T> = PropHandle<HalfedgeHandle, T>;
+pub type FPropHandle<T> = PropHandle<FaceHandle, T>;
+
+/// Values of one property, by element id, with the value of elements which haven't been set
+struct PropColumn<T> {
+  default: T,
+  values: HashMap<u32, T>,
+}
+
+/// Custom per-element data, standing in for OpenMesh's `add_property` and `property`. Values are kept by
+/// element id, so they stay with their elements through edits, but aren't dropped when an element is
+#[derive(Default)]
+pub struct Properties {
+  columns: Vec<Option<Box<dyn Any>>>,
+}
+
+impl Properties {
+  pub fn new() -> Properties { Properties::default() }
+
+  /// Adds a property where every element starts out with the default value
+  pub fn add_property<H: OmHandle, T: 'static>(&mut self, default: T) -> PropHandle<H, T> {
+    self.columns.push(Some(Box::new(PropColumn { default, values: HashMap::new() })));
+    PropHandle { idx: self.columns.len() - 1, marker: PhantomData }
+  }
+
+  /// Drops the property and its values. The handle leads nowhere after
+  pub fn remove_property<H: OmHandle, T: 'static>(&mut self, prop: PropHandle<H, T>) {
+    if let Some(column) = self.columns.get_mut(prop.idx) { *column = None; }
+  }
+
+  /// The element's value, or None if the property was removed
+  pub fn property<H: OmHandle, T: 'static>(& self, prop: PropHandle<H, T>, handle: H) -> Option<& T> {
+    let column = self.column(prop)?;
+    Some(column.values.get(& handle.idx()).unwrap_or(& column.default))
+  }
+
+  /// The element's value to change in place, starting from the default, or None if the property was removed
+  pub fn property_mut<H: OmHandle, T: Clone + 'static>(&mut self, prop: PropHandle<H, T>, handle: H) -> Option<&mut T> {
+    let column = self.columns.get_mut(prop.idx)?.as_mut()?.downcast_mut::<PropColumn<T>>()?;
+    let default = column.default.clone();
+    Some(column.values.entry(handle.idx()).or_insert(default))
+  }
+
+  /// Sets the element's value. Returns false if the property was removed
+  pub fn set_property<H: OmHandle, T: 'static>(&mut self, prop: PropHandle<H, T>, handle: H, value: T) -> bool {
+    match self.columns.get_mut(prop.idx).and_then(|c| c.as_mut()).and_then(|c| c.downcast_mut::<PropColumn<T>>()) {
+      Some(column) => { column.values.insert(handle.idx(), value); true },
+      None => false,
+    }
+  }
+
+  fn column<H, T: 'static>(& self, prop: PropHandle<H, T>) -> Option<& PropColumn<T>> {
+    self.columns.get(prop.idx)?.as_ref()?.downcast_ref::<PropColumn<T>>()
+  }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use cgmath::InnerSpace;
+
+use mesh::HalfEdgeMesh;
+use ptr::VertRc;
+
+/// A sparse matrix in triplet (coordinate) format, with duplicate entries summed.
+/// Entries are kept sorted by row, then column, so they can be handed
+/// directly to a solver library expecting COO or CSR input
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseMatrix {
+  pub rows: usize,
+  pub cols: usize,
+  entries: Vec<(usize, usize, f64)>,
+}
+
+impl SparseMatrix {
+  /// Builds a matrix from unsorted triplets. Duplicate positions are added together
+  pub fn from_triplets(rows: usize, cols: usize, mut triplets: Vec<(usize, usize, f64)>) -> SparseMatrix {
+    triplets.sort_by_key(|&(row, col, _)| (row, col));
+    let mut entries: Vec<(usize, usize, f64)> = Vec::with_capacity(triplets.len());
+    for (row, col, value) in triplets {
+      debug_assert!(row < rows && col < cols);
+      match entries.last_mut() {
+        Some(last) if last.0 == row && last.1 == col => last.2 += value,
+        _ => entries.push((row, col, value)),
+      }
+    }
+    SparseMatrix { rows, cols, entries }
+  }
+
+  /// The (row, column, value) entries, sorted by row and then column
+  pub fn triplets(& self) -> & [(usize, usize, f64)] { & self.entries }
+
+  /// Number of stored entries
+  pub fn nnz(& self) -> usize { self.entries.len() }
+
+  pub fn get(& self, row: usize, col: usize) -> f64 {
+    self.entries.binary_search_by_key(& (row, col), |&(r, c, _)| (r, c))
+      .map_or(0.0, |idx| self.entries[idx].2)
+  }
+
+  pub fn diagonal(& self) -> Vec<f64> {
+    let mut diag = vec![0.0; self.rows.min(self.cols)];
+    for &(row, col, value) in & self.entries {
+      if row == col { diag[row] = value; }
+    }
+    diag
+  }
+
+  /// Matrix-vector product
+  pub fn mul_vec(& self, x: & [f64]) -> Vec<f64> {
+    debug_assert_eq!(x.len(), self.cols);
+    let mut out = vec![0.0; self.rows];
+    for &(row, col, value) in & self.entries {
+      out[row] += value * x[col];
+    }
+    out
+  }
+
+  /// Solves `self * x = b` with the Jacobi-preconditioned conjugate gradient method.
+  /// The matrix must be square, symmetric, and positive definite (or semi-definite with b in its range).
+  /// Stops when the residual norm drops below tolerance times the norm of b
+  pub fn solve_cg(& self, b: & [f64], tolerance: f64, max_iterations: usize) -> Vec<f64> {
+    debug_assert_eq!(self.rows, self.cols);
+    debug_assert_eq!(b.len(), self.rows);
+    let inv_diag: Vec<f64> = self.diagonal().iter().map(|&d| if d.abs() > 1e-12 { 1.0 / d } else { 1.0 }).collect();
+
+    let mut x = vec![0.0; self.rows];
+    let mut residual = b.to_vec();
+    let mut z: Vec<f64> = residual.iter().zip(& inv_diag).map(|(r, d)| r * d).collect();
+    let mut direction = z.clone();
+    let mut rz = dot(& residual, & z);
+    let threshold = tolerance * dot(b, b).sqrt();
+
+    for _ in 0..max_iterations {
+      if dot(& residual, & residual).sqrt() <= threshold { break; }
+      let a_dir = self.mul_vec(& direction);
+      let denom = dot(& direction, & a_dir);
+      if denom.abs() <= 1e-30 { break; }
+      let alpha = rz / denom;
+      for idx in 0..x.len() {
+        x[idx] += alpha * direction[idx];
+        residual[idx] -= alpha * a_dir[idx];
+      }
+      z = residual.iter().zip(& inv_diag).map(|(r, d)| r * d).collect();
+      let rz_next = dot(& residual, & z);
+      let beta = rz_next / rz;
+      rz = rz_next;
+      for idx in 0..direction.len() {
+        direction[idx] = z[idx] + beta * direction[idx];
+      }
+    }
+
+    x
+  }
+
+  /// Solves `self * x = 0` where some entries of x are fixed ahead of time (Dirichlet conditions),
+  /// by moving the fixed columns to the right hand side and solving for the rest with `solve_cg`
+  pub fn solve_with_fixed(& self, fixed: & HashMap<usize, f64>, tolerance: f64, max_iterations: usize) -> Vec<f64> {
+    // Rows of the reduced system, for the unknown entries
+    let mut free_index: Vec<Option<usize>> = vec![None; self.rows];
+    let mut free_count = 0;
+    for (row, slot) in free_index.iter_mut().enumerate() {
+      if !fixed.contains_key(& row) {
+        *slot = Some(free_count);
+        free_count += 1;
+      }
+    }
+
+    let mut rhs = vec![0.0; free_count];
+    let mut triplets: Vec<(usize, usize, f64)> = Vec::with_capacity(self.entries.len());
+    for &(row, col, value) in & self.entries {
+      let free_row = match free_index[row] { Some(r) => r, None => continue };
+      match free_index[col] {
+        Some(free_col) => triplets.push((free_row, free_col, value)),
+        None => rhs[free_row] -= value * fixed[& col],
+      }
+    }
+
+    let reduced = SparseMatrix::from_triplets(free_count, free_count, triplets);
+    let solved = reduced.solve_cg(& rhs, tolerance, max_iterations);
+
+    (0..self.rows).map(|row| match free_index[row] {
+      Some(free_row) => solved[free_row],
+      None => fixed[& row],
+    }).collect()
+  }
+}
+
+fn dot(a: & [f64], b: & [f64]) -> f64 {
+  a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+impl HalfEdgeMesh {
+  /// Vertex ids in the order used for the rows and columns of the mesh matrices
+  pub fn vertex_order(& self) -> Vec<u32> {
+    let mut ids: Vec<u32> = self.vertices.keys().cloned().collect();
+    ids.sort();
+    ids
+  }
+
+  /// Maps each vertex id to its row in the mesh matrices
+  pub fn vertex_index(& self) -> HashMap<u32, usize> {
+    self.vertex_order().into_iter().enumerate().map(|(idx, id)| (id, idx)).collect()
+  }
+
+  /// The cotangent Laplacian, with rows and columns ordered by `vertex_order`.
+  /// Off-diagonal entries are -(cot a + cot b) / 2 for the angles opposite each edge,
+  /// and each diagonal entry is minus the sum of its row, so the matrix is
+  /// symmetric positive semi-definite. Assumes triangular faces
+  pub fn cotangent_laplacian(& self) -> SparseMatrix {
+    let index = self.vertex_index();
+    let mut triplets: Vec<(usize, usize, f64)> = Vec::with_capacity(self.faces.len() * 12);
+
+    for face in self.faces.values() {
+      let face_b = face.borrow();
+      let corners = match face_b.triangle() { Some(c) => c, None => continue };
+      let ids: Vec<usize> = face_b.adjacent_verts()
+        .filter_map(|v| v.upgrade().and_then(|v| index.get(& v.borrow().id).cloned()))
+        .collect();
+      if ids.len() != 3 { continue; }
+
+      for corner in 0..3 {
+        let (i, j) = ((corner + 1) % 3, (corner + 2) % 3);
+        let to_i = corners[i] - corners[corner];
+        let to_j = corners[j] - corners[corner];
+        let sin = to_i.cross(to_j).magnitude() as f64;
+        if sin <= 0.0 { continue; }
+        let weight = 0.5 * to_i.dot(to_j) as f64 / sin;
+        let (row_i, row_j) = (ids[i], ids[j]);
+        triplets.push((row_i, row_j, -weight));
+        triplets.push((row_j, row_i, -weight));
+        triplets.push((row_i, row_i, weight));
+        triplets.push((row_j, row_j, weight));
+      }
+    }
+
+    let size = index.len();
+    SparseMatrix::from_triplets(size, size, triplets)
+  }
+
+  /// The lumped (diagonal) mass matrix, with rows and columns ordered by `vertex_order`.
+  /// Each vertex gets a third of the area of each of its faces
+  pub fn mass_matrix(& self) -> SparseMatrix {
+    let index = self.vertex_index();
+    let mut triplets: Vec<(usize, usize, f64)> = Vec::with_capacity(self.faces.len() * 3);
+
+    for face in self.faces.values() {
+      let face_b = face.borrow();
+      let ids: Vec<usize> = face_b.adjacent_verts()
+        .filter_map(|v| v.upgrade().and_then(|v| index.get(& v.borrow().id).cloned()))
+        .collect();
+      let share = face_b.area() as f64 / ids.len().max(1) as f64;
+      for row in ids { triplets.push((row, row, share)); }
+    }
+
+    let size = index.len();
+    SparseMatrix::from_triplets(size, size, triplets)
+  }
+
+  /// Harmonic weights for a set of handle vertices, as used for skinning and deformation.
+  /// The result has one map from vertex id to weight per handle: the weight is 1 at that handle,
+  /// 0 at the other handles, and smoothly interpolated (solving the Laplace equation) everywhere else.
+  /// The weights at each vertex sum to 1. Vertices not connected to any handle get 0
+  pub fn harmonic_weights(& self, handles: & [VertRc]) -> Vec<HashMap<u32, f32>> {
+    let order = self.vertex_order();
+    let index = self.vertex_index();
+    let laplacian = self.cotangent_laplacian();
+    let handle_rows: Vec<usize> = handles.iter().filter_map(|h| index.get(& h.borrow().id).cloned()).collect();
+    let max_iterations = 10 * order.len().max(1);
+
+    handle_rows.iter().map(|&handle_row| {
+      let fixed: HashMap<usize, f64> = handle_rows.iter()
+        .map(|&row| (row, if row == handle_row { 1.0 } else { 0.0 }))
+        .collect();
+      let solved = laplacian.solve_with_fixed(& fixed, 1e-8, max_iterations);
+      order.iter().zip(solved).map(|(&id, weight)| (id, weight as f32)).collect()
+    }).collect()
+  }
+}
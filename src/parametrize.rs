@@ -0,0 +1,404 @@
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+
+use cgmath::{Vector2, InnerSpace};
+
+use ptr::{Ptr, EdgePtr, EdgeRc, VertRc};
+use vert::Vert;
+use mesh::HalfEdgeMesh;
+use operators::SparseMatrix;
+use util::OpTimer;
+
+/// Limits for the Gauss-Seidel refinement in `map_to_disk`
+const MAX_SWEEPS: usize = 2000;
+const SWEEP_TOLERANCE: f64 = 1e-7;
+
+/// Steps of the search for the largest scale at which the charts still fit in `pack_atlas`
+const PACK_STEPS: usize = 40;
+
+/// A closed loop of boundary edges (edges without a pair) on an open mesh.
+/// The edges are in order, each one starting where the previous one ends,
+/// and run counterclockwise around the faces inside the loop
+#[derive(Debug, Clone)]
+pub struct BoundaryLoop {
+  pub edges: Vec<EdgeRc>,
+}
+
+impl BoundaryLoop {
+  /// The vertices of the loop, in order. The first is the origin of the first edge
+  pub fn verts(& self) -> Vec<VertRc> {
+    self.edges.iter().filter_map(|e| e.borrow().get_origin()).collect()
+  }
+
+  /// Total length of the loop
+  pub fn length(& self) -> f32 {
+    self.edges.iter().filter_map(|e| {
+      let e_b = e.borrow();
+      match (e_b.get_origin(), e_b.get_target()) {
+        (Some(o), Some(t)) => Some((t.borrow().get_pos() - o.borrow().get_pos()).magnitude()),
+        _ => None,
+      }
+    }).sum()
+  }
+}
+
+/// A piece of the surface flattened on its own, e.g. with `map_to_disk`, for packing into a texture atlas
+#[derive(Debug, Clone, Default)]
+pub struct Chart {
+  /// Ids of the faces in the chart
+  pub faces: HashSet<u32>,
+  /// Flattened positions of the chart's vertices, by vertex id. Only the chart's own faces use them,
+  /// so a vertex on a seam can be somewhere else in the neighboring chart
+  pub uvs: HashMap<u32, Vector2<f32>>,
+}
+
+/// Twice the signed area of the polygon
+fn doubled_area(points: & [Vector2<f32>]) -> f32 {
+  let mut area = 0.0;
+  for idx in 0..points.len() {
+    let (a, b) = (points[idx], points[(idx + 1) % points.len()]);
+    area += a.x * b.y - b.x * a.y;
+  }
+  area
+}
+
+/// Places the rectangles, plus the padding, in rows across the unit square, tallest first. Returns their
+/// lower left corners, or None if they don't fit
+fn pack_shelves(sizes: & [Vector2<f32>], order: & [usize], scale: f32, padding: f32) -> Option<Vec<Vector2<f32>>> {
+  let mut corners = vec![Vector2::new(0.0, 0.0); sizes.len()];
+  let (mut x, mut y, mut shelf_height) = (0.0f32, 0.0f32, 0.0f32);
+  for &idx in order {
+    let (width, height) = (sizes[idx].x * scale + padding, sizes[idx].y * scale + padding);
+    if width > 1.0 { return None; }
+    if x + width > 1.0 {
+      y += shelf_height;
+      x = 0.0;
+      shelf_height = 0.0;
+    }
+    corners[idx] = Vector2::new(x, y);
+    x += width;
+    shelf_height = shelf_height.max(height);
+  }
+  if y + shelf_height > 1.0 { None } else { Some(corners) }
+}
+
+impl HalfEdgeMesh {
+  /// Cuts the mesh apart where faces from different groups meet, giving each fan of faces around a vertex
+  /// on the cut its own copy of the vertex, with the same position and data. Returns the vertex each copy
+  /// was made from, by the copy's id
+  fn cut_between_groups(&mut self, group_of: & HashMap<u32, usize>) -> HashMap<u32, u32> {
+    let group = |edge: & EdgeRc| edge.borrow().get_face().and_then(|f| group_of.get(& f.borrow().id).cloned());
+
+    let mut edge_ids: Vec<u32> = self.edges.keys().cloned().collect();
+    edge_ids.sort();
+    let mut cut_verts: HashSet<u32> = HashSet::new();
+    for id in & edge_ids {
+      let edge = self.edges[id].clone();
+      let pair = match edge.borrow().get_pair() { Some(p) => p, None => continue };
+      if group(& edge) == group(& pair) { continue; }
+      for vert in edge.borrow().adjacent_verts().filter_map(|v| v.upgrade()) { cut_verts.insert(vert.borrow().id); }
+      edge.borrow_mut().take_pair(EdgePtr::empty());
+      pair.borrow_mut().take_pair(EdgePtr::empty());
+    }
+
+    let mut leaving: HashMap<u32, Vec<EdgeRc>> = HashMap::new();
+    for id in & edge_ids {
+      let edge = & self.edges[id];
+      if let Some(origin) = edge.borrow().get_origin() {
+        if cut_verts.contains(& origin.borrow().id) { leaving.entry(origin.borrow().id).or_default().push(edge.clone()); }
+      }
+    }
+
+    let mut copied_from: HashMap<u32, u32> = HashMap::new();
+    let mut vert_ids: Vec<u32> = leaving.keys().cloned().collect();
+    vert_ids.sort();
+    for vert_id in vert_ids {
+      let edges = & leaving[& vert_id];
+      // Edges leaving the vertex are in the same fan if one is the pair of the edge coming in before the other
+      let mut fan: Vec<usize> = (0..edges.len()).collect();
+      loop {
+        let mut merged = false;
+        for (idx, edge) in edges.iter().enumerate() {
+          let before = edge.borrow().get_prev().and_then(|p| p.borrow().get_pair());
+          let before_idx = before.and_then(|b| edges.iter().position(|e| e.borrow().id == b.borrow().id));
+          if let Some(other) = before_idx {
+            let lowest = fan[idx].min(fan[other]);
+            if fan[idx] != lowest || fan[other] != lowest {
+              fan[idx] = lowest;
+              fan[other] = lowest;
+              merged = true;
+            }
+          }
+        }
+        if !merged { break; }
+      }
+
+      // The fan with the edge the vertex points to keeps the vertex
+      let vert = self.vertices[& vert_id].clone();
+      let kept_edge = vert.borrow().get_edge().map(|e| e.borrow().id);
+      let kept_fan = edges.iter().position(|e| Some(e.borrow().id) == kept_edge).map(|idx| fan[idx]);
+      let mut fans: Vec<usize> = fan.clone();
+      fans.sort();
+      fans.dedup();
+      for f in fans {
+        if Some(f) == kept_fan { continue; }
+        let copy_id = self.new_vert_id();
+        let copy = Ptr::new_rc(Vert::with_edge(copy_id, vert.borrow().get_pos(), Ptr::new(& edges[f])));
+        let mut changed: Vec<u32> = Vec::new();
+        for (idx, edge) in edges.iter().enumerate() {
+          if fan[idx] != f { continue; }
+          edge.borrow_mut().set_origin_rc(& copy);
+          if let Some(face) = edge.borrow().get_face() { changed.push(face.borrow().id); }
+        }
+        self.push_vert(copy);
+        for face_id in changed { self.mark_face_changed(face_id); }
+        self.interpolate_vert_data(vert_id, vert_id, 0.0, copy_id);
+        if let Some(&tangent) = self.tangents.get(& vert_id) { self.tangents.insert(copy_id, tangent); }
+        copied_from.insert(copy_id, vert_id);
+      }
+    }
+    trace!("cut_between_groups: cut at {} vertices, making {} copies", cut_verts.len(), copied_from.len());
+    copied_from
+  }
+
+  /// Packs the charts into a texture atlas, writing their texture coordinates into `uvs` within [0, 1]².
+  /// That's the mesh's own texture coordinates, which `compute_tangents` and `to_buffers` read, rather than
+  /// a registered attribute, so that the atlas is the texture the rest of the crate sees.
+  /// Charts are scaled so a texel covers about the same area of surface everywhere, turned upright if they're
+  /// taller than wide, and laid out in rows with at least `padding` texels between them at the given
+  /// resolution, and half that to the edge of the atlas, so they don't bleed into each other when filtered.
+  /// As texture coordinates are kept per vertex, the mesh is cut apart along the borders of the charts, with
+  /// a copy of each vertex on a border for each chart it's in. Faces outside every chart keep their texture
+  /// coordinates. Tangents are cleared, as they follow the texture coordinates. Returns the texel density:
+  /// texels per unit of surface length. Fails without changing the mesh if a chart has a face which isn't
+  /// in the mesh, or is in another chart, or a vertex without a flattened position, or if the padding
+  /// leaves no room for the charts
+  pub fn pack_atlas(&mut self, charts: & [Chart], resolution: u32, padding: u32) -> Result<f32, &'static str> {
+    let start = OpTimer::start();
+    if resolution == 0 { return Err("Atlas resolution has to be at least one texel"); }
+
+    let mut group_of: HashMap<u32, usize> = HashMap::new();
+    // Size of each chart's bounding box once scaled to the surface, and whether it's turned a quarter turn
+    let mut sizes: Vec<Vector2<f32>> = Vec::with_capacity(charts.len());
+    let mut placements: Vec<(Vector2<f32>, f32, bool)> = Vec::with_capacity(charts.len());
+    for (idx, chart) in charts.iter().enumerate() {
+      let (mut surface_area, mut flat_area) = (0.0f32, 0.0f32);
+      let mut min = Vector2::new(f32::INFINITY, f32::INFINITY);
+      let mut max = Vector2::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+      for face_id in & chart.faces {
+        let face = self.faces.get(face_id).ok_or("Chart has a face which isn't in the mesh")?;
+        if group_of.insert(*face_id, idx).is_some() { return Err("Face is in more than one chart"); }
+        let face_b = face.borrow();
+        let mut corners: Vec<Vector2<f32>> = Vec::new();
+        for vert in face_b.adjacent_verts().filter_map(|v| v.upgrade()) {
+          let uv = *chart.uvs.get(& vert.borrow().id).ok_or("Chart is missing the flattened position of a vertex")?;
+          min = Vector2::new(min.x.min(uv.x), min.y.min(uv.y));
+          max = Vector2::new(max.x.max(uv.x), max.y.max(uv.y));
+          corners.push(uv);
+        }
+        surface_area += face_b.area();
+        flat_area += doubled_area(& corners).abs() / 2.0;
+      }
+      if chart.faces.is_empty() { min = Vector2::new(0.0, 0.0); max = min; }
+      let scale = if flat_area > 0.0 && surface_area > 0.0 { (surface_area / flat_area).sqrt() } else { 1.0 };
+      let size = (max - min) * scale;
+      let upright = size.y > size.x;
+      sizes.push(if upright { Vector2::new(size.y, size.x) } else { size });
+      placements.push((min, scale, upright));
+    }
+
+    let mut order: Vec<usize> = (0..charts.len()).collect();
+    order.sort_by(|&a, &b| sizes[b].y.partial_cmp(& sizes[a].y).unwrap_or(::std::cmp::Ordering::Equal).then(a.cmp(& b)));
+    let pad = padding as f32 / resolution as f32;
+    if pack_shelves(& sizes, & order, 0.0, pad).is_none() { return Err("Padding leaves no room for the charts"); }
+
+    // The largest scale which fits, by bisection
+    let largest = sizes.iter().map(|s| s.x.max(s.y)).fold(0.0f32, f32::max);
+    let (mut lo, mut hi) = (0.0f32, if largest > 0.0 { 1.0 / largest } else { 0.0 });
+    for _ in 0..PACK_STEPS {
+      let mid = (lo + hi) / 2.0;
+      if pack_shelves(& sizes, & order, mid, pad).is_some() { lo = mid; } else { hi = mid; }
+    }
+    let corners = pack_shelves(& sizes, & order, lo, pad).ok_or("Padding leaves no room for the charts")?;
+
+    let copied_from = self.cut_between_groups(& group_of);
+    self.check_after("pack_atlas");
+    for (idx, chart) in charts.iter().enumerate() {
+      let (min, scale, upright) = placements[idx];
+      let corner = corners[idx] + Vector2::new(pad / 2.0, pad / 2.0);
+      let width = sizes[idx].x;
+      for face_id in & chart.faces {
+        let verts: Vec<VertRc> = self.faces[face_id].borrow().adjacent_verts().filter_map(|v| v.upgrade()).collect();
+        for vert in verts {
+          let id = vert.borrow().id;
+          let local = (chart.uvs[copied_from.get(& id).unwrap_or(& id)] - min) * scale;
+          // A quarter turn counterclockwise keeps the faces facing the same way
+          let local = if upright { Vector2::new(width - local.y, local.x) } else { local };
+          self.uvs.insert(id, corner + local * lo);
+        }
+      }
+    }
+    self.tangents.clear();
+
+    let density = lo * resolution as f32;
+    debug!("pack_atlas: packed {} charts at {} texels per unit, took {:?}", charts.len(), density, start.elapsed());
+    Ok(density)
+  }
+
+  /// Finds every loop of boundary edges in the mesh. A closed mesh has none.
+  /// Loops start at their lowest edge id, and are sorted by it
+  pub fn boundary_loops(& self) -> Vec<BoundaryLoop> {
+    let mut boundary: Vec<&EdgeRc> = self.edges.values().filter(|e| e.borrow().is_boundary()).collect();
+    boundary.sort_by_key(|e| e.borrow().id);
+
+    let mut visited: HashSet<u32> = HashSet::new();
+    let mut loops: Vec<BoundaryLoop> = Vec::new();
+
+    for start in boundary {
+      if visited.contains(& start.borrow().id) { continue; }
+      let mut edges: Vec<EdgeRc> = Vec::new();
+      let mut current = Some(start.clone());
+
+      while let Some(edge) = current {
+        if !visited.insert(edge.borrow().id) { break; }
+        edges.push(edge.clone());
+        // The next boundary edge is the one leaving the end of this one
+        current = edge.borrow().get_target().and_then(|target| {
+          target.borrow().outgoing_edges().into_iter().find(|e| e.borrow().is_boundary())
+        });
+      }
+
+      loops.push(BoundaryLoop { edges });
+    }
+
+    loops
+  }
+
+  /// Flattens a disk-shaped patch into the unit disk, using the mean value weights of Floater.
+  /// The boundary loop is spread around the unit circle in proportion to edge length,
+  /// and every other vertex is placed at the weighted average of its neighbors (a Tutte embedding).
+  /// Mean value weights are always positive, so the result has no flipped triangles.
+  /// Assumes the mesh has disk topology with the given loop as its border, and triangular faces
+  pub fn map_to_disk(& self, boundary: BoundaryLoop) -> HashMap<u32, Vector2<f32>> {
+    let order = self.vertex_order();
+    let index = self.vertex_index();
+    let size = order.len();
+
+    // Boundary positions, by arc length
+    let mut fixed_u: HashMap<usize, f64> = HashMap::new();
+    let mut fixed_v: HashMap<usize, f64> = HashMap::new();
+    let total = boundary.length().max(f32::EPSILON) as f64;
+    let mut travelled = 0.0f64;
+    for edge in & boundary.edges {
+      let edge_b = edge.borrow();
+      let (origin, target) = match (edge_b.get_origin(), edge_b.get_target()) {
+        (Some(o), Some(t)) => (o, t),
+        _ => continue,
+      };
+      if let Some(&row) = index.get(& origin.borrow().id) {
+        let angle = 2.0 * PI * travelled / total;
+        fixed_u.insert(row, angle.cos());
+        fixed_v.insert(row, angle.sin());
+      }
+      travelled += (target.borrow().get_pos() - origin.borrow().get_pos()).magnitude() as f64;
+    }
+
+    // Mean value weights: w_ij = (tan(a / 2) + tan(b / 2)) / |x_i - x_j|, for the angles
+    // at vertex i on either side of the edge to j. Summed across the faces around each edge
+    let mut weights: Vec<(usize, usize, f64)> = Vec::new();
+    let mut uniform: Vec<(usize, usize, f64)> = Vec::new();
+    for face in self.faces.values() {
+      let face_b = face.borrow();
+      let corners = match face_b.triangle() { Some(c) => c, None => continue };
+      let rows: Vec<usize> = face_b.adjacent_verts()
+        .filter_map(|v| v.upgrade().and_then(|v| index.get(& v.borrow().id).cloned()))
+        .collect();
+      if rows.len() != 3 { continue; }
+
+      for corner in 0..3 {
+        let to_j = corners[(corner + 1) % 3] - corners[corner];
+        let to_k = corners[(corner + 2) % 3] - corners[corner];
+        let (len_j, len_k) = (to_j.magnitude() as f64, to_k.magnitude() as f64);
+        if len_j <= 0.0 || len_k <= 0.0 { continue; }
+        let half_tan = (to_j.angle(to_k).0 as f64 / 2.0).tan();
+        weights.push((rows[corner], rows[(corner + 1) % 3], half_tan / len_j));
+        weights.push((rows[corner], rows[(corner + 2) % 3], half_tan / len_k));
+        // Graph Laplacian, with each interior edge getting half its weight from each face
+        uniform.push((rows[corner], rows[(corner + 1) % 3], -0.5));
+        uniform.push((rows[(corner + 1) % 3], rows[corner], -0.5));
+        uniform.push((rows[corner], rows[corner], 0.5));
+        uniform.push((rows[(corner + 1) % 3], rows[(corner + 1) % 3], 0.5));
+      }
+    }
+    let weights = SparseMatrix::from_triplets(size, size, weights);
+    let uniform = SparseMatrix::from_triplets(size, size, uniform);
+
+    // The mean value system isn't symmetric, so start from the uniform Tutte embedding,
+    // which conjugate gradients can solve, and refine it with Gauss-Seidel sweeps
+    let max_iterations = 10 * size.max(1);
+    let mut u = uniform.solve_with_fixed(& fixed_u, 1e-10, max_iterations);
+    let mut v = uniform.solve_with_fixed(& fixed_v, 1e-10, max_iterations);
+
+    let mut neighbors: Vec<Vec<(usize, f64)>> = vec![Vec::new(); size];
+    for &(row, col, weight) in weights.triplets() {
+      if row != col { neighbors[row].push((col, weight)); }
+    }
+
+    for _ in 0..MAX_SWEEPS {
+      let mut change = 0.0f64;
+      for row in 0..size {
+        if fixed_u.contains_key(& row) || neighbors[row].is_empty() { continue; }
+        let (mut sum_u, mut sum_v, mut sum_w) = (0.0, 0.0, 0.0);
+        for &(col, weight) in & neighbors[row] {
+          sum_u += weight * u[col];
+          sum_v += weight * v[col];
+          sum_w += weight;
+        }
+        if sum_w <= 0.0 { continue; }
+        let (new_u, new_v) = (sum_u / sum_w, sum_v / sum_w);
+        change = change.max((new_u - u[row]).abs()).max((new_v - v[row]).abs());
+        u[row] = new_u;
+        v[row] = new_v;
+      }
+      if change < SWEEP_TOLERANCE { break; }
+    }
+
+    order.iter().enumerate().map(|(row, &id)| (id, Vector2::new(u[row] as f32, v[row] as f32))).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Point3, Vector2};
+
+  use mesh::HalfEdgeMesh;
+  use super::Chart;
+
+  #[test]
+  fn atlas_cuts_the_mesh_between_charts() {
+    // Two squares side by side, each made of two triangles, one chart per square
+    let positions: Vec<Point3<f32>> = (0..6).map(|idx| Point3::new((idx % 3) as f32, (idx / 3) as f32, 0.0)).collect();
+    let polygons = vec![vec![0, 1, 4], vec![0, 4, 3], vec![1, 2, 5], vec![1, 5, 4]];
+    let mut mesh = HalfEdgeMesh::from_polygons(& positions, & polygons);
+    let mut face_ids: Vec<u32> = mesh.faces().map(|f| f.borrow().id).collect();
+    face_ids.sort();
+
+    let charts: Vec<Chart> = face_ids.chunks(2).map(|faces| {
+      let mut chart = Chart::default();
+      for &face_id in faces {
+        chart.faces.insert(face_id);
+        for vert in mesh.face(face_id).unwrap().borrow().adjacent_verts().filter_map(|v| v.upgrade()) {
+          let pos = vert.borrow().get_pos();
+          chart.uvs.insert(vert.borrow().id, Vector2::new(pos.x, pos.y));
+        }
+      }
+      chart
+    }).collect();
+
+    mesh.pack_atlas(& charts, 64, 2).unwrap();
+    assert_eq!(mesh.validate(), Ok(()));
+    // The two vertices on the seam get a copy each
+    assert_eq!(mesh.num_verts(), 8);
+    assert!(mesh.uvs().values().all(|uv| uv.x >= 0.0 && uv.x <= 1.0 && uv.y >= 0.0 && uv.y <= 1.0));
+  }
+}
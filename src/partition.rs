@@ -0,0 +1,173 @@
+use std::collections::{HashSet, VecDeque};
+
+use mesh::HalfEdgeMesh;
+use graph::MeshGraph;
+use selection::Selection;
+
+/// Nodes reachable from the start without leaving the nodes `include` accepts, in breadth-first order
+fn flood<F>(graph: & MeshGraph, start: usize, include: F) -> Vec<usize> where F: Fn(usize) -> bool {
+  let mut seen = vec![false; graph.node_count()];
+  seen[start] = true;
+  let mut order = vec![start];
+  let mut idx = 0;
+  while idx < order.len() {
+    for &next in & graph.neighbors[order[idx]] {
+      if !seen[next] && include(next) {
+        seen[next] = true;
+        order.push(next);
+      }
+    }
+    idx += 1;
+  }
+  order
+}
+
+/// Grows each part breadth-first from a seed, until it has its share of the nodes.
+/// A part which runs out of room before then carries on from another seed
+fn grow_parts(graph: & MeshGraph, k: usize) -> Vec<Option<usize>> {
+  let mut part: Vec<Option<usize>> = vec![None; graph.node_count()];
+  let mut remaining = graph.node_count();
+  // Seeds are taken in breadth-first order from far out on each piece of the mesh,
+  // so the parts sweep across it without leaving pockets behind
+  let mut sweep: Vec<usize> = Vec::new();
+  let mut swept = vec![false; graph.node_count()];
+  for start in 0..graph.node_count() {
+    if swept[start] { continue; }
+    let far = *flood(graph, start, |_| true).last().unwrap_or(& start);
+    for n in flood(graph, far, |_| true) { swept[n] = true; sweep.push(n); }
+  }
+  for p in 0..k {
+    let target = remaining / (k - p);
+    let mut size = 0;
+    while size < target {
+      let seed = match sweep.iter().find(|&&n| part[n].is_none()) { Some(&seed) => seed, None => break };
+
+      let mut queue: VecDeque<usize> = VecDeque::new();
+      queue.push_back(seed);
+      part[seed] = Some(p);
+      size += 1;
+      while let Some(node) = queue.pop_front() {
+        for &next in & graph.neighbors[node] {
+          if size >= target { break; }
+          if part[next].is_none() {
+            part[next] = Some(p);
+            size += 1;
+            queue.push_back(next);
+          }
+        }
+      }
+    }
+    remaining -= size;
+  }
+  part
+}
+
+/// Moves the stray pieces of each part, apart from its biggest, to the smallest part next to them,
+/// so that parts come out connected wherever the mesh is
+fn merge_strays(graph: & MeshGraph, part: &mut [Option<usize>], k: usize) {
+  for _ in 0..k {
+    let mut sizes = vec![0usize; k];
+    for p in part.iter().flatten() { sizes[*p] += 1; }
+
+    let mut moved = false;
+    for p in 0..k {
+      let mut pieces: Vec<Vec<usize>> = Vec::new();
+      let mut seen = vec![false; part.len()];
+      for start in 0..part.len() {
+        if part[start] != Some(p) || seen[start] { continue; }
+        let piece = flood(graph, start, |n| part[n] == Some(p));
+        for &n in & piece { seen[n] = true; }
+        pieces.push(piece);
+      }
+      pieces.sort_by_key(|piece| ::std::cmp::Reverse(piece.len()));
+
+      for piece in pieces.iter().skip(1) {
+        let target = piece.iter()
+          .flat_map(|&n| graph.neighbors[n].iter())
+          .filter_map(|&n| part[n])
+          .filter(|&q| q != p)
+          .min_by_key(|&q| (sizes[q], q));
+        if let Some(q) = target {
+          for &n in piece { part[n] = Some(q); }
+          sizes[p] -= piece.len();
+          sizes[q] += piece.len();
+          moved = true;
+        }
+      }
+    }
+    if !moved { break; }
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Splits the faces into `k` regions of about the same size, which are connected as long as the mesh is,
+  /// for processing a large mesh in parallel or in chunks. Regions are grown breadth-first, so they come out
+  /// roughly round, and bits of a region left stranded by the others are handed to a neighbor, which can make
+  /// sizes uneven. Returns fewer than `k` regions if there are fewer faces than that.
+  /// The selections only hold faces
+  pub fn partition(& self, k: usize) -> Vec<Selection> {
+    let graph = self.face_graph();
+    let k = k.min(graph.node_count());
+    if k == 0 { return Vec::new(); }
+
+    let mut part = grow_parts(& graph, k);
+    merge_strays(& graph, &mut part, k);
+
+    let mut regions: Vec<Selection> = vec![Selection::empty(); k];
+    for (idx, p) in part.iter().enumerate() {
+      if let Some(p) = *p { regions[p].faces.insert(graph.ids[idx]); }
+    }
+    regions
+  }
+
+  /// Colors the faces so that no two faces which share an edge have the same color, greedily, most connected
+  /// faces first (Welsh-Powell). Each color is an independent set of faces, so a per-face change which only
+  /// reads and writes the face and its edges can run on all the faces of a color at once, one color after another.
+  /// Faces which only share a corner can get the same color. A triangle mesh needs at most four colors this way.
+  /// The selections only hold faces
+  pub fn color_faces(& self) -> Vec<Selection> {
+    let graph = self.face_graph();
+    let mut order: Vec<usize> = (0..graph.node_count()).collect();
+    order.sort_by_key(|&n| (::std::cmp::Reverse(graph.neighbors[n].len()), graph.ids[n]));
+
+    let mut color: Vec<Option<usize>> = vec![None; graph.node_count()];
+    let mut colors: Vec<Selection> = Vec::new();
+    for n in order {
+      let taken: Vec<usize> = graph.neighbors[n].iter().filter_map(|&m| color[m]).collect();
+      let c = (0..).find(|c| !taken.contains(c)).unwrap_or(0);
+      if c == colors.len() { colors.push(Selection::empty()); }
+      colors[c].faces.insert(graph.ids[n]);
+      color[n] = Some(c);
+    }
+    colors
+  }
+  /// Splits the faces into regions which don't cross edges weighted at `threshold` or more, such as creases
+  /// marked with `set_edge_weight`, each made of the faces which can be reached from each other across lighter
+  /// edges. Edges without a weight count as 1. Regions come in order of their lowest face id.
+  /// The selections hold the faces and the edges between them
+  pub fn segment_by_edge_weight(& self, threshold: f32) -> Vec<Selection> {
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+
+    let mut seen: HashSet<u32> = HashSet::new();
+    let mut regions: Vec<Selection> = Vec::new();
+    for start in face_ids {
+      if !seen.insert(start) { continue; }
+      let mut region = Selection::empty();
+      let mut queue: VecDeque<u32> = VecDeque::new();
+      queue.push_back(start);
+      while let Some(face_id) = queue.pop_front() {
+        region.faces.insert(face_id);
+        for edge in self.faces[& face_id].borrow().adjacent_edges().filter_map(|e| e.upgrade()) {
+          if self.edge_weight(& edge) >= threshold { continue; }
+          let edge_b = edge.borrow();
+          let next = match edge_b.get_pair_face() { Some(f) => f.borrow().id, None => continue };
+          region.edges.insert(edge_b.id);
+          if seen.insert(next) { queue.push_back(next); }
+        }
+      }
+      regions.push(region);
+    }
+    regions
+  }
+}
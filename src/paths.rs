@@ -0,0 +1,149 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use cgmath::{Point3, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::{EdgeRc, VertRc};
+
+/// How `HalfEdgeMesh::edge_path` measures the edges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeWeight {
+  /// Every edge counts the same, so the path has the fewest edges
+  Unit,
+  /// Edges count by their length, so the path is as short as possible
+  Length,
+  /// Edges count by their weight, as set with `HalfEdgeMesh::set_edge_weight`, or 1 if they have none
+  Weight,
+  /// Edges count by their length times their weight, so a heavy edge is as costly as a longer one
+  WeightedLength,
+}
+
+/// Heap entry for `edge_path`, ordered so that the closest vertex is popped first
+struct PathCandidate {
+  dist: f32,
+  vert_id: u32,
+}
+
+impl PartialEq for PathCandidate {
+  fn eq(& self, other: & PathCandidate) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for PathCandidate {}
+
+impl PartialOrd for PathCandidate {
+  fn partial_cmp(& self, other: & PathCandidate) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for PathCandidate {
+  fn cmp(& self, other: & PathCandidate) -> Ordering {
+    other.dist.partial_cmp(& self.dist).unwrap_or(Ordering::Equal)
+      .then_with(|| other.vert_id.cmp(& self.vert_id))
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Sets the weight of the edge and its pair, e.g. how sharp a crease is, or how costly it is to go along.
+  /// `edge_path`, `distances_along_edges`, and `segment_by_edge_weight` use it. Pieces of a split edge keep it
+  pub fn set_edge_weight(&mut self, edge: & EdgeRc, weight: f32) {
+    let edge_b = edge.borrow();
+    self.edge_weights.insert(edge_b.id, weight);
+    if let Some(pair) = edge_b.get_pair() { self.edge_weights.insert(pair.borrow().id, weight); }
+  }
+
+  /// Removes the weight of the edge and its pair, so they count as 1 again
+  pub fn clear_edge_weight(&mut self, edge: & EdgeRc) {
+    let edge_b = edge.borrow();
+    self.edge_weights.remove(& edge_b.id);
+    if let Some(pair) = edge_b.get_pair() { self.edge_weights.remove(& pair.borrow().id); }
+  }
+
+  /// Weight of the edge, or 1 if it hasn't been given one
+  pub fn edge_weight(& self, edge: & EdgeRc) -> f32 {
+    self.edge_weights.get(& edge.borrow().id).cloned().unwrap_or(1.0)
+  }
+
+  /// How much the edge from the vertex at `from` to `neighbor` counts for
+  fn edge_cost(& self, edge: & EdgeRc, from: Point3<f32>, neighbor: & VertRc, weight: EdgeWeight) -> f32 {
+    match weight {
+      EdgeWeight::Unit => 1.0,
+      EdgeWeight::Length => from.distance(neighbor.borrow().get_pos()),
+      EdgeWeight::Weight => self.edge_weight(edge),
+      EdgeWeight::WeightedLength => from.distance(neighbor.borrow().get_pos()) * self.edge_weight(edge),
+    }
+  }
+
+  /// Distance along the edges from the nearest of the sources to every vertex which can be reached from them,
+  /// by id, with Dijkstra's algorithm. With weights, this is an approximate geodesic distance which takes
+  /// the longer way round edges made costly. Weights shouldn't be negative
+  pub fn distances_along_edges(& self, sources: & [VertRc], weight: EdgeWeight) -> HashMap<u32, f32> {
+    let mut dist: HashMap<u32, f32> = HashMap::new();
+    let mut done: HashSet<u32> = HashSet::new();
+    let mut heap: BinaryHeap<PathCandidate> = BinaryHeap::new();
+    for source in sources {
+      let id = source.borrow().id;
+      dist.insert(id, 0.0);
+      heap.push(PathCandidate { dist: 0.0, vert_id: id });
+    }
+
+    while let Some(PathCandidate { dist: vert_dist, vert_id }) = heap.pop() {
+      if !done.insert(vert_id) { continue; }
+      let vert = match self.vertices.get(& vert_id) { Some(v) => v, None => continue };
+      let pos = vert.borrow().get_pos();
+      for (edge, neighbor) in vert.borrow().neighbor_edges() {
+        let neighbor_id = neighbor.borrow().id;
+        let new_dist = vert_dist + self.edge_cost(& edge, pos, & neighbor, weight);
+        if dist.get(& neighbor_id).is_none_or(|&d| new_dist < d) {
+          dist.insert(neighbor_id, new_dist);
+          heap.push(PathCandidate { dist: new_dist, vert_id: neighbor_id });
+        }
+      }
+    }
+    dist
+  }
+
+  /// Finds the shortest path of edges from `a` to `b` with Dijkstra's algorithm, e.g. for marking seams.
+  /// The edges are in order from `a` to `b`, and each one runs from one vertex of the path to the next,
+  /// except along a border, where the only half-edge there is runs the other way.
+  /// None if `b` can't be reached from `a`. Empty if they are the same vertex
+  pub fn edge_path(& self, a: & VertRc, b: & VertRc, weight: EdgeWeight) -> Option<Vec<EdgeRc>> {
+    let (start_id, end_id) = (a.borrow().id, b.borrow().id);
+    let mut dist: HashMap<u32, f32> = HashMap::new();
+    // The edge used to reach each vertex, and the vertex it came from
+    let mut came_from: HashMap<u32, (EdgeRc, u32)> = HashMap::new();
+    let mut heap: BinaryHeap<PathCandidate> = BinaryHeap::new();
+
+    dist.insert(start_id, 0.0);
+    heap.push(PathCandidate { dist: 0.0, vert_id: start_id });
+
+    while let Some(PathCandidate { dist: vert_dist, vert_id }) = heap.pop() {
+      if vert_id == end_id { break; }
+      if dist.get(& vert_id).is_some_and(|&d| vert_dist > d) { continue; }
+      let vert = match self.vertices.get(& vert_id) { Some(v) => v, None => continue };
+
+      let pos = vert.borrow().get_pos();
+      for (edge, neighbor) in vert.borrow().neighbor_edges() {
+        let step = self.edge_cost(& edge, pos, & neighbor, weight);
+        let neighbor_id = neighbor.borrow().id;
+        let new_dist = vert_dist + step;
+        if dist.get(& neighbor_id).is_none_or(|&d| new_dist < d) {
+          dist.insert(neighbor_id, new_dist);
+          came_from.insert(neighbor_id, (edge, vert_id));
+          heap.push(PathCandidate { dist: new_dist, vert_id: neighbor_id });
+        }
+      }
+    }
+
+    if !dist.contains_key(& end_id) { return None; }
+
+    let mut path: Vec<EdgeRc> = Vec::new();
+    let mut current = end_id;
+    while current != start_id {
+      let (edge, previous) = came_from.get(& current)?;
+      path.push(edge.clone());
+      current = *previous;
+    }
+    path.reverse();
+    Some(path)
+  }
+}
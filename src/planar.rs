@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Point2, Vector2, MetricSpace};
+
+use geom::Polygon2;
+
+/// Points closer together than this fraction of the size of the input are welded into one vertex
+pub(crate) const WELD_TOLERANCE: f32 = 1e-6;
+
+/// Vertex of a `HalfEdgeMesh2`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vert2 {
+  pub pos: Point2<f32>,
+  /// One of the half-edges leaving the vertex
+  pub edge: usize,
+}
+
+/// Half-edge of a `HalfEdgeMesh2`. Every half-edge has a twin, since even the outside of
+/// the subdivision is a face
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge2 {
+  pub origin: usize,
+  pub twin: usize,
+  pub next: usize,
+  pub prev: usize,
+  /// The face on the left of the half-edge
+  pub face: usize,
+}
+
+/// Face of a `HalfEdgeMesh2`. The unbounded face has no outer boundary. Any face can have holes,
+/// one for each separate piece of the subdivision inside it
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Face2 {
+  /// A half-edge on the outer boundary, which runs counterclockwise
+  pub outer: Option<usize>,
+  /// A half-edge on the boundary of each hole, which runs clockwise
+  pub inner: Vec<usize>,
+}
+
+/// A planar subdivision as a doubly connected edge list (DCEL): vertices, half-edges, and faces,
+/// indexed by position in their lists. Unlike `HalfEdgeMesh`, it covers the whole plane, so there is
+/// a face on both sides of every edge, including the unbounded face around everything
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HalfEdgeMesh2 {
+  pub verts: Vec<Vert2>,
+  pub edges: Vec<Edge2>,
+  pub faces: Vec<Face2>,
+}
+
+/// Welds points which are within the tolerance of each other, by bucketing them in a grid
+pub(crate) struct Welder {
+  pub(crate) positions: Vec<Point2<f32>>,
+  cells: HashMap<(i64, i64), Vec<usize>>,
+  tolerance: f32,
+}
+
+impl Welder {
+  pub(crate) fn new(tolerance: f32) -> Welder {
+    Welder { positions: Vec::new(), cells: HashMap::new(), tolerance }
+  }
+
+  pub(crate) fn weld(&mut self, point: Point2<f32>) -> usize {
+    let cell_size = self.tolerance.max(f32::MIN_POSITIVE);
+    let cell = ((point.x / cell_size).floor() as i64, (point.y / cell_size).floor() as i64);
+    for dx in -1..2 {
+      for dy in -1..2 {
+        if let Some(found) = self.cells.get(& (cell.0 + dx, cell.1 + dy)) {
+          if let Some(&idx) = found.iter().find(|&&idx| self.positions[idx].distance(point) <= self.tolerance) {
+            return idx;
+          }
+        }
+      }
+    }
+    self.positions.push(point);
+    self.cells.entry(cell).or_default().push(self.positions.len() - 1);
+    self.positions.len() - 1
+  }
+}
+
+fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 { a.x * b.y - a.y * b.x }
+
+/// Where along the first segment the second one touches it, as fractions of its length:
+/// the crossing point, or the ends of the second segment if they lie on the first
+fn touch_params(a: (Point2<f32>, Point2<f32>), b: (Point2<f32>, Point2<f32>), tolerance: f32) -> Vec<f32> {
+  let (r, s) = (a.1 - a.0, b.1 - b.0);
+  let len2 = r.x * r.x + r.y * r.y;
+  if len2 == 0.0 { return Vec::new(); }
+  let mut params = Vec::new();
+
+  let denom = cross(r, s);
+  if denom.abs() > tolerance * tolerance {
+    let t = cross(b.0 - a.0, s) / denom;
+    let u = cross(b.0 - a.0, r) / denom;
+    let (t_tol, u_tol) = (tolerance / len2.sqrt(), tolerance / (s.x * s.x + s.y * s.y).sqrt());
+    if t >= -t_tol && t <= 1.0 + t_tol && u >= -u_tol && u <= 1.0 + u_tol { params.push(t.clamp(0.0, 1.0)); }
+  }
+
+  // Ends of the other segment lying on this one, which covers overlapping segments
+  for end in & [b.0, b.1] {
+    let t = (end - a.0).x * r.x / len2 + (end - a.0).y * r.y / len2;
+    if (0.0..=1.0).contains(& t) && (a.0 + r * t).distance(*end) <= tolerance { params.push(t); }
+  }
+  params
+}
+
+impl HalfEdgeMesh2 {
+  /// Index of the unbounded face
+  pub const UNBOUNDED_FACE: usize = 0;
+
+  /// Builds the subdivision of the plane made by the segments (a planar straight-line graph).
+  /// Segments are split wherever they cross or touch each other, ends which nearly meet are welded,
+  /// and overlapping pieces are merged, so the input doesn't have to be clean
+  pub fn from_segments(segments: & [(Point2<f32>, Point2<f32>)]) -> HalfEdgeMesh2 {
+    let mut min = Point2::new(f32::INFINITY, f32::INFINITY);
+    let mut max = Point2::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for point in segments.iter().flat_map(|s| vec![s.0, s.1]) {
+      min = Point2::new(min.x.min(point.x), min.y.min(point.y));
+      max = Point2::new(max.x.max(point.x), max.y.max(point.y));
+    }
+    let tolerance = if segments.is_empty() { 0.0 } else { min.distance(max) * WELD_TOLERANCE };
+
+    let mut welder = Welder::new(tolerance);
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    for (idx, &segment) in segments.iter().enumerate() {
+      let mut params: Vec<f32> = vec![0.0, 1.0];
+      for (other_idx, &other) in segments.iter().enumerate() {
+        if other_idx != idx { params.extend(touch_params(segment, other, tolerance)); }
+      }
+      params.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+
+      let along: Vec<usize> = params.iter().map(|&t| welder.weld(segment.0 + (segment.1 - segment.0) * t)).collect();
+      for pair in along.windows(2) { edges.push((pair[0], pair[1])); }
+    }
+
+    HalfEdgeMesh2::from_edges(welder.positions, & edges)
+  }
+
+  /// Builds the subdivision from vertex positions and the edges between them, which must only meet
+  /// at their ends. Edges of zero length, and repeated edges, are skipped.
+  /// Faces are found by walking around each vertex in angle order, so nothing else about them has to be given
+  pub fn from_edges(positions: Vec<Point2<f32>>, edges: & [(usize, usize)]) -> HalfEdgeMesh2 {
+    let mut mesh = HalfEdgeMesh2::default();
+
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+    for &(a, b) in edges {
+      if a == b || !seen.insert((a.min(b), a.max(b))) { continue; }
+      let idx = mesh.edges.len();
+      mesh.edges.push(Edge2 { origin: a, twin: idx + 1, next: 0, prev: 0, face: 0 });
+      mesh.edges.push(Edge2 { origin: b, twin: idx, next: 0, prev: 0, face: 0 });
+      outgoing[a].push(idx);
+      outgoing[b].push(idx + 1);
+    }
+
+    // Vertices which no edge reaches are dropped
+    let mut vert_index: Vec<usize> = vec![usize::MAX; positions.len()];
+    for (old, pos) in positions.iter().enumerate() {
+      if outgoing[old].is_empty() { continue; }
+      vert_index[old] = mesh.verts.len();
+      mesh.verts.push(Vert2 { pos: *pos, edge: outgoing[old][0] });
+    }
+    for edge in &mut mesh.edges { edge.origin = vert_index[edge.origin]; }
+    outgoing.retain(|out| !out.is_empty());
+
+    // Around each vertex, counterclockwise. An edge arriving at a vertex continues along
+    // the edge leaving it just clockwise of its twin, which keeps the face on the left
+    for out in &mut outgoing {
+      out.sort_by(|&a, &b| {
+        let angle = |e: usize| { let d = mesh.edge_vector(e); d.y.atan2(d.x) };
+        angle(a).partial_cmp(& angle(b)).unwrap_or(::std::cmp::Ordering::Equal)
+      });
+      for (pos, &leaving) in out.iter().enumerate() {
+        let arriving = mesh.edges[leaving].twin;
+        let next = out[(pos + out.len() - 1) % out.len()];
+        mesh.edges[arriving].next = next;
+        mesh.edges[next].prev = arriving;
+      }
+    }
+
+    mesh.build_faces();
+    mesh
+  }
+
+  /// Direction and length of the half-edge
+  pub fn edge_vector(& self, edge: usize) -> Vector2<f32> {
+    let twin = self.edges[edge].twin;
+    self.verts[self.edges[twin].origin].pos - self.verts[self.edges[edge].origin].pos
+  }
+
+  /// The half-edges around the cycle the half-edge is part of, starting with it
+  pub fn cycle(& self, start: usize) -> Vec<usize> {
+    let mut cycle = vec![start];
+    let mut edge = self.edges[start].next;
+    while edge != start && cycle.len() <= self.edges.len() {
+      cycle.push(edge);
+      edge = self.edges[edge].next;
+    }
+    cycle
+  }
+
+  /// Corners of the cycle the half-edge is part of, as a polygon
+  pub fn cycle_polygon(& self, start: usize) -> Polygon2 {
+    Polygon2::new(self.cycle(start).iter().map(|&e| self.verts[self.edges[e].origin].pos).collect())
+  }
+
+  /// The outer boundary of the face, counterclockwise. None for the unbounded face
+  pub fn face_polygon(& self, face: usize) -> Option<Polygon2> {
+    self.faces[face].outer.map(|edge| self.cycle_polygon(edge))
+  }
+
+  /// Area of the face, not counting its holes. Infinite for the unbounded face
+  pub fn face_area(& self, face: usize) -> f32 {
+    let face = & self.faces[face];
+    match face.outer {
+      Some(edge) => self.cycle_polygon(edge).signed_area() + face.inner.iter().map(|&e| self.cycle_polygon(e).signed_area()).sum::<f32>(),
+      None => f32::INFINITY,
+    }
+  }
+
+  /// Splits the half-edges into cycles, and makes faces out of them. Counterclockwise cycles are
+  /// the outer boundaries of bounded faces. The rest run clockwise around a connected piece of
+  /// the subdivision, and are holes in the smallest face of another piece which has them inside
+  fn build_faces(&mut self) {
+    // Connected pieces, to keep a piece from being put inside its own faces
+    let mut piece: Vec<usize> = vec![usize::MAX; self.verts.len()];
+    for start in 0..self.verts.len() {
+      if piece[start] != usize::MAX { continue; }
+      let mut stack = vec![start];
+      piece[start] = start;
+      while let Some(vert) = stack.pop() {
+        let first = self.verts[vert].edge;
+        let mut edge = first;
+        loop {
+          let target = self.edges[self.edges[edge].twin].origin;
+          if piece[target] == usize::MAX { piece[target] = start; stack.push(target); }
+          edge = self.edges[self.edges[edge].twin].next;
+          if edge == first { break; }
+        }
+      }
+    }
+
+    let mut visited: Vec<bool> = vec![false; self.edges.len()];
+    let mut outers: Vec<(usize, f32, Polygon2)> = Vec::new();
+    let mut holes: Vec<usize> = Vec::new();
+    for start in 0..self.edges.len() {
+      if visited[start] { continue; }
+      for &edge in & self.cycle(start) { visited[edge] = true; }
+      let polygon = self.cycle_polygon(start);
+      let area = polygon.signed_area();
+      if area > 0.0 { outers.push((start, area, polygon)); } else { holes.push(start); }
+    }
+
+    self.faces = vec![Face2::default()];
+    for &(edge, _, _) in & outers {
+      self.faces.push(Face2 { outer: Some(edge), inner: Vec::new() });
+    }
+    for hole in holes {
+      let origin = self.edges[hole].origin;
+      let point = self.verts[origin].pos;
+      let around = outers.iter().enumerate()
+        .filter(|&(_, (edge, _, polygon))| piece[self.edges[*edge].origin] != piece[origin] && polygon.contains(& point))
+        .min_by(|a, b| (a.1).1.partial_cmp(& (b.1).1).unwrap_or(::std::cmp::Ordering::Equal))
+        .map_or(HalfEdgeMesh2::UNBOUNDED_FACE, |(idx, _)| idx + 1);
+      self.faces[around].inner.push(hole);
+    }
+
+    for face in 0..self.faces.len() {
+      let starts: Vec<usize> = self.faces[face].outer.iter().chain(self.faces[face].inner.iter()).cloned().collect();
+      for start in starts {
+        for edge in self.cycle(start) { self.edges[edge].face = face; }
+      }
+    }
+  }
+
+  /// The face the point is in, or `UNBOUNDED_FACE` if it's outside everything. Points right on an edge
+  /// may go either way. Tests every face, so this takes time linear in the size of the subdivision
+  pub fn face_containing(& self, point: Point2<f32>) -> usize {
+    let mut best: Option<(usize, f32)> = None;
+    for (idx, face) in self.faces.iter().enumerate() {
+      let outer = match face.outer { Some(edge) => self.cycle_polygon(edge), None => continue };
+      if !outer.contains(& point) { continue; }
+      if face.inner.iter().any(|&hole| self.cycle_polygon(hole).contains(& point)) { continue; }
+      let area = outer.signed_area();
+      if best.is_none_or(|(_, best_area)| area < best_area) { best = Some((idx, area)); }
+    }
+    best.map_or(HalfEdgeMesh2::UNBOUNDED_FACE, |(idx, _)| idx)
+  }
+}
@@ -0,0 +1,75 @@
+use cgmath::{Point3, EuclideanSpace, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+
+/// Faces of the icosahedron, by index into the points given to `from_icosahedron_pts`
+const ICOSAHEDRON_FACES: [[usize; 3]; 20] = [
+  [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+  [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+  [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+  [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+];
+
+/// Sides of the cube, by index into the points given to `from_cube_pts`
+const CUBE_SIDES: [[usize; 4]; 6] = [
+  [0, 2, 3, 1], [4, 5, 7, 6],
+  [0, 1, 5, 4], [2, 6, 7, 3],
+  [0, 4, 6, 2], [1, 3, 7, 5],
+];
+
+impl HalfEdgeMesh {
+  /// Construct a half edge mesh from the twelve corners of an icosahedron. For the faces to point outward,
+  /// the points should be arranged like these ones, where phi is the golden ratio:
+  /// (-1, phi, 0), (1, phi, 0), (-1, -phi, 0), (1, -phi, 0),
+  /// (0, -1, phi), (0, 1, phi), (0, -1, -phi), (0, 1, -phi),
+  /// (phi, 0, -1), (phi, 0, 1), (-phi, 0, -1), (-phi, 0, 1)
+  pub fn from_icosahedron_pts(pts: & [Point3<f32>; 12]) -> HalfEdgeMesh {
+    HalfEdgeMesh::from_face_vertex_mesh(pts, & ICOSAHEDRON_FACES)
+  }
+
+  /// Construct a half edge mesh from the eight corners of a cube, with each side split into two triangles.
+  /// Point i should be the corner which is at (i & 1, (i >> 1) & 1, (i >> 2) & 1) on the unit cube,
+  /// e.g. pts[0] is the corner at the minimum x, y, and z, and pts[7] the one at the maximum
+  pub fn from_cube_pts(pts: & [Point3<f32>; 8]) -> HalfEdgeMesh {
+    let triangles: Vec<[usize; 3]> = CUBE_SIDES.iter()
+      .flat_map(|s| vec![[s[0], s[1], s[2]], [s[0], s[2], s[3]]])
+      .collect();
+    HalfEdgeMesh::from_face_vertex_mesh(pts, & triangles)
+  }
+
+  /// An icosahedron with the given circumradius, subdivided `rounds` times with `subdivided`
+  pub fn icosphere(center: Point3<f32>, radius: f32, rounds: usize) -> HalfEdgeMesh {
+    let phi = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let scale = radius / (1.0 + phi * phi).sqrt();
+    let unit = [
+      (-1.0, phi, 0.0), (1.0, phi, 0.0), (-1.0, -phi, 0.0), (1.0, -phi, 0.0),
+      (0.0, -1.0, phi), (0.0, 1.0, phi), (0.0, -1.0, -phi), (0.0, 1.0, -phi),
+      (phi, 0.0, -1.0), (phi, 0.0, 1.0), (-phi, 0.0, -1.0), (-phi, 0.0, 1.0),
+    ];
+    let mut pts = [center; 12];
+    for (pt, &(x, y, z)) in pts.iter_mut().zip(unit.iter()) {
+      *pt = Point3::new(center.x + x * scale, center.y + y * scale, center.z + z * scale);
+    }
+    HalfEdgeMesh::from_icosahedron_pts(& pts).subdivided(rounds)
+  }
+
+  /// A copy of the mesh with every triangle split into four at its edge midpoints, `rounds` times over,
+  /// with the vertices pushed out onto a sphere after each round. The sphere is centered on the average
+  /// of the original vertices, and its radius is their average distance from there, so a platonic solid
+  /// turns into a sphere with the same circumradius. Polygons are split into triangle fans first
+  pub fn subdivided(& self, rounds: usize) -> HalfEdgeMesh {
+    let mut mesh = self.clone();
+    if rounds == 0 || self.vertices.is_empty() { return mesh; }
+
+    let positions: Vec<Point3<f32>> = self.vertices.values().map(|v| v.borrow().get_pos()).collect();
+    let center = Point3::centroid(& positions);
+    let radius = positions.iter().map(|p| p.distance(center)).sum::<f32>() / positions.len() as f32;
+
+    for _ in 0..rounds {
+      mesh.subdivide_adaptive(|_| true, 1);
+      mesh.project_to_sphere(center, radius);
+    }
+
+    mesh
+  }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::VertRc;
+use kernel::EditableMesh;
+
+impl HalfEdgeMesh {
+  /// Moves every vertex which is within `max_dist` of the surface of `target` onto the closest
+  /// point of that surface, e.g. to conform a retopologized mesh to a scan.
+  /// Vertices further away are left where they are. Returns the number of vertices moved.
+  /// Only the triangular faces of `target` are considered
+  pub fn snap_vertices_to(&mut self, target: & HalfEdgeMesh, max_dist: f32) -> usize {
+    let bvh = target.build_bvh();
+
+    let mut moves: Vec<(u32, Point3<f32>)> = Vec::new();
+    for vert in self.vertices.values() {
+      let vert_b = vert.borrow();
+      if let Some((pos, _)) = bvh.closest_point(& vert_b.get_pos(), max_dist) { moves.push((vert_b.id, pos)); }
+    }
+
+    for (id, pos) in & moves {
+      if let Some(vert) = self.vertices.get(id).cloned() { self.move_vert(& vert, *pos); }
+    }
+    if !moves.is_empty() {
+      for face in self.faces.values() { face.borrow_mut().compute_attrs(); }
+    }
+
+    moves.len()
+  }
+
+  /// Pushes every vertex straight out from `center`, or in toward it, onto the sphere with the given radius,
+  /// e.g. to round off a subdivided solid into a planet or a dome. A vertex right at the center stays there
+  pub fn project_to_sphere(&mut self, center: Point3<f32>, radius: f32) {
+    let verts: Vec<VertRc> = self.vertices.values().cloned().collect();
+    for vert in & verts {
+      let offset = vert.borrow().get_pos() - center;
+      if offset.magnitude2() > 0.0 { self.move_vert(vert, center + offset.normalize() * radius); }
+    }
+    for face in self.faces.values() { face.borrow_mut().compute_attrs(); }
+  }
+
+  /// Scales and moves the mesh so that its bounding box is centered on the origin, with its longest side
+  /// 1 long, keeping its proportions. Returns the scale and the offset, so that every new position is
+  /// old * scale + offset, to map other points along or undo it. A mesh with no extent is only moved
+  pub fn normalize_to_unit_cube(&mut self) -> (f32, Vector3<f32>) {
+    let bounds = self.aabb();
+    if bounds.is_empty() { return (1.0, Vector3::new(0.0, 0.0, 0.0)); }
+
+    let size = bounds.size();
+    let longest = size.x.max(size.y).max(size.z);
+    let scale = if longest > 0.0 { 1.0 / longest } else { 1.0 };
+    let offset = -bounds.center().to_vec() * scale;
+
+    let verts: Vec<VertRc> = self.vertices.values().cloned().collect();
+    for vert in & verts {
+      let pos = vert.borrow().get_pos();
+      self.move_vert(vert, Point3::from_vec(pos.to_vec() * scale + offset));
+    }
+    for face in self.faces.values() { face.borrow_mut().compute_attrs(); }
+
+    (scale, offset)
+  }
+
+  /// Wraps the mesh onto the surface of `target`, for retopology. Each of the `iterations` first relaxes
+  /// every interior vertex toward the average of its neighbors by `smoothing` (0 for none, 1 for all the way),
+  /// then moves every vertex onto the closest point of `target`, so the mesh always ends up on the surface.
+  /// Relaxation only slides vertices along the surface (the part along the vertex normal is dropped),
+  /// which evens out the spacing without shrinking the mesh.
+  /// Border vertices are projected but not relaxed, so that open meshes don't shrink away at the edges.
+  /// Only the triangular faces of `target` are considered
+  pub fn shrinkwrap(&mut self, target: & HalfEdgeMesh, iterations: usize, smoothing: f32) {
+    shrinkwrap(self, target, iterations, smoothing)
+  }
+}
+
+/// `HalfEdgeMesh::shrinkwrap` for any mesh kernel
+pub fn shrinkwrap<M: EditableMesh>(mesh: &mut M, target: & HalfEdgeMesh, iterations: usize, smoothing: f32) {
+  let bvh = target.build_bvh();
+  if bvh.is_empty() { return; }
+
+  let vert_ids = mesh.vert_ids();
+  let neighbors: HashMap<u32, Vec<u32>> = vert_ids.iter()
+    .filter(|&&v| !mesh.is_boundary_vert(v))
+    .map(|&v| (v, mesh.vert_neighbors(v)))
+    .collect();
+
+  for _ in 0..iterations {
+    let mut moved: Vec<(u32, Point3<f32>)> = Vec::with_capacity(vert_ids.len());
+    for &id in & vert_ids {
+      let pos = mesh.vert_pos(id);
+      let relaxed = match neighbors.get(& id) {
+        Some(around) if !around.is_empty() => {
+          let sum = around.iter().fold(Point3::origin(), |acc, &n| acc + mesh.vert_pos(n).to_vec());
+          let offset = (sum / around.len() as f32) - pos;
+          let normal = mesh.vert_normal(id);
+          // Faces squashed flat by the projection have no normal, and then moving straight
+          // toward the neighbors is what spreads the vertices out again
+          let along = if normal.x.is_finite() { offset - normal * offset.dot(normal) } else { offset };
+          pos + along * smoothing
+        },
+        _ => pos,
+      };
+      let projected = bvh.closest_point(& relaxed, f32::INFINITY).map_or(relaxed, |(p, _)| p);
+      moved.push((id, projected));
+    }
+
+    // Vertex normals come from the faces, which `set_vert_pos` keeps current for the next round
+    for (id, pos) in moved { mesh.set_vert_pos(id, pos); }
+  }
+}
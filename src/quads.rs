@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
+
+use cgmath::{Point3, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use selection::Selection;
+use simplify::DecimateOptions;
+
+/// Two triangles are only merged if the angle between their normals is at most this, in radians
+const MAX_QUAD_DIHEDRAL: f32 = 0.35;
+/// Quads scoring below this (see `quad_quality`) are left as two triangles
+const MIN_QUAD_QUALITY: f32 = 0.4;
+/// Edges bending more than this are kept as features while decimating, in radians
+const FEATURE_ANGLE: f32 = 0.7;
+
+/// How close a quad is to a rectangle: 1 when every corner is a right angle, dropping
+/// to 0 as the worst corner approaches 0 or 180 degrees. Zero for non-convex quads
+fn quad_quality(corners: & [Point3<f32>; 4]) -> f32 {
+  let normal = (corners[2] - corners[0]).cross(corners[3] - corners[1]);
+  let mut worst: f32 = 0.0;
+
+  for idx in 0..4 {
+    let to_prev = corners[(idx + 3) % 4] - corners[idx];
+    let to_next = corners[(idx + 1) % 4] - corners[idx];
+    if to_prev.magnitude2() == 0.0 || to_next.magnitude2() == 0.0 { return 0.0; }
+    // A reflex corner turns the wrong way relative to the quad's normal
+    if to_next.cross(to_prev).dot(normal) <= 0.0 { return 0.0; }
+    worst = worst.max((to_prev.angle(to_next).0 - FRAC_PI_2).abs());
+  }
+
+  1.0 - worst / FRAC_PI_2
+}
+
+impl HalfEdgeMesh {
+  /// Produces a quad-dominant copy of a triangle mesh. The copy is first decimated
+  /// until its average edge is about `target_len` long, keeping the border and sharp edges
+  /// (it's never refined, so a coarser mesh keeps its resolution). Then pairs of neighboring
+  /// triangles which are nearly coplanar and form a well-shaped convex quad are merged,
+  /// best quads first. Triangles without a good partner are left as they are
+  pub fn to_quad_dominant(& self, target_len: f32) -> HalfEdgeMesh {
+    let (positions, polygons) = self.to_polygons();
+    let mut mesh = HalfEdgeMesh::from_polygons(& positions, & polygons);
+
+    let all_triangles = polygons.iter().all(|p| p.len() == 3);
+    if all_triangles && target_len > 0.0 {
+      // Area of an equilateral triangle with sides of target_len
+      let target_area = 3.0f32.sqrt() / 4.0 * target_len * target_len;
+      let target_faces = (mesh.surface_area() / target_area).ceil() as usize;
+      if target_faces < mesh.faces.len() {
+        let options = DecimateOptions {
+          preserve_boundary: true,
+          features: Selection::from_edges(& mesh.detect_feature_edges(FEATURE_ANGLE)),
+          ..DecimateOptions::default()
+        };
+        mesh.decimate(target_faces, & options);
+      }
+    }
+
+    let (positions, polygons) = mesh.to_polygons();
+    let index = mesh.vertex_index();
+    let mut face_ids: Vec<u32> = mesh.faces.keys().cloned().collect();
+    face_ids.sort();
+
+    // Candidate merges, as (quality, edge id)
+    let mut candidates: Vec<(f32, u32)> = Vec::new();
+    for edge in mesh.edges.values() {
+      let edge_b = edge.borrow();
+      let pair = match edge_b.get_pair() { Some(p) => p, None => continue };
+      // Only consider each pair once
+      if pair.borrow().id < edge_b.id { continue; }
+      if edge_b.dihedral_angle().is_none_or(|angle| angle > MAX_QUAD_DIHEDRAL) { continue; }
+      let (face, pair_face) = match (edge_b.get_face(), pair.borrow().get_face()) {
+        (Some(f), Some(p)) => (f, p),
+        _ => continue,
+      };
+      if face.borrow().num_vertices() != 3 || pair_face.borrow().num_vertices() != 3 { continue; }
+
+      // Triangles (u, v, w) and (v, u, x) merge into the quad (u, x, v, w)
+      let corners = match (edge_b.get_origin(), edge_b.get_target(), edge_b.get_next_next(), pair.borrow().get_next_next()) {
+        (Some(u), Some(v), Some(w), Some(x)) => {
+          match (w.borrow().get_origin(), x.borrow().get_origin()) {
+            (Some(w), Some(x)) => [u.borrow().get_pos(), x.borrow().get_pos(), v.borrow().get_pos(), w.borrow().get_pos()],
+            _ => continue,
+          }
+        },
+        _ => continue,
+      };
+
+      let quality = quad_quality(& corners);
+      if quality >= MIN_QUAD_QUALITY { candidates.push((quality, edge_b.id)); }
+    }
+    // Best first, with ties broken by id so that the result is deterministic
+    candidates.sort_by(|a, b| b.0.partial_cmp(& a.0).unwrap_or(::std::cmp::Ordering::Equal).then(a.1.cmp(& b.1)));
+
+    let mut merged: HashSet<u32> = HashSet::new();
+    let mut quads: Vec<Vec<usize>> = Vec::new();
+    for (_, edge_id) in candidates {
+      let edge_b = mesh.edges[& edge_id].borrow();
+      let pair = match edge_b.get_pair() { Some(p) => p, None => continue };
+      let (face, pair_face) = match (edge_b.get_face(), pair.borrow().get_face()) {
+        (Some(f), Some(p)) => (f.borrow().id, p.borrow().id),
+        _ => continue,
+      };
+      if merged.contains(& face) || merged.contains(& pair_face) { continue; }
+
+      let quad: Vec<usize> = [edge_b.get_origin(), pair.borrow().get_next_next().and_then(|e| e.borrow().get_origin()),
+                              edge_b.get_target(), edge_b.get_next_next().and_then(|e| e.borrow().get_origin())]
+        .iter()
+        .filter_map(|v| v.as_ref().and_then(|v| index.get(& v.borrow().id).cloned()))
+        .collect();
+      if quad.len() != 4 { continue; }
+
+      merged.insert(face);
+      merged.insert(pair_face);
+      quads.push(quad);
+    }
+
+    let mut output: Vec<Vec<usize>> = face_ids.iter().zip(polygons)
+      .filter(|&(id, _)| !merged.contains(id))
+      .map(|(_, polygon)| polygon)
+      .collect();
+    output.append(&mut quads);
+
+    HalfEdgeMesh::from_polygons(& positions, & output)
+  }
+}
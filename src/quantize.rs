@@ -0,0 +1,100 @@
+use cgmath::{Matrix4, Vector3};
+
+use mesh::HalfEdgeMesh;
+use export::{BufferOptions, MeshBuffers};
+
+/// Vertex buffers with positions and normals packed into 16-bit integers, to be read by the GPU as normalized
+/// attributes (UNORM16 positions, SNORM16 normals), half the size of float positions and normals.
+/// Positions cover the bounding box, and `transform` scales them back into place
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuantizedBuffers {
+  /// Positions relative to the bounding box, with 0 at its minimum and 65535 at its maximum on each axis
+  pub positions: Vec<[u16; 3]>,
+  /// Normals with each component from -32767 to 32767
+  pub normals: Vec<[i16; 3]>,
+  /// Three indices per triangle, as in `MeshBuffers`
+  pub indices: Vec<u32>,
+  /// Position of a normalized (0, 0, 0)
+  pub offset: [f32; 3],
+  /// Distance along each axis from a normalized 0 to a normalized 1
+  pub scale: [f32; 3],
+  /// Bits of precision kept per component: each one only takes 2^bits different values
+  pub bits: u8,
+}
+
+impl QuantizedBuffers {
+  /// Takes positions read as normalized attributes, from 0 to 1, back to where they are in the mesh.
+  /// Goes at the start of the model matrix
+  pub fn transform(& self) -> Matrix4<f32> {
+    Matrix4::from_translation(Vector3::from(self.offset)) * Matrix4::from_nonuniform_scale(self.scale[0], self.scale[1], self.scale[2])
+  }
+
+  /// Unpacks the positions and normals into floats again, for checking how much precision was lost
+  pub fn dequantize(& self) -> MeshBuffers {
+    let positions = self.positions.iter()
+      .map(|q| {
+        let coord = |axis: usize| self.offset[axis] + self.scale[axis] * q[axis] as f32 / u16::MAX as f32;
+        [coord(0), coord(1), coord(2)]
+      })
+      .collect();
+    let normals = self.normals.iter()
+      .map(|q| {
+        let n = Vector3::new(q[0] as f32, q[1] as f32, q[2] as f32) / i16::MAX as f32;
+        [n.x, n.y, n.z]
+      })
+      .collect();
+    MeshBuffers { positions, normals, indices: self.indices.clone(), ..MeshBuffers::default() }
+  }
+}
+
+/// Snaps a value from 0 to 1 to the grid of the bits, and spreads it over the 16-bit range
+fn unorm(value: f32, bits: u8) -> u16 {
+  let top = ((1u32 << bits) - 1) as f32;
+  let snapped = (value.clamp(0.0, 1.0) * top).round() / top;
+  (snapped * u16::MAX as f32).round() as u16
+}
+
+/// Snaps a value from -1 to 1 to the grid of the bits, and spreads it over the 16-bit range
+fn snorm(value: f32, bits: u8) -> i16 {
+  let top = ((1i32 << (bits - 1)) - 1) as f32;
+  let snapped = (value.clamp(-1.0, 1.0) * top).round() / top;
+  (snapped * i16::MAX as f32).round() as i16
+}
+
+impl MeshBuffers {
+  /// Packs the positions and normals into 16-bit integers, keeping `bits` bits of each component,
+  /// clamped to between 2 and 16. 16 bits is within 1/65535 of the bounding box's size on each axis,
+  /// and 10 bits, which is plenty for normals, within 1/1023. Fewer bits don't make the buffers any smaller,
+  /// but they repeat values more, which a general-purpose compressor can squeeze out.
+  /// Texture coordinates, tangents, and vertex ids are left out
+  pub fn quantize(& self, bits: u8) -> QuantizedBuffers {
+    let bits = bits.clamp(2, 16);
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for pos in & self.positions {
+      for axis in 0..3 {
+        min[axis] = min[axis].min(pos[axis]);
+        max[axis] = max[axis].max(pos[axis]);
+      }
+    }
+    if self.positions.is_empty() { min = [0.0; 3]; max = [0.0; 3]; }
+    let scale = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+
+    let positions = self.positions.iter()
+      .map(|pos| {
+        let coord = |axis: usize| if scale[axis] > 0.0 { unorm((pos[axis] - min[axis]) / scale[axis], bits) } else { 0 };
+        [coord(0), coord(1), coord(2)]
+      })
+      .collect();
+    let normals = self.normals.iter().map(|n| [snorm(n[0], bits), snorm(n[1], bits), snorm(n[2], bits)]).collect();
+
+    QuantizedBuffers { positions, normals, indices: self.indices.clone(), offset: min, scale, bits }
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Builds buffers with `to_buffers` and the default options, and packs them with `MeshBuffers::quantize`
+  pub fn to_buffers_quantized(& self, bits: u8) -> QuantizedBuffers {
+    self.to_buffers(& BufferOptions::default()).quantize(bits)
+  }
+}
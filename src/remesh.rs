@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::{EdgeRc, VertRc};
+use kernel::EditableMesh;
+use project::shrinkwrap;
+use geom::ear_clip;
+use util::OpTimer;
+
+/// Valence a vertex would have in a mesh of equilateral triangles
+fn ideal_valence(vert: & VertRc) -> i64 {
+  if vert.borrow().is_boundary() { 4 } else { 6 }
+}
+
+/// The edge's ends, and the corners opposite it, if it's between two triangles
+fn flip_quad(edge: & EdgeRc) -> Option<[VertRc; 4]> {
+  let edge_b = edge.borrow();
+  let pair = edge_b.get_pair()?;
+  if edge_b.get_face()?.borrow().num_vertices() != 3 || pair.borrow().get_face()?.borrow().num_vertices() != 3 { return None; }
+  let opposite = edge_b.get_next()?.borrow().get_target()?;
+  let pair_opposite = pair.borrow().get_next()?.borrow().get_target()?;
+  Some([edge_b.get_origin()?, edge_b.get_target()?, opposite, pair_opposite])
+}
+
+/// Whether the two triangles made by the flip both face the same way as each of the ones they replace,
+/// which they don't if the quad they form isn't convex
+fn flip_keeps_orientation(edge: & EdgeRc, quad: & [VertRc; 4]) -> bool {
+  let pos: Vec<Point3<f32>> = quad.iter().map(|v| v.borrow().get_pos()).collect();
+  let (a, b, c, d) = (pos[0], pos[1], pos[2], pos[3]);
+  let edge_b = edge.borrow();
+  let (normal, pair_normal) = match (edge_b.get_face(), edge_b.get_pair_face()) {
+    (Some(f), Some(p)) => (f.borrow().normal, p.borrow().normal),
+    _ => return false,
+  };
+  [(c - d).cross(a - d), (d - c).cross(b - c)].iter().all(|n| n.dot(normal) > 0.0 && n.dot(pair_normal) > 0.0)
+}
+
+impl HalfEdgeMesh {
+  /// Flips edges wherever that brings the valences of the four vertices involved closer to ideal, by the sum
+  /// of their squared differences,
+  /// 6 inside the mesh and 4 on the border, going round until no flip helps, and returns how many were flipped.
+  /// Flips which would turn a face over are skipped, and so are weighted edges, like creases, which `flip_edge` refuses.
+  /// One of the steps of isotropic remeshing, and it evens out a mesh after decimation too. Assumes triangular faces
+  pub fn equalize_valences(&mut self) -> usize {
+    let start = OpTimer::start();
+    let mut flipped = 0;
+    loop {
+      let mut edge_ids: Vec<u32> = self.edges.keys().cloned().collect();
+      edge_ids.sort();
+
+      let mut progress = false;
+      for id in edge_ids {
+        let edge = match self.edges.get(& id) { Some(e) => e.clone(), None => continue };
+        // Each pair only needs looking at once
+        if edge.borrow().get_pair().is_some_and(|p| p.borrow().id < id) { continue; }
+        if self.flip_to_even_valences(& edge) {
+          flipped += 1;
+          progress = true;
+        }
+      }
+      if !progress { break; }
+    }
+
+    debug!("equalize_valences: flipped {} edges, took {:?}", flipped, start.elapsed());
+    flipped
+  }
+
+  /// Flips the edge if that brings the valences around it closer to ideal, as in `equalize_valences`.
+  /// Returns whether it was flipped
+  pub(crate) fn flip_to_even_valences(&mut self, edge: & EdgeRc) -> bool {
+    let quad = match flip_quad(edge) { Some(q) => q, None => return false };
+
+    let valences: Vec<i64> = quad.iter().map(|v| v.borrow().valence() as i64).collect();
+    let ideals: Vec<i64> = quad.iter().map(ideal_valence).collect();
+    let change = [-1, -1, 1, 1];
+    let before: i64 = (0..4).map(|idx| (valences[idx] - ideals[idx]).pow(2)).sum();
+    let after: i64 = (0..4).map(|idx| (valences[idx] + change[idx] - ideals[idx]).pow(2)).sum();
+    if after >= before || !flip_keeps_orientation(edge, & quad) { return false; }
+
+    self.flip_edge(edge).is_ok()
+  }
+
+  /// Evens out the shapes of the triangles by moving each vertex to the average of its neighbors, but only
+  /// sideways, within its tangent plane, and then back onto the closest point of the surface as it was
+  /// before relaxing, so unlike plain Laplacian smoothing the mesh keeps its shape and doesn't shrink.
+  /// Border vertices stay put. Shrinkwraps the mesh onto a copy of itself (see `shrinkwrap`), with the faces
+  /// of the copy cut into triangles, since `shrinkwrap` only projects onto triangles
+  pub fn relax_tangential(&mut self, iterations: usize) {
+    relax_tangential(self, iterations)
+  }
+}
+
+/// `HalfEdgeMesh::relax_tangential` for any mesh kernel
+pub fn relax_tangential<M: EditableMesh>(mesh: &mut M, iterations: usize) {
+  let start = OpTimer::start();
+  let vert_ids = mesh.vert_ids();
+  let index: HashMap<u32, usize> = vert_ids.iter().enumerate().map(|(idx, &id)| (id, idx)).collect();
+  let positions: Vec<Point3<f32>> = vert_ids.iter().map(|&id| mesh.vert_pos(id)).collect();
+  let polygons: Vec<Vec<usize>> = mesh.face_ids().into_iter()
+    .flat_map(|f| {
+      let ring: Vec<usize> = mesh.face_verts(f).into_iter().map(|v| index[& v]).collect();
+      ear_clip(& positions, ring, false, |_, _| true).unwrap_or_default()
+    })
+    .map(|tri| tri.to_vec())
+    .collect();
+  let original = HalfEdgeMesh::from_polygons(& positions, & polygons);
+  shrinkwrap(mesh, & original, iterations, 1.0);
+  debug!("relax_tangential: {} iterations over {} vertices, took {:?}", iterations, vert_ids.len(), start.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Point3, EuclideanSpace, InnerSpace};
+
+  use mesh::HalfEdgeMesh;
+  use kernel::{MeshTopology, MeshGeometry};
+  use super::relax_tangential;
+
+  #[test]
+  fn relaxing_keeps_the_sphere() {
+    let mut mesh = HalfEdgeMesh::icosphere(Point3::new(0.0, 0.0, 0.0), 1.0, 2);
+    let faces = mesh.num_faces();
+
+    relax_tangential(&mut mesh, 3);
+    assert!(mesh.validate().is_ok());
+    assert_eq!(mesh.num_faces(), faces);
+    for id in mesh.vert_ids() {
+      let radius = mesh.vert_pos(id).to_vec().magnitude();
+      assert!(radius > 0.9 && radius < 1.0 + 1e-4, "vertex {} at radius {}", id, radius);
+    }
+  }
+
+  #[test]
+  fn relaxing_moves_quads_too() {
+    // A 3 by 3 grid of vertices, making 4 quads, with the middle one pulled off center
+    let positions: Vec<Point3<f32>> = (0..9).map(|idx| {
+      if idx == 4 { Point3::new(0.3, 0.2, 0.0) } else { Point3::new((idx % 3) as f32 - 1.0, (idx / 3) as f32 - 1.0, 0.0) }
+    }).collect();
+    let polygons = vec![vec![0, 1, 4, 3], vec![1, 2, 5, 4], vec![3, 4, 7, 6], vec![4, 5, 8, 7]];
+    let mut mesh = HalfEdgeMesh::from_polygons(& positions, & polygons);
+    let middle = mesh.vert_ids().into_iter().find(|&id| mesh.vert_pos(id) == positions[4]).unwrap();
+
+    relax_tangential(&mut mesh, 3);
+    let moved = mesh.vert_pos(middle);
+    assert!(moved.to_vec().magnitude() < 0.1, "middle vertex stayed at {:?}", moved);
+    assert!(moved.z.abs() < 1e-6);
+  }
+}
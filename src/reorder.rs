@@ -0,0 +1,120 @@
+use cgmath::Point3;
+
+use mesh::HalfEdgeMesh;
+use geom::Aabb;
+
+/// Bits per axis of the grid that positions are snapped to before they're put in curve order
+const CURVE_BITS: u32 = 10;
+
+/// Space-filling curve for `HalfEdgeMesh::reorder_spatially`. Elements close together along the curve
+/// are close together in space, so walking them in order keeps memory accesses local
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpatialOrder {
+  /// Z-order curve: interleaves the bits of the coordinates. Cheap, but jumps across the mesh now and then
+  #[default]
+  Morton,
+  /// Hilbert curve: every step goes to a neighboring cell, so locality is a little better than Morton's
+  Hilbert,
+}
+
+/// Spreads the low bits out so that there are two zeros between each of them
+fn spread_bits(value: u32) -> u64 {
+  let mut spread = 0u64;
+  for bit in 0..CURVE_BITS {
+    spread |= (((value >> bit) & 1) as u64) << (bit * 3);
+  }
+  spread
+}
+
+fn morton_code(cell: [u32; 3]) -> u64 {
+  spread_bits(cell[0]) << 2 | spread_bits(cell[1]) << 1 | spread_bits(cell[2])
+}
+
+/// Position along the Hilbert curve through the grid, with John Skilling's transpose algorithm
+/// ("Programming the Hilbert curve", 2004)
+fn hilbert_code(cell: [u32; 3]) -> u64 {
+  let mut x = cell;
+  let top = 1u32 << (CURVE_BITS - 1);
+
+  // Undo the rotations and reflections of each level
+  let mut q = top;
+  while q > 1 {
+    let p = q - 1;
+    for i in 0..3 {
+      if x[i] & q != 0 {
+        x[0] ^= p;
+      } else {
+        let t = (x[0] ^ x[i]) & p;
+        x[0] ^= t;
+        x[i] ^= t;
+      }
+    }
+    q >>= 1;
+  }
+
+  // Gray code
+  for i in 1..3 { x[i] ^= x[i - 1]; }
+  let mut t = 0;
+  q = top;
+  while q > 1 {
+    if x[2] & q != 0 { t ^= q - 1; }
+    q >>= 1;
+  }
+  for coord in &mut x { *coord ^= t; }
+
+  let mut code = 0u64;
+  for bit in (0..CURVE_BITS).rev() {
+    for coord in & x { code = code << 1 | ((coord >> bit) & 1) as u64; }
+  }
+  code
+}
+
+impl SpatialOrder {
+  /// Position of the point along the curve, through a grid laid over the bounds
+  fn code(& self, point: & Point3<f32>, bounds: & Aabb) -> u64 {
+    let cells = ((1u32 << CURVE_BITS) - 1) as f32;
+    let size = bounds.max - bounds.min;
+    let snap = |value: f32, min: f32, extent: f32| -> u32 {
+      if extent > 0.0 { (((value - min) / extent).clamp(0.0, 1.0) * cells).round() as u32 } else { 0 }
+    };
+    let cell = [snap(point.x, bounds.min.x, size.x), snap(point.y, bounds.min.y, size.y), snap(point.z, bounds.min.z, size.z)];
+    match *self {
+      SpatialOrder::Morton => morton_code(cell),
+      SpatialOrder::Hilbert => hilbert_code(cell),
+    }
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Renumbers the vertices, edges, and faces so that their ids run from 1 up without gaps,
+  /// keeping their order. Ids are never reused, so a mesh which has been edited a lot has gaps,
+  /// which waste space in anything indexed by id
+  pub fn compact(&mut self) {
+    let mut vert_order: Vec<u32> = self.vertices.keys().cloned().collect();
+    vert_order.sort();
+    let mut face_order: Vec<u32> = self.faces.keys().cloned().collect();
+    face_order.sort();
+    self.renumber(& vert_order, & face_order);
+  }
+
+  /// Renumbers the vertices and faces in order along a space-filling curve, so that elements which are
+  /// close together get ids which are close together, and the edges of each face get consecutive ids.
+  /// Algorithms which walk the mesh by id, or buffers built from it, then touch memory in a more
+  /// cache-friendly order. Faces go by their centers. Like `compact`, leaves no gaps in the ids
+  pub fn reorder_spatially(&mut self, order: SpatialOrder) {
+    let bounds = self.aabb();
+
+    let mut verts: Vec<(u64, u32)> = self.vertices.iter()
+      .map(|(&id, v)| (order.code(& v.borrow().get_pos(), & bounds), id))
+      .collect();
+    verts.sort();
+    let mut faces: Vec<(u64, u32)> = self.faces.iter()
+      .map(|(&id, f)| (order.code(& f.borrow().center, & bounds), id))
+      .collect();
+    faces.sort();
+
+    let vert_order: Vec<u32> = verts.into_iter().map(|(_, id)| id).collect();
+    let face_order: Vec<u32> = faces.into_iter().map(|(_, id)| id).collect();
+    self.renumber(& vert_order, & face_order);
+  }
+}
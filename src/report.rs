@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use cgmath::Point3;
+use approx::ApproxEq;
+
+use ptr::FaceRc;
+use iterators::ToPtrVec;
+use mesh::HalfEdgeMesh;
+use geom::{Aabb, Ray, triangles_intersect};
+
+/// Summary of the checks that matter before sending a mesh to a 3D printer.
+/// Produced by `HalfEdgeMesh::printability_report`
+#[derive(Debug, Clone)]
+pub struct PrintabilityReport {
+  /// True if every edge has a valid pair, and all pairs point back at each other
+  pub is_watertight: bool,
+  /// Ids of edges which don't have a valid pair edge (holes in the surface)
+  pub open_edges: Vec<u32>,
+  /// True if every pair of adjacent faces traverses their shared edge in opposite directions
+  pub is_consistently_oriented: bool,
+  /// Ids of pairs of faces which intersect each other without sharing a vertex
+  pub self_intersections: Vec<(u32, u32)>,
+  /// Smallest distance from a face center, along the inverted face normal, to the opposite
+  /// side of the mesh. None if no such ray hits anything (e.g. the mesh is a single sheet)
+  pub min_wall_thickness: Option<f32>,
+  /// Ids of faces which have (nearly) zero area
+  pub degenerate_faces: Vec<u32>,
+}
+
+impl PrintabilityReport {
+  /// True if none of the checks found a problem.
+  /// Wall thickness is not checked, since the acceptable value depends on the printer
+  pub fn is_printable(& self) -> bool {
+    self.is_watertight &&
+    self.is_consistently_oriented &&
+    self.self_intersections.is_empty() &&
+    self.degenerate_faces.is_empty()
+  }
+}
+
+/// Ids of the corner vertices of a face
+fn face_vert_ids(face: & FaceRc) -> HashSet<u32> {
+  face.borrow().adjacent_verts().to_ptr_vec().iter()
+    .map(|v| v.borrow().id)
+    .collect()
+}
+
+impl HalfEdgeMesh {
+  /// Runs every check relevant to 3D printing in one pass, and collects the results.
+  /// The self-intersection and wall thickness checks compare every pair of faces,
+  /// so this is quadratic in the number of faces.
+  /// Assumes triangular faces
+  pub fn printability_report(& self) -> PrintabilityReport {
+    let mut open_edges: Vec<u32> = Vec::new();
+    let mut is_consistently_oriented = true;
+    let mut directed_keys: HashSet<(u32, u32)> = HashSet::new();
+
+    for edge in self.edges.values() {
+      let edge_b = edge.borrow();
+      let origin_id = edge_b.get_origin().map(|o| o.borrow().id);
+      let target_id = edge_b.get_target().map(|t| t.borrow().id);
+
+      // Two edges going the same direction between the same vertices
+      // means that the faces on either side of them disagree on orientation
+      if let (Some(o_id), Some(t_id)) = (origin_id, target_id) {
+        if !directed_keys.insert((o_id, t_id)) { is_consistently_oriented = false; }
+      }
+
+      match edge_b.get_pair() {
+        Some(pair) => {
+          // A correctly oriented pair starts where this edge ends
+          if pair.borrow().get_origin().map(|o| o.borrow().id) != target_id {
+            is_consistently_oriented = false;
+          }
+        },
+        None => { open_edges.push(edge_b.id); },
+      }
+    }
+
+    open_edges.sort();
+
+    let is_watertight = open_edges.is_empty() && self.are_edge_pairs_valid().is_ok();
+
+    let mut face_list: Vec<&FaceRc> = self.faces.values().collect();
+    face_list.sort_by_key(|f| f.borrow().id);
+
+    let degenerate_faces: Vec<u32> = face_list.iter()
+      .filter(|f| f.borrow().area() <= f32::default_epsilon())
+      .map(|f| f.borrow().id)
+      .collect();
+
+    let triangles: Vec<Option<[Point3<f32>; 3]>> = face_list.iter().map(|f| f.borrow().triangle()).collect();
+    let vert_ids: Vec<HashSet<u32>> = face_list.iter().map(|f| face_vert_ids(f)).collect();
+    let bounds: Vec<Aabb> = face_list.iter().map(|f| f.borrow().aabb()).collect();
+
+    let mut self_intersections: Vec<(u32, u32)> = Vec::new();
+    for idx_a in 0..face_list.len() {
+      let tri_a = match triangles[idx_a] { Some(ref tri) => tri, None => continue };
+      for idx_b in (idx_a + 1)..face_list.len() {
+        let tri_b = match triangles[idx_b] { Some(ref tri) => tri, None => continue };
+        // Faces sharing a vertex always touch, which doesn't count
+        if !vert_ids[idx_a].is_disjoint(& vert_ids[idx_b]) { continue; }
+        if !bounds[idx_a].intersects(& bounds[idx_b]) { continue; }
+        if triangles_intersect(tri_a, tri_b) {
+          self_intersections.push((face_list[idx_a].borrow().id, face_list[idx_b].borrow().id));
+        }
+      }
+    }
+
+    // Shoot a ray inward from each face, and find the closest face on the other side
+    let mut min_wall_thickness: Option<f32> = None;
+    for (idx_a, face) in face_list.iter().enumerate() {
+      let face_b = face.borrow();
+      let inward = Ray::new(face_b.center, -face_b.normal);
+      for (idx_b, tri_opt) in triangles.iter().enumerate() {
+        if idx_a == idx_b { continue; }
+        let tri = match *tri_opt { Some(ref tri) => tri, None => continue };
+        if let Some(dist) = inward.intersect_triangle(tri) {
+          if dist > f32::default_epsilon() && min_wall_thickness.is_none_or(|cur| dist < cur) {
+            min_wall_thickness = Some(dist);
+          }
+        }
+      }
+    }
+
+    PrintabilityReport {
+      is_watertight,
+      open_edges,
+      is_consistently_oriented,
+      self_intersections,
+      min_wall_thickness,
+      degenerate_faces,
+    }
+  }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, EuclideanSpace, MetricSpace};
+use rand::Rng;
+
+use ptr::FaceRc;
+use mesh::HalfEdgeMesh;
+
+/// Uniformly random point on a triangle
+fn sample_triangle<R: Rng>(corners: & [Point3<f32>; 3], rng: &mut R) -> Point3<f32> {
+  let root = rng.gen::<f32>().sqrt();
+  let r = rng.gen::<f32>();
+  let (w_a, w_b, w_c) = (1.0 - root, root * (1.0 - r), root * r);
+  Point3::from_vec(corners[0].to_vec() * w_a + corners[1].to_vec() * w_b + corners[2].to_vec() * w_c)
+}
+
+/// How many uniform candidates are drawn for each point that could fit in the surface area
+const POISSON_OVERSAMPLING: f32 = 8.0;
+
+/// Uniform grid of accepted points, with cells as wide as the minimum spacing,
+/// so only the neighboring cells need to be checked for conflicts
+struct PointGrid {
+  cell_size: f32,
+  cells: HashMap<(i64, i64, i64), Vec<Point3<f32>>>,
+}
+
+impl PointGrid {
+  fn cell(& self, point: & Point3<f32>) -> (i64, i64, i64) {
+    ((point.x / self.cell_size).floor() as i64, (point.y / self.cell_size).floor() as i64, (point.z / self.cell_size).floor() as i64)
+  }
+
+  fn has_point_within(& self, point: & Point3<f32>, radius: f32) -> bool {
+    let (c_x, c_y, c_z) = self.cell(point);
+    for d_x in -1..2 {
+      for d_y in -1..2 {
+        for d_z in -1..2 {
+          if let Some(points) = self.cells.get(& (c_x + d_x, c_y + d_y, c_z + d_z)) {
+            if points.iter().any(|p| p.distance(*point) < radius) { return true; }
+          }
+        }
+      }
+    }
+    false
+  }
+
+  fn insert(&mut self, point: Point3<f32>) {
+    let key = self.cell(& point);
+    self.cells.entry(key).or_default().push(point);
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Picks n points uniformly distributed over the surface of the mesh: faces are chosen
+  /// with probability proportional to their area, and then a point is picked uniformly inside the face.
+  /// Each point is returned with the face it lies on. Assumes triangular faces
+  pub fn sample_surface<R: Rng>(& self, n: usize, rng: &mut R) -> Vec<(Point3<f32>, FaceRc)> {
+    let mut faces: Vec<(& FaceRc, [Point3<f32>; 3])> = self.faces.values()
+      .filter_map(|f| f.borrow().triangle().map(|tri| (f, tri)))
+      .collect();
+    // Sorted so that the same rng gives the same points
+    faces.sort_by_key(|&(f, _)| f.borrow().id);
+
+    let mut cumulative: Vec<f32> = Vec::with_capacity(faces.len());
+    let mut total = 0.0;
+    for &(face, _) in & faces {
+      total += face.borrow().area();
+      cumulative.push(total);
+    }
+
+    if faces.is_empty() || total <= 0.0 { return Vec::new(); }
+
+    (0..n).map(|_| {
+      let target = rng.gen::<f32>() * total;
+      let idx = cumulative.partition_point(|&area| area <= target).min(faces.len() - 1);
+      let (face, ref corners) = faces[idx];
+      (sample_triangle(corners, rng), face.clone())
+    }).collect()
+  }
+
+  /// Picks points spread over the surface of the mesh so that no two of them are closer than
+  /// `radius` (Euclidean distance), giving an even "blue noise" distribution.
+  /// Works by drawing many uniform samples with `sample_surface` and keeping each one which
+  /// isn't too close to a point that was already kept. Assumes triangular faces
+  pub fn sample_poisson_disk<R: Rng>(& self, radius: f32, rng: &mut R) -> Vec<(Point3<f32>, FaceRc)> {
+    if radius <= 0.0 { return Vec::new(); }
+
+    let disk_area = ::std::f32::consts::PI * radius * radius * 0.25;
+    let candidate_count = (POISSON_OVERSAMPLING * self.surface_area() / disk_area).ceil() as usize;
+
+    let mut grid = PointGrid { cell_size: radius, cells: HashMap::new() };
+    let mut accepted: Vec<(Point3<f32>, FaceRc)> = Vec::new();
+
+    for (point, face) in self.sample_surface(candidate_count, rng) {
+      if grid.has_point_within(& point, radius) { continue; }
+      grid.insert(point);
+      accepted.push((point, face));
+    }
+
+    accepted
+  }
+}
@@ -0,0 +1,148 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace, MetricSpace};
+
+use mesh::{HalfEdgeMesh, vert_neighbor_ids};
+use ptr::EdgeRc;
+use surface::SurfacePoint;
+use brush::{Falloff, BrushMetric};
+use simplify::{collapse_position, collapse_flips_face};
+
+/// Rounds of splitting per dab in `sculpt_dyntopo`. Each round halves the longest edges,
+/// so this is how many times finer than the mesh the detail can get in one dab
+const DYNTOPO_SPLIT_ROUNDS: usize = 6;
+
+/// Length of the edge, or None if it's missing an end
+fn edge_length(edge: & EdgeRc) -> Option<f32> {
+  let edge_b = edge.borrow();
+  Some(edge_b.get_origin()?.borrow().get_pos().distance(edge_b.get_target()?.borrow().get_pos()))
+}
+
+/// The standard sculpting brushes, each with how strongly it works at its center
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SculptBrush {
+  /// Pushes the surface out along its normals by this distance, or in if it's negative
+  Inflate(f32),
+  /// Moves each vertex this fraction of the way, from 0 to 1, toward the average of its neighbors,
+  /// evening out bumps and noise
+  Smooth(f32),
+  /// Drags the surface along by this offset, as when pulling it with the mouse
+  Grab(Vector3<f32>),
+  /// Draws the surface in toward the center by this fraction of the way, from 0 to 1, along the surface,
+  /// tightening creases. A negative amount pushes it away instead
+  Pinch(f32),
+}
+
+impl HalfEdgeMesh {
+  /// One dab of the brush at the center, reaching `radius` along the surface and fading out by the falloff.
+  /// Everything the brush does is worked out from where the vertices were before the dab, so the result
+  /// doesn't depend on the order vertices are visited in. Only the faces around moved vertices have their
+  /// normals updated, so a dab costs the same however large the mesh is. Returns the number of vertices reached
+  pub fn sculpt(&mut self, center: & SurfacePoint, radius: f32, falloff: Falloff, brush: SculptBrush) -> usize {
+    let origin = center.position();
+    match brush {
+      SculptBrush::Inflate(amount) => {
+        self.brush(center, radius, falloff, |vert, weight| vert.pos += vert.normal * (amount * weight))
+      },
+      SculptBrush::Smooth(amount) => {
+        let amount = amount.clamp(0.0, 1.0);
+        let averages: HashMap<u32, Point3<f32>> = self.brush_reach(center, radius, BrushMetric::Geodesic).keys()
+          .filter_map(|id| {
+            let neighbors: Vec<Point3<f32>> = vert_neighbor_ids(& self.vertices[id]).iter()
+              .filter_map(|n| self.vertices.get(n).map(|v| v.borrow().get_pos()))
+              .collect();
+            if neighbors.is_empty() { None } else { Some((*id, Point3::centroid(& neighbors))) }
+          })
+          .collect();
+        self.brush(center, radius, falloff, |vert, weight| {
+          if let Some(average) = averages.get(& vert.id) { vert.pos += (average - vert.pos) * (amount * weight); }
+        })
+      },
+      SculptBrush::Grab(offset) => {
+        self.brush(center, radius, falloff, |vert, weight| vert.pos += offset * weight)
+      },
+      SculptBrush::Pinch(amount) => {
+        self.brush(center, radius, falloff, |vert, weight| {
+          // Only along the surface, so pinching doesn't also flatten it
+          let toward = origin - vert.pos;
+          let along_surface = toward - vert.normal * toward.dot(vert.normal);
+          vert.pos += along_surface * (amount.clamp(-1.0, 1.0) * weight);
+        })
+      },
+    }
+  }
+
+  /// `sculpt` with dynamic topology: after the dab, the mesh under the brush is remeshed toward edges
+  /// `detail_size` long, so detail is added where the brush works instead of being limited by the mesh
+  /// as it was. Edges longer than 4/3 of the detail size are split, edges shorter than 4/5 of it collapsed,
+  /// as long as that keeps the mesh manifold, doesn't turn faces over, and doesn't make edges too long
+  /// again, and then edges are flipped to even out valences. Only edges with an end in reach are touched.
+  /// Faces under the brush change, so `center` and anything else holding them may be stale afterwards;
+  /// `take_dirty` tells what changed. Returns the number of vertices the dab reached. Assumes triangular faces
+  pub fn sculpt_dyntopo(&mut self, center: & SurfacePoint, radius: f32, falloff: Falloff, brush: SculptBrush, detail_size: f32) -> usize {
+    let reached = self.sculpt(center, radius, falloff, brush);
+    if detail_size.is_nan() || detail_size <= 0.0 { return reached; }
+    let mut region: HashSet<u32> = self.brush_reach(center, radius, BrushMetric::Geodesic).keys().cloned().collect();
+    let (max_len, min_len) = (detail_size * 4.0 / 3.0, detail_size * 4.0 / 5.0);
+
+    // Edges with an end in the region, one from each pair, longest or shortest first
+    let region_edges = |mesh: & HalfEdgeMesh, region: & HashSet<u32>, keep: & dyn Fn(f32) -> bool, longest_first: bool| -> Vec<u32> {
+      let mut found: Vec<(f32, u32)> = Vec::new();
+      for &vert_id in region {
+        let vert = match mesh.vertices.get(& vert_id) { Some(vert) => vert, None => continue };
+        for out_edge in vert.borrow().outgoing_edges() {
+          let edge_b = out_edge.borrow();
+          let pair_id = edge_b.get_pair().map(|p| p.borrow().id);
+          // A pair with both ends in the region comes up from both ends, so only the lower id is taken
+          let other_end_in = edge_b.get_target().is_some_and(|t| region.contains(& t.borrow().id));
+          if other_end_in && pair_id.is_some_and(|p| p < edge_b.id) { continue; }
+          if let Some(len) = edge_length(& out_edge).filter(|&len| keep(len)) { found.push((len, edge_b.id)); }
+        }
+      }
+      found.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+      if longest_first { found.reverse(); }
+      found.dedup_by_key(|found| found.1);
+      found.into_iter().map(|(_, id)| id).collect()
+    };
+
+    let mut split = 0;
+    for _ in 0..DYNTOPO_SPLIT_ROUNDS {
+      let long = region_edges(self, & region, & |len| len > max_len, true);
+      if long.is_empty() { break; }
+      for id in long {
+        let edge = match self.edges.get(& id) { Some(e) => e.clone(), None => continue };
+        if !edge_length(& edge).is_some_and(|len| len > max_len) { continue; }
+        if let Ok(vert) = self.split_edge(& edge, 0.5) {
+          region.insert(vert.borrow().id);
+          split += 1;
+        }
+      }
+    }
+
+    let mut collapsed = 0;
+    for id in region_edges(self, & region, & |len| len < min_len, false) {
+      let edge = match self.edges.get(& id) { Some(e) => e.clone(), None => continue };
+      if !edge_length(& edge).is_some_and(|len| len < min_len) || !self.can_collapse_edge(& edge) { continue; }
+      let (origin, target) = match (edge.borrow().get_origin(), edge.borrow().get_target()) {
+        (Some(o), Some(t)) => (o, t),
+        _ => continue,
+      };
+      let pos = collapse_position(& origin, & target);
+      let stretches = vert_neighbor_ids(& origin).union(& vert_neighbor_ids(& target))
+        .filter_map(|n| self.vertices.get(n))
+        .any(|n| n.borrow().get_pos().distance(pos) > max_len);
+      if stretches || collapse_flips_face(& edge, & origin, & target, pos) { continue; }
+      if self.collapse_edge(& edge, pos).is_ok() { collapsed += 1; }
+    }
+
+    let mut flipped = 0;
+    for id in region_edges(self, & region, & |_| true, false) {
+      let edge = match self.edges.get(& id) { Some(e) => e.clone(), None => continue };
+      if self.flip_to_even_valences(& edge) { flipped += 1; }
+    }
+
+    trace!("sculpt_dyntopo: split {}, collapsed {}, flipped {} edges", split, collapsed, flipped);
+    reached
+  }
+}
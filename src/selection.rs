@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+
+use cgmath::{Point3, InnerSpace};
+
+use ptr::{VertRc, EdgeRc, FaceRc};
+use mesh::HalfEdgeMesh;
+use geom::{Aabb, Plane, Ray, Segment};
+use bvh::Bvh;
+use iterators::ToPtrVec;
+
+/// A set of mesh entities, stored by id.
+/// Ids are only meaningful for the mesh the selection was made from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+  pub verts: HashSet<u32>,
+  pub edges: HashSet<u32>,
+  pub faces: HashSet<u32>,
+}
+
+impl Selection {
+  pub fn empty() -> Selection {
+    Selection::default()
+  }
+
+  /// Selection of the given edges, for example the result of `HalfEdgeMesh::detect_feature_edges`
+  pub fn from_edges(edges: & [EdgeRc]) -> Selection {
+    let mut selection = Selection::empty();
+    selection.edges.extend(edges.iter().map(|e| e.borrow().id));
+    selection
+  }
+
+  pub fn is_empty(& self) -> bool {
+    self.verts.is_empty() && self.edges.is_empty() && self.faces.is_empty()
+  }
+
+  pub fn contains_vert(& self, id: u32) -> bool { self.verts.contains(& id) }
+
+  pub fn contains_edge(& self, id: u32) -> bool { self.edges.contains(& id) }
+
+  pub fn contains_face(& self, id: u32) -> bool { self.faces.contains(& id) }
+
+  /// Adds everything in the other selection to this one
+  pub fn extend(&mut self, other: & Selection) {
+    self.verts.extend(other.verts.iter().cloned());
+    self.edges.extend(other.edges.iter().cloned());
+    self.faces.extend(other.faces.iter().cloned());
+  }
+
+  /// Looks up the selected vertices in the mesh. Ids which aren't in the mesh are skipped
+  pub fn vert_rcs(& self, mesh: & HalfEdgeMesh) -> Vec<VertRc> {
+    self.verts.iter().filter_map(|id| mesh.vertices.get(id).cloned()).collect()
+  }
+
+  /// Looks up the selected edges in the mesh. Ids which aren't in the mesh are skipped
+  pub fn edge_rcs(& self, mesh: & HalfEdgeMesh) -> Vec<EdgeRc> {
+    self.edges.iter().filter_map(|id| mesh.edges.get(id).cloned()).collect()
+  }
+
+  /// Looks up the selected faces in the mesh. Ids which aren't in the mesh are skipped
+  pub fn face_rcs(& self, mesh: & HalfEdgeMesh) -> Vec<FaceRc> {
+    self.faces.iter().filter_map(|id| mesh.faces.get(id).cloned()).collect()
+  }
+}
+
+/// The result of casting a ray at the mesh with `HalfEdgeMesh::pick`.
+/// The closest vertex and edge are chosen from the corners and sides of the hit face
+#[derive(Debug, Clone)]
+pub struct PickResult {
+  pub face: FaceRc,
+  /// Point where the ray hit the face
+  pub point: Point3<f32>,
+  /// Distance along the ray to the hit point
+  pub distance: f32,
+  pub vert: VertRc,
+  /// Distance from the hit point to the closest vertex
+  pub vert_distance: f32,
+  pub edge: EdgeRc,
+  /// Distance from the hit point to the closest edge
+  pub edge_distance: f32,
+}
+
+impl HalfEdgeMesh {
+  /// Finds the first face hit by the ray, along with the closest vertex and edge of that face
+  /// to the hit point. This is what an editor needs to implement click-selection.
+  /// Both sides of each face can be hit. Assumes triangular faces
+  pub fn pick(& self, ray: Ray) -> Option<PickResult> {
+    let mut closest: Option<(f32, FaceRc)> = None;
+
+    for face in self.faces.values() {
+      let face_b = face.borrow();
+      let max_dist = closest.as_ref().map_or(f32::INFINITY, |c| c.0);
+      // Cheap rejection test before the triangle test
+      match ray.intersect_aabb(& face_b.aabb()) {
+        Some((t_enter, _)) if t_enter <= max_dist => {},
+        _ => continue,
+      }
+      if let Some(dist) = face_b.triangle().and_then(|tri| ray.intersect_triangle(& tri)) {
+        if dist < max_dist { closest = Some((dist, face.clone())); }
+      }
+    }
+
+    let (distance, face) = closest?;
+    let point = ray.at(distance);
+
+    let mut best_vert: Option<(f32, VertRc)> = None;
+    let mut best_edge: Option<(f32, EdgeRc)> = None;
+
+    for edge in face.borrow().adjacent_edges().to_ptr_vec() {
+      let (origin, target) = match (edge.borrow().get_origin(), edge.borrow().get_target()) {
+        (Some(o), Some(t)) => (o, t),
+        _ => continue,
+      };
+      let origin_pos = origin.borrow().get_pos();
+
+      let vert_dist = (point - origin_pos).magnitude();
+      if best_vert.as_ref().is_none_or(|b| vert_dist < b.0) { best_vert = Some((vert_dist, origin)); }
+
+      let edge_dist = Segment::new(origin_pos, target.borrow().get_pos()).distance_to_point(& point);
+      if best_edge.as_ref().is_none_or(|b| edge_dist < b.0) { best_edge = Some((edge_dist, edge.clone())); }
+    }
+
+    match (best_vert, best_edge) {
+      (Some((vert_distance, vert)), Some((edge_distance, edge))) => Some(PickResult {
+        face,
+        point,
+        distance,
+        vert,
+        vert_distance,
+        edge,
+        edge_distance,
+      }),
+      _ => None,
+    }
+  }
+
+  /// Selects the vertices inside the box, along with the edges and faces
+  /// whose vertices are all inside the box. Checks every vertex; `select_in_box_indexed`
+  /// only looks at the faces a bvh finds near the box
+  pub fn select_in_box(& self, aabb: & Aabb) -> Selection {
+    self.select_where(|pos| aabb.contains(pos))
+  }
+
+  /// Selects the vertices inside the frustum, along with the edges and faces
+  /// whose vertices are all inside it. The frustum is the region in front of all
+  /// six planes, so the plane normals should point inward. Checks every vertex;
+  /// `select_in_frustum_indexed` only looks at the faces a bvh finds near the frustum
+  pub fn select_in_frustum(& self, planes: & [Plane; 6]) -> Selection {
+    let bounds = self.aabb();
+    // Skip the per-vertex checks entirely if the whole mesh is outside one of the planes
+    let culled = planes.iter().any(|plane| {
+      !plane.intersects_aabb(& bounds) && plane.signed_distance(& bounds.center()) < 0.0
+    });
+    if culled { return Selection::empty(); }
+
+    self.select_where(|pos| planes.iter().all(|plane| plane.signed_distance(pos) >= 0.0))
+  }
+
+  /// `select_in_box`, with the bvh, which has to be up to date with the mesh, finding the faces near the box,
+  /// so that only their vertices are checked. Vertices which aren't a corner of any triangle are never selected
+  pub fn select_in_box_indexed(& self, bvh: & Bvh, aabb: & Aabb) -> Selection {
+    self.select_among(& bvh.query_aabb(aabb), |pos| aabb.contains(pos))
+  }
+
+  /// `select_in_frustum`, with the bvh, which has to be up to date with the mesh, finding the faces near the
+  /// frustum, so that only their vertices are checked. Vertices which aren't a corner of any triangle are never selected
+  pub fn select_in_frustum_indexed(& self, bvh: & Bvh, planes: & [Plane; 6]) -> Selection {
+    self.select_among(& bvh.query_planes(planes), |pos| planes.iter().all(|plane| plane.signed_distance(pos) >= 0.0))
+  }
+
+  /// Selects the corners of the faces by position, then the edges and faces among them made up only of
+  /// selected vertices. Every face with a vertex inside has to be among the faces for this to find everything
+  fn select_among<F>(& self, face_ids: & [u32], inside: F) -> Selection where F: Fn(& Point3<f32>) -> bool {
+    let mut selection = Selection::empty();
+    let faces: Vec<FaceRc> = face_ids.iter().filter_map(|id| self.faces.get(id).cloned()).collect();
+
+    for face in & faces {
+      for vert in face.borrow().adjacent_verts().filter_map(|v| v.upgrade()) {
+        let vert_b = vert.borrow();
+        if inside(& vert_b.pos) { selection.verts.insert(vert_b.id); }
+      }
+    }
+
+    if selection.verts.is_empty() { return selection; }
+
+    for face in & faces {
+      let face_b = face.borrow();
+      let mut all_inside = true;
+      for edge in face_b.adjacent_edges().filter_map(|e| e.upgrade()) {
+        let edge_b = edge.borrow();
+        let ends_inside = edge_b.adjacent_verts()
+          .all(|v| v.upgrade().is_some_and(|v| selection.verts.contains(& v.borrow().id)));
+        if !ends_inside { all_inside = false; continue; }
+        selection.edges.insert(edge_b.id);
+      }
+      if all_inside { selection.faces.insert(face_b.id); }
+    }
+
+    selection
+  }
+
+  /// Selects vertices by position, then selects the edges and faces made up only of selected vertices
+  fn select_where<F>(& self, inside: F) -> Selection where F: Fn(& Point3<f32>) -> bool {
+    let mut selection = Selection::empty();
+
+    for vert in self.vertices.values() {
+      let vert_b = vert.borrow();
+      if inside(& vert_b.pos) { selection.verts.insert(vert_b.id); }
+    }
+
+    if selection.verts.is_empty() { return selection; }
+
+    for edge in self.edges.values() {
+      let edge_b = edge.borrow();
+      let all_inside = edge_b.adjacent_verts()
+        .all(|v| v.upgrade().is_some_and(|v| selection.verts.contains(& v.borrow().id)));
+      if all_inside { selection.edges.insert(edge_b.id); }
+    }
+
+    for face in self.faces.values() {
+      let face_b = face.borrow();
+      let all_inside = face_b.adjacent_verts()
+        .all(|v| v.upgrade().is_some_and(|v| selection.verts.contains(& v.borrow().id)));
+      if all_inside { selection.faces.insert(face_b.id); }
+    }
+
+    selection
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{Point3, Vector3};
+
+  use mesh::HalfEdgeMesh;
+  use geom::{Aabb, Plane};
+
+  #[test]
+  fn indexed_selection_matches_full_scan() {
+    let mesh = HalfEdgeMesh::icosphere(Point3::new(0.0, 0.0, 0.0), 1.0, 3);
+    let bvh = mesh.build_bvh();
+
+    let aabb = Aabb::from_points(& [Point3::new(-0.3, -0.2, 0.1), Point3::new(0.6, 0.9, 1.2)]);
+    let indexed = mesh.select_in_box_indexed(& bvh, & aabb);
+    assert!(!indexed.faces.is_empty());
+    assert_eq!(indexed, mesh.select_in_box(& aabb));
+
+    let planes = [
+      Plane::from_point_normal(Point3::new(-0.5, 0.0, 0.0), Vector3::new(1.0, 0.2, 0.0)),
+      Plane::from_point_normal(Point3::new(0.4, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.1)),
+      Plane::from_point_normal(Point3::new(0.0, -0.3, 0.0), Vector3::new(0.0, 1.0, 0.0)),
+      Plane::from_point_normal(Point3::new(0.0, 0.5, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+      Plane::from_point_normal(Point3::new(0.0, 0.0, 0.2), Vector3::new(0.0, 0.0, 1.0)),
+      Plane::from_point_normal(Point3::new(0.0, 0.0, 2.0), Vector3::new(0.0, 0.0, -1.0)),
+    ];
+    let indexed = mesh.select_in_frustum_indexed(& bvh, & planes);
+    assert!(!indexed.faces.is_empty());
+    assert_eq!(indexed, mesh.select_in_frustum(& planes));
+  }
+}
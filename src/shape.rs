@@ -0,0 +1,218 @@
+use cgmath::{Point2, Point3, Vector2, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use geom::{Aabb, Obb, Plane, symmetric_eigen};
+use bvh::{Bvh, BvhTriangle};
+use util::OpTimer;
+
+/// Most points checked against each candidate plane by `detect_symmetry_planes`
+const SYMMETRY_SAMPLES: usize = 2000;
+/// Rounds of refitting each candidate plane to its reflected points
+const SYMMETRY_REFINE_ROUNDS: usize = 5;
+
+/// Convex hull of the points by Andrew's monotone chain, counterclockwise, without repeated or collinear points
+fn convex_hull_2d(points: & [Point2<f32>]) -> Vec<Point2<f32>> {
+  let mut sorted: Vec<Point2<f32>> = points.to_vec();
+  sorted.sort_by(|a, b| a.x.total_cmp(& b.x).then(a.y.total_cmp(& b.y)));
+  sorted.dedup();
+  if sorted.len() < 3 { return sorted; }
+
+  let turn = |o: Point2<f32>, a: Point2<f32>, b: Point2<f32>| (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x);
+  let mut hull: Vec<Point2<f32>> = Vec::new();
+  for pass in 0..2 {
+    let start = hull.len();
+    let ordered: Box<dyn Iterator<Item=& Point2<f32>>> = if pass == 0 { Box::new(sorted.iter()) } else { Box::new(sorted.iter().rev()) };
+    for &point in ordered {
+      while hull.len() >= start + 2 && turn(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0 { hull.pop(); }
+      hull.push(point);
+    }
+    // The last point of each half is the first of the other
+    hull.pop();
+  }
+  hull
+}
+
+/// Unit direction, in the plane, of the side of the smallest rectangle around the convex polygon.
+/// One side of that rectangle always lies along a side of the polygon, so every side is tried
+fn min_area_rect_dir(hull: & [Point2<f32>]) -> Vector2<f32> {
+  let mut best = (f32::INFINITY, Vector2::new(1.0, 0.0));
+  for idx in 0..hull.len() {
+    let side = hull[(idx + 1) % hull.len()] - hull[idx];
+    if side.magnitude2() == 0.0 { continue; }
+    let dir = side.normalize();
+    let perp = Vector2::new(-dir.y, dir.x);
+    let (mut lo, mut hi) = (Vector2::new(f32::INFINITY, f32::INFINITY), Vector2::new(f32::NEG_INFINITY, f32::NEG_INFINITY));
+    for point in hull {
+      let local = Vector2::new(point.to_vec().dot(dir), point.to_vec().dot(perp));
+      lo = Vector2::new(lo.x.min(local.x), lo.y.min(local.y));
+      hi = Vector2::new(hi.x.max(local.x), hi.y.max(local.y));
+    }
+    let area = (hi.x - lo.x) * (hi.y - lo.y);
+    if area < best.0 { best = (area, dir); }
+  }
+  best.1
+}
+
+/// Reflection of the point through the plane
+fn reflect(plane: & Plane, point: & Point3<f32>) -> Point3<f32> {
+  point - plane.normal * (2.0 * plane.signed_distance(point))
+}
+
+/// How far each point, reflected through the plane, lands from the surface, limited to `max_distance`.
+/// Also gives the closest point on the surface to each reflected point which was close enough
+fn reflected_gaps(plane: & Plane, points: & [Point3<f32>], surface: & Bvh, max_distance: f32) -> (Vec<f32>, Vec<Option<Point3<f32>>>) {
+  points.iter()
+    .map(|point| match surface.closest_point(& reflect(plane, point), max_distance) {
+      Some((found, hit)) => (hit.distance, Some(found)),
+      None => (max_distance, None),
+    })
+    .unzip()
+}
+
+impl HalfEdgeMesh {
+  /// Center and principal axes of the mesh's surface: the directions in which it's spread out the most,
+  /// the middle amount, and the least, as a right-handed frame. The surface is weighted by area, so the
+  /// axes don't depend on how finely it's tessellated. Meshes without any area fall back to their vertices.
+  /// None for an empty mesh. Each axis could as well point the other way, and axes with the same spread,
+  /// like any two of a cube's, can come out turned any way around each other
+  pub fn pca_axes(& self) -> Option<(Point3<f32>, [Vector3<f32>; 3])> {
+    // Second moment of a triangle about the origin is area / 12 * (a a' + b b' + c c' + s s'), with s = a + b + c
+    let mut total_area = 0.0;
+    let mut first = Vector3::new(0.0, 0.0, 0.0);
+    let mut second = [[0.0f32; 3]; 3];
+    for face in self.faces.values() {
+      let positions = face.borrow().vert_positions();
+      for idx in 1..positions.len().saturating_sub(1) {
+        let (a, b, c) = (positions[0].to_vec(), positions[idx].to_vec(), positions[idx + 1].to_vec());
+        let area = (b - a).cross(c - a).magnitude() / 2.0;
+        let sum = a + b + c;
+        total_area += area;
+        first += sum * (area / 3.0);
+        for row in 0..3 {
+          for col in 0..3 {
+            second[row][col] += area / 12.0 * (a[row] * a[col] + b[row] * b[col] + c[row] * c[col] + sum[row] * sum[col]);
+          }
+        }
+      }
+    }
+    if total_area <= 0.0 {
+      if self.vertices.is_empty() { return None; }
+      total_area = self.vertices.len() as f32;
+      first = Vector3::new(0.0, 0.0, 0.0);
+      second = [[0.0; 3]; 3];
+      for vert in self.vertices.values() {
+        let p = vert.borrow().get_pos().to_vec();
+        first += p;
+        for row in 0..3 {
+          for col in 0..3 { second[row][col] += p[row] * p[col]; }
+        }
+      }
+    }
+
+    let mean = first / total_area;
+    let mut covariance = [[0.0f32; 3]; 3];
+    for row in 0..3 {
+      for col in 0..3 { covariance[row][col] = second[row][col] / total_area - mean[row] * mean[col]; }
+    }
+    let (_, axes) = symmetric_eigen(& covariance);
+    Some((Point3::from_vec(mean), axes))
+  }
+
+  /// A tight box around the mesh, turned to fit it. Starts from the principal axes, then for each of them,
+  /// keeps it and turns the box around it to fit the outline of the mesh seen along it as closely as possible,
+  /// keeping whichever box has the least volume. This finds the best box whenever one of the principal axes
+  /// is an axis of the best box, as for most manufactured parts. None for an empty mesh
+  pub fn oriented_bounding_box(& self) -> Option<Obb> {
+    let (_, pca) = self.pca_axes()?;
+    let points: Vec<Point3<f32>> = self.vertices.values().map(|v| v.borrow().get_pos()).collect();
+
+    let mut best = Obb::from_points_with_axes(& points, pca)?;
+    for up in 0..3 {
+      let (u, v) = (pca[(up + 1) % 3], pca[(up + 2) % 3]);
+      let outline: Vec<Point2<f32>> = points.iter().map(|p| Point2::new(p.to_vec().dot(u), p.to_vec().dot(v))).collect();
+      let dir = min_area_rect_dir(& convex_hull_2d(& outline));
+      let first = u * dir.x + v * dir.y;
+      let axes = [first, pca[up].cross(first), pca[up]];
+      if let Some(obb) = Obb::from_points_with_axes(& points, axes) {
+        if obb.volume() < best.volume() { best = obb; }
+      }
+    }
+    Some(best)
+  }
+
+  /// Finds planes the mesh is mirror symmetric across, to within `tolerance`: every vertex and face center,
+  /// reflected through the plane, has to land no further than that from the surface. Candidates are the planes
+  /// through the center of the surface across each principal axis, and across the diagonals between each
+  /// two of them, each refit a few times to the points and where their reflections land, so a scan which is
+  /// slightly off still counts. The planes are returned best fit first, and are what was found, not all
+  /// there are: a shape like a cube, whose principal axes are arbitrary, may show only some of its planes
+  pub fn detect_symmetry_planes(& self, tolerance: f32) -> Vec<Plane> {
+    let start = OpTimer::start();
+    let (center, axes) = match self.pca_axes() {
+      Some(found) => found,
+      None => return Vec::new(),
+    };
+
+    let mut triangles: Vec<BvhTriangle> = Vec::new();
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+    for &face_id in & face_ids {
+      let positions = self.faces[& face_id].borrow().vert_positions();
+      for idx in 1..positions.len().saturating_sub(1) {
+        let corners = [positions[0], positions[idx], positions[idx + 1]];
+        triangles.push(BvhTriangle { face_id, corners, aabb: Aabb::from_points(& corners) });
+      }
+    }
+    let surface = Bvh::from_triangles(triangles);
+    if surface.is_empty() { return Vec::new(); }
+
+    let mut points: Vec<Point3<f32>> = self.vertex_order().iter().map(|id| self.vertices[id].borrow().get_pos()).collect();
+    points.extend(face_ids.iter().map(|id| self.faces[id].borrow().center));
+    let stride = points.len().div_ceil(SYMMETRY_SAMPLES);
+    let points: Vec<Point3<f32>> = points.into_iter().step_by(stride.max(1)).collect();
+
+    let mut normals: Vec<Vector3<f32>> = axes.to_vec();
+    for (i, j) in [(0, 1), (0, 2), (1, 2)] {
+      normals.push((axes[i] + axes[j]).normalize());
+      normals.push((axes[i] - axes[j]).normalize());
+    }
+
+    // Refitting looks further than the tolerance, so candidates which start out a little off can be pulled in
+    let reach = (self.aabb().size().magnitude() * 0.05).max(tolerance * 4.0);
+    let mut found: Vec<(f32, Plane)> = Vec::new();
+    for normal in normals {
+      let mut plane = Plane::from_point_normal(center, normal);
+      for _ in 0..SYMMETRY_REFINE_ROUNDS {
+        // Each point and the surface point its reflection lands by should be mirror images,
+        // so the plane is refit to run halfway between them, at right angles to the line between them
+        let (_, matches) = reflected_gaps(& plane, & points, & surface, reach);
+        let mut direction = Vector3::new(0.0, 0.0, 0.0);
+        let mut pairs: Vec<(Point3<f32>, Point3<f32>)> = Vec::new();
+        for (point, matched) in points.iter().zip(& matches) {
+          let mirror = match matched { Some(mirror) => *mirror, None => continue };
+          let across = point - mirror;
+          if across.magnitude2() <= tolerance * tolerance { continue; }
+          direction += if across.dot(plane.normal) >= 0.0 { across } else { -across };
+          pairs.push((*point, mirror));
+        }
+        if pairs.is_empty() || direction.magnitude2() == 0.0 { break; }
+        let normal = direction.normalize();
+        let offset = pairs.iter().map(|(a, b)| normal.dot(a.to_vec() + b.to_vec()) / 2.0).sum::<f32>() / pairs.len() as f32;
+        plane = Plane::new(normal, offset);
+      }
+
+      let (gaps, _) = reflected_gaps(& plane, & points, & surface, tolerance * 2.0);
+      if gaps.iter().any(|&gap| gap > tolerance) { continue; }
+      let mean_gap = gaps.iter().sum::<f32>() / gaps.len() as f32;
+      // Candidates which refit to a plane already found are the same plane
+      let duplicate = found.iter().any(|(_, other)| {
+        other.normal.dot(plane.normal).abs() > 0.999 && (other.offset - plane.offset * other.normal.dot(plane.normal).signum()).abs() <= tolerance
+      });
+      if !duplicate { found.push((mean_gap, plane)); }
+    }
+
+    found.sort_by(|a, b| a.0.total_cmp(& b.0));
+    debug!("detect_symmetry_planes: {} planes from {} points, took {:?}", found.len(), points.len(), start.elapsed());
+    found.into_iter().map(|(_, plane)| plane).collect()
+  }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, Vector3, InnerSpace, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::EdgeRc;
+use util::OpTimer;
+
+/// Settings for `ClothSim`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClothOptions {
+  /// Acceleration applied to every free vertex, in the mesh's units per second squared
+  pub gravity: Vector3<f32>,
+  /// Fraction of each vertex's velocity lost every step, from 0 for none to 1 for all of it
+  pub damping: f32,
+  /// How far each edge is pulled back toward its rest length per iteration, from 0 for not at all to 1 for all the way
+  pub stretch_stiffness: f32,
+  /// Like `stretch_stiffness`, for the invisible springs across each pair of neighboring triangles which
+  /// resist folding. 0 leaves the cloth free to crumple
+  pub bend_stiffness: f32,
+  /// Constraint passes per step. More passes make the cloth less stretchy
+  pub iterations: usize,
+}
+
+impl Default for ClothOptions {
+  fn default() -> ClothOptions {
+    ClothOptions {
+      gravity: Vector3::new(0.0, -9.8, 0.0),
+      damping: 0.01,
+      stretch_stiffness: 1.0,
+      bend_stiffness: 0.1,
+      iterations: 10,
+    }
+  }
+}
+
+/// A distance constraint between two vertices, by their index in the simulation
+#[derive(Debug, Clone, Copy)]
+struct Constraint {
+  a: usize,
+  b: usize,
+  rest: f32,
+  bend: bool,
+}
+
+/// Position-based dynamics over a mesh: every edge is a distance constraint which wants to stay as long as
+/// it was when the simulation was made, so the mesh hangs and swings like cloth. Pinned vertices stay where
+/// the mesh has them, and can be moved with `HalfEdgeMesh::move_vert` between steps to drag the cloth around.
+///
+/// The simulation keeps each vertex's previous position to know its velocity, and steps the mesh's own
+/// vertex positions. It holds on to the vertex ids it was made with, so make a new one after any change
+/// to the mesh's topology
+#[derive(Debug, Clone)]
+pub struct ClothSim {
+  pub options: ClothOptions,
+  ids: Vec<u32>,
+  index: HashMap<u32, usize>,
+  previous: Vec<Point3<f32>>,
+  pinned: Vec<bool>,
+  constraints: Vec<Constraint>,
+}
+
+impl ClothSim {
+  /// Makes a simulation of the mesh at rest, with every edge at its rest length and nothing pinned
+  pub fn new(mesh: & HalfEdgeMesh, options: ClothOptions) -> ClothSim {
+    let ids = mesh.vertex_order();
+    let index = mesh.vertex_index();
+    let previous: Vec<Point3<f32>> = ids.iter().map(|id| mesh.vertices[id].borrow().get_pos()).collect();
+
+    let mut constraints: Vec<Constraint> = Vec::new();
+    for edge in mesh.edges.values() {
+      let edge_b = edge.borrow();
+      let (origin, target) = match (edge_b.get_origin(), edge_b.get_target()) {
+        (Some(origin), Some(target)) => (origin, target),
+        _ => continue,
+      };
+      let (a, b) = (index[& origin.borrow().id], index[& target.borrow().id]);
+      let pair = edge_b.get_pair();
+      // Each edge once, from the half with the lower origin index, or the only half on a border
+      if a > b && pair.is_some() { continue; }
+      constraints.push(Constraint { a, b, rest: previous[a].distance(previous[b]), bend: false });
+
+      // The bending spring runs between the corners opposite the edge in the two faces either side of it
+      let opposite = |e: & EdgeRc| e.borrow().get_next_next().and_then(|n| n.borrow().get_origin());
+      if let Some(pair) = pair {
+        if let (Some(c), Some(d)) = (opposite(edge), opposite(& pair)) {
+          let (c, d) = (index[& c.borrow().id], index[& d.borrow().id]);
+          if c != d { constraints.push(Constraint { a: c, b: d, rest: previous[c].distance(previous[d]), bend: true }); }
+        }
+      }
+    }
+
+    let pinned = vec![false; ids.len()];
+    ClothSim { options, ids, index, previous, pinned, constraints }
+  }
+
+  /// Holds the vertex where the mesh has it. Returns false if the vertex isn't part of the simulation
+  pub fn pin(&mut self, vert_id: u32) -> bool { self.set_pinned(vert_id, true) }
+
+  /// Lets the vertex move again, starting at rest. Returns false if the vertex isn't part of the simulation
+  pub fn unpin(&mut self, vert_id: u32) -> bool { self.set_pinned(vert_id, false) }
+
+  pub fn is_pinned(& self, vert_id: u32) -> bool {
+    self.index.get(& vert_id).is_some_and(|&idx| self.pinned[idx])
+  }
+
+  fn set_pinned(&mut self, vert_id: u32, pinned: bool) -> bool {
+    match self.index.get(& vert_id) {
+      Some(&idx) => { self.pinned[idx] = pinned; true },
+      None => false,
+    }
+  }
+
+  /// Advances the simulation by `dt` seconds, moving the mesh's vertices in place and updating its face normals.
+  /// Fails without changing anything if a vertex of the simulation is no longer in the mesh
+  pub fn step(&mut self, mesh: &mut HalfEdgeMesh, dt: f32) -> Result<(), &'static str> {
+    let start = OpTimer::start();
+    let verts = self.ids.iter()
+      .map(|id| mesh.vertices.get(id).cloned().ok_or("Vertex of the simulation is no longer in the mesh"))
+      .collect::<Result<Vec<_>, _>>()?;
+    let current: Vec<Point3<f32>> = verts.iter().map(|v| v.borrow().get_pos()).collect();
+    let ClothOptions { gravity, damping, stretch_stiffness, bend_stiffness, iterations } = self.options;
+    let damping = damping.clamp(0.0, 1.0);
+
+    // Verlet integration: keep going at the same velocity, less damping, plus gravity
+    let mut positions: Vec<Point3<f32>> = current.iter().enumerate()
+      .map(|(idx, &pos)| if self.pinned[idx] { pos } else { pos + (pos - self.previous[idx]) * (1.0 - damping) + gravity * dt * dt })
+      .collect();
+
+    // Project the constraints one after another, moving only the free ends
+    for _ in 0..iterations {
+      for constraint in & self.constraints {
+        let Constraint { a, b, rest, bend } = *constraint;
+        let (free_a, free_b) = (!self.pinned[a], !self.pinned[b]);
+        if !free_a && !free_b { continue; }
+        let stiffness = if bend { bend_stiffness } else { stretch_stiffness }.clamp(0.0, 1.0);
+        let along = positions[b] - positions[a];
+        let length = along.magnitude();
+        if length <= 0.0 || stiffness == 0.0 { continue; }
+        let correction = along * (stiffness * (length - rest) / length);
+        match (free_a, free_b) {
+          (true, true) => { positions[a] += correction / 2.0; positions[b] -= correction / 2.0; },
+          (true, false) => positions[a] += correction,
+          _ => positions[b] -= correction,
+        }
+      }
+    }
+
+    for (idx, vert) in verts.iter().enumerate() {
+      // Pinned vertices have no velocity, so they don't fly off when they're unpinned
+      self.previous[idx] = if self.pinned[idx] { positions[idx] } else { current[idx] };
+      mesh.move_vert(vert, positions[idx]);
+    }
+    for face in mesh.faces.values() { face.borrow_mut().compute_attrs(); }
+    debug!("ClothSim::step: {} vertices, {} constraints, took {:?}", verts.len(), self.constraints.len(), start.elapsed());
+    Ok(())
+  }
+}
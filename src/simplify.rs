@@ -0,0 +1,473 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::{EdgeRc, VertRc};
+use selection::Selection;
+use kernel::{MeshTopology, MeshGeometry, EditableMesh};
+use util::OpTimer;
+
+/// Symmetric 4x4 error quadric (Garland & Heckbert), stored as its upper triangle.
+/// Evaluating it at a point gives the sum of squared distances to the planes it was built from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quadric {
+  vals: [f64; 10],
+}
+
+impl Quadric {
+  pub fn zero() -> Quadric {
+    Quadric { vals: [0.0; 10] }
+  }
+
+  /// Quadric of the plane through the point with the given unit normal, scaled by weight
+  pub fn from_plane(normal: Vector3<f32>, point: Point3<f32>, weight: f32) -> Quadric {
+    let (a, b, c) = (normal.x as f64, normal.y as f64, normal.z as f64);
+    let d = -(normal.dot(point.to_vec()) as f64);
+    let w = weight as f64;
+    Quadric {
+      vals: [
+        w * a * a, w * a * b, w * a * c, w * a * d,
+                   w * b * b, w * b * c, w * b * d,
+                              w * c * c, w * c * d,
+                                         w * d * d,
+      ],
+    }
+  }
+
+  pub fn add(& self, other: & Quadric) -> Quadric {
+    let mut vals = self.vals;
+    for (val, o) in vals.iter_mut().zip(other.vals.iter()) { *val += *o; }
+    Quadric { vals }
+  }
+
+  /// Squared distance error of a point
+  pub fn evaluate(& self, point: & Point3<f32>) -> f32 {
+    let q = & self.vals;
+    let (x, y, z) = (point.x as f64, point.y as f64, point.z as f64);
+    let err = q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9];
+    err.max(0.0) as f32
+  }
+
+  /// The point with the least error, if the quadric isn't singular
+  pub fn minimizer(& self) -> Option<Point3<f32>> {
+    let q = & self.vals;
+    // Solve the 3x3 system with Cramer's rule
+    let det = q[0] * (q[4] * q[7] - q[5] * q[5])
+            - q[1] * (q[1] * q[7] - q[5] * q[2])
+            + q[2] * (q[1] * q[5] - q[4] * q[2]);
+    if det.abs() < 1e-12 { return None; }
+
+    let (b_x, b_y, b_z) = (-q[3], -q[6], -q[8]);
+    let x = (b_x * (q[4] * q[7] - q[5] * q[5]) - q[1] * (b_y * q[7] - q[5] * b_z) + q[2] * (b_y * q[5] - q[4] * b_z)) / det;
+    let y = (q[0] * (b_y * q[7] - q[5] * b_z) - b_x * (q[1] * q[7] - q[5] * q[2]) + q[2] * (q[1] * b_z - b_y * q[2])) / det;
+    let z = (q[0] * (q[4] * b_z - b_y * q[5]) - q[1] * (q[1] * b_z - b_y * q[2]) + b_x * (q[1] * q[5] - q[4] * q[2])) / det;
+    Some(Point3::new(x as f32, y as f32, z as f32))
+  }
+}
+
+/// Constraints for `HalfEdgeMesh::decimate`. The default has no constraints at all
+#[derive(Debug, Clone, Default)]
+pub struct DecimateOptions {
+  /// Never collapse the border edges of an open mesh, and never move border vertices,
+  /// so that the outline of the mesh is preserved exactly
+  pub preserve_boundary: bool,
+  /// Selected edges (either half of a pair) are never collapsed, and selected vertices,
+  /// including the ends of selected edges, are never moved. Use this for seams and creases,
+  /// e.g. `Selection::from_edges(& mesh.detect_feature_edges(angle))`
+  pub features: Selection,
+  /// Reject any collapse which would rotate a face normal by more than this angle, in radians
+  pub max_normal_deviation: Option<f32>,
+}
+
+/// Heap entry for an edge collapse. Ordered so that the cheapest collapse is popped first
+struct Candidate {
+  cost: f32,
+  edge_id: u32,
+  /// Versions of the edge's origin and target vertices when the cost was computed
+  stamp: (u32, u32, u32, u32),
+}
+
+impl PartialEq for Candidate {
+  fn eq(& self, other: & Candidate) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+  fn partial_cmp(& self, other: & Candidate) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Candidate {
+  fn cmp(& self, other: & Candidate) -> Ordering {
+    // Reversed, since BinaryHeap is a max-heap
+    other.cost.partial_cmp(& self.cost).unwrap_or(Ordering::Equal)
+      .then_with(|| other.edge_id.cmp(& self.edge_id))
+  }
+}
+
+/// State shared by the steps of a decimation run
+struct Decimator<'a> {
+  options: &'a DecimateOptions,
+  locked: HashSet<u32>,
+  quadrics: HashMap<u32, Quadric>,
+  versions: HashMap<u32, u32>,
+  min_normal_dot: f32,
+}
+
+impl<'a> Decimator<'a> {
+  fn is_feature_edge<M: MeshTopology>(& self, mesh: & M, edge: u32) -> bool {
+    self.options.features.contains_edge(edge) ||
+      mesh.edge_pair(edge).is_some_and(|p| self.options.features.contains_edge(p))
+  }
+
+  /// Where the merged vertex should go, and the error of putting it there.
+  /// None if the constraints forbid collapsing the edge
+  fn plan<M: MeshGeometry>(& self, mesh: & M, edge: u32) -> Option<(f32, Point3<f32>)> {
+    if self.is_feature_edge(mesh, edge) { return None; }
+    if self.options.preserve_boundary && mesh.is_boundary_edge(edge) { return None; }
+
+    let (origin, target) = mesh.edge_verts(edge)?;
+    let quadric = self.quadrics.get(& origin)?.add(self.quadrics.get(& target)?);
+    let (origin_pos, target_pos) = (mesh.vert_pos(origin), mesh.vert_pos(target));
+
+    let pos = match (self.locked.contains(& origin), self.locked.contains(& target)) {
+      (true, true) => return None,
+      (true, false) => origin_pos,
+      (false, true) => target_pos,
+      (false, false) => {
+        let midpoint = Point3::midpoint(origin_pos, target_pos);
+        // Fall back to the best of the endpoints and the midpoint if the quadric is singular
+        quadric.minimizer().unwrap_or_else(|| {
+          let options = [origin_pos, target_pos, midpoint];
+          let mut best = midpoint;
+          for option in & options {
+            if quadric.evaluate(option) < quadric.evaluate(& best) { best = *option; }
+          }
+          best
+        })
+      },
+    };
+
+    Some((quadric.evaluate(& pos), pos))
+  }
+
+  fn stamp<M: MeshTopology>(& self, mesh: & M, edge: u32) -> Option<(u32, u32, u32, u32)> {
+    let (origin, target) = mesh.edge_verts(edge)?;
+    Some((origin, self.versions[& origin], target, self.versions[& target]))
+  }
+
+  fn candidate<M: MeshGeometry>(& self, mesh: & M, edge: u32) -> Option<Candidate> {
+    let (cost, _) = self.plan(mesh, edge)?;
+    Some(Candidate { cost, edge_id: edge, stamp: self.stamp(mesh, edge)? })
+  }
+
+  /// Checks that none of the faces which survive the collapse would flip over,
+  /// become degenerate, or turn further than the allowed normal deviation
+  fn normals_ok<M: MeshGeometry>(& self, mesh: & M, edge: u32, pos: Point3<f32>) -> bool {
+    let (origin, target) = match mesh.edge_verts(edge) { Some(ends) => ends, None => return false };
+    let removed_faces = mesh.edge_faces(edge);
+
+    for &end in & [origin, target] {
+      for face in mesh.vert_faces(end) {
+        if removed_faces.contains(& face) { continue; }
+
+        let corners: Vec<Point3<f32>> = mesh.face_verts(face).into_iter()
+          .map(|v| if v == origin || v == target { pos } else { mesh.vert_pos(v) })
+          .collect();
+        if corners.len() != 3 { return false; }
+
+        let new_normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        let length = new_normal.magnitude();
+        if length <= f32::EPSILON { return false; }
+        if (new_normal / length).dot(mesh.face_normal(face)) < self.min_normal_dot { return false; }
+      }
+    }
+
+    true
+  }
+}
+
+/// `HalfEdgeMesh::decimate` for any mesh kernel
+pub fn decimate<M: EditableMesh>(mesh: &mut M, target_faces: usize, options: & DecimateOptions) -> usize {
+  let start = OpTimer::start();
+  let start_faces = mesh.num_faces();
+  let mut locked: HashSet<u32> = options.features.verts.clone();
+  for &edge_id in & options.features.edges {
+    if let Some((origin, target)) = mesh.edge_verts(edge_id) {
+      locked.insert(origin);
+      locked.insert(target);
+    }
+  }
+  let vert_ids = mesh.vert_ids();
+  if options.preserve_boundary {
+    for &vert in & vert_ids {
+      if mesh.is_boundary_vert(vert) { locked.insert(vert); }
+    }
+  }
+
+  let mut quadrics: HashMap<u32, Quadric> = vert_ids.iter().map(|id| (*id, Quadric::zero())).collect();
+  for face in mesh.face_ids() {
+    let plane = Quadric::from_plane(mesh.face_normal(face), mesh.face_center(face), mesh.face_area(face));
+    for vert in mesh.face_verts(face) {
+      if let Some(q) = quadrics.get_mut(& vert) { *q = q.add(& plane); }
+    }
+  }
+
+  let mut decimator = Decimator {
+    options,
+    locked,
+    quadrics,
+    versions: vert_ids.iter().map(|id| (*id, 0)).collect(),
+    // Even without a limit, faces must never flip over
+    min_normal_dot: options.max_normal_deviation.map_or(0.0, |angle| angle.cos().max(0.0)),
+  };
+
+  let mut heap: BinaryHeap<Candidate> = BinaryHeap::new();
+  for edge in mesh.edge_ids() {
+    // Only consider one half of each pair
+    let is_canonical = mesh.edge_pair(edge).is_none_or(|p| p > edge);
+    if !is_canonical { continue; }
+    if let Some(candidate) = decimator.candidate(mesh, edge) { heap.push(candidate); }
+  }
+
+  let mut collapsed = 0;
+
+  while mesh.num_faces() > target_faces {
+    let candidate = match heap.pop() { Some(c) => c, None => break };
+    let edge = candidate.edge_id;
+    // Skip the entry if the edge is gone, or either end has changed since it was pushed
+    if decimator.stamp(mesh, edge) != Some(candidate.stamp) { continue; }
+
+    let (_, pos) = match decimator.plan(mesh, edge) { Some(p) => p, None => continue };
+    if !decimator.normals_ok(mesh, edge, pos) || !mesh.can_collapse(edge) { continue; }
+
+    let (origin_id, target_id) = (candidate.stamp.0, candidate.stamp.2);
+    let kept = match mesh.collapse(edge, pos) { Some(v) => v, None => continue };
+    collapsed += 1;
+
+    let merged = decimator.quadrics[& origin_id].add(& decimator.quadrics[& target_id]);
+    decimator.quadrics.insert(origin_id, merged);
+    decimator.quadrics.remove(& target_id);
+    decimator.versions.remove(& target_id);
+    if let Some(version) = decimator.versions.get_mut(& origin_id) { *version += 1; }
+    if decimator.locked.remove(& target_id) { decimator.locked.insert(origin_id); }
+
+    // Every edge touching the merged vertex has a new cost
+    for touching in mesh.vert_edges(kept) {
+      if let Some(c) = decimator.candidate(mesh, touching) { heap.push(c); }
+    }
+  }
+
+  debug!("decimate: collapsed {} edges, {} faces down to {} (target {}), took {:?}",
+    collapsed, start_faces, mesh.num_faces(), target_faces, start.elapsed());
+  collapsed
+}
+
+impl HalfEdgeMesh {
+  /// Simplifies the mesh by collapsing edges, cheapest first according to the quadric error metric,
+  /// until the mesh has at most `target_faces` faces or no more edges can be collapsed.
+  /// Collapses which would break the manifold structure, flip a face, or violate
+  /// one of the constraints in `options` are skipped. Returns the number of edges collapsed.
+  /// Assumes triangular faces
+  pub fn decimate(&mut self, target_faces: usize, options: & DecimateOptions) -> usize {
+    decimate(self, target_faces, options)
+  }
+
+  /// Builds a set of levels of detail, one for each ratio of the mesh's face count, in the same order.
+  /// Ratios are clamped to [0, 1], and a ratio of 1 gives a copy. Each level is decimated from the next more
+  /// detailed one, with the constraints in `options`, so the levels nest and later ones come cheap; a limit on
+  /// `max_normal_deviation` keeps the silhouettes from caving in. Texture coordinates and registered attributes
+  /// are blended across every collapse, and tangents are recomputed if the mesh has them. Texture seams cut with
+  /// `pack_atlas` are borders, so set `preserve_boundary` to keep them from pulling apart.
+  /// A level may have more faces than asked for if no more edges can be collapsed. Assumes triangular faces
+  pub fn generate_lod_chain(& self, ratios: & [f32], options: & DecimateOptions) -> Vec<HalfEdgeMesh> {
+    let start = OpTimer::start();
+    let face_count = self.faces.len();
+    let targets: Vec<usize> = ratios.iter()
+      .map(|ratio| (ratio.clamp(0.0, 1.0) * face_count as f32).ceil() as usize)
+      .collect();
+    let mut order: Vec<usize> = (0..ratios.len()).collect();
+    order.sort_by_key(|&idx| ::std::cmp::Reverse(targets[idx]));
+
+    let mut levels: Vec<Option<HalfEdgeMesh>> = (0..ratios.len()).map(|_| None).collect();
+    let mut previous: Option<usize> = None;
+    for idx in order {
+      let mut level = previous.and_then(|p| levels[p].as_ref()).unwrap_or(self).clone();
+      if level.faces.len() > targets[idx] {
+        level.decimate(targets[idx], options);
+        if !self.tangents.is_empty() { level.compute_tangents(); }
+      }
+      levels[idx] = Some(level);
+      previous = Some(idx);
+    }
+
+    let levels: Vec<HalfEdgeMesh> = levels.into_iter().flatten().collect();
+    debug!("generate_lod_chain: {} faces down to {:?}, took {:?}",
+      face_count, levels.iter().map(|l| l.faces.len()).collect::<Vec<_>>(), start.elapsed());
+    levels
+  }
+
+  /// Simplifies the mesh by snapping the vertices to a grid of cubes `cell_size` across, and merging all
+  /// the vertices in each cube into one at their average position (Rossignac & Borrel). Much faster than
+  /// `decimate`, but with no regard for the shape, so it suits far-off levels of detail of large meshes.
+  /// Faces left with fewer than three corners are dropped, and so are faces which would fold back
+  /// onto themselves or reuse an edge running the same way as an earlier face.
+  /// A cell size which isn't positive gives back a copy of the mesh
+  pub fn vertex_clustering(& self, cell_size: f32) -> HalfEdgeMesh {
+    let start = OpTimer::start();
+    let (positions, polygons) = self.to_polygons();
+    if cell_size.is_nan() || cell_size <= 0.0 {
+      let mut copy = HalfEdgeMesh::from_polygons(& positions, & polygons);
+      copy.set_units(self.units());
+      return copy;
+    }
+
+    let mut cluster_of: Vec<usize> = Vec::with_capacity(positions.len());
+    let mut cells: HashMap<(i64, i64, i64), usize> = HashMap::new();
+    let mut sums: Vec<(Vector3<f32>, usize)> = Vec::new();
+    for pos in & positions {
+      let cell = ((pos.x / cell_size).floor() as i64, (pos.y / cell_size).floor() as i64, (pos.z / cell_size).floor() as i64);
+      let cluster = *cells.entry(cell).or_insert_with(|| { sums.push((Vector3::new(0.0, 0.0, 0.0), 0)); sums.len() - 1 });
+      sums[cluster].0 += pos.to_vec();
+      sums[cluster].1 += 1;
+      cluster_of.push(cluster);
+    }
+
+    let mut used: HashSet<(usize, usize)> = HashSet::new();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+    for polygon in & polygons {
+      let mut face: Vec<usize> = polygon.iter().map(|&idx| cluster_of[idx]).collect();
+      face.dedup();
+      while face.len() > 1 && face.first() == face.last() { face.pop(); }
+      if face.len() < 3 { continue; }
+      if face.iter().collect::<HashSet<_>>().len() != face.len() { continue; }
+
+      let sides: Vec<(usize, usize)> = (0..face.len()).map(|idx| (face[idx], face[(idx + 1) % face.len()])).collect();
+      if sides.iter().any(|side| used.contains(side)) { continue; }
+      used.extend(sides);
+      faces.push(face);
+    }
+
+    // Clusters which aren't in any face would be left without an edge
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    let mut merged: Vec<Point3<f32>> = Vec::new();
+    for face in &mut faces {
+      for cluster in face.iter_mut() {
+        *cluster = *index.entry(*cluster).or_insert_with(|| {
+          let (sum, count) = sums[*cluster];
+          merged.push(Point3::from_vec(sum / count as f32));
+          merged.len() - 1
+        });
+      }
+    }
+
+    let mut mesh = HalfEdgeMesh::from_polygons(& merged, & faces);
+    mesh.set_units(self.units());
+    debug!("vertex_clustering: {} vertices and {} faces down to {} and {}, took {:?}",
+      positions.len(), polygons.len(), merged.len(), faces.len(), start.elapsed());
+    mesh
+  }
+
+  /// Collapses every edge shorter than `min_len`, shortest first, going round again until none are left
+  /// which can be collapsed, and returns how many were. Edges whose collapse would break the manifold
+  /// structure (see `can_collapse_edge`) or flip a face over are skipped. The merged vertex goes
+  /// to the middle of the edge, unless an end is on the border, where it stays put so the border keeps
+  /// its shape. Along the border, the end where it turns more stays put.
+  /// Cleans up the slivers in scanned meshes. Assumes triangular faces
+  pub fn collapse_short_edges(&mut self, min_len: f32) -> usize {
+    let start = OpTimer::start();
+    let length = |edge: & EdgeRc| -> Option<f32> {
+      let edge_b = edge.borrow();
+      Some(edge_b.get_origin()?.borrow().get_pos().distance(edge_b.get_target()?.borrow().get_pos()))
+    };
+
+    let mut collapsed = 0;
+    loop {
+      let mut short: Vec<(f32, u32)> = self.edges.iter()
+        .filter_map(|(&id, e)| length(e).filter(|&len| len < min_len).map(|len| (len, id)))
+        .collect();
+      short.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+      let mut progress = false;
+      for (_, id) in short {
+        // Earlier collapses may have removed the edge, or stretched it
+        let edge = match self.edges.get(& id) { Some(e) => e.clone(), None => continue };
+        if !length(& edge).is_some_and(|len| len < min_len) || !self.can_collapse_edge(& edge) { continue; }
+        let (origin, target) = match (edge.borrow().get_origin(), edge.borrow().get_target()) {
+          (Some(o), Some(t)) => (o, t),
+          _ => continue,
+        };
+        let pos = collapse_position(& origin, & target);
+        if collapse_flips_face(& edge, & origin, & target, pos) { continue; }
+        if self.collapse_edge(& edge, pos).is_ok() {
+          collapsed += 1;
+          progress = true;
+        }
+      }
+      if !progress { break; }
+    }
+
+    debug!("collapse_short_edges: collapsed {} edges shorter than {}, took {:?}", collapsed, min_len, start.elapsed());
+    collapsed
+  }
+}
+
+/// Where `collapse_short_edges` puts the vertex merged from the ends of an edge: in the middle, unless an end
+/// is on the border, where it stays put so the border keeps its shape, and along the border, at the end
+/// where it turns more
+pub(crate) fn collapse_position(origin: & VertRc, target: & VertRc) -> Point3<f32> {
+  match (origin.borrow().is_boundary(), target.borrow().is_boundary()) {
+    (true, false) => origin.borrow().get_pos(),
+    (false, true) => target.borrow().get_pos(),
+    (false, false) => Point3::midpoint(origin.borrow().get_pos(), target.borrow().get_pos()),
+    // Keeping the end where the border turns more keeps corners in place
+    (true, true) => if border_straightness(origin) <= border_straightness(target) {
+      origin.borrow().get_pos()
+    } else {
+      target.borrow().get_pos()
+    },
+  }
+}
+
+/// Cosine of the angle the border turns by at the vertex: 1 where it goes straight on
+fn border_straightness(vert: & VertRc) -> f32 {
+  let pos = vert.borrow().get_pos();
+  let mut incoming: Option<Vector3<f32>> = None;
+  let mut outgoing: Option<Vector3<f32>> = None;
+  for out_edge in vert.borrow().outgoing_edges() {
+    let out_b = out_edge.borrow();
+    if out_b.is_boundary() {
+      outgoing = out_b.get_target().map(|t| t.borrow().get_pos() - pos);
+    }
+    if let Some(prev) = out_b.get_prev().filter(|p| p.borrow().is_boundary()) {
+      incoming = prev.borrow().get_origin().map(|o| pos - o.borrow().get_pos());
+    }
+  }
+  match (incoming, outgoing) {
+    (Some(a), Some(b)) if a.magnitude() > 0.0 && b.magnitude() > 0.0 => a.normalize().dot(b.normalize()),
+    _ => 1.0,
+  }
+}
+
+/// Whether any face which survives collapsing the edge, with the merged vertex at `pos`, would turn over
+pub(crate) fn collapse_flips_face(edge: & EdgeRc, origin: & VertRc, target: & VertRc, pos: Point3<f32>) -> bool {
+  let removed: HashSet<u32> = edge.borrow().adjacent_faces().filter_map(|f| f.upgrade()).map(|f| f.borrow().id).collect();
+  let ends = [origin.borrow().id, target.borrow().id];
+  for end in & [origin, target] {
+    for face in end.borrow().adjacent_faces().filter_map(|f| f.upgrade()) {
+      let face_b = face.borrow();
+      if removed.contains(& face_b.id) { continue; }
+      let corners: Vec<Point3<f32>> = face_b.adjacent_verts().filter_map(|v| v.upgrade())
+        .map(|v| if ends.contains(& v.borrow().id) { pos } else { v.borrow().get_pos() })
+        .collect();
+      if corners.len() < 3 { continue; }
+      if (corners[1] - corners[0]).cross(corners[2] - corners[0]).dot(face_b.normal) <= 0.0 { return true; }
+    }
+  }
+  false
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use cgmath::Point2;
+
+use mesh::HalfEdgeMesh;
+use geom::Polygon2;
+use ptr::EdgeRc;
+
+/// Which side of the plane at the height the point is on. Points right on the plane count as above,
+/// so that an edge is either cut through the middle, or not at all
+fn above(z: f32, height: f32) -> bool { z >= height }
+
+/// Whether the edge's origin and target are above the plane
+fn edge_sides(edge: & EdgeRc, height: f32) -> Option<(bool, bool)> {
+  let edge_b = edge.borrow();
+  let (origin, target) = (edge_b.get_origin()?, edge_b.get_target()?);
+  let (origin_z, target_z) = (origin.borrow().get_pos().z, target.borrow().get_pos().z);
+  Some((above(origin_z, height), above(target_z, height)))
+}
+
+/// Where the edge crosses the plane. Measured from the end below it,
+/// so that both halves of an edge give exactly the same point
+fn crossing(edge: & EdgeRc, height: f32) -> Option<Point2<f32>> {
+  let edge_b = edge.borrow();
+  let (origin, target) = (edge_b.get_origin()?.borrow().get_pos(), edge_b.get_target()?.borrow().get_pos());
+  let (low, high) = if origin.z < target.z { (origin, target) } else { (target, origin) };
+  let t = (height - low.z) / (high.z - low.z);
+  Some(Point2::new(low.x + (high.x - low.x) * t, low.y + (high.y - low.y) * t))
+}
+
+/// Follows the contour starting from an edge which goes down through the plane: across its face
+/// to the edge where the face comes back up, then over that edge's pair into the next face, and so on
+/// until it gets back to the start. None if the contour runs into a border, and doesn't close up
+fn trace_contour(start: & EdgeRc, height: f32, visited: &mut HashSet<u32>) -> Option<Polygon2> {
+  let start_id = start.borrow().id;
+  let mut points: Vec<Point2<f32>> = Vec::new();
+  let mut down = start.clone();
+
+  loop {
+    let down_id = down.borrow().id;
+    visited.insert(down_id);
+
+    let mut up = down.borrow().get_next()?;
+    while edge_sides(& up, height)? != (false, true) {
+      if up.borrow().id == down_id { return None; }
+      let next = up.borrow().get_next()?;
+      up = next;
+    }
+
+    let point = crossing(& up, height)?;
+    // Contours through a vertex on the plane cross both of its edges at the vertex
+    if points.last() != Some(& point) { points.push(point); }
+
+    down = up.borrow().get_pair()?;
+    let next_id = down.borrow().id;
+    if next_id == start_id { break; }
+    if visited.contains(& next_id) { return None; }
+  }
+
+  if points.len() > 1 && points.first() == points.last() { points.pop(); }
+  if points.len() < 3 { return None; }
+  Some(Polygon2::new(points))
+}
+
+impl HalfEdgeMesh {
+  /// Slices the mesh into layers for 3D printing, by cutting it with horizontal planes through the middle of
+  /// each `layer_height` thick layer, from the bottom of the mesh up. Each layer's contours are closed polygons
+  /// in the xy plane, found by following the cut from face to face across edges. For a closed mesh
+  /// with outward faces, outer contours run counterclockwise and holes clockwise (see `Polygon2::signed_area`).
+  /// Vertices right on a plane count as above it. Contours which don't close up, because of holes in the mesh,
+  /// are left out
+  pub fn slice_layers(& self, layer_height: f32) -> Vec<Vec<Polygon2>> {
+    let bounds = self.aabb();
+    if bounds.is_empty() || layer_height <= 0.0 || !layer_height.is_finite() { return Vec::new(); }
+
+    let num_layers = ((bounds.max.z - bounds.min.z) / layer_height).ceil().max(1.0) as usize;
+    let base = bounds.min.z + layer_height * 0.5;
+    let height = |layer: usize| base + layer as f32 * layer_height;
+
+    // The edges going down through each layer's plane
+    let mut starts: Vec<Vec<EdgeRc>> = vec![Vec::new(); num_layers];
+    let mut edge_ids: Vec<u32> = self.edges.keys().cloned().collect();
+    edge_ids.sort();
+    for id in edge_ids {
+      let edge = & self.edges[& id];
+      let (origin_z, target_z) = {
+        let edge_b = edge.borrow();
+        match (edge_b.get_origin(), edge_b.get_target()) {
+          (Some(o), Some(t)) => (o.borrow().get_pos().z, t.borrow().get_pos().z),
+          _ => continue,
+        }
+      };
+      if target_z >= origin_z { continue; }
+
+      let first = ((target_z - base) / layer_height).floor().max(0.0) as usize;
+      let last = ((origin_z - base) / layer_height).floor().max(0.0) as usize;
+      for (layer, layer_starts) in starts.iter_mut().enumerate().take(last + 1).skip(first) {
+        if above(origin_z, height(layer)) && !above(target_z, height(layer)) { layer_starts.push(edge.clone()); }
+      }
+    }
+
+    starts.iter().enumerate().map(|(layer, layer_starts)| {
+      let mut visited: HashSet<u32> = HashSet::new();
+      layer_starts.iter()
+        .filter_map(|start| {
+          if visited.contains(& start.borrow().id) { return None; }
+          trace_contour(start, height(layer), &mut visited)
+        })
+        .collect()
+    }).collect()
+  }
+}
@@ -0,0 +1,258 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use cgmath::{Point3, EuclideanSpace, InnerSpace, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use iterators::ToPtrVec;
+
+/// How far apart positions, areas, and volumes can be before `MeshSnapshot::diff` reports them,
+/// relative to the size of the mesh, or to the area and volume themselves
+const TOLERANCE: f32 = 1e-5;
+
+/// How many differences of one kind `MeshSnapshot::diff` lists before summing up the rest
+const MAX_LISTED: usize = 10;
+
+/// A canonical copy of a mesh's contents, for comparing against a golden copy in tests.
+/// Vertices are numbered in order of id, each face starts at its lowest index, and the faces
+/// are sorted, so the snapshot doesn't depend on how the mesh's hash maps are laid out.
+/// Written out with `to_string`, and read back with `parse`, as lines of `v x y z` and `f a b c ...`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshSnapshot {
+  pub positions: Vec<Point3<f32>>,
+  /// Indices into the positions, counterclockwise
+  pub faces: Vec<Vec<u32>>,
+}
+
+/// Counts which follow from a snapshot's faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotCounts {
+  pub verts: usize,
+  pub faces: usize,
+  /// Edges between two vertices, counted once no matter how many faces they border
+  pub edges: usize,
+  /// Edges with a face on only one side
+  pub boundary_edges: usize,
+  /// Edges with more than two faces, or two faces going the same way
+  pub bad_edges: usize,
+}
+
+impl SnapshotCounts {
+  /// V - E + F, which is 2 for a closed mesh without holes through it
+  pub fn euler_characteristic(& self) -> i64 {
+    self.verts as i64 - self.edges as i64 + self.faces as i64
+  }
+}
+
+/// Rotates the face so that it starts at its lowest index, keeping the winding
+fn canonical_face(mut face: Vec<u32>) -> Vec<u32> {
+  if let Some(start) = face.iter().enumerate().min_by_key(|&(_, idx)| *idx).map(|(pos, _)| pos) {
+    face.rotate_left(start);
+  }
+  face
+}
+
+/// Lists the first few lines, and how many more there are
+fn push_listed(out: &mut Vec<String>, lines: Vec<String>) {
+  let extra = lines.len().saturating_sub(MAX_LISTED);
+  out.extend(lines.into_iter().take(MAX_LISTED));
+  if extra > 0 { out.push(format!("  ... and {} more", extra)); }
+}
+
+/// Whether the difference is over the tolerance, counting NaN as over
+fn beyond(difference: f32, tolerance: f32) -> bool {
+  difference > tolerance || difference.is_nan()
+}
+
+fn format_face(face: & [u32]) -> String {
+  face.iter().map(|idx| idx.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+impl MeshSnapshot {
+  /// Canonical snapshot of the positions and faces given, e.g. a golden copy written out by hand
+  pub fn new(positions: Vec<Point3<f32>>, faces: Vec<Vec<u32>>) -> MeshSnapshot {
+    let mut faces: Vec<Vec<u32>> = faces.into_iter().map(canonical_face).collect();
+    faces.sort();
+    MeshSnapshot { positions, faces }
+  }
+
+  pub fn counts(& self) -> SnapshotCounts {
+    // Number of times each undirected edge is used in each direction
+    let mut uses: HashMap<(u32, u32), (usize, usize)> = HashMap::new();
+    for face in & self.faces {
+      for (pos, &from) in face.iter().enumerate() {
+        let to = face[(pos + 1) % face.len()];
+        let entry = uses.entry((from.min(to), from.max(to))).or_insert((0, 0));
+        if from < to { entry.0 += 1; } else { entry.1 += 1; }
+      }
+    }
+    SnapshotCounts {
+      verts: self.positions.len(),
+      faces: self.faces.len(),
+      edges: uses.len(),
+      boundary_edges: uses.values().filter(|&&(a, b)| a + b == 1).count(),
+      bad_edges: uses.values().filter(|&&(a, b)| a > 1 || b > 1).count(),
+    }
+  }
+
+  /// The corners of each face's triangle fan, skipping faces with indices out of range
+  fn triangles(& self) -> Vec<[Point3<f32>; 3]> {
+    let mut triangles = Vec::new();
+    for face in & self.faces {
+      if face.iter().any(|&idx| idx as usize >= self.positions.len()) { continue; }
+      let corner = |pos: usize| self.positions[face[pos] as usize];
+      for idx in 1..face.len().saturating_sub(1) {
+        triangles.push([corner(0), corner(idx), corner(idx + 1)]);
+      }
+    }
+    triangles
+  }
+
+  pub fn surface_area(& self) -> f32 {
+    self.triangles().iter().map(|t| (t[1] - t[0]).cross(t[2] - t[0]).magnitude() / 2.0).sum()
+  }
+
+  /// Volume enclosed by the faces, negative if they point inward. Only meaningful for a closed mesh
+  pub fn volume(& self) -> f32 {
+    self.triangles().iter().map(|t| t[0].to_vec().dot(t[1].to_vec().cross(t[2].to_vec())) / 6.0).sum()
+  }
+
+  /// Length of the diagonal of the box around the positions
+  fn size(& self) -> f32 {
+    let mut points = self.positions.iter();
+    let first = match points.next() { Some(p) => *p, None => return 0.0 };
+    let (min, max) = points.fold((first, first), |(min, max), p| {
+      (Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)), Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+    });
+    min.distance(max)
+  }
+
+  /// Describes how this snapshot differs from the other one, one difference per line, or None if they match.
+  /// Counts, faces, positions, area, and volume are compared, so that a failing test says what changed,
+  /// e.g. that a face was split, or that a vertex moved, instead of just that the files differ.
+  /// Faces and positions are only compared one by one when both have the same number of vertices.
+  /// Positions, area, and volume are allowed to be off by a small relative tolerance
+  pub fn diff(& self, other: & MeshSnapshot) -> Option<String> {
+    let mut out: Vec<String> = Vec::new();
+
+    let (mine, theirs) = (self.counts(), other.counts());
+    let count_lines = [
+      ("vertices", mine.verts as i64, theirs.verts as i64),
+      ("faces", mine.faces as i64, theirs.faces as i64),
+      ("edges", mine.edges as i64, theirs.edges as i64),
+      ("boundary edges", mine.boundary_edges as i64, theirs.boundary_edges as i64),
+      ("non-manifold or misoriented edges", mine.bad_edges as i64, theirs.bad_edges as i64),
+      ("euler characteristic", mine.euler_characteristic(), theirs.euler_characteristic()),
+    ];
+    for &(name, a, b) in & count_lines {
+      if a != b { out.push(format!("{}: {} != {}", name, a, b)); }
+    }
+
+    // Faces and positions are matched up by vertex index, which means nothing once the vertices are numbered differently
+    if mine.verts != theirs.verts {
+      out.push("vertex counts differ, so faces and positions aren't compared one by one".to_string());
+    } else {
+      let my_faces: BTreeSet<& Vec<u32>> = self.faces.iter().collect();
+      let their_faces: BTreeSet<& Vec<u32>> = other.faces.iter().collect();
+      let only_mine: Vec<String> = my_faces.difference(& their_faces).map(|f| format!("  [{}]", format_face(f))).collect();
+      let only_theirs: Vec<String> = their_faces.difference(& my_faces).map(|f| format!("  [{}]", format_face(f))).collect();
+      if !only_mine.is_empty() {
+        out.push(format!("{} faces only in the first snapshot:", only_mine.len()));
+        push_listed(&mut out, only_mine);
+      }
+      if !only_theirs.is_empty() {
+        out.push(format!("{} faces only in the second snapshot:", only_theirs.len()));
+        push_listed(&mut out, only_theirs);
+      }
+
+      let tolerance = TOLERANCE * self.size().max(other.size()).max(1.0);
+      let moved: Vec<String> = self.positions.iter().zip(other.positions.iter()).enumerate()
+        .filter(|&(_, (a, b))| beyond(a.distance(*b), tolerance))
+        .map(|(idx, (a, b))| format!("  {}: ({}, {}, {}) != ({}, {}, {}), {} apart", idx, a.x, a.y, a.z, b.x, b.y, b.z, a.distance(*b)))
+        .collect();
+      if !moved.is_empty() {
+        out.push(format!("{} vertices in different positions:", moved.len()));
+        push_listed(&mut out, moved);
+      }
+    }
+
+    let measures = [
+      ("surface area", self.surface_area(), other.surface_area()),
+      ("volume", self.volume(), other.volume()),
+    ];
+    for &(name, a, b) in & measures {
+      if beyond((a - b).abs(), TOLERANCE * a.abs().max(b.abs()).max(1.0)) {
+        out.push(format!("{}: {} != {}", name, a, b));
+      }
+    }
+
+    if out.is_empty() { None } else { Some(out.join("\n")) }
+  }
+}
+
+/// Positions are written with as many digits as it takes to read them back exactly
+impl fmt::Display for MeshSnapshot {
+  fn fmt(& self, f: &mut fmt::Formatter) -> fmt::Result {
+    for pos in & self.positions {
+      writeln!(f, "v {} {} {}", pos.x, pos.y, pos.z)?;
+    }
+    for face in & self.faces {
+      writeln!(f, "f {}", format_face(face))?;
+    }
+    Ok(())
+  }
+}
+
+/// Reads a snapshot written by `to_string`. Blank lines and lines starting with # are skipped
+impl FromStr for MeshSnapshot {
+  type Err = &'static str;
+
+  fn from_str(text: & str) -> Result<MeshSnapshot, &'static str> {
+    let mut positions: Vec<Point3<f32>> = Vec::new();
+    let mut faces: Vec<Vec<u32>> = Vec::new();
+
+    for line in text.lines() {
+      let mut words = line.split_whitespace();
+      match words.next() {
+        None => {},
+        Some(word) if word.starts_with('#') => {},
+        Some("v") => {
+          let coords: Vec<f32> = words.map(|w| w.parse::<f32>()).collect::<Result<_, _>>()
+            .map_err(|_| "Vertex coordinate is not a number")?;
+          if coords.len() != 3 { return Err("Vertex line does not have three coordinates"); }
+          positions.push(Point3::new(coords[0], coords[1], coords[2]));
+        },
+        Some("f") => {
+          let face: Vec<u32> = words.map(|w| w.parse::<u32>()).collect::<Result<_, _>>()
+            .map_err(|_| "Face index is not a whole number")?;
+          if face.len() < 3 { return Err("Face line has fewer than three indices"); }
+          faces.push(face);
+        },
+        Some(_) => return Err("Line does not start with v or f"),
+      }
+    }
+
+    if faces.iter().flatten().any(|&idx| idx as usize >= positions.len()) {
+      return Err("Face index is out of range");
+    }
+    Ok(MeshSnapshot::new(positions, faces))
+  }
+}
+
+impl HalfEdgeMesh {
+  /// A canonical snapshot of the mesh's positions and faces, to compare against a golden copy
+  /// in tests with `MeshSnapshot::diff`. Vertices are numbered in order of id
+  pub fn snapshot(& self) -> MeshSnapshot {
+    let index = self.vertex_index();
+    let positions: Vec<Point3<f32>> = self.vertex_order().iter().map(|id| self.vertices[id].borrow().get_pos()).collect();
+    let faces: Vec<Vec<u32>> = self.faces.values()
+      .map(|face| {
+        face.borrow().adjacent_verts().to_ptr_vec().iter()
+          .filter_map(|v| index.get(& v.borrow().id).map(|&idx| idx as u32))
+          .collect()
+      })
+      .collect();
+    MeshSnapshot::new(positions, faces)
+  }
+}
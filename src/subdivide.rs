@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::PI;
+
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use face::Face;
+use ptr::{EdgeRc, VertRc};
+use kernel::{MeshGeometry, EditableMesh};
+use attributes::interpolate_vert;
+use util::OpTimer;
+
+#[derive(Debug, Clone, Copy)]
+struct AdaptiveTri {
+  corners: [usize; 3],
+  /// Green triangles are halves of a triangle that was bisected to avoid a T-junction
+  green: bool,
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) { if a < b { (a, b) } else { (b, a) } }
+
+fn tri_edges(corners: & [usize; 3]) -> [(usize, usize); 3] {
+  [edge_key(corners[0], corners[1]), edge_key(corners[1], corners[2]), edge_key(corners[2], corners[0])]
+}
+
+/// The one-ring of an interior vertex: neighbors in clockwise order, and for each
+/// neighbor, the centroid of the face between it and the next one
+struct Ring {
+  center: Point3<f32>,
+  neighbors: Vec<Point3<f32>>,
+  face_centers: Vec<Point3<f32>>,
+  all_triangles: bool,
+}
+
+fn average(points: & [Point3<f32>]) -> Point3<f32> {
+  if points.is_empty() { return Point3::origin(); }
+  let sum = points.iter().fold(Vector3::new(0.0, 0.0, 0.0), |acc, p| acc + p.to_vec());
+  Point3::from_vec(sum / points.len() as f32)
+}
+
+fn target_pos(edge: & EdgeRc) -> Option<Point3<f32>> {
+  edge.borrow().get_target().map(|t| t.borrow().get_pos())
+}
+
+/// None for boundary vertices
+fn interior_ring(vert: & VertRc) -> Option<Ring> {
+  let vert_b = vert.borrow();
+  if vert_b.is_boundary() { return None; }
+  let out_edges = vert_b.outgoing_edges();
+  if out_edges.len() < 3 { return None; }
+
+  let mut ring = Ring { center: vert_b.get_pos(), neighbors: Vec::new(), face_centers: Vec::new(), all_triangles: true };
+  for out_edge in & out_edges {
+    ring.neighbors.push(target_pos(out_edge)?);
+    // The face across the outgoing edge lies between this neighbor and the next one clockwise
+    let face = out_edge.borrow().get_pair_face()?;
+    let corners = face.borrow().vert_positions();
+    ring.all_triangles &= corners.len() == 3;
+    ring.face_centers.push(average(& corners));
+  }
+  Some(ring)
+}
+
+/// The two neighbors along the border of a boundary vertex, as (previous, next)
+fn boundary_neighbors(vert: & VertRc) -> Option<(Point3<f32>, Point3<f32>)> {
+  let out_edges = vert.borrow().outgoing_edges();
+  let next = out_edges.iter().find(|e| e.borrow().is_boundary()).and_then(target_pos)?;
+  let prev = out_edges.iter()
+    .filter_map(|e| e.borrow().get_prev())
+    .find(|p| p.borrow().is_boundary())
+    .and_then(|p| p.borrow().get_origin())
+    .map(|v| v.borrow().get_pos())?;
+  Some((prev, next))
+}
+
+/// Sums points weighted by cos or sin of their angle around the ring
+fn ring_tangents(points: & [Point3<f32>], center: Point3<f32>, weights: & [(f32, f32)]) -> (Vector3<f32>, Vector3<f32>) {
+  let mut t_1 = Vector3::new(0.0, 0.0, 0.0);
+  let mut t_2 = Vector3::new(0.0, 0.0, 0.0);
+  for (point, &(w_1, w_2)) in points.iter().zip(weights) {
+    t_1 += (point - center) * w_1;
+    t_2 += (point - center) * w_2;
+  }
+  (t_1, t_2)
+}
+
+/// The mesh as positions, and polygons of indices into them, as in `HalfEdgeMesh::to_polygons`
+fn polygons_of<M: MeshGeometry>(mesh: & M) -> (Vec<Point3<f32>>, Vec<Vec<usize>>) {
+  let vert_ids = mesh.vert_ids();
+  let index: HashMap<u32, usize> = vert_ids.iter().enumerate().map(|(idx, &id)| (id, idx)).collect();
+  let positions = vert_ids.iter().map(|&id| mesh.vert_pos(id)).collect();
+  let polygons = mesh.face_ids().into_iter()
+    .map(|face| mesh.face_verts(face).iter().filter_map(|v| index.get(v).cloned()).collect())
+    .collect();
+  (positions, polygons)
+}
+
+fn tri_polygons(tris: & [AdaptiveTri]) -> Vec<Vec<usize>> {
+  tris.iter().map(|t| t.corners.to_vec()).collect()
+}
+
+/// `HalfEdgeMesh::subdivide_adaptive` for any mesh kernel. `should_split` is given the mesh and a face id.
+/// The mesh is rebuilt with `EditableMesh::rebuild`, so on a `HalfEdgeMesh` the new vertices are left without the
+/// texture coordinates and attributes that the method interpolates for them
+pub fn subdivide_adaptive<M, F>(mesh: &mut M, should_split: F, max_depth: usize) where M: EditableMesh, F: Fn(& M, u32) -> bool {
+  subdivide_adaptive_tracked(mesh, should_split, max_depth);
+}
+
+/// `subdivide_adaptive`, also giving the two vertices each new one is the midpoint of. The vertices of the rebuilt mesh
+/// are the old ones, in order of id, followed by the new ones, in the order they're listed, and the parents are indices
+/// into that order. A new vertex's parents always come before it
+fn subdivide_adaptive_tracked<M, F>(mesh: &mut M, should_split: F, max_depth: usize) -> Vec<(usize, usize)>
+    where M: EditableMesh, F: Fn(& M, u32) -> bool {
+  let start = OpTimer::start();
+  let (mut positions, polygons) = polygons_of(mesh);
+  let mut parents: Vec<(usize, usize)> = Vec::new();
+  let mut tris: Vec<AdaptiveTri> = Vec::new();
+  for polygon in & polygons {
+    for idx in 1..polygon.len().saturating_sub(1) {
+      tris.push(AdaptiveTri { corners: [polygon[0], polygon[idx], polygon[idx + 1]], green: false });
+    }
+  }
+
+  for depth in 0..max_depth {
+    // Faces of the rebuilt mesh have ids in the same order as the triangles
+    let marked: Vec<bool> = {
+      let rebuilt;
+      let current: & M = if depth == 0 && polygons.iter().all(|p| p.len() == 3) { & *mesh } else {
+        rebuilt = M::with_polygons(& positions, & tri_polygons(& tris));
+        & rebuilt
+      };
+      current.face_ids().into_iter().map(|id| should_split(current, id)).collect()
+    };
+    if !marked.iter().any(|&m| m) { break; }
+
+    let mut red: Vec<bool> = marked;
+    let mut split_edges: HashSet<(usize, usize)> = HashSet::new();
+    let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+
+    // Grow the set of red triangles until every other triangle has at most one split edge,
+    // and no green triangle has any, so that green triangles are never bisected again
+    loop {
+      for (tri, &is_red) in tris.iter().zip(& red) {
+        if is_red { split_edges.extend(tri_edges(& tri.corners).iter().cloned()); }
+      }
+
+      let mut changed = false;
+      for (tri, is_red) in tris.iter().zip(red.iter_mut()) {
+        if *is_red { continue; }
+        let count = tri_edges(& tri.corners).iter().filter(|e| split_edges.contains(e)).count();
+        if count >= 2 || (count == 1 && tri.green) {
+          *is_red = true;
+          changed = true;
+        }
+      }
+      if !changed { break; }
+    }
+
+    let mut midpoint = |a: usize, b: usize, positions: &mut Vec<Point3<f32>>| -> usize {
+      *midpoints.entry(edge_key(a, b)).or_insert_with(|| {
+        parents.push((a, b));
+        positions.push(positions[a].midpoint(positions[b]));
+        positions.len() - 1
+      })
+    };
+
+    let mut next_tris: Vec<AdaptiveTri> = Vec::with_capacity(tris.len() * 2);
+    for (tri, is_red) in tris.iter().zip(& red) {
+      let [a, b, c] = tri.corners;
+      if *is_red {
+        let (ab, bc, ca) = (midpoint(a, b, &mut positions), midpoint(b, c, &mut positions), midpoint(c, a, &mut positions));
+        for corners in & [[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]] {
+          next_tris.push(AdaptiveTri { corners: *corners, green: false });
+        }
+        continue;
+      }
+
+      // Rotate the split edge, if there is one, to the front
+      let rotation = (0..3).find(|&r| split_edges.contains(& tri_edges(& tri.corners)[r]));
+      match rotation {
+        Some(r) => {
+          let (p, q, o) = (tri.corners[r], tri.corners[(r + 1) % 3], tri.corners[(r + 2) % 3]);
+          let mid = midpoint(p, q, &mut positions);
+          next_tris.push(AdaptiveTri { corners: [p, mid, o], green: true });
+          next_tris.push(AdaptiveTri { corners: [mid, q, o], green: true });
+        },
+        None => next_tris.push(*tri),
+      }
+    }
+    tris = next_tris;
+  }
+
+  mesh.rebuild(& positions, & tri_polygons(& tris));
+  debug!("subdivide_adaptive: {} polygons refined into {} triangles, took {:?}", polygons.len(), tris.len(), start.elapsed());
+  parents
+}
+
+impl HalfEdgeMesh {
+  /// Refines the faces for which `should_split` returns true, up to `max_depth` times,
+  /// using red-green refinement: marked faces are split into four (red), and their neighbors
+  /// are bisected (green) so that no T-junctions are left. A face with two or more split edges
+  /// is split into four as well, and so is a green face with any split edge, so that
+  /// faces are never bisected twice in a row, which would make them too thin.
+  /// New vertices go at edge midpoints, so the shape of the surface doesn't change.
+  /// The mesh is rebuilt, so its ids are renumbered. Texture coordinates and registered attributes follow
+  /// their vertices, and new vertices get them interpolated halfway between the ends of their edge.
+  /// Tangents are dropped, to be computed again. Polygons are split into triangle fans first
+  pub fn subdivide_adaptive<F>(&mut self, should_split: F, max_depth: usize) where F: Fn(& Face) -> bool {
+    let old_ids = self.vertex_order();
+    let mut uvs = ::std::mem::take(&mut self.uvs);
+    let mut attributes = ::std::mem::take(self.attributes_mut());
+    let parents = subdivide_adaptive_tracked(self, |mesh: & HalfEdgeMesh, id| should_split(& mesh.faces[& id].borrow()), max_depth);
+
+    // The rebuilt mesh numbers its vertices in the same order as the positions they were built from
+    let new_ids = self.vertex_order();
+    let renumbered: HashMap<u32, u32> = old_ids.iter().cloned().zip(new_ids.iter().cloned()).collect();
+    uvs = uvs.drain().filter_map(|(old, uv)| renumbered.get(& old).map(|&new| (new, uv))).collect();
+    attributes.renumber(& renumbered);
+    for (offset, &(a, b)) in parents.iter().enumerate() {
+      let (a, b, into) = (new_ids[a], new_ids[b], new_ids[old_ids.len() + offset]);
+      interpolate_vert(&mut uvs, a, b, 0.5, into);
+      attributes.interpolate_vert(a, b, 0.5, into);
+    }
+    self.uvs = uvs;
+    *self.attributes_mut() = attributes;
+  }
+
+  /// Position that the vertex converges to under repeated subdivision. Uses Loop subdivision
+  /// if all of the faces around the vertex are triangles, and Catmull-Clark otherwise.
+  /// Border vertices follow the cubic B-spline rule along the border, (prev + 4 v + next) / 6
+  pub fn limit_position(& self, vert: & VertRc) -> Point3<f32> {
+    let pos = vert.borrow().get_pos();
+    match interior_ring(vert) {
+      Some(ring) => if ring.all_triangles { loop_limit(& ring) } else { catmull_clark_limit(& ring).0 },
+      None => match boundary_neighbors(vert) {
+        Some((prev, next)) => Point3::from_vec((prev.to_vec() + pos.to_vec() * 4.0 + next.to_vec()) / 6.0),
+        None => pos,
+      },
+    }
+  }
+
+  /// Normal of the limit surface at the vertex, using the same scheme as `limit_position`.
+  /// Falls back to the averaged face normal (`Vert::normal`) for border vertices
+  pub fn limit_normal(& self, vert: & VertRc) -> Vector3<f32> {
+    let fallback = vert.borrow().normal();
+    let ring = match interior_ring(vert) { Some(r) => r, None => return fallback };
+    let valence = ring.neighbors.len();
+    let angle = |idx: usize| 2.0 * PI * idx as f32 / valence as f32;
+
+    let (t_1, t_2) = if ring.all_triangles {
+      let weights: Vec<(f32, f32)> = (0..valence).map(|i| (angle(i).cos(), angle(i).sin())).collect();
+      ring_tangents(& ring.neighbors, ring.center, & weights)
+    } else {
+      // Tangents of the Catmull-Clark limit surface (Halstead et al.), on the once-refined ring
+      let (limit, edge_points, face_points) = catmull_clark_limit(& ring);
+      let n = valence as f32;
+      let a_n = 1.0 + (2.0 * PI / n).cos() + (PI / n).cos() * (2.0 * (9.0 + (2.0 * PI / n).cos())).sqrt();
+      let edge_weights: Vec<(f32, f32)> = (0..valence).map(|i| (a_n * angle(i).cos(), a_n * angle(i).sin())).collect();
+      let face_weights: Vec<(f32, f32)> = (0..valence)
+        .map(|i| (angle(i).cos() + angle(i + 1).cos(), angle(i).sin() + angle(i + 1).sin()))
+        .collect();
+      let (e_1, e_2) = ring_tangents(& edge_points, limit, & edge_weights);
+      let (f_1, f_2) = ring_tangents(& face_points, limit, & face_weights);
+      (e_1 + f_1, e_2 + f_2)
+    };
+
+    let normal = t_1.cross(t_2);
+    if normal.magnitude2() <= 1e-20 { return fallback; }
+    // The ring runs clockwise, so the cross product may point inward
+    let normal = normal.normalize();
+    if normal.dot(fallback) < 0.0 { -normal } else { normal }
+  }
+}
+
+/// Loop's limit mask for an interior vertex
+fn loop_limit(ring: & Ring) -> Point3<f32> {
+  let n = ring.neighbors.len() as f32;
+  let inner = 3.0 / 8.0 + 0.25 * (2.0 * PI / n).cos();
+  let beta = (5.0 / 8.0 - inner * inner) / n;
+  let chi = 1.0 / (3.0 / (8.0 * beta) + n);
+  let sum = ring.neighbors.iter().fold(Vector3::new(0.0, 0.0, 0.0), |acc, p| acc + p.to_vec());
+  Point3::from_vec(ring.center.to_vec() * (1.0 - n * chi) + sum * chi)
+}
+
+/// Catmull-Clark limit position for an interior vertex. Applies one subdivision step first,
+/// which turns every face into quads, so the standard quad limit mask applies to any polygons.
+/// Returns the limit position along with the refined ring's edge points and face points
+fn catmull_clark_limit(ring: & Ring) -> (Point3<f32>, Vec<Point3<f32>>, Vec<Point3<f32>>) {
+  let valence = ring.neighbors.len();
+  let n = valence as f32;
+
+  // Face points are face centroids. The edge to neighbor i lies between face i - 1 and face i
+  let face_points = ring.face_centers.clone();
+  let edge_points: Vec<Point3<f32>> = (0..valence).map(|idx| {
+    let sum = ring.center.to_vec() + ring.neighbors[idx].to_vec() +
+      face_points[(idx + valence - 1) % valence].to_vec() + face_points[idx].to_vec();
+    Point3::from_vec(sum / 4.0)
+  }).collect();
+
+  let face_avg = average(& face_points).to_vec();
+  let midpoints: Vec<Point3<f32>> = ring.neighbors.iter().map(|p| ring.center.midpoint(*p)).collect();
+  let edge_avg = average(& midpoints).to_vec();
+  let refined = (face_avg + edge_avg * 2.0 + ring.center.to_vec() * (n - 3.0)) / n;
+
+  let edge_sum = edge_points.iter().fold(Vector3::new(0.0, 0.0, 0.0), |acc, p| acc + p.to_vec());
+  let face_sum = face_points.iter().fold(Vector3::new(0.0, 0.0, 0.0), |acc, p| acc + p.to_vec());
+  let limit = Point3::from_vec((refined * n * n + edge_sum * 4.0 + face_sum) / (n * (n + 5.0)));
+
+  (limit, edge_points, face_points)
+}
@@ -0,0 +1,40 @@
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::VertRc;
+
+/// A convex shape described by its support function, as used by GJK and EPA
+pub trait SupportMap {
+  /// The point of the shape furthest along the direction
+  fn support(& self, dir: Vector3<f32>) -> Point3<f32>;
+}
+
+/// Only valid for convex meshes. Starts at an arbitrary vertex and keeps moving to the neighbor
+/// furthest along the direction, which on a convex mesh only stops at the furthest vertex,
+/// in about O(sqrt(n)) steps instead of checking every vertex. The origin for an empty mesh
+impl SupportMap for HalfEdgeMesh {
+  fn support(& self, dir: Vector3<f32>) -> Point3<f32> {
+    let mut current: VertRc = match self.vertices.values().next() { Some(v) => v.clone(), None => return Point3::origin() };
+    let mut best = current.borrow().get_pos().to_vec().dot(dir);
+
+    loop {
+      let mut next: Option<VertRc> = None;
+      for out_edge in current.borrow().outgoing_edges() {
+        let target = match out_edge.borrow().get_target() { Some(t) => t, None => continue };
+        let dist = target.borrow().get_pos().to_vec().dot(dir);
+        if dist > best {
+          best = dist;
+          next = Some(target);
+        }
+      }
+
+      match next {
+        Some(vert) => current = vert,
+        None => break,
+      }
+    }
+
+    let pos = current.borrow().get_pos();
+    pos
+  }
+}
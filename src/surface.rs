@@ -0,0 +1,202 @@
+use cgmath::{Point3, Vector3, EuclideanSpace, InnerSpace};
+
+use ptr::{EdgeRc, FaceRc, VertRc};
+use mesh::HalfEdgeMesh;
+use iterators::ToPtrVec;
+
+/// Barycentric coordinates of a point with respect to a triangle.
+/// The point is projected onto the triangle's plane first
+pub fn barycentric(corners: & [Point3<f32>; 3], point: & Point3<f32>) -> (f32, f32, f32) {
+  let v_0 = corners[1] - corners[0];
+  let v_1 = corners[2] - corners[0];
+  let v_2 = point - corners[0];
+  let (d_00, d_01, d_11) = (v_0.dot(v_0), v_0.dot(v_1), v_1.dot(v_1));
+  let (d_20, d_21) = (v_2.dot(v_0), v_2.dot(v_1));
+  let denom = d_00 * d_11 - d_01 * d_01;
+  if denom.abs() <= f32::EPSILON { return (1.0, 0.0, 0.0); }
+  let w_1 = (d_11 * d_20 - d_01 * d_21) / denom;
+  let w_2 = (d_00 * d_21 - d_01 * d_20) / denom;
+  (1.0 - w_1 - w_2, w_1, w_2)
+}
+
+/// A location on the surface of a mesh, given as a face and barycentric coordinates in it.
+/// The coordinates are weights for the face's vertices in the order of `Face::adjacent_verts`
+/// (counterclockwise, starting at the origin of `face.edge`). Assumes triangular faces
+#[derive(Debug, Clone)]
+pub struct SurfacePoint {
+  pub face: FaceRc,
+  pub bary: (f32, f32, f32),
+}
+
+/// Face data needed while walking across it
+struct FaceFrame {
+  corners: [Point3<f32>; 3],
+  edges: Vec<EdgeRc>,
+  normal: Vector3<f32>,
+}
+
+impl FaceFrame {
+  fn new(face: & FaceRc) -> Option<FaceFrame> {
+    let face_b = face.borrow();
+    let corners = face_b.triangle()?;
+    let edges = face_b.adjacent_edges().to_ptr_vec();
+    if edges.len() != 3 { return None; }
+    let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+    if normal.magnitude2() == 0.0 { return None; }
+    Some(FaceFrame { corners, edges, normal: normal.normalize() })
+  }
+
+  fn point(& self, bary: (f32, f32, f32)) -> Point3<f32> {
+    Point3::from_vec(self.corners[0].to_vec() * bary.0 + self.corners[1].to_vec() * bary.1 + self.corners[2].to_vec() * bary.2)
+  }
+
+  /// Change in barycentric coordinates per unit of movement along dir
+  fn bary_delta(& self, dir: Vector3<f32>) -> [f32; 3] {
+    let origin = self.corners[0];
+    let moved = barycentric(& self.corners, & (origin + dir));
+    [moved.0 - 1.0, moved.1, moved.2]
+  }
+}
+
+impl SurfacePoint {
+  pub fn new(face: FaceRc, bary: (f32, f32, f32)) -> SurfacePoint {
+    SurfacePoint { face, bary }
+  }
+
+  /// The point on the face closest to `point`, assuming it's near the face's plane.
+  /// The coordinates are clamped so that the result stays inside the face
+  pub fn from_point(face: FaceRc, point: & Point3<f32>) -> Option<SurfacePoint> {
+    let corners = face.borrow().triangle()?;
+    let (w_0, w_1, w_2) = barycentric(& corners, point);
+    let (c_0, c_1, c_2) = (w_0.max(0.0), w_1.max(0.0), w_2.max(0.0));
+    let sum = c_0 + c_1 + c_2;
+    if sum <= 0.0 { return None; }
+    Some(SurfacePoint { face, bary: (c_0 / sum, c_1 / sum, c_2 / sum) })
+  }
+
+  /// World space position of the point
+  pub fn position(& self) -> Point3<f32> {
+    let corners = self.face.borrow().vert_positions();
+    if corners.len() != 3 { return self.face.borrow().center; }
+    Point3::from_vec(corners[0].to_vec() * self.bary.0 + corners[1].to_vec() * self.bary.1 + corners[2].to_vec() * self.bary.2)
+  }
+
+  /// Smooth normal at the point, interpolated from the vertex normals
+  pub fn normal(& self) -> Vector3<f32> {
+    let verts = self.face.borrow().adjacent_verts().to_ptr_vec();
+    if verts.len() != 3 { return self.face.borrow().normal; }
+    let normal = verts[0].borrow().normal() * self.bary.0 +
+                 verts[1].borrow().normal() * self.bary.1 +
+                 verts[2].borrow().normal() * self.bary.2;
+    if normal.magnitude2() > 0.0 { normal.normalize() } else { self.face.borrow().normal }
+  }
+
+  /// Slides the point across the surface for `distance` units, starting in `direction`
+  /// (projected onto the face). When the path reaches an edge, it continues into the neighboring
+  /// face, keeping the same angle to the edge, as if the two faces were unfolded flat.
+  /// Stops early at the border of an open mesh
+  pub fn walk(& self, direction: Vector3<f32>, distance: f32) -> SurfacePoint {
+    self.trace(direction, distance, None)
+  }
+
+  /// Walks like `walk`, and optionally records the point where the path crosses each edge
+  pub(crate) fn trace(& self, direction: Vector3<f32>, distance: f32, mut crossings: Option<&mut Vec<SurfacePoint>>) -> SurfacePoint {
+    let mut face = self.face.clone();
+    let mut bary = [self.bary.0, self.bary.1, self.bary.2];
+    let mut remaining = distance;
+    let mut frame = match FaceFrame::new(& face) { Some(f) => f, None => return self.clone() };
+
+    // Direction flattened into the starting face
+    let mut dir = direction - frame.normal * direction.dot(frame.normal);
+    if dir.magnitude2() == 0.0 || remaining <= 0.0 { return self.clone(); }
+    dir = dir.normalize();
+
+    // Each crossing enters a new face, so this is only a guard against numerical trouble
+    let max_crossings = 10_000;
+    for _ in 0..max_crossings {
+      let delta = frame.bary_delta(dir);
+
+      // Find the first barycentric coordinate to reach zero, which is where the path leaves the face
+      let mut exit: Option<(f32, usize)> = None;
+      for corner in 0..3 {
+        if delta[corner] < 0.0 {
+          let t = (bary[corner].max(0.0) / -delta[corner]).max(0.0);
+          if exit.is_none_or(|(best, _)| t < best) { exit = Some((t, corner)); }
+        }
+      }
+
+      let (t_exit, corner) = match exit {
+        Some((t, corner)) if t < remaining => (t, corner),
+        _ => {
+          for idx in 0..3 { bary[idx] += delta[idx] * remaining; }
+          return SurfacePoint::new(face, clamp_bary(bary));
+        },
+      };
+
+      for idx in 0..3 { bary[idx] += delta[idx] * t_exit; }
+      bary[corner] = 0.0;
+      remaining -= t_exit;
+      let exit_point = frame.point((bary[0], bary[1], bary[2]));
+
+      if let Some(ref mut list) = crossings {
+        list.push(SurfacePoint::new(face.clone(), clamp_bary(bary)));
+      }
+
+      // The edge opposite the corner runs from corner + 1 to corner + 2
+      let crossed = frame.edges[(corner + 1) % 3].clone();
+      let next_face = match crossed.borrow().get_pair_face() {
+        Some(f) => f,
+        None => return SurfacePoint::new(face, clamp_bary(bary)),
+      };
+      let next_frame = match FaceFrame::new(& next_face) {
+        Some(f) => f,
+        None => return SurfacePoint::new(face, clamp_bary(bary)),
+      };
+
+      // Unfold the direction: keep its component along the edge, and turn the rest
+      // so that it points away from the edge within the new face's plane
+      let edge_start = frame.corners[(corner + 1) % 3];
+      let axis = (frame.corners[(corner + 2) % 3] - edge_start).normalize();
+      let along = dir.dot(axis);
+      let across = (dir - axis * along).magnitude();
+      let mut into = next_frame.normal.cross(axis);
+      let next_center = Point3::centroid(& next_frame.corners);
+      if into.dot(next_center - edge_start) < 0.0 { into = -into; }
+      dir = (axis * along + into.normalize() * across).normalize();
+
+      let next_bary = barycentric(& next_frame.corners, & exit_point);
+      bary = [next_bary.0, next_bary.1, next_bary.2];
+      face = next_face;
+      frame = next_frame;
+    }
+
+    SurfacePoint::new(face, clamp_bary(bary))
+  }
+
+  /// Vertices of the face, in the same order as the barycentric coordinates
+  pub fn verts(& self) -> Vec<VertRc> {
+    self.face.borrow().adjacent_verts().to_ptr_vec()
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Traces a straightest geodesic from `start`, going `length` units in `direction`.
+  /// The path is unfolded across each edge it reaches, so it goes straight on the surface.
+  /// Returns the start point, every edge crossing in order, and the end point.
+  /// Each segment between consecutive points lies within one face, so the path can be drawn as a polyline.
+  /// Stops early at the border of an open mesh
+  pub fn trace_geodesic(& self, start: SurfacePoint, direction: Vector3<f32>, length: f32) -> Vec<SurfacePoint> {
+    let mut path = vec![start.clone()];
+    let end = start.trace(direction, length, Some(&mut path));
+    path.push(end);
+    path
+  }
+}
+
+/// Removes small negative values left by floating-point error, and renormalizes
+pub(crate) fn clamp_bary(bary: [f32; 3]) -> (f32, f32, f32) {
+  let clamped = [bary[0].max(0.0), bary[1].max(0.0), bary[2].max(0.0)];
+  let sum = clamped[0] + clamped[1] + clamped[2];
+  if sum <= 0.0 { return (1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0); }
+  (clamped[0] / sum, clamped[1] / sum, clamped[2] / sum)
+}
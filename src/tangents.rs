@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use cgmath::{Point3, Vector2, Vector3, Vector4, InnerSpace};
+
+use mesh::HalfEdgeMesh;
+use iterators::ToPtrVec;
+
+/// Tangent and bitangent of a triangle: the directions in which the u and v
+/// texture coordinates increase. None if the texture coordinates are degenerate
+pub fn triangle_tangents(corners: & [Point3<f32>; 3], uvs: & [Vector2<f32>; 3]) -> Option<(Vector3<f32>, Vector3<f32>)> {
+  let (edge_1, edge_2) = (corners[1] - corners[0], corners[2] - corners[0]);
+  let (duv_1, duv_2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+  let det = duv_1.x * duv_2.y - duv_2.x * duv_1.y;
+  if det.abs() <= 1e-12 { return None; }
+  let tangent = (edge_1 * duv_2.y - edge_2 * duv_1.y) / det;
+  let bitangent = (edge_2 * duv_1.x - edge_1 * duv_2.x) / det;
+  Some((tangent, bitangent))
+}
+
+impl HalfEdgeMesh {
+  /// Computes a tangent for every vertex with texture coordinates in `self.uvs`, and stores them in
+  /// `self.tangents`. Each face's tangent and bitangent are weighted by the angle of the face at the
+  /// vertex (as MikkTSpace does), summed, and made orthogonal to the vertex normal.
+  /// The handedness is stored in w, so that mirrored uvs work. Polygons are split into triangle fans
+  pub fn compute_tangents(&mut self) {
+    let mut sums: HashMap<u32, (Vector3<f32>, Vector3<f32>)> = HashMap::new();
+
+    for face in self.faces.values() {
+      let verts = face.borrow().adjacent_verts().to_ptr_vec();
+      for idx in 1..verts.len().saturating_sub(1) {
+        let tri = [& verts[0], & verts[idx], & verts[idx + 1]];
+        let ids = [tri[0].borrow().id, tri[1].borrow().id, tri[2].borrow().id];
+        let uvs = match (self.uvs.get(& ids[0]), self.uvs.get(& ids[1]), self.uvs.get(& ids[2])) {
+          (Some(&a), Some(&b), Some(&c)) => [a, b, c],
+          _ => continue,
+        };
+        let corners = [tri[0].borrow().get_pos(), tri[1].borrow().get_pos(), tri[2].borrow().get_pos()];
+        let (tangent, bitangent) = match triangle_tangents(& corners, & uvs) { Some(t) => t, None => continue };
+        let (tangent, bitangent) = (tangent.normalize(), bitangent.normalize());
+
+        for corner in 0..3 {
+          let to_next = corners[(corner + 1) % 3] - corners[corner];
+          let to_prev = corners[(corner + 2) % 3] - corners[corner];
+          if to_next.magnitude2() == 0.0 || to_prev.magnitude2() == 0.0 { continue; }
+          let angle = to_next.angle(to_prev).0;
+          let sum = sums.entry(ids[corner]).or_insert((Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)));
+          sum.0 += tangent * angle;
+          sum.1 += bitangent * angle;
+        }
+      }
+    }
+
+    self.tangents.clear();
+    for (id, (tangent, bitangent)) in sums {
+      let normal = match self.vertices.get(& id) { Some(v) => v.borrow().normal(), None => continue };
+      // Gram-Schmidt against the normal
+      let ortho = tangent - normal * normal.dot(tangent);
+      if ortho.magnitude2() <= 1e-12 { continue; }
+      let ortho = ortho.normalize();
+      let handedness = if normal.cross(ortho).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+      self.tangents.insert(id, Vector4::new(ortho.x, ortho.y, ortho.z, handedness));
+    }
+  }
+}
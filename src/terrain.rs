@@ -0,0 +1,291 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use cgmath::{Point3, Vector3, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::{EdgeRc, FaceRc, VertRc};
+use util::anchor_boundary_vert;
+
+/// Height of the triangle above (x, z), if the point lies inside it when seen from above
+fn height_at(corners: & [Point3<f32>; 3], point: & Point3<f32>) -> Option<f32> {
+  let (a, b, c) = (corners[0], corners[1], corners[2]);
+  let area = (b.x - a.x) * (c.z - a.z) - (c.x - a.x) * (b.z - a.z);
+  if area.abs() <= f32::EPSILON { return None; }
+  let w_b = ((point.x - a.x) * (c.z - a.z) - (c.x - a.x) * (point.z - a.z)) / area;
+  let w_c = ((b.x - a.x) * (point.z - a.z) - (point.x - a.x) * (b.z - a.z)) / area;
+  let w_a = 1.0 - w_b - w_c;
+  let tolerance = -1e-4;
+  if w_a < tolerance || w_b < tolerance || w_c < tolerance { return None; }
+  Some(a.y * w_a + b.y * w_b + c.y * w_c)
+}
+
+fn faces_around(vert: & VertRc) -> Vec<FaceRc> {
+  vert.borrow().outgoing_edges().iter().filter_map(|e| e.borrow().get_face()).collect()
+}
+
+/// Heap entry for `simplify_terrain`, ordered so that the smallest error is popped first
+struct TerrainCandidate {
+  error: f32,
+  edge_id: u32,
+}
+
+impl PartialEq for TerrainCandidate {
+  fn eq(& self, other: & TerrainCandidate) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for TerrainCandidate {}
+
+impl PartialOrd for TerrainCandidate {
+  fn partial_cmp(& self, other: & TerrainCandidate) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for TerrainCandidate {
+  fn cmp(& self, other: & TerrainCandidate) -> Ordering {
+    other.error.partial_cmp(& self.error).unwrap_or(Ordering::Equal)
+      .then_with(|| other.edge_id.cmp(& self.edge_id))
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Builds a regular triangulated grid from a heightfield. `heights` holds `width` samples
+  /// per row, for `depth` rows. Sample (x, z) is placed at (x * scale.x, height * scale.y, z * scale.z),
+  /// so y is up, and faces wind counterclockwise seen from above. Every cell is split
+  /// along the same diagonal. The border edges of the grid are left without pairs
+  pub fn from_heightfield(heights: & [f32], width: usize, depth: usize, scale: Vector3<f32>) -> Result<HalfEdgeMesh, &'static str> {
+    HalfEdgeMesh::heightfield(heights, width, depth, scale, false)
+  }
+
+  /// Like `from_heightfield`, but alternates the diagonal from cell to cell in a checkerboard
+  /// pattern, which avoids the directional bias of a uniform triangulation
+  pub fn from_heightfield_alternating(heights: & [f32], width: usize, depth: usize, scale: Vector3<f32>) -> Result<HalfEdgeMesh, &'static str> {
+    HalfEdgeMesh::heightfield(heights, width, depth, scale, true)
+  }
+
+  fn heightfield(heights: & [f32], width: usize, depth: usize, scale: Vector3<f32>, alternate: bool) -> Result<HalfEdgeMesh, &'static str> {
+    if width < 2 || depth < 2 { return Err("Heightfield must be at least 2 by 2 samples"); }
+    if heights.len() != width * depth { return Err("Heightfield has the wrong number of samples"); }
+
+    let positions: Vec<Point3<f32>> = heights.iter().enumerate()
+      .map(|(idx, height)| Point3::new((idx % width) as f32 * scale.x, height * scale.y, (idx / width) as f32 * scale.z))
+      .collect();
+
+    let mut triangles: Vec<[usize; 3]> = Vec::with_capacity((width - 1) * (depth - 1) * 2);
+    for z in 0..(depth - 1) {
+      for x in 0..(width - 1) {
+        let a = z * width + x;
+        let (b, c, d) = (a + 1, a + width, a + width + 1);
+        if alternate && (x + z) % 2 == 1 {
+          triangles.push([a, c, b]);
+          triangles.push([b, c, d]);
+        } else {
+          triangles.push([a, c, d]);
+          triangles.push([a, d, b]);
+        }
+      }
+    }
+
+    let mut mesh = HalfEdgeMesh::from_polygons_unpaired(& positions, & triangles);
+    mesh.connect_all_pairs()?;
+    Ok(mesh)
+  }
+
+  /// Simplifies a terrain (a y-up heightfield-like mesh, as made by `from_heightfield`) by removing
+  /// vertices for as long as the surface stays within `max_vertical_error` of every original vertex,
+  /// measured straight up and down. Each step merges a vertex into one of its neighbors, smallest error first.
+  /// Border vertices are never removed or moved, so the outline stays exactly the same,
+  /// and neighboring tiles simplified this way still line up. Returns the number of vertices removed.
+  /// Assumes triangular faces
+  pub fn simplify_terrain(&mut self, max_vertical_error: f32) -> usize {
+    // Original vertices which have been removed, by the face they lie under
+    let mut removed: HashMap<u32, Vec<Point3<f32>>> = HashMap::new();
+    let mut heap: BinaryHeap<TerrainCandidate> = BinaryHeap::new();
+    for edge in self.edges.values() {
+      if let Some(error) = self.terrain_collapse_error(edge, & removed) {
+        if error <= max_vertical_error { heap.push(TerrainCandidate { error, edge_id: edge.borrow().id }); }
+      }
+    }
+
+    let mut count = 0;
+    while let Some(candidate) = heap.pop() {
+      let edge = match self.edges.get(& candidate.edge_id) { Some(e) => e.clone(), None => continue };
+      // Costs go stale as the mesh changes around the edge, so check again before collapsing
+      let error = match self.terrain_collapse_error(& edge, & removed) { Some(e) => e, None => continue };
+      if error > max_vertical_error { continue; }
+      if error > candidate.error + f32::EPSILON {
+        heap.push(TerrainCandidate { error, edge_id: candidate.edge_id });
+        continue;
+      }
+      if !self.can_collapse_edge(& edge) { continue; }
+
+      let (keep, gone) = match (edge.borrow().get_origin(), edge.borrow().get_target()) {
+        (Some(o), Some(t)) => (o, t),
+        _ => continue,
+      };
+      let mut points: Vec<Point3<f32>> = vec![gone.borrow().get_pos()];
+      for face in faces_around(& gone) {
+        if let Some(mut list) = removed.remove(& face.borrow().id) { points.append(&mut list); }
+      }
+
+      let keep_pos = keep.borrow().get_pos();
+      let kept = match self.collapse_edge(& edge, keep_pos) { Ok(v) => v, Err(_) => continue };
+      count += 1;
+
+      // Put the removed points under the faces that now cover them
+      let new_faces = faces_around(& kept);
+      for point in points {
+        let under = new_faces.iter().find(|f| f.borrow().triangle().is_some_and(|t| height_at(& t, & point).is_some()));
+        if let Some(face) = under {
+          removed.entry(face.borrow().id).or_default().push(point);
+        }
+      }
+
+      // Every edge around the kept vertex and its neighbors may have a new error
+      let mut touched: HashSet<u32> = HashSet::new();
+      let mut verts = vec![kept.clone()];
+      verts.extend(kept.borrow().outgoing_edges().iter().filter_map(|e| e.borrow().get_target()));
+      for vert in verts {
+        for out_edge in vert.borrow().outgoing_edges() {
+          let incoming = out_edge.borrow().get_prev();
+          for touching in Some(out_edge).into_iter().chain(incoming) {
+            if !touched.insert(touching.borrow().id) { continue; }
+            if let Some(error) = self.terrain_collapse_error(& touching, & removed) {
+              if error <= max_vertical_error { heap.push(TerrainCandidate { error, edge_id: touching.borrow().id }); }
+            }
+          }
+        }
+      }
+    }
+
+    count
+  }
+
+  /// Largest vertical distance between the surface and the removed points under the faces
+  /// around the edge's target, if the target were merged into the origin.
+  /// None if the target is on the border, or if the merge would fold the surface over
+  fn terrain_collapse_error(& self, edge: & EdgeRc, removed: & HashMap<u32, Vec<Point3<f32>>>) -> Option<f32> {
+    let edge_b = edge.borrow();
+    let (keep, gone) = (edge_b.get_origin()?, edge_b.get_target()?);
+    if gone.borrow().is_boundary() { return None; }
+    let keep_pos = keep.borrow().get_pos();
+    let keep_id = keep.borrow().id;
+    let gone_id = gone.borrow().id;
+
+    let old_faces = faces_around(& gone);
+    let mut new_triangles: Vec<[Point3<f32>; 3]> = Vec::new();
+    for face in & old_faces {
+      let face_b = face.borrow();
+      let verts: Vec<VertRc> = face_b.adjacent_verts().filter_map(|v| v.upgrade()).collect();
+      // The two faces beside the edge disappear
+      if verts.iter().any(|v| v.borrow().id == keep_id) { continue; }
+      let corners: Vec<Point3<f32>> = verts.iter()
+        .map(|v| if v.borrow().id == gone_id { keep_pos } else { v.borrow().get_pos() })
+        .collect();
+      if corners.len() != 3 { return None; }
+      let triangle = [corners[0], corners[1], corners[2]];
+      // Faces have to keep facing up
+      if (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).y <= 0.0 { return None; }
+      new_triangles.push(triangle);
+    }
+
+    let mut error: f32 = 0.0;
+    let gone_pos = gone.borrow().get_pos();
+    let points = Some(& gone_pos).into_iter()
+      .chain(old_faces.iter().flat_map(|f| removed.get(& f.borrow().id).into_iter().flatten()));
+    for point in points {
+      let height = new_triangles.iter().filter_map(|t| height_at(t, point)).next()?;
+      error = error.max((point.y - height).abs());
+    }
+
+    Some(error)
+  }
+
+  /// Moves everything in `other` into this mesh and welds the two together where their borders meet,
+  /// as with neighboring terrain tiles. A border edge of `other` is joined to a border edge of this mesh
+  /// running the opposite way if both of its ends are within `eps` of the other edge's ends.
+  /// The ends of joined edges are merged, keeping the vertices (and positions) of this mesh.
+  /// Everything taken from `other` gets a new id, and `other` is left empty.
+  /// Returns the number of edge pairs joined
+  pub fn stitch(&mut self, other: &mut HalfEdgeMesh, eps: f32) -> usize {
+    let own_border: Vec<VertRc> = self.vertices.values().filter(|v| v.borrow().is_boundary()).cloned().collect();
+    let own_border_edges: HashMap<(u32, u32), EdgeRc> = self.edges.values()
+      .filter(|e| e.borrow().is_boundary())
+      .filter_map(|e| border_key(e).map(|key| (key, e.clone())))
+      .collect();
+    let other_border_edges: Vec<EdgeRc> = other.edges.values().filter(|e| e.borrow().is_boundary()).cloned().collect();
+
+    // Closest border vertex of this mesh to each border vertex of the other, by the other's ids
+    let eps2 = eps * eps;
+    let mut closest: HashMap<u32, VertRc> = HashMap::new();
+    for vert in other.vertices.values().filter(|v| v.borrow().is_boundary()) {
+      let pos = vert.borrow().get_pos();
+      let nearest = own_border.iter()
+        .map(|v| (v.borrow().get_pos().distance2(pos), v))
+        .filter(|&(dist2, _)| dist2 <= eps2)
+        .min_by(|a, b| a.0.partial_cmp(& b.0).unwrap_or(Ordering::Equal));
+      if let Some((_, own)) = nearest { closest.insert(vert.borrow().id, own.clone()); }
+    }
+
+    let mut joined: Vec<(EdgeRc, EdgeRc)> = Vec::new();
+    let mut merges: HashMap<u32, VertRc> = HashMap::new();
+    for edge in & other_border_edges {
+      let (origin_id, target_id) = match border_key(edge) { Some(key) => key, None => continue };
+      let (origin, target) = match (closest.get(& origin_id), closest.get(& target_id)) {
+        (Some(o), Some(t)) => (o.clone(), t.clone()),
+        _ => continue,
+      };
+      let reversed = (target.borrow().id, origin.borrow().id);
+      if let Some(own_edge) = own_border_edges.get(& reversed) {
+        joined.push((edge.clone(), own_edge.clone()));
+        merges.insert(origin_id, origin);
+        merges.insert(target_id, target);
+      }
+    }
+
+    for edge in other.edges.values() {
+      let origin_id = match edge.borrow().get_origin() { Some(o) => o.borrow().id, None => continue };
+      if let Some(keep) = merges.get(& origin_id) { edge.borrow_mut().set_origin_rc(keep); }
+    }
+    for (edge, own_edge) in & joined {
+      edge.borrow_mut().set_pair_rc(own_edge);
+      own_edge.borrow_mut().set_pair_rc(edge);
+    }
+
+    let taken = other.take_contents();
+    for (old_id, vert) in taken.vertices {
+      if merges.contains_key(& old_id) { continue; }
+      let id = self.new_vert_id();
+      vert.borrow_mut().id = id;
+      if let Some(uv) = taken.uvs.get(& old_id) { self.uvs.insert(id, *uv); }
+      if let Some(tangent) = taken.tangents.get(& old_id) { self.tangents.insert(id, *tangent); }
+      self.push_vert(vert);
+    }
+    for (_, edge) in taken.edges {
+      edge.borrow_mut().id = self.new_edge_id();
+      self.push_edge(edge);
+    }
+    for (_, face) in taken.faces {
+      face.borrow_mut().id = self.new_face_id();
+      self.push_face(face);
+    }
+
+    // The merged vertices can be interior now, or have a different incoming border edge
+    let merged: Vec<VertRc> = merges.values().cloned().collect();
+    for vert in & merged {
+      anchor_boundary_vert(vert);
+      for out_edge in vert.borrow().outgoing_edges() {
+        if let Some(face) = out_edge.borrow().get_face() { face.borrow_mut().compute_attrs(); }
+      }
+    }
+
+    debug!("stitch: joined {} edge pairs, merged {} vertices", joined.len(), merged.len());
+    self.check_after("stitch");
+    joined.len()
+  }
+}
+
+/// Origin and target ids of an edge
+fn border_key(edge: & EdgeRc) -> Option<(u32, u32)> {
+  let edge_b = edge.borrow();
+  Some((edge_b.get_origin()?.borrow().id, edge_b.get_target()?.borrow().id))
+}
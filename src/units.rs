@@ -0,0 +1,80 @@
+use cgmath::{Point3, EuclideanSpace};
+
+use mesh::HalfEdgeMesh;
+use ptr::VertRc;
+use convention::CoordinateConvention;
+
+/// Unit of length of a mesh's coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LengthUnit {
+  Millimeter,
+  Centimeter,
+  #[default]
+  Meter,
+  Inch,
+}
+
+impl LengthUnit {
+  /// Length of one of these units in meters
+  pub fn meters(& self) -> f32 {
+    match *self {
+      LengthUnit::Millimeter => 0.001,
+      LengthUnit::Centimeter => 0.01,
+      LengthUnit::Meter => 1.0,
+      LengthUnit::Inch => 0.0254,
+    }
+  }
+
+  /// What to multiply lengths in these units by to get them in the other units
+  pub fn scale_to(& self, to: LengthUnit) -> f32 {
+    if *self == to { 1.0 } else { self.meters() / to.meters() }
+  }
+
+  /// Converts a length in these units to the other units
+  pub fn convert(& self, length: f32, to: LengthUnit) -> f32 {
+    length * self.scale_to(to)
+  }
+}
+
+/// Options for `HalfEdgeMesh::from_polygons_with`
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+  /// Units the positions are in, e.g. millimeters for most STL files
+  pub units: LengthUnit,
+  /// Scale the mesh into these units after building it, instead of keeping the ones it came in
+  pub convert_units: Option<LengthUnit>,
+  /// Convert the mesh from the first axis convention to the second, see `HalfEdgeMesh::convert_axes`
+  pub convert_axes: Option<(CoordinateConvention, CoordinateConvention)>,
+}
+
+impl HalfEdgeMesh {
+  /// Construct a half edge mesh as in `from_polygons`, from data in some file's units and axis convention,
+  /// e.g. an STL file in millimeters to be worked on in meters
+  pub fn from_polygons_with<F>(vertices: &[Point3<f32>], indices: &[F], options: & ImportOptions) -> HalfEdgeMesh where F: AsRef<[usize]> {
+    let mut mesh = HalfEdgeMesh::from_polygons(vertices, indices);
+    mesh.set_units(options.units);
+    if let Some(units) = options.convert_units { mesh.convert_units(units); }
+    if let Some((from, to)) = options.convert_axes { mesh.convert_axes(from, to); }
+    mesh
+  }
+
+  /// Scales the mesh about the origin from its current units to the given ones
+  pub fn convert_units(&mut self, units: LengthUnit) {
+    let scale = self.units().scale_to(units);
+    self.set_units(units);
+    if scale == 1.0 { return; }
+
+    let verts: Vec<VertRc> = self.vertices.values().cloned().collect();
+    for vert in & verts {
+      let pos = vert.borrow().get_pos();
+      self.move_vert(vert, Point3::from_vec(pos.to_vec() * scale));
+    }
+    for face in self.faces.values() { face.borrow_mut().compute_attrs(); }
+  }
+
+  /// A length given in some units, in the mesh's units. Handy for tolerances, which mean
+  /// something different for a mesh in millimeters than for one in meters
+  pub fn to_mesh_units(& self, length: f32, units: LengthUnit) -> f32 {
+    units.convert(length, self.units())
+  }
+}
@@ -1,8 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
-use ptr::{Ptr, EdgeRc};
+use ptr::{Ptr, EdgeRc, VertRc};
 use mesh::HalfEdgeMesh;
 
+/// Time an operation has taken, for its debug log line. The clock is only read when debug logging is on, since
+/// otherwise the reading goes to waste, and some targets, like wasm32-unknown-unknown, have no clock and panic
+pub(crate) struct OpTimer(Option<Instant>);
+
+impl OpTimer {
+  pub(crate) fn start() -> OpTimer {
+    OpTimer(if log_enabled!(::log::Level::Debug) { Some(Instant::now()) } else { None })
+  }
+
+  /// Time since `start`, or zero if the clock wasn't read
+  pub(crate) fn elapsed(& self) -> Duration {
+    self.0.map_or(Duration::ZERO, |start| start.elapsed())
+  }
+}
+
 fn merge_tuple_opt<A, B>(o: (Option<A>, Option<B>)) -> Option<(A, B)> {
   match o {
     (Some(a), Some(b)) => Some((a, b)),
@@ -18,88 +34,237 @@ fn vert_ab_key(e: & EdgeRc) -> Option<(u32, u32)> {
 
 fn vert_ba_key(e: & EdgeRc) -> Option<(u32, u32)> { vert_ab_key(e).map(|tuple| (tuple.1, tuple.0)) }
 
-/// Takes what is assumed to be a fully constructed mesh, with no
-/// pair links, and establishes pair links between adjacent edges.
-/// If this function runs successfully on a mesh, all links in the mesh
-/// should point to their adjacent pair
-pub fn connect_pairs(mesh: &mut HalfEdgeMesh) -> Result<(), &'static str> {
-  // Two-stage algorithm: first collect all edge A -> B relationships,
-  // Then go through and look for edges that are B -> A
-  let mut edge_hash: HashMap<(u32, u32), & EdgeRc> = HashMap::new();
-
-  for ref edge in mesh.edges.values() {
-    // The types returned by match arms must be the same,
-    // hence the braces and semicolon used in the first branch
-    match vert_ab_key(edge) {
-      Some(key) => { edge_hash.insert(key, edge); },
-      // This happens if one of the mesh edges doesn't have a valid .origin or .next.origin pointer
-      None => { return Err("Could not hash all mesh edges"); }
-    }
-  }
+/// Points a boundary vertex at its first outgoing edge in clockwise order
+/// (the one following the incoming boundary edge), so that the vertex
+/// iterators can walk all the way around it. Does nothing for interior vertices
+pub fn anchor_boundary_vert(vert: & VertRc) {
+  let anchor = vert.borrow().outgoing_edges().into_iter()
+    .find(|e| e.borrow().get_prev().is_some_and(|p| p.borrow().is_boundary()));
 
-  for ref edge in mesh.edges.values() {
-    // This if statement should skip half the edges, because two
-    // edge pairs are set each time it's true
-    if !edge.borrow().pair.is_valid() {
-      if let Some(key) = vert_ba_key(edge) {
-        match edge_hash.get(& key) {
-          Some(pair_edge) => {
-            // if one edge A -> B matches another edge B -> A, the edges are adjacent
-            edge.borrow_mut().take_pair(Ptr::new(pair_edge));
-            pair_edge.borrow_mut().take_pair(Ptr::new(edge));
-          },
-          None => { /* Happens when mesh is not closed */
-            return Err("Could not find pair edge");
-          }
-        }
-      } else {
-        // Theoretically this shouldn't ever happen
-        // because of the early return in the previous match block
-        return Err("Could not find reverse hash for mesh edge");
-      }
-    }
+  if let Some(edge) = anchor {
+    vert.borrow_mut().set_edge_rc(& edge);
   }
-
-  Ok(())
 }
 
 /// Utility function for reporting problems with edge connectivity
 pub fn report_connect_err(res: Result<(), &str>) {
   if let Err(e) = res {
-    println!("Error connecting mesh pairs! Mesh is not valid! {}", e);
+    warn!("Error connecting mesh pairs! Mesh is not valid! {}", e);
+  }
+}
+
+/// The next edge around the edge's origin, clockwise or counterclockwise, through its pair
+fn step_around_origin(edge: & EdgeRc, clockwise: bool) -> Option<EdgeRc> {
+  if clockwise {
+    edge.borrow().get_pair().and_then(|p| p.borrow().get_next())
+  } else {
+    edge.borrow().get_prev().and_then(|p| p.borrow().get_pair())
   }
 }
 
-/// Checks if edge pair connections are all valid
-pub fn are_edge_pairs_valid(mesh: & HalfEdgeMesh) -> Result<(), &'static str> {
-  let mut edge_hash: HashMap<(u32, u32), & EdgeRc> = HashMap::new();
-
-  for ref edge in mesh.edges.values() {
-    // The types returned by match arms must be the same,
-    // hence the braces and semicolon used in the first branch
-    match vert_ab_key(edge) {
-      Some(key) => { edge_hash.insert(key, edge); },
-      // This happens if one of the mesh edges doesn't have a valid .origin or .next.origin pointer
-      None => { return Err("Could not hash all mesh edges"); }
+/// Every edge starting where this one does, found by walking around its origin through pair links both ways
+fn edges_around_origin(edge: & EdgeRc) -> Vec<EdgeRc> {
+  let mut seen: HashSet<u32> = HashSet::new();
+  seen.insert(edge.borrow().id);
+  let mut found: Vec<EdgeRc> = vec![edge.clone()];
+  for &clockwise in & [true, false] {
+    let mut current = step_around_origin(edge, clockwise);
+    while let Some(next) = current {
+      if !seen.insert(next.borrow().id) { break; }
+      found.push(next.clone());
+      current = step_around_origin(& next, clockwise);
     }
   }
+  found
+}
+
+impl HalfEdgeMesh {
+  /// Takes what is assumed to be a fully constructed mesh, with no
+  /// pair links, and establishes pair links between adjacent edges.
+  /// If this function runs successfully on a mesh, all links in the mesh
+  /// should point to their adjacent pair.
+  /// Open meshes are connected as far as possible, with the border edges
+  /// left without a pair, which isn't an error. Returns an error if an edge is missing
+  /// its origin or next edge, or if two edges run the same way between the same vertices,
+  /// in which case only the first of them can be paired
+  pub fn connect_all_pairs(&mut self) -> Result<(), &'static str> {
+    let start = OpTimer::start();
+    // Two-stage algorithm: first collect all edge A -> B relationships,
+    // Then go through and look for edges that are B -> A
+    let mut edge_hash: HashMap<(u32, u32), & EdgeRc> = HashMap::new();
+    let mut num_duplicates = 0;
+
+    for ref edge in self.edges.values() {
+      // The types returned by match arms must be the same,
+      // hence the braces and semicolon used in the first branch
+      match vert_ab_key(edge) {
+        Some(key) => { if edge_hash.insert(key, edge).is_some() { num_duplicates += 1; } },
+        // This happens if one of the mesh edges doesn't have a valid .origin or .next.origin pointer
+        None => { return Err("Could not hash all mesh edges"); }
+      }
+    }
+
+    let mut border_edges: Vec<EdgeRc> = Vec::new();
+    let mut num_paired = 0;
 
-  for ref edge in mesh.edges.values() {
-    match vert_ba_key(edge) {
-      Some(key) => {
-        match edge_hash.get(& key) {
-          Some(ref pair) => {
-            if (edge.borrow().pair.upgrade().as_ref() != Some(pair)) ||
-               (pair.borrow().pair.upgrade().as_ref() != Some(edge)) {
-                return Err("Pairs don't match");
+    for ref edge in self.edges.values() {
+      // This if statement should skip half the edges, because two
+      // edge pairs are set each time it's true
+      if !edge.borrow().pair.is_valid() {
+        if let Some(key) = vert_ba_key(edge) {
+          match edge_hash.get(& key) {
+            Some(pair_edge) => {
+              // if one edge A -> B matches another edge B -> A, the edges are adjacent
+              edge.borrow_mut().take_pair(Ptr::new(pair_edge));
+              pair_edge.borrow_mut().take_pair(Ptr::new(edge));
+              num_paired += 1;
+            },
+            None => { /* Happens when mesh is not closed */
+              border_edges.push((*edge).clone());
             }
-          },
-          None => { return Err("Could not find a pair edge"); }
+          }
+        } else {
+          // Theoretically this shouldn't ever happen
+          // because of the early return in the previous match block
+          return Err("Could not find reverse hash for mesh edge");
         }
-      },
-      None => { return Err("Could not find reverse hash for mesh edge"); }
+      }
     }
+
+    debug!("connect_all_pairs: {} edges, {} pairs connected, {} border edges, took {:?}",
+      self.edges.len(), num_paired, border_edges.len(), start.elapsed());
+
+    // Vertices at the end of a border edge need to point at the edge after it
+    for edge in & border_edges {
+      if let Some(target) = edge.borrow().get_target() {
+        anchor_boundary_vert(& target);
+      }
+    }
+
+    if num_duplicates > 0 { return Err("Two edges run between the same vertices in the same direction"); }
+    Ok(())
   }
 
-  Ok(())
+  /// Like `connect_all_pairs`, but only pairs up the given edges, such as those of a few faces just added,
+  /// without hashing the whole mesh. Their pairs are looked for among the given edges themselves, and around
+  /// the vertices they start and end at, going by the edge each vertex points to. `make_triangle` points
+  /// the vertices at the new edges, so to join new faces onto the border of the mesh, pass the border
+  /// edges they meet along with them. Edges which are already paired are left alone.
+  /// Returns an error if any of the edges are left without a pair. Vertices left on the border are pointed
+  /// at their first outgoing edge, as `anchor_boundary_vert` does
+  pub fn connect_pairs_in(&mut self, edges: & [EdgeRc]) -> Result<(), &'static str> {
+    let start = OpTimer::start();
+    // The edges, and the edges around each end along with the edges coming into them,
+    // which include any border edge running back
+    let mut nearby: HashMap<(u32, u32), EdgeRc> = HashMap::new();
+    for edge in edges {
+      let (next, origin) = (edge.borrow().get_next(), edge.borrow().get_origin());
+      let target = next.as_ref().and_then(|n| n.borrow().get_origin());
+      let starts = Some(edge.clone()).into_iter().chain(next)
+        .chain(origin.and_then(|o| o.borrow().get_edge()))
+        .chain(target.and_then(|t| t.borrow().get_edge()));
+      for around in starts.flat_map(|s| edges_around_origin(& s)) {
+        let prev = around.borrow().get_prev();
+        for candidate in Some(around).into_iter().chain(prev) {
+          let key = vert_ab_key(& candidate).ok_or("Could not hash all mesh edges")?;
+          nearby.insert(key, candidate);
+        }
+      }
+    }
+
+    let mut border_edges: Vec<EdgeRc> = Vec::new();
+    let mut num_paired = 0;
+    for edge in edges {
+      if edge.borrow().pair.is_valid() { continue; }
+      let key = vert_ba_key(edge).ok_or("Could not find reverse hash for mesh edge")?;
+      match nearby.get(& key).filter(|pair_edge| !pair_edge.borrow().pair.is_valid()) {
+        Some(pair_edge) => {
+          edge.borrow_mut().take_pair(Ptr::new(pair_edge));
+          pair_edge.borrow_mut().take_pair(Ptr::new(edge));
+          num_paired += 1;
+        },
+        None => border_edges.push(edge.clone()),
+      }
+    }
+
+    debug!("connect_pairs_in: {} edges, {} pairs connected, {} border edges, took {:?}",
+      edges.len(), num_paired, border_edges.len(), start.elapsed());
+    // Any of the vertices could have ended up on the border, or be pointing at an edge in the wrong place for it
+    for edge in edges {
+      if let Some(origin) = edge.borrow().get_origin() { anchor_boundary_vert(& origin); }
+    }
+    if border_edges.is_empty() { Ok(()) } else { Err("Could not find pair edge") }
+  }
+
+  /// Checks if edge pair connections are all valid
+  pub fn are_edge_pairs_valid(& self) -> Result<(), &'static str> {
+    let mut edge_hash: HashMap<(u32, u32), & EdgeRc> = HashMap::new();
+
+    for ref edge in self.edges.values() {
+      // The types returned by match arms must be the same,
+      // hence the braces and semicolon used in the first branch
+      match vert_ab_key(edge) {
+        Some(key) => { edge_hash.insert(key, edge); },
+        // This happens if one of the mesh edges doesn't have a valid .origin or .next.origin pointer
+        None => { return Err("Could not hash all mesh edges"); }
+      }
+    }
+
+    for ref edge in self.edges.values() {
+      match vert_ba_key(edge) {
+        Some(key) => {
+          match edge_hash.get(& key) {
+            Some(ref pair) => {
+              if (edge.borrow().pair.upgrade().as_ref() != Some(pair)) ||
+                 (pair.borrow().pair.upgrade().as_ref() != Some(edge)) {
+                  return Err("Pairs don't match");
+              }
+            },
+            None => { return Err("Could not find a pair edge"); }
+          }
+        },
+        None => { return Err("Could not find reverse hash for mesh edge"); }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Like `are_edge_pairs_valid`, but only checks the given edges: each has to be paired with an edge
+  /// running the other way, which points back at it
+  pub fn are_edge_pairs_valid_in(& self, edges: & [EdgeRc]) -> Result<(), &'static str> {
+    for edge in edges {
+      let key = vert_ba_key(edge).ok_or("Could not find reverse hash for mesh edge")?;
+      let pair = edge.borrow().pair.upgrade().ok_or("Could not find a pair edge")?;
+      if vert_ab_key(& pair) != Some(key) || pair.borrow().pair.upgrade().as_ref() != Some(edge) {
+        return Err("Pairs don't match");
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::Point3;
+
+  use mesh::HalfEdgeMesh;
+
+  fn square() -> Vec<Point3<f32>> {
+    vec![Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(1.0, 1.0, 0.0), Point3::new(0.0, 1.0, 0.0)]
+  }
+
+  #[test]
+  fn open_mesh_connects_without_error() {
+    let mut mesh = HalfEdgeMesh::from_polygons_unpaired(& square(), & [vec![0, 1, 2], vec![0, 2, 3]]);
+    assert_eq!(mesh.connect_all_pairs(), Ok(()));
+    assert_eq!(mesh.edges().filter(|e| e.borrow().is_boundary()).count(), 4);
+    assert_eq!(mesh.validate(), Ok(()));
+  }
+
+  #[test]
+  fn edges_running_the_same_way_are_an_error() {
+    let mut mesh = HalfEdgeMesh::from_polygons_unpaired(& square(), & [vec![0, 1, 2], vec![0, 1, 3]]);
+    assert!(mesh.connect_all_pairs().is_err());
+  }
 }
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use mesh::HalfEdgeMesh;
+use ptr::{Ptr, EdgeRc, VertRc, FaceRc};
+
+/// What's wrong, and the id of the element where it shows up
+type Broken = (&'static str, u32);
+
+/// Whether the pointer leads to the element the mesh holds under that id
+fn in_map<T>(map: & HashMap<u32, Rc<RefCell<T>>>, id: u32, rc: & Rc<RefCell<T>>) -> bool {
+  map.get(& id).is_some_and(|held| Rc::ptr_eq(held, rc))
+}
+
+/// Follows the pointer, which has to lead to an element of the mesh
+fn linked<T, F>(ptr: & Ptr<T>, map: & HashMap<u32, Rc<RefCell<T>>>, id_of: F) -> Option<Rc<RefCell<T>>> where F: Fn(& T) -> u32 {
+  let rc = ptr.upgrade()?;
+  let id = id_of(& rc.borrow());
+  if in_map(map, id, & rc) { Some(rc) } else { None }
+}
+
+fn check_edge(mesh: & HalfEdgeMesh, edge: & EdgeRc) -> Result<(), Broken> {
+  let edge_b = edge.borrow();
+  let id = edge_b.id;
+
+  let origin = linked(& edge_b.origin, & mesh.vertices, |v| v.id).ok_or(("Edge's origin is not in the mesh", id))?;
+  let next = linked(& edge_b.next, & mesh.edges, |e| e.id).ok_or(("Edge's next is not in the mesh", id))?;
+  let face = linked(& edge_b.face, & mesh.faces, |f| f.id).ok_or(("Edge's face is not in the mesh", id))?;
+  if !next.borrow().face.upgrade().is_some_and(|f| Rc::ptr_eq(& f, & face)) {
+    return Err(("Edge's next is in a different face", id));
+  }
+  let target = next.borrow().origin.upgrade().ok_or(("Edge's next has no origin", id))?;
+  if Rc::ptr_eq(& origin, & target) { return Err(("Edge starts and ends at the same vertex", id)); }
+
+  // No pair means the edge is on the border, but a pair which has been removed is a dangling link
+  if edge_b.pair.as_ref().is_some() {
+    let pair = linked(& edge_b.pair, & mesh.edges, |e| e.id).ok_or(("Edge's pair is not in the mesh", id))?;
+    let pair_b = pair.borrow();
+    if !pair_b.pair.upgrade().is_some_and(|p| Rc::ptr_eq(& p, edge)) {
+      return Err(("Edge's pair doesn't point back at it", id));
+    }
+    if !pair_b.origin.upgrade().is_some_and(|o| Rc::ptr_eq(& o, & target)) {
+      return Err(("Edge's pair doesn't start where the edge ends", id));
+    }
+  }
+
+  Ok(())
+}
+
+fn check_vert(mesh: & HalfEdgeMesh, vert: & VertRc) -> Result<(), Broken> {
+  let vert_b = vert.borrow();
+  let edge = linked(& vert_b.edge, & mesh.edges, |e| e.id).ok_or(("Vertex's edge is not in the mesh", vert_b.id))?;
+  if !edge.borrow().origin.upgrade().is_some_and(|o| Rc::ptr_eq(& o, vert)) {
+    return Err(("Vertex's edge doesn't start at it", vert_b.id));
+  }
+  Ok(())
+}
+
+/// Walks around the face, and returns how many edges it has
+fn check_face(mesh: & HalfEdgeMesh, face: & FaceRc) -> Result<usize, Broken> {
+  let face_b = face.borrow();
+  let id = face_b.id;
+  let start = linked(& face_b.edge, & mesh.edges, |e| e.id).ok_or(("Face's edge is not in the mesh", id))?;
+
+  let mut current = start.clone();
+  let mut count = 0;
+  loop {
+    if !current.borrow().face.upgrade().is_some_and(|f| Rc::ptr_eq(& f, face)) {
+      return Err(("Face's edge loop runs into another face", id));
+    }
+    count += 1;
+    if count > mesh.edges.len() { return Err(("Face's edge loop doesn't get back to its first edge", id)); }
+    let next = current.borrow().next.upgrade().ok_or(("Face's edge loop is broken", id))?;
+    current = next;
+    if Rc::ptr_eq(& current, & start) { break; }
+  }
+
+  if count < 3 { return Err(("Face has fewer than three edges", id)); }
+  Ok(count)
+}
+
+impl HalfEdgeMesh {
+  /// The checks `validate` runs which only follow the links of each element: every link leads to an element
+  /// in the mesh, pairs point back at each other and run the other way, vertices start their edge,
+  /// and faces form closed loops of at least three edges. Linear in the size of the mesh
+  fn find_broken_link(& self) -> Result<usize, Broken> {
+    for id in self.sorted_edge_ids() { check_edge(self, & self.edges[& id])?; }
+
+    let mut vert_ids: Vec<u32> = self.vertices.keys().cloned().collect();
+    vert_ids.sort();
+    for id in vert_ids { check_vert(self, & self.vertices[& id])?; }
+
+    let mut face_ids: Vec<u32> = self.faces.keys().cloned().collect();
+    face_ids.sort();
+    let mut loop_edges = 0;
+    for id in face_ids { loop_edges += check_face(self, & self.faces[& id])?; }
+    Ok(loop_edges)
+  }
+
+  fn sorted_edge_ids(& self) -> Vec<u32> {
+    let mut ids: Vec<u32> = self.edges.keys().cloned().collect();
+    ids.sort();
+    ids
+  }
+
+  /// Checks that the mesh is a valid half-edge mesh, and says what's wrong with the first broken element if not.
+  /// On top of the links between elements, checks that every edge is in its face's loop, that no two edges
+  /// run between the same vertices in the same direction, and that edges are paired up wherever they can be.
+  /// Open borders are fine
+  pub fn validate(& self) -> Result<(), &'static str> {
+    let loop_edges = self.find_broken_link().map_err(|(message, _)| message)?;
+    if loop_edges != self.edges.len() { return Err("Some edges aren't in their face's edge loop"); }
+
+    let mut directed: HashMap<(u32, u32), u32> = HashMap::new();
+    for id in self.sorted_edge_ids() {
+      let edge_b = self.edges[& id].borrow();
+      let key = match (edge_b.get_origin(), edge_b.get_target()) {
+        (Some(o), Some(t)) => (o.borrow().id, t.borrow().id),
+        _ => return Err("Edge has no origin or target"),
+      };
+      if directed.insert(key, id).is_some() { return Err("Two edges run between the same vertices in the same direction"); }
+    }
+
+    let paired: HashSet<u32> = self.edges.values().filter(|e| e.borrow().get_pair().is_some()).map(|e| e.borrow().id).collect();
+    for (&(origin, target), id) in & directed {
+      if !paired.contains(id) && directed.contains_key(& (target, origin)) {
+        return Err("Border edge could be paired with the edge running the other way");
+      }
+    }
+
+    Ok(())
+  }
+
+  /// With the `paranoid` feature, checks the links between elements after an operation, and panics naming
+  /// the operation and the element it broke. Without it, does nothing
+  #[cfg(feature = "paranoid")]
+  pub(crate) fn check_after(& self, operation: & str) {
+    if let Err((message, id)) = self.find_broken_link() {
+      panic!("{} left the mesh broken: {} (id {})", operation, message, id);
+    }
+  }
+
+  #[cfg(not(feature = "paranoid"))]
+  #[inline(always)]
+  pub(crate) fn check_after(& self, _operation: & str) {}
+}
@@ -1,8 +1,9 @@
 use std::hash;
 
-use cgmath::Point3;
+use cgmath::{Point3, Vector3, InnerSpace};
 
-use ptr::{Ptr, EdgePtr, EdgeRc};
+use ptr::{Ptr, EdgePtr, EdgeRc, VertRc};
+use iterators::ToPtrVec;
 use iterators::*;
 
 #[derive(Debug)]
@@ -46,6 +47,72 @@ impl Vert {
 
   pub fn get_edge(& self) -> Option<EdgeRc> { self.edge.upgrade() }
 
+  /// Collects every edge starting at this vertex. Unlike `adjacent_edges`, this finds all of
+  /// the edges around a boundary vertex, no matter which of them the vertex points to.
+  /// The edges are in *clockwise* order
+  pub fn outgoing_edges(& self) -> Vec<EdgeRc> {
+    let mut edges = self.adjacent_edges().to_ptr_vec();
+
+    // The clockwise walk stops at the first boundary edge, so walk counterclockwise
+    // from the start edge (edge.prev.pair) to pick up the rest
+    let mut before: Vec<EdgeRc> = Vec::new();
+    let mut current = self.get_edge();
+    while let Some(prev_pair) = current.and_then(|c| c.borrow().get_prev()).and_then(|p| p.borrow().get_pair()) {
+      let seen = edges.iter().chain(before.iter()).any(|e| e.borrow().id == prev_pair.borrow().id);
+      if seen { break; }
+      before.push(prev_pair.clone());
+      current = Some(prev_pair);
+    }
+
+    before.reverse();
+    before.append(&mut edges);
+    before
+  }
+
+  /// Every edge leading from the vertex to a neighbor, along with that neighbor, clockwise.
+  /// An incoming border edge has no half going the other way, so it's used backwards
+  pub fn neighbor_edges(& self) -> Vec<(EdgeRc, VertRc)> {
+    let mut found: Vec<(EdgeRc, VertRc)> = Vec::new();
+    for out_edge in self.outgoing_edges() {
+      if let Some(target) = out_edge.borrow().get_target() { found.push((out_edge.clone(), target)); }
+      let incoming = out_edge.borrow().get_prev().filter(|p| p.borrow().is_boundary());
+      if let Some(border) = incoming {
+        if let Some(origin) = border.borrow().get_origin() { found.push((border.clone(), origin)); }
+      }
+    }
+    found
+  }
+
+  /// Checks if the vertex is on the border of an open mesh
+  pub fn is_boundary(& self) -> bool {
+    self.outgoing_edges().iter().any(|e| {
+      let e_b = e.borrow();
+      e_b.is_boundary() || e_b.get_prev().is_some_and(|p| p.borrow().is_boundary())
+    })
+  }
+
+  /// Normal of the vertex, averaged from the normals of the faces around it,
+  /// weighted by face area. Zero if the vertex has no faces
+  pub fn normal(& self) -> Vector3<f32> {
+    let mut sum = Vector3::new(0.0, 0.0, 0.0);
+    for out_edge in self.outgoing_edges() {
+      if let Some(face) = out_edge.borrow().get_face() {
+        let face_b = face.borrow();
+        sum += face_b.normal * face_b.area();
+      }
+    }
+    if sum.magnitude2() > 0.0 { sum.normalize() } else { sum }
+  }
+
+  /// Number of edges connected to the vertex
+  pub fn valence(& self) -> usize {
+    let edges = self.outgoing_edges();
+    // A boundary vertex has one more neighbor than outgoing edges,
+    // at the start of the incoming boundary edge
+    let open_fan = edges.first().and_then(|e| e.borrow().get_prev()).is_some_and(|p| p.borrow().is_boundary());
+    if open_fan { edges.len() + 1 } else { edges.len() }
+  }
+
   /// Important: Iterates over the vertices connected to a vertex in *clockwise* order
   pub fn adjacent_verts(& self) -> VertAdjacentVertIterator {
     VertAdjacentVertIterator::new(self.edge.clone())
@@ -60,6 +127,12 @@ impl Vert {
   pub fn adjacent_faces(& self) -> VertAdjacentFaceIterator {
     VertAdjacentFaceIterator::new(self.edge.clone())
   }
+
+  /// Iterates breadth-first over the vertices at most `rings` edges away, not including this one.
+  /// Use `next_with_ring` or `into_rings` on the iterator to tell the rings apart
+  pub fn neighborhood(& self, rings: usize) -> VertNeighborhoodIterator {
+    VertNeighborhoodIterator::from_vert(self, rings)
+  }
 }
 
 impl PartialEq<Vert> for Vert {
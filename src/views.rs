@@ -0,0 +1,185 @@
+use std::rc::Rc;
+
+use cgmath::{Point3, Vector2, Vector3};
+
+use mesh::HalfEdgeMesh;
+use iterators::ToPtrVec;
+use ptr::{EdgeRc, FaceRc, VertRc};
+
+/// A vertex along with the mesh it's in, for walking around the mesh without borrowing and upgrading
+/// pointers by hand. Every step which could lead to a dead pointer, or to an element which has been dropped
+/// from the mesh, gives an `Option`, so `edge.next()?.origin()?.pos()` never panics
+#[derive(Clone)]
+pub struct VertView<'a> {
+  mesh: &'a HalfEdgeMesh,
+  rc: VertRc,
+}
+
+/// A half-edge along with the mesh it's in. See `VertView`
+#[derive(Clone)]
+pub struct EdgeView<'a> {
+  mesh: &'a HalfEdgeMesh,
+  rc: EdgeRc,
+}
+
+/// A face along with the mesh it's in. See `VertView`
+#[derive(Clone)]
+pub struct FaceView<'a> {
+  mesh: &'a HalfEdgeMesh,
+  rc: FaceRc,
+}
+
+impl<'a> VertView<'a> {
+  /// None if the vertex isn't in the mesh
+  pub fn new(mesh: &'a HalfEdgeMesh, rc: & VertRc) -> Option<VertView<'a>> {
+    let id = rc.borrow().id;
+    mesh.vert(id).filter(|held| Rc::ptr_eq(held, rc)).map(|rc| VertView { mesh, rc: rc.clone() })
+  }
+
+  pub fn mesh(& self) -> &'a HalfEdgeMesh { self.mesh }
+
+  pub fn rc(& self) -> & VertRc { & self.rc }
+
+  pub fn id(& self) -> u32 { self.rc.borrow().id }
+
+  pub fn pos(& self) -> Point3<f32> { self.rc.borrow().get_pos() }
+
+  pub fn normal(& self) -> Vector3<f32> { self.rc.borrow().normal() }
+
+  /// Texture coordinate, if the mesh has one for the vertex
+  pub fn uv(& self) -> Option<Vector2<f32>> { self.mesh.uvs.get(& self.id()).cloned() }
+
+  pub fn is_boundary(& self) -> bool { self.rc.borrow().is_boundary() }
+
+  pub fn valence(& self) -> usize { self.rc.borrow().valence() }
+
+  /// The edge the vertex points to, which starts at it
+  pub fn edge(& self) -> Option<EdgeView<'a>> {
+    self.rc.borrow().get_edge().and_then(|e| EdgeView::new(self.mesh, & e))
+  }
+
+  /// Every edge starting at the vertex, clockwise, as `Vert::outgoing_edges`
+  pub fn outgoing(& self) -> Vec<EdgeView<'a>> {
+    self.rc.borrow().outgoing_edges().iter().filter_map(|e| EdgeView::new(self.mesh, e)).collect()
+  }
+
+  /// The vertices joined to this one by an edge
+  pub fn neighbors(& self) -> Vec<VertView<'a>> {
+    self.rc.borrow().neighbor_edges().iter().filter_map(|(_, neighbor)| VertView::new(self.mesh, neighbor)).collect()
+  }
+
+  /// The faces around the vertex, clockwise
+  pub fn faces(& self) -> Vec<FaceView<'a>> {
+    self.outgoing().iter().filter_map(|e| e.face()).collect()
+  }
+}
+
+impl<'a> EdgeView<'a> {
+  /// None if the edge isn't in the mesh
+  pub fn new(mesh: &'a HalfEdgeMesh, rc: & EdgeRc) -> Option<EdgeView<'a>> {
+    let id = rc.borrow().id;
+    mesh.edge(id).filter(|held| Rc::ptr_eq(held, rc)).map(|rc| EdgeView { mesh, rc: rc.clone() })
+  }
+
+  pub fn mesh(& self) -> &'a HalfEdgeMesh { self.mesh }
+
+  pub fn rc(& self) -> & EdgeRc { & self.rc }
+
+  pub fn id(& self) -> u32 { self.rc.borrow().id }
+
+  pub fn is_boundary(& self) -> bool { self.rc.borrow().is_boundary() }
+
+  pub fn is_sharp(& self) -> bool { self.rc.borrow().sharp }
+
+  pub fn next(& self) -> Option<EdgeView<'a>> {
+    self.rc.borrow().get_next().and_then(|e| EdgeView::new(self.mesh, & e))
+  }
+
+  /// The edge before this one in its face
+  pub fn prev(& self) -> Option<EdgeView<'a>> {
+    self.rc.borrow().get_prev().and_then(|e| EdgeView::new(self.mesh, & e))
+  }
+
+  /// The other half of the edge, running the other way. None on the border
+  pub fn pair(& self) -> Option<EdgeView<'a>> {
+    self.rc.borrow().get_pair().and_then(|e| EdgeView::new(self.mesh, & e))
+  }
+
+  pub fn origin(& self) -> Option<VertView<'a>> {
+    self.rc.borrow().get_origin().and_then(|v| VertView::new(self.mesh, & v))
+  }
+
+  /// The vertex the edge runs to, the origin of the next edge
+  pub fn target(& self) -> Option<VertView<'a>> {
+    self.rc.borrow().get_target().and_then(|v| VertView::new(self.mesh, & v))
+  }
+
+  pub fn face(& self) -> Option<FaceView<'a>> {
+    self.rc.borrow().get_face().and_then(|f| FaceView::new(self.mesh, & f))
+  }
+
+  /// The vector from the origin to the target
+  pub fn vector(& self) -> Option<Vector3<f32>> {
+    Some(self.target()?.pos() - self.origin()?.pos())
+  }
+
+  /// Angle between the faces either side, as `Edge::dihedral_angle`
+  pub fn dihedral_angle(& self) -> Option<f32> { self.rc.borrow().dihedral_angle() }
+}
+
+impl<'a> FaceView<'a> {
+  /// None if the face isn't in the mesh
+  pub fn new(mesh: &'a HalfEdgeMesh, rc: & FaceRc) -> Option<FaceView<'a>> {
+    let id = rc.borrow().id;
+    mesh.face(id).filter(|held| Rc::ptr_eq(held, rc)).map(|rc| FaceView { mesh, rc: rc.clone() })
+  }
+
+  pub fn mesh(& self) -> &'a HalfEdgeMesh { self.mesh }
+
+  pub fn rc(& self) -> & FaceRc { & self.rc }
+
+  pub fn id(& self) -> u32 { self.rc.borrow().id }
+
+  pub fn normal(& self) -> Vector3<f32> { self.rc.borrow().normal }
+
+  pub fn center(& self) -> Point3<f32> { self.rc.borrow().center }
+
+  pub fn area(& self) -> f32 { self.rc.borrow().area() }
+
+  /// The edge the face points to, its first edge
+  pub fn edge(& self) -> Option<EdgeView<'a>> {
+    self.rc.borrow().get_edge().and_then(|e| EdgeView::new(self.mesh, & e))
+  }
+
+  /// The face's edges, counterclockwise from its first
+  pub fn edges(& self) -> Vec<EdgeView<'a>> {
+    self.rc.borrow().adjacent_edges().to_ptr_vec().iter().filter_map(|e| EdgeView::new(self.mesh, e)).collect()
+  }
+
+  /// The face's corners, counterclockwise, starting at the origin of its first edge
+  pub fn verts(& self) -> Vec<VertView<'a>> {
+    self.edges().iter().filter_map(|e| e.origin()).collect()
+  }
+
+  /// The faces across the face's edges, leaving out border edges
+  pub fn neighbors(& self) -> Vec<FaceView<'a>> {
+    self.edges().iter().filter_map(|e| e.pair()).filter_map(|p| p.face()).collect()
+  }
+}
+
+impl HalfEdgeMesh {
+  /// A view of the vertex with the id, for walking around the mesh. See `VertView`
+  pub fn vert_view(& self, id: u32) -> Option<VertView<'_>> {
+    self.vert(id).map(|rc| VertView { mesh: self, rc: rc.clone() })
+  }
+
+  /// A view of the half-edge with the id. See `VertView`
+  pub fn edge_view(& self, id: u32) -> Option<EdgeView<'_>> {
+    self.edge(id).map(|rc| EdgeView { mesh: self, rc: rc.clone() })
+  }
+
+  /// A view of the face with the id. See `VertView`
+  pub fn face_view(& self, id: u32) -> Option<FaceView<'_>> {
+    self.face(id).map(|rc| FaceView { mesh: self, rc: rc.clone() })
+  }
+}
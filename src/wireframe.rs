@@ -0,0 +1,154 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use cgmath::{Point3, Vector3, InnerSpace, MetricSpace};
+
+use mesh::HalfEdgeMesh;
+use geom::Aabb;
+
+/// Distances below this fraction of the size of the wireframe count as zero,
+/// both for welding endpoints together and for deciding whether a point is in a plane
+const WELD_TOLERANCE: f32 = 1e-5;
+
+/// Welded vertices and the segments between them
+struct Wireframe {
+  positions: Vec<Point3<f32>>,
+  neighbors: Vec<Vec<usize>>,
+  tolerance: f32,
+}
+
+impl Wireframe {
+  fn new(segments: & [(Point3<f32>, Point3<f32>)]) -> Wireframe {
+    let bounds = Aabb::from_points(segments.iter().flat_map(|s| vec![& s.0, & s.1]));
+    let tolerance = if bounds.is_empty() { 0.0 } else { bounds.min.distance(bounds.max) * WELD_TOLERANCE };
+
+    let mut wireframe = Wireframe { positions: Vec::new(), neighbors: Vec::new(), tolerance };
+    let mut cells: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+    let mut edges: HashSet<(usize, usize)> = HashSet::new();
+    for &(a, b) in segments {
+      let (a, b) = (wireframe.weld(a, &mut cells), wireframe.weld(b, &mut cells));
+      if a == b || !edges.insert((a.min(b), a.max(b))) { continue; }
+      wireframe.neighbors[a].push(b);
+      wireframe.neighbors[b].push(a);
+    }
+    wireframe
+  }
+
+  /// Index of the vertex at the point, adding one if there isn't one there yet.
+  /// Vertices are bucketed in a grid of cells as big as the tolerance, so only neighboring cells are searched
+  fn weld(&mut self, point: Point3<f32>, cells: &mut HashMap<(i64, i64, i64), Vec<usize>>) -> usize {
+    let cell_size = self.tolerance.max(f32::MIN_POSITIVE);
+    let cell = ((point.x / cell_size).floor() as i64, (point.y / cell_size).floor() as i64, (point.z / cell_size).floor() as i64);
+    for dx in -1..2 {
+      for dy in -1..2 {
+        for dz in -1..2 {
+          if let Some(found) = cells.get(& (cell.0 + dx, cell.1 + dy, cell.2 + dz)) {
+            if let Some(&idx) = found.iter().find(|&&idx| self.positions[idx].distance(point) <= self.tolerance) {
+              return idx;
+            }
+          }
+        }
+      }
+    }
+    let idx = self.positions.len();
+    self.positions.push(point);
+    self.neighbors.push(Vec::new());
+    cells.entry(cell).or_default().push(idx);
+    idx
+  }
+
+  fn dir(& self, from: usize, to: usize) -> Vector3<f32> { self.positions[to] - self.positions[from] }
+
+  /// Planes through each pair of segments meeting at a vertex, with the normals pointing along
+  /// the positive side of the axis they're closest to, so that each plane only shows up one way
+  fn corner_normals(& self, vert: usize) -> Vec<Vector3<f32>> {
+    let around = & self.neighbors[vert];
+    let mut normals: Vec<Vector3<f32>> = Vec::new();
+    for (idx, &a) in around.iter().enumerate() {
+      for &b in & around[idx + 1..] {
+        let normal = self.dir(vert, a).cross(self.dir(vert, b));
+        if normal.magnitude() <= self.tolerance * self.tolerance { continue; }
+        let normal = normal.normalize();
+        let largest = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() { normal.x }
+          else if normal.y.abs() >= normal.z.abs() { normal.y } else { normal.z };
+        normals.push(if largest < 0.0 { -normal } else { normal });
+      }
+    }
+    normals
+  }
+
+  /// Follows the cycle in the plane which starts along the segment, keeping it on the left by always
+  /// taking the sharpest left turn among the segments in the plane. None if it runs into a dead end,
+  /// or goes through a vertex twice
+  fn trace(& self, from: usize, to: usize, normal: Vector3<f32>) -> Option<Vec<usize>> {
+    let origin = self.positions[from];
+    let in_plane = |idx: usize| (self.positions[idx] - origin).dot(normal).abs() <= self.tolerance;
+    let mut cycle: Vec<usize> = vec![from];
+    let (mut prev, mut cur) = (from, to);
+
+    while cur != from {
+      if cycle.len() > self.positions.len() || cycle.contains(& cur) { return None; }
+      cycle.push(cur);
+      let incoming = self.dir(prev, cur);
+      let next = self.neighbors[cur].iter().cloned()
+        .filter(|&n| n != prev && in_plane(n))
+        .max_by(|&a, &b| {
+          let turn = |n: usize| { let out = self.dir(cur, n); incoming.cross(out).dot(normal).atan2(incoming.dot(out)) };
+          turn(a).partial_cmp(& turn(b)).unwrap_or(::std::cmp::Ordering::Equal)
+        })?;
+      prev = cur;
+      cur = next;
+    }
+
+    if cycle.len() < 3 { return None; }
+    Some(cycle)
+  }
+
+  /// Twice the signed area of the cycle, positive if it runs counterclockwise around the normal
+  fn signed_area(& self, cycle: & [usize], normal: Vector3<f32>) -> f32 {
+    let origin = self.positions[cycle[0]];
+    (1..cycle.len() - 1)
+      .map(|idx| (self.positions[cycle[idx]] - origin).cross(self.positions[cycle[idx + 1]] - origin).dot(normal))
+      .sum()
+  }
+}
+
+impl HalfEdgeMesh {
+  /// Construct a half edge mesh from a wireframe: line segments whose ends meet (within a small tolerance)
+  /// to form closed, flat cycles, like the edges of a CAD model. Each flat cycle which doesn't have
+  /// any segments crossing its inside becomes a face. Segments which don't end up in a face are left out,
+  /// and so are cycles which aren't flat.
+  /// If `close_faces` is set, the wireframe is taken to outline a closed solid, and the faces are wound to point
+  /// out of it (see `orient_outward`). Otherwise each face is wound counterclockwise around its plane's normal,
+  /// pointing along the positive side of the axis it's closest to, e.g. up for a drawing in the xy plane
+  pub fn from_polylines(segments: & [(Point3<f32>, Point3<f32>)], close_faces: bool) -> HalfEdgeMesh {
+    let wireframe = Wireframe::new(segments);
+
+    let mut seen: HashSet<BTreeSet<usize>> = HashSet::new();
+    let mut faces: Vec<Vec<usize>> = Vec::new();
+    for vert in 0..wireframe.positions.len() {
+      for normal in wireframe.corner_normals(vert) {
+        for &next in & wireframe.neighbors[vert] {
+          if (wireframe.positions[next] - wireframe.positions[vert]).dot(normal).abs() > wireframe.tolerance { continue; }
+          let cycle = match wireframe.trace(vert, next, normal) { Some(c) => c, None => continue };
+          // The cycle around the outside of a flat piece runs clockwise, and so does any cycle found
+          // from the wrong side. The counterclockwise cycles are the faces
+          if wireframe.signed_area(& cycle, normal) <= 0.0 { continue; }
+          if seen.insert(cycle.iter().cloned().collect()) { faces.push(cycle); }
+        }
+      }
+    }
+
+    // Vertices which aren't in a face would be left without an edge
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    let mut positions: Vec<Point3<f32>> = Vec::new();
+    for face in &mut faces {
+      for vert in face.iter_mut() {
+        *vert = *index.entry(*vert).or_insert_with(|| { positions.push(wireframe.positions[*vert]); positions.len() - 1 });
+      }
+    }
+
+    let mut mesh = HalfEdgeMesh::from_polygons(& positions, & faces);
+    if close_faces { mesh.orient_outward(); }
+    mesh
+  }
+}